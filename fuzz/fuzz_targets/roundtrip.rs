@@ -0,0 +1,73 @@
+//! Parses the input as a Mach-o object, rebuilds an artifact from its symbols and sections, and
+//! emits it again; emitting may fail, but must never panic.
+#![no_main]
+use faerie::{Artifact, Decl, ImportKind};
+use goblin::mach::{cputype, Mach};
+use libfuzzer_sys::fuzz_target;
+use std::str::FromStr;
+use target_lexicon::Triple;
+
+fuzz_target!(|data: &[u8]| {
+    let mach = match Mach::parse(data) {
+        Ok(Mach::Binary(mach)) => mach,
+        _ => return,
+    };
+    let target = match mach.header.cputype() {
+        cputype::CPU_TYPE_X86_64 => "x86_64-apple-darwin",
+        cputype::CPU_TYPE_ARM64 => "aarch64-apple-darwin",
+        _ => return,
+    };
+    let mut sections = Vec::new();
+    for segment in mach.segments.iter() {
+        match segment.sections() {
+            Ok(segment_sections) => sections.extend(segment_sections),
+            Err(_) => return,
+        }
+    }
+
+    let mut obj = Artifact::new(Triple::from_str(target).unwrap(), "roundtrip.o".into());
+    let mut symbols = Vec::new();
+    for symbol in mach.symbols() {
+        let (name, nlist) = match symbol {
+            Ok(symbol) => symbol,
+            Err(_) => return,
+        };
+        // faerie adds the underscore prefix itself
+        let name = name.trim_start_matches('_');
+        if name.is_empty() {
+            continue;
+        }
+        if nlist.is_undefined() {
+            let _ = obj.import(name, ImportKind::Function);
+        } else if nlist.n_sect > 0 && nlist.n_sect <= sections.len() {
+            symbols.push((name, nlist.n_sect - 1, nlist.n_value, nlist.is_global()));
+        }
+    }
+
+    // every defined symbol's contents extend to the next symbol in its section, or the section end
+    symbols.sort_by_key(|&(_, sect, value, _)| (sect, value));
+    for (i, &(name, sect, value, global)) in symbols.iter().enumerate() {
+        let (section, section_data) = &sections[sect];
+        let start = value.saturating_sub(section.addr) as usize;
+        let end = match symbols.get(i + 1) {
+            Some(&(_, next_sect, next_value, _)) if next_sect == sect => {
+                next_value.saturating_sub(section.addr) as usize
+            }
+            _ => section_data.len(),
+        };
+        let contents = match section_data.get(start..end) {
+            Some(contents) => contents.to_vec(),
+            None => continue,
+        };
+        let is_code = section.name().map_or(false, |name| name == "__text");
+        let decl: Decl = match (is_code, global) {
+            (true, true) => Decl::function().global().into(),
+            (true, false) => Decl::function().into(),
+            (false, true) => Decl::data().global().into(),
+            (false, false) => Decl::data().into(),
+        };
+        let _: Result<(), _> = obj.declare_with(name, decl, contents);
+    }
+
+    let _ = obj.emit();
+});
@@ -0,0 +1,78 @@
+//! Builds an artifact from structured input, and emits it as both ELF and Mach-o; building and
+//! emitting may fail, but must never panic.
+#![no_main]
+use faerie::{Artifact, Decl, ImportKind, Link};
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use std::str::FromStr;
+use target_lexicon::{BinaryFormat, Triple};
+
+#[derive(Arbitrary, Debug)]
+enum Kind {
+    Function,
+    Data { writable: bool },
+    CString,
+    ZeroInit(u16),
+    FunctionImport,
+    DataImport,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Symbol {
+    kind: Kind,
+    global: bool,
+    contents: Vec<u8>,
+}
+
+#[derive(Arbitrary, Debug)]
+struct FuzzLink {
+    from: u8,
+    to: u8,
+    at: u16,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    aarch64: bool,
+    symbols: Vec<Symbol>,
+    links: Vec<FuzzLink>,
+}
+
+fuzz_target!(|input: Input| {
+    let target = if input.aarch64 { "aarch64" } else { "x86_64" };
+    let mut obj = Artifact::new(Triple::from_str(target).unwrap(), "structured.o".into());
+    let names = (0..input.symbols.len())
+        .map(|i| format!("sym{}", i))
+        .collect::<Vec<_>>();
+    for (name, symbol) in names.iter().zip(input.symbols) {
+        let _ = match symbol.kind {
+            Kind::Function if symbol.global => {
+                obj.declare_with(name, Decl::function().global(), symbol.contents)
+            }
+            Kind::Function => obj.declare_with(name, Decl::function(), symbol.contents),
+            Kind::Data { writable } => obj.declare_with(
+                name,
+                Decl::data().with_writable(writable).global(),
+                symbol.contents,
+            ),
+            Kind::CString => obj.declare_with(name, Decl::cstring(), symbol.contents),
+            Kind::ZeroInit(size) => obj
+                .declare(name, Decl::data().writable())
+                .and_then(|_| obj.define_zero_init(name, size as usize)),
+            Kind::FunctionImport => obj.import(name, ImportKind::Function),
+            Kind::DataImport => obj.import(name, ImportKind::Data),
+        };
+    }
+    if !names.is_empty() {
+        for link in input.links {
+            let _ = obj.link(Link {
+                from: &names[link.from as usize % names.len()],
+                to: &names[link.to as usize % names.len()],
+                at: u64::from(link.at),
+            });
+        }
+    }
+
+    let _ = obj.emit_as(BinaryFormat::Elf);
+    let _ = obj.emit_as(BinaryFormat::Macho);
+});
@@ -0,0 +1,270 @@
+//! Static libraries, i.e., `ar` archives of object files, with an index of the symbols the objects
+//! define, so that linkers only pull in the members they need.
+
+use crate::artifact::{Artifact, ArtifactError, DefinedDecl};
+use crate::coff;
+use std::io::Write;
+use target_lexicon::BinaryFormat;
+
+/// The magic every archive starts with
+const MAGIC: &[u8] = b"!<arch>\n";
+/// The size of the header preceding every member
+const SIZEOF_HEADER: usize = 60;
+/// The name of the symbol index of BSD archives, whose entries are sorted by symbol name
+const BSD_SYMDEF: &str = "__.SYMDEF SORTED";
+/// The alignment of the members of BSD archives, which ld64 expects
+const BSD_ALIGN: usize = 8;
+/// The alignment of the members of GNU archives
+const GNU_ALIGN: usize = 2;
+
+/// A static library of several artifacts.
+///
+/// Archives of Mach-o objects are written in the BSD variant of the `ar` format, with a
+/// `__.SYMDEF SORTED` symbol index; all others in the GNU variant, with a `/` symbol index.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Archive {
+    /// The artifacts in this archive, in the order they are written
+    pub members: Vec<Artifact>,
+}
+
+/// An emitted member of an archive
+struct Member {
+    name: String,
+    data: Vec<u8>,
+    /// The symbols this member defines for other objects, as they are named in its symbol table
+    symbols: Vec<String>,
+}
+
+impl Archive {
+    /// Create a new, empty archive
+    pub fn new() -> Self {
+        Archive::default()
+    }
+    /// Add `artifact` to the end of this archive; its name is the name of the member
+    pub fn add(&mut self, artifact: Artifact) {
+        self.members.push(artifact);
+    }
+    /// Emit every member in the format specified in the target it was constructed with, and the
+    /// archive containing them
+    pub fn emit(&self) -> Result<Vec<u8>, ArtifactError> {
+        let members = self
+            .members
+            .iter()
+            .map(|artifact| {
+                Ok(Member {
+                    name: artifact.name.clone(),
+                    data: artifact.emit()?,
+                    symbols: index_symbols(artifact),
+                })
+            })
+            .collect::<Result<Vec<_>, ArtifactError>>()?;
+        let is_bsd = self
+            .members
+            .first()
+            .map(|artifact| artifact.target.binary_format)
+            == Some(BinaryFormat::Macho);
+        Ok(if is_bsd {
+            bsd_archive(&members)
+        } else {
+            gnu_archive(&members)
+        })
+    }
+    /// Emit the archive, and write it to `sink`
+    pub fn write<W: Write>(&self, mut sink: W) -> Result<(), ArtifactError> {
+        let bytes = self.emit()?;
+        sink.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+/// The symbols `artifact` defines for other objects, i.e., its global and weak definitions
+fn index_symbols(artifact: &Artifact) -> Vec<String> {
+    let is_mach = artifact.target.binary_format == BinaryFormat::Macho;
+    let mut symbols = Vec::new();
+    for def in artifact.definitions() {
+        match def.decl {
+            DefinedDecl::Function(_) | DefinedDecl::Data(_)
+                if def.decl.is_global() || def.decl.is_weak() =>
+            {
+                symbols.push(def.name.to_string())
+            }
+            // the custom symbols of sections are only external in Mach-o
+            DefinedDecl::Section(_) if is_mach => symbols.extend(def.symbols.keys().cloned()),
+            _ => (),
+        }
+    }
+    if is_mach {
+        // mach-o prefixes every symbol with an _
+        for symbol in symbols.iter_mut() {
+            symbol.insert(0, '_');
+        }
+    } else if artifact.target.binary_format == BinaryFormat::Coff {
+        for symbol in symbols.iter_mut() {
+            *symbol = coff::mangle(artifact.target.architecture, symbol);
+        }
+    } else {
+        // indirect functions are only supported in ELF
+        symbols.extend(artifact.ifuncs().map(|(name, _)| name.to_string()));
+    }
+    symbols
+}
+
+/// The number of bytes needed to align `size` to `align`
+fn padding(size: usize, align: usize) -> usize {
+    (align - size % align) % align
+}
+
+/// Write a member header; archives are deterministic, so every timestamp and id is 0
+fn write_header(out: &mut Vec<u8>, name: &str, mode: &str, size: usize) {
+    writeln!(
+        out,
+        "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`",
+        name, 0, 0, 0, mode, size
+    )
+    .expect("can write to vec");
+}
+
+/// Write a GNU archive, whose symbol index contains the big endian offsets of the headers of the
+/// members defining each symbol, followed by the null terminated symbol names
+fn gnu_archive(members: &[Member]) -> Vec<u8> {
+    // names which don't fit into the header go into the `//` member, and are referred to by their
+    // offset in it
+    let mut long_names = Vec::new();
+    let names = members
+        .iter()
+        .map(|member| {
+            if member.name.len() < 16 && !member.name.contains('/') {
+                format!("{}/", member.name)
+            } else {
+                let name = format!("/{}", long_names.len());
+                long_names.extend_from_slice(member.name.as_bytes());
+                long_names.extend_from_slice(b"/\n");
+                name
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let nsymbols = members.iter().map(|m| m.symbols.len()).sum::<usize>();
+    let sizeof_names = members
+        .iter()
+        .flat_map(|m| m.symbols.iter())
+        .map(|symbol| symbol.len() + 1)
+        .sum::<usize>();
+    let sizeof_index = 4 + 4 * nsymbols + sizeof_names;
+    let mut offset = MAGIC.len() + SIZEOF_HEADER + sizeof_index + padding(sizeof_index, GNU_ALIGN);
+    if !long_names.is_empty() {
+        offset += SIZEOF_HEADER + long_names.len() + padding(long_names.len(), GNU_ALIGN);
+    }
+    let mut index = Vec::with_capacity(sizeof_index);
+    let mut symbol_names = Vec::with_capacity(sizeof_names);
+    index.extend_from_slice(&(nsymbols as u32).to_be_bytes());
+    for member in members {
+        for symbol in &member.symbols {
+            index.extend_from_slice(&(offset as u32).to_be_bytes());
+            symbol_names.extend_from_slice(symbol.as_bytes());
+            symbol_names.push(0);
+        }
+        offset += SIZEOF_HEADER + member.data.len() + padding(member.data.len(), GNU_ALIGN);
+    }
+    index.extend_from_slice(&symbol_names);
+
+    let mut out = MAGIC.to_vec();
+    let mut write_member = |name: &str, mode: &str, data: &[u8]| {
+        write_header(&mut out, name, mode, data.len());
+        out.extend_from_slice(data);
+        out.resize(out.len() + padding(data.len(), GNU_ALIGN), b'\n');
+    };
+    write_member("/", "0", &index);
+    if !long_names.is_empty() {
+        write_member("//", "0", &long_names);
+    }
+    for (member, name) in members.iter().zip(names.iter()) {
+        write_member(name, "644", &member.data);
+    }
+    out
+}
+
+/// The name of a BSD archive member, which always follows its header, padded with nulls such that
+/// the data after it is aligned
+fn bsd_name(name: &str) -> Vec<u8> {
+    let mut padded = name.as_bytes().to_vec();
+    padded.resize(
+        name.len() + padding(SIZEOF_HEADER + name.len(), BSD_ALIGN),
+        0,
+    );
+    padded
+}
+
+/// Write a BSD archive, whose symbol index contains the offsets of the symbol names and of the
+/// headers of the members defining them, sorted by name, followed by the symbol names
+fn bsd_archive(members: &[Member]) -> Vec<u8> {
+    let names = members
+        .iter()
+        .map(|member| bsd_name(&member.name))
+        .collect::<Vec<_>>();
+    let index_name = bsd_name(BSD_SYMDEF);
+
+    let mut symbols = members
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, member)| member.symbols.iter().map(move |symbol| (symbol, idx)))
+        .collect::<Vec<_>>();
+    symbols.sort_by_key(|&(symbol, _)| symbol);
+    let sizeof_ranlibs = 8 * symbols.len();
+    let mut symbol_names = Vec::new();
+    let mut ranlibs = Vec::with_capacity(sizeof_ranlibs);
+    let mut symbol_name_offsets = Vec::with_capacity(symbols.len());
+    for (symbol, _) in symbols.iter() {
+        symbol_name_offsets.push(symbol_names.len());
+        symbol_names.extend_from_slice(symbol.as_bytes());
+        symbol_names.push(0);
+    }
+    // the symbol names are padded such that the member after the index is aligned
+    let sizeof_index = 4 + sizeof_ranlibs + 4 + symbol_names.len();
+    symbol_names.resize(symbol_names.len() + padding(sizeof_index, BSD_ALIGN), 0);
+
+    let mut offset = MAGIC.len() + SIZEOF_HEADER + index_name.len() + 4 + sizeof_ranlibs + 4;
+    offset += symbol_names.len();
+    let mut member_offsets = Vec::with_capacity(members.len());
+    for (member, name) in members.iter().zip(names.iter()) {
+        member_offsets.push(offset);
+        offset += SIZEOF_HEADER + name.len() + member.data.len();
+        offset += padding(member.data.len(), BSD_ALIGN);
+    }
+    for ((_, idx), name_offset) in symbols.iter().zip(symbol_name_offsets.iter()) {
+        ranlibs.extend_from_slice(&(*name_offset as u32).to_le_bytes());
+        ranlibs.extend_from_slice(&(member_offsets[*idx] as u32).to_le_bytes());
+    }
+
+    let mut out = MAGIC.to_vec();
+    let mut write_member = |name: &[u8], mode: &str, data: &[&[u8]]| {
+        let size = data.iter().map(|data| data.len()).sum::<usize>();
+        let padding = padding(size, BSD_ALIGN);
+        write_header(
+            &mut out,
+            &format!("#1/{}", name.len()),
+            mode,
+            name.len() + size + padding,
+        );
+        out.extend_from_slice(name);
+        for data in data {
+            out.extend_from_slice(data);
+        }
+        out.resize(out.len() + padding, b'\n');
+    };
+    write_member(
+        &index_name,
+        "0",
+        &[
+            &(sizeof_ranlibs as u32).to_le_bytes(),
+            &ranlibs,
+            &(symbol_names.len() as u32).to_le_bytes(),
+            &symbol_names,
+        ],
+    );
+    for (member, name) in members.iter().zip(names.iter()) {
+        write_member(name, "644", &[&member.data]);
+    }
+    out
+}
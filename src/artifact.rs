@@ -5,20 +5,27 @@ use string_interner::StringInterner;
 use target_lexicon::{BinaryFormat, Triple};
 use thiserror::Error;
 
-use std::collections::{BTreeMap, BTreeSet};
-use std::fs::File;
-use std::io::Write;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Cursor, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::{elf, mach};
+use crate::mach::{BuildVersion, MachPlatform};
+use crate::{coff, elf, mach, wasm};
 
 pub(crate) mod decl;
 pub use crate::artifact::decl::{
-    DataType, Decl, DefinedDecl, ImportKind, Scope, SectionKind, Visibility,
+    AsmSyntax, DataType, Decl, DefinedDecl, ImportKind, Scope, SectionKind, TlsModel, Visibility,
 };
 
 // we need Ord so that `InternalDefinition` can go in a BTreeSet
 /// The data to be stored in an artifact, representing a function body or data object.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Data {
     /// A blob of binary bytes, representing a function body, or data object
     Blob(Vec<u8>),
@@ -28,9 +35,17 @@ pub enum Data {
 
 /// The kind of relocation for a link.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Reloc {
     /// Automatic relocation determined by the `from` and `to` of the link.
     Auto,
+    /// Automatic relocation like `Auto`, which refers to the address of `to` plus `addend`, e.g.,
+    /// to a field of a data object. ELF stores the addend in the relocation itself, whereas Mach-o
+    /// and COFF write it into the relocated bytes.
+    AutoWithAddend {
+        /// Added to the address of the relocation's target
+        addend: i64,
+    },
     /// A raw relocation and its addend, to optionally override the "auto" relocation behavior of faerie.
     /// **NB**: This is implementation defined, and can break code invariants if used improperly, you have been warned.
     Raw {
@@ -39,6 +54,41 @@ pub enum Reloc {
         /// Raw addend, significance depends on the raw relocation used
         addend: i32,
     },
+    /// An ARM64e signed pointer, i.e., `ARM64_RELOC_AUTHENTICATED_POINTER`, which the dynamic linker
+    /// signs with `key` and the given diversity when binding it. Currently only supported by the Mach-o backend.
+    Authenticated {
+        /// The key the pointer is signed with
+        key: PtrauthKey,
+        /// Whether the address of the pointer is blended into the discriminator
+        addr_div: bool,
+        /// The constant discriminator
+        discriminator: u16,
+        /// Addend for the relocation
+        addend: i32,
+    },
+    /// An absolute, pointer sized reference, e.g., a function pointer in a vtable
+    Absolute {
+        /// Addend for the relocation
+        addend: i32,
+    },
+    /// The subtrahend of a difference of two symbols, whose address is subtracted from the
+    /// relocated value; it must be immediately followed by the difference's `Minuend`, at the same
+    /// location. Use [link_difference](struct.Artifact.html#method.link_difference) to create both.
+    /// Currently only supported by the Mach-o backend.
+    Subtrahend {
+        /// Size (in bytes) of the relocated difference
+        size: u8,
+    },
+    /// The minuend of a difference of two symbols, whose address is added to the relocated value;
+    /// it must immediately follow the difference's `Subtrahend`.
+    Minuend {
+        /// Size (in bytes) of the relocated difference
+        size: u8,
+    },
+    /// A relocation of a backend agnostic `RelocationKind`, rather than the one faerie would deduce
+    /// from the `from` and `to` of the link. Use
+    /// [link_with_reloc](struct.Artifact.html#method.link_with_reloc) to create it.
+    Kind(RelocationKind),
     /// A relocation in a debug section.
     Debug {
         /// Size (in bytes) of the pointer to be relocated
@@ -48,9 +98,335 @@ pub enum Reloc {
     },
 }
 
+/// A kind of relocation which every backend maps to its native relocation type, to override the
+/// relocation faerie would otherwise deduce for a link
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RelocationKind {
+    /// An absolute, pointer sized address, e.g., `X86_64_RELOC_UNSIGNED` or `R_X86_64_64`
+    Absolute,
+    /// A 32 bit signed displacement from the end of the relocated bytes, e.g., of a `leaq`, i.e.,
+    /// `X86_64_RELOC_SIGNED` or `R_X86_64_PC32`
+    PcRelative,
+    /// The displacement of a call or jump, e.g., `X86_64_RELOC_BRANCH` or `R_X86_64_PLT32`
+    Branch,
+    /// A 32 bit signed displacement to the target's Global Offset Table entry, e.g.,
+    /// `X86_64_RELOC_GOT_LOAD` or `R_X86_64_GOTPCREL`
+    GotPcRelative,
+}
+
+/// The ARM64e pointer authentication keys
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PtrauthKey {
+    /// Instruction key A
+    IA = 0,
+    /// Instruction key B
+    IB = 1,
+    /// Data key A
+    DA = 2,
+    /// Data key B
+    DB = 3,
+}
+
+/// A range of data embedded in the code section, such as an ARM Thumb jump table, recorded so that
+/// disassemblers can skip over it. Currently only emitted by the Mach-o backend, as `LC_DATA_IN_CODE`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DataInCodeEntry {
+    /// Byte offset of the start of the data, relative to the start of the code section
+    pub offset: u32,
+    /// Length of the data, in bytes
+    pub length: u16,
+    /// The kind of data, one of the Mach-o `DICE_KIND_*` values, e.g. `DICE_KIND_JUMP_TABLE8`
+    pub kind: u16,
+}
+
+/// The object file format to emit an artifact as, independent of the format its target would
+/// natively use.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ObjectFormat {
+    /// Mach-o, as used by Darwin targets
+    MachO,
+    /// ELF, as used by most unix-like targets
+    Elf,
+    /// COFF, as used by Windows targets
+    Coff,
+    /// WebAssembly, as used by wasm32 targets
+    Wasm,
+}
+
+impl ObjectFormat {
+    /// The platform-native object format for `target`, if any.
+    pub fn native(target: &Triple) -> Option<Self> {
+        match target.binary_format {
+            BinaryFormat::Macho => Some(ObjectFormat::MachO),
+            BinaryFormat::Elf => Some(ObjectFormat::Elf),
+            BinaryFormat::Coff => Some(ObjectFormat::Coff),
+            BinaryFormat::Wasm => Some(ObjectFormat::Wasm),
+            _ => None,
+        }
+    }
+}
+
+impl From<ObjectFormat> for BinaryFormat {
+    fn from(format: ObjectFormat) -> Self {
+        match format {
+            ObjectFormat::MachO => BinaryFormat::Macho,
+            ObjectFormat::Elf => BinaryFormat::Elf,
+            ObjectFormat::Coff => BinaryFormat::Coff,
+            ObjectFormat::Wasm => BinaryFormat::Wasm,
+        }
+    }
+}
+
+/// Constraints on what an artifact may contain, e.g., to check invariants about its shape in
+/// compiler tests; see [verify_against_schema](struct.Artifact.html#method.verify_against_schema)
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArtifactSchema {
+    no_imports: bool,
+    no_data: bool,
+}
+
+impl ArtifactSchema {
+    /// A schema which any artifact satisfies
+    pub fn any() -> Self {
+        ArtifactSchema::default()
+    }
+    /// A schema which forbids function and data imports
+    pub fn no_imports() -> Self {
+        ArtifactSchema {
+            no_imports: true,
+            ..ArtifactSchema::default()
+        }
+    }
+    /// A schema which forbids data declarations
+    pub fn functions_only() -> Self {
+        ArtifactSchema {
+            no_data: true,
+            ..ArtifactSchema::default()
+        }
+    }
+    /// A schema with the constraints of both `self` and `other`
+    pub fn and(self, other: ArtifactSchema) -> Self {
+        ArtifactSchema {
+            no_imports: self.no_imports || other.no_imports,
+            no_data: self.no_data || other.no_data,
+        }
+    }
+}
+
+/// The ways a declaration can violate an [ArtifactSchema](struct.ArtifactSchema.html)
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SchemaViolationKind {
+    /// An import, forbidden by `ArtifactSchema::no_imports`
+    Import(ImportKind),
+    /// A data declaration, forbidden by `ArtifactSchema::functions_only`
+    Data,
+}
+
+/// A declaration which violates an [ArtifactSchema](struct.ArtifactSchema.html)
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SchemaViolation {
+    /// The name of the offending declaration
+    pub name: String,
+    /// How it violates the schema
+    pub kind: SchemaViolationKind,
+}
+
+/// A set of changes to the definitions of an artifact, to update it incrementally with
+/// [apply_patch](struct.Artifact.html#method.apply_patch) instead of rebuilding it from scratch
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArtifactPatch {
+    /// Definitions of symbols which were declared, but not defined yet
+    pub added_definitions: Vec<(String, Vec<u8>)>,
+    /// Definitions to remove, along with their declarations and links, see
+    /// [remove_definition](struct.Artifact.html#method.remove_definition)
+    pub removed_definitions: Vec<String>,
+    /// New contents for existing definitions, which keep their declarations and links
+    pub changed_definitions: Vec<(String, Vec<u8>)>,
+}
+
+/// The definitions removed by [gc_sections](struct.Artifact.html#method.gc_sections)
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArtifactGcReport {
+    /// The names of the removed definitions
+    pub removed_symbols: Vec<String>,
+    /// The number of bytes of data the removed definitions would have used in the object file
+    pub bytes_saved: usize,
+}
+
+/// A problem [verify_with_backend](struct.Artifact.html#method.verify_with_backend) found in the
+/// object file a backend would emit for an artifact
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VerificationError {
+    /// A link refers to a symbol which is neither defined nor imported in the symbol table
+    MissingSymbol {
+        /// The name of the missing symbol
+        name: String,
+    },
+    /// A relocation extends past the end of the section it is in
+    RelocationOutOfBounds {
+        /// The name of the section
+        section: String,
+        /// The section relative offset of the relocation
+        offset: u64,
+        /// The size of the relocated value, in bytes
+        size: u64,
+        /// The size of the section, in bytes
+        section_size: u64,
+    },
+    /// The sizes of the sections, including the padding which aligns them, do not add up to the
+    /// size of their segment
+    SegmentSizeMismatch {
+        /// The sum of the sizes of the sections
+        sections: u64,
+        /// The size of the segment
+        segment: u64,
+    },
+    /// The string table would be written with a different size than the one it is recorded as
+    StrtableSizeMismatch {
+        /// The recorded size
+        expected: u64,
+        /// The number of bytes which would be written
+        actual: u64,
+    },
+}
+
+/// A problem found by parsing an object file back after emitting it, which is done for Mach-o
+/// objects emitted in memory when the `validate` feature is enabled
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum ValidationError {
+    /// `goblin` could not parse the object file
+    #[error("cannot parse the emitted object: {0}")]
+    Parse(String),
+    /// The name of a symbol starts past the end of the string table
+    #[error("symbol {index} has string table offset {n_strx}, but the string table is only {strsize} bytes")]
+    StringOutOfBounds {
+        /// The index of the symbol in the symbol table
+        index: usize,
+        /// The string table offset of the symbol's name
+        n_strx: usize,
+        /// The size of the string table
+        strsize: usize,
+    },
+}
+
+/// The backends which object files are emitted by; every supported binary format is routed to one
+/// of these, so that new targets don't need any changes at the callers.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Backend {
+    Mach,
+    Elf,
+    Coff,
+    Wasm,
+}
+
+impl Backend {
+    /// The backend which emits `format`
+    fn for_format(format: BinaryFormat) -> Result<Self, ArtifactError> {
+        match format {
+            BinaryFormat::Macho => Ok(Backend::Mach),
+            BinaryFormat::Elf => Ok(Backend::Elf),
+            BinaryFormat::Coff => Ok(Backend::Coff),
+            BinaryFormat::Wasm => Ok(Backend::Wasm),
+            _ => Err(ArtifactError::UnsupportedBinaryFormat(format)),
+        }
+    }
+    /// Emit `artifact` as an object file with this backend
+    fn to_bytes(self, artifact: &Artifact) -> Result<Vec<u8>, ArtifactError> {
+        match self {
+            Backend::Mach => Ok(mach::to_bytes(artifact)?),
+            Backend::Elf => elf::to_bytes(artifact),
+            Backend::Coff => coff::to_bytes(artifact),
+            Backend::Wasm => wasm::to_bytes(artifact),
+        }
+    }
+    /// Write `artifact` as an object file with this backend to `sink`; COFF and wasm objects are
+    /// built in memory first, since their headers depend on the size of everything following them
+    fn write<W: Write + Seek>(self, artifact: &Artifact, mut sink: W) -> Result<(), ArtifactError> {
+        match self {
+            Backend::Mach => mach::write(artifact, sink)?,
+            Backend::Elf => elf::write(artifact, sink)?,
+            Backend::Coff => sink.write_all(&coff::to_bytes(artifact)?)?,
+            Backend::Wasm => sink.write_all(&wasm::to_bytes(artifact)?)?,
+        }
+        Ok(())
+    }
+}
+
+/// The backend for the binary format of `artifact`'s target
+fn select_backend(artifact: &Artifact) -> Result<Backend, ArtifactError> {
+    Backend::for_format(artifact.target.binary_format)
+}
+
+/// Check that `data`, and the custom `symbols`, are a valid definition of `name`, which is declared
+/// as `decl`, and, if it was declared with one, has at least `size` bytes
+fn check_definition(
+    name: &str,
+    decl: DefinedDecl,
+    data: &Data,
+    symbols: &BTreeMap<String, u64>,
+    size: Option<usize>,
+) -> Result<(), ArtifactError> {
+    match decl {
+        DefinedDecl::Section(_) => {}
+        _ => {
+            if !symbols.is_empty() {
+                return Err(ArtifactError::NonSectionCustomSymbols(
+                    decl,
+                    symbols.clone(),
+                ));
+            }
+        }
+    }
+    match decl {
+        DefinedDecl::Data(_) => {}
+        _ => {
+            if let Data::ZeroInit(_) = data {
+                return Err(ArtifactError::InvalidZeroInit(decl));
+            }
+        }
+    }
+    if let Some(size) = size {
+        if size > data.memory_size() {
+            return Err(ArtifactError::InvalidSize {
+                name: name.to_string(),
+                size,
+                defined: data.memory_size(),
+            });
+        }
+    }
+    Ok(())
+}
+
 type StringID = usize;
 type Relocation = (StringID, StringID, u64, Reloc);
 
+/// `Triple` has no serde support of its own, so it is (de)serialized as its string form
+#[cfg(feature = "serde")]
+mod triple_serde {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+    use target_lexicon::Triple;
+
+    pub fn serialize<S: Serializer>(triple: &Triple, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(triple)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Triple, D::Error> {
+        let triple = String::deserialize(deserializer)?;
+        Triple::from_str(&triple).map_err(D::Error::custom)
+    }
+}
+
 /// The kinds of errors that can befall someone creating an Artifact
 #[derive(Error, Debug)]
 pub enum ArtifactError {
@@ -63,6 +439,15 @@ pub enum ArtifactError {
     #[error("Attempt to add a relocation to an import: {0}")]
     /// Attempt to use a relocation inside an import
     RelocateImport(String),
+    #[error("Attempt to add a relocation to an absolute symbol: {0}")]
+    /// Attempt to use a relocation inside an absolute symbol, which has no data
+    RelocateAbsolute(String),
+    #[error("Attempt to define a common symbol: {0}")]
+    /// Attempt to define a common symbol, whose allocation is left to the linker
+    CommonDefined(String),
+    #[error("Attempt to add a relocation to a common symbol: {0}")]
+    /// Attempt to use a relocation inside a common symbol, which has no data
+    RelocateCommon(String),
     // FIXME: don't use debugging prints for decl formats
     #[error("Incompatible declarations, old declaration {old:?} is incompatible with new {new:?}")]
     /// An incompatble declaration occurred, please see the [absorb](enum.Decl.html#method.absorb) method on `Decl`
@@ -91,6 +476,65 @@ pub enum ArtifactError {
     #[error("Undefined symbols: {0:?}")]
     UndefinedSymbols(Vec<String>),
 
+    /// Definitions could not be sorted topologically, because these link to each other in a cycle
+    #[error("Cyclic dependency between definitions: {0:?}")]
+    CyclicDependency(Vec<String>),
+
+    /// Attempt to change an artifact after it was sealed
+    #[error("Attempt to modify a sealed artifact")]
+    Sealed,
+
+    /// Artifacts which cannot be merged, e.g., because they have different targets
+    #[error("Cannot merge artifacts: {0}")]
+    IncompatibleMerge(String),
+
+    /// A second slice for an architecture which a fat binary already has a slice for
+    #[error("Duplicate architecture in fat binary: {0}")]
+    DuplicateArchitecture(String),
+
+    /// A section which is not in the object file emitted for the artifact
+    #[error("No section named {0} in the emitted object")]
+    NoSuchSection(String),
+
+    /// Attempt to rename a symbol to a name which is already declared
+    #[error("Symbol name is already taken: {0}")]
+    NameTaken(String),
+
+    /// A declared size which is larger than the symbol's definition
+    #[error("Size {size} of {name} is larger than its definition of {defined} bytes")]
+    InvalidSize {
+        /// The name of the symbol
+        name: String,
+        /// The declared size
+        size: usize,
+        /// The size of the definition
+        defined: usize,
+    },
+
+    /// An address space which the target cannot encode
+    #[error("Invalid address space: {0}")]
+    InvalidAddressSpace(String),
+
+    /// An entry point which is not a function
+    #[error("Entry point is not a function: {0}")]
+    InvalidEntryPoint(String),
+
+    /// A feature which the target's object format does not support
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+
+    /// A relocation which the target's object format cannot express
+    #[error("Unsupported relocation: {0}")]
+    UnsupportedRelocation(String),
+
+    /// A declared alignment which the object format cannot encode
+    #[error("Invalid alignment: {0}")]
+    InvalidAlignment(#[from] AlignError),
+
+    /// The emitted object file is malformed, see `ValidationError`
+    #[error("Invalid object file: {0}")]
+    Validation(#[from] ValidationError),
+
     /// Output of ELF format encountered error from `goblin` crate
     #[error("Goblin error: {0}")]
     Goblin(#[from] goblin::error::Error),
@@ -100,7 +544,14 @@ pub enum ArtifactError {
     Io(#[from] std::io::Error),
 }
 
+/// An alignment which is not a power of two, which Mach-o cannot encode, since it stores
+/// alignments as their exponent
+#[derive(Error, Debug, PartialEq, Eq, Clone, Copy)]
+#[error("alignment of {0} bytes is not a power of two")]
+pub struct AlignError(pub u64);
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct InternalDefinition {
     decl: DefinedDecl,
     name: StringID,
@@ -110,6 +561,7 @@ struct InternalDefinition {
 
 /// A declaration, plus a flag to track whether we have a definition for it yet
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct InternalDecl {
     decl: Decl,
     defined: bool,
@@ -132,6 +584,13 @@ impl Data {
             Data::ZeroInit(_) => 0,
         }
     }
+    /// Return the number of bytes of _memory_ this data will use.
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Data::Blob(blob) => blob.len(),
+            Data::ZeroInit(size) => *size,
+        }
+    }
     /// Return whether the data has at least one byte defined
     pub fn is_empty(&self) -> bool {
         match self {
@@ -195,6 +654,8 @@ pub(crate) struct Definition<'a> {
     pub symbols: &'a BTreeMap<String, u64>,
     /// Declaration of symbol
     pub decl: &'a DefinedDecl,
+    /// Size of symbol, if it was declared with one
+    pub size_hint: Option<usize>,
 }
 
 impl<'a> From<(&'a InternalDefinition, &'a StringInterner<StringID>)> for Definition<'a> {
@@ -206,10 +667,64 @@ impl<'a> From<(&'a InternalDefinition, &'a StringInterner<StringID>)> for Defini
             data: &def.data,
             symbols: &def.symbols,
             decl: &def.decl,
+            size_hint: None,
         }
     }
 }
 
+/// A section of an emitted object file, as returned by `Artifact::sections`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SectionInfo {
+    /// Name of the section in the object file
+    pub name: String,
+    /// Name of the segment containing the section; empty for ELF, whose relocatable objects
+    /// have no segments
+    pub segment: String,
+    /// Offset of the section's contents in the file; zero filled sections take up no space in
+    /// the file, so their offset is meaningless
+    pub file_offset: usize,
+    /// Size of the section, in bytes
+    pub size: usize,
+    /// Alignment of the section, in bytes
+    pub align: usize,
+}
+
+/// A section of the output, and the symbols placed in it, as listed in a map file
+#[derive(Debug)]
+pub(crate) struct MapSection {
+    /// Name of the section in the object file
+    pub name: String,
+    /// Address of the section
+    pub addr: u64,
+    /// Size of the section, in bytes
+    pub size: u64,
+    /// The symbols in this section, as `(name, address, size)`
+    pub symbols: Vec<(String, u64, u64)>,
+}
+
+/// A section emitted verbatim, see [add_raw_section](struct.Artifact.html#method.add_raw_section)
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct RawSection {
+    /// Name of the section
+    pub name: String,
+    /// The section's contents
+    pub data: Vec<u8>,
+    /// The backend specific section type and attributes
+    pub flags: u64,
+}
+
+/// The unwinding information of every function, as the contents of `.eh_frame`, see
+/// [add_frame_info](struct.Artifact.html#method.add_frame_info)
+#[derive(Debug)]
+pub(crate) struct EhFrame<'a> {
+    /// The CIEs and FDEs of every function in turn, with the initial locations of the FDEs zeroed
+    pub data: Vec<u8>,
+    /// The offset of the initial location of every FDE, and the function it is the address of
+    pub initial_locations: Vec<(u64, &'a str)>,
+}
+
 /// An abstract relocation linking one symbol to another, at an offset
 pub struct Link<'a> {
     /// The relocation is relative `from` this symbol
@@ -256,20 +771,62 @@ impl ArtifactBuilder {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// An abstract binary artifact, which contains code, data, imports, and relocations
+///
+/// With the `serde` feature, artifacts (and all the public types they are built from) can be
+/// serialized and deserialized, e.g., to checkpoint them between compilation stages. A
+/// deserialized artifact emits the same bytes as the original. The serialized form refers to
+/// symbols by their internal ids, so it is only meant to be deserialized by the same version of
+/// faerie which serialized it.
 pub struct Artifact {
     /// The name of this artifact
     pub name: String,
     /// The machine target this is intended for
+    #[cfg_attr(feature = "serde", serde(with = "triple_serde"))]
     pub target: Triple,
     /// Whether this is a static library or not
     pub is_library: bool,
     // will keep this for now; may be useful to pre-partition code and data vectors, not sure
-    imports: Vec<(StringID, ImportKind)>,
+    // the kind of each import is that of its declaration
+    imports: Vec<StringID>,
     links: Vec<Relocation>,
     declarations: IndexMap<StringID, InternalDecl>,
     local_definitions: BTreeSet<InternalDefinition>,
     nonlocal_definitions: BTreeSet<InternalDefinition>,
+    data_in_code: Vec<DataInCodeEntry>,
+    raw_sections: Vec<RawSection>,
+    // flags which are added to those the backend gives a section, by section name
+    section_flags: IndexMap<String, u64>,
+    // definitions which were merged into an identical, canonical definition
+    aliases: HashMap<StringID, StringID>,
+    // indirect functions, and their resolvers
+    ifuncs: IndexMap<StringID, StringID>,
+    // sizes of declarations which were known before their definition
+    sizes: HashMap<StringID, usize>,
+    // type names and their hashes, for LLVM's one definition rule checks
+    odr_entries: Vec<(String, u64)>,
+    // the file the debug info was stripped into, and its CRC32
+    debuglink: Option<(String, u32)>,
+    // an identifier of this build, e.g., a hash of its inputs
+    build_id: Option<Vec<u8>>,
+    // null terminated strings identifying the tools this artifact was built with
+    comment: Vec<u8>,
+    // the Mach-o platform, and the minimum OS and SDK versions
+    platform: Option<BuildVersion>,
+    // the object file this artifact is written to, for debuggers looking for its debug info
+    source_path: Option<(PathBuf, u64)>,
+    // functions, and the CIEs and FDEs describing how to unwind them
+    frame_info: Vec<(StringID, Vec<u8>)>,
+    // whether the linker may split sections into subsections at symbol boundaries
+    subsections_via_symbols: bool,
+    // whether the code is position independent, e.g., to be linked into a shared object
+    pic: bool,
+    // the symbol at which an executable starts
+    entry_point: Option<StringID>,
+    sealed: bool,
+    // the position of each definition, if they have been explicitly reordered
+    definition_order: HashMap<StringID, usize>,
     strings: StringInterner<StringID>,
 }
 
@@ -286,6 +843,24 @@ impl Artifact {
             declarations: IndexMap::new(),
             local_definitions: BTreeSet::new(),
             nonlocal_definitions: BTreeSet::new(),
+            data_in_code: Vec::new(),
+            raw_sections: Vec::new(),
+            section_flags: IndexMap::new(),
+            aliases: HashMap::new(),
+            ifuncs: IndexMap::new(),
+            sizes: HashMap::new(),
+            odr_entries: Vec::new(),
+            debuglink: None,
+            build_id: None,
+            comment: Vec::new(),
+            platform: None,
+            source_path: None,
+            frame_info: Vec::new(),
+            subsections_via_symbols: true,
+            pic: false,
+            entry_point: None,
+            sealed: false,
+            definition_order: HashMap::new(),
             strings: StringInterner::new(),
         }
     }
@@ -294,15 +869,101 @@ impl Artifact {
         Box::new(
             self.imports
                 .iter()
-                .map(move |&(id, ref kind)| (self.strings.resolve(id).unwrap(), kind)),
+                .map(move |id| match self.declarations[id].decl {
+                    Decl::Import(ref kind) => (self.strings.resolve(*id).unwrap(), kind),
+                    _ => unreachable!("only imports are in the imports array"),
+                }),
         )
     }
+    /// Get an iterator over this artifact's imports, in the same order as `imports`, whose kinds
+    /// can be changed, e.g., to make a function import weak after further analysis. Returns an
+    /// error if the artifact is sealed.
+    pub fn imports_mut<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = (&'a str, &'a mut ImportKind)> + 'a>, ArtifactError> {
+        self.check_unsealed()?;
+        let positions = self
+            .imports
+            .iter()
+            .map(|id| {
+                let (idx, _, _) = self
+                    .declarations
+                    .get_full(id)
+                    .expect("imports are declared");
+                idx
+            })
+            .collect::<Vec<_>>();
+        let strings = &self.strings;
+        let mut declarations = self.declarations.iter_mut().map(Some).collect::<Vec<_>>();
+        Ok(Box::new(positions.into_iter().map(move |idx| {
+            let (&id, int) = declarations[idx].take().expect("imports are unique");
+            match int.decl {
+                Decl::Import(ref mut kind) => (strings.resolve(id).unwrap(), kind),
+                _ => unreachable!("only imports are in the imports array"),
+            }
+        })))
+    }
     pub(crate) fn definitions<'a>(&'a self) -> Box<dyn Iterator<Item = Definition<'a>> + 'a> {
         Box::new(
-            self.local_definitions
+            self.internal_definitions()
+                .into_iter()
+                .map(move |int_def| Definition {
+                    size_hint: self.sizes.get(&int_def.name).cloned(),
+                    ..Definition::from((int_def, &self.strings))
+                }),
+        )
+    }
+    /// The definitions in the order the backends receive them: locals first, then nonlocals,
+    /// each following `definition_order` if the definitions have been reordered
+    fn internal_definitions(&self) -> Vec<&InternalDefinition> {
+        let mut definitions = self
+            .local_definitions
+            .iter()
+            .chain(self.nonlocal_definitions.iter())
+            .collect::<Vec<_>>();
+        if !self.definition_order.is_empty() {
+            // definitions made after reordering keep their usual order, after the others
+            definitions.sort_by_key(|def| {
+                (
+                    def.decl.is_global(),
+                    self.definition_order
+                        .get(&def.name)
+                        .cloned()
+                        .unwrap_or(usize::MAX),
+                )
+            });
+        }
+        definitions
+    }
+    /// Get this artifact's absolute symbols, and their addresses
+    pub(crate) fn absolute_symbols<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, u64)> + 'a> {
+        Box::new(
+            self.declarations
                 .iter()
-                .chain(self.nonlocal_definitions.iter())
-                .map(move |int_def| Definition::from((int_def, &self.strings))),
+                .filter_map(move |(&name, int)| match int.decl {
+                    Decl::AbsoluteSymbol(address) => Some((
+                        self.strings.resolve(name).expect("declaration has a name"),
+                        address,
+                    )),
+                    _ => None,
+                }),
+        )
+    }
+    /// Get this artifact's common symbols, and their sizes and alignments
+    pub(crate) fn common_symbols<'a>(
+        &'a self,
+    ) -> Box<dyn Iterator<Item = (&'a str, u64, u64)> + 'a> {
+        Box::new(
+            self.declarations
+                .iter()
+                .filter_map(move |(&name, int)| match int.decl {
+                    Decl::Common { size, align } => Some((
+                        self.strings.resolve(name).expect("declaration has a name"),
+                        size,
+                        align,
+                    )),
+                    _ => None,
+                }),
         )
     }
     /// Get this artifacts relocations
@@ -311,6 +972,7 @@ impl Artifact {
             self.links
                 .iter()
                 .map(move |&(ref from, ref to, ref at, ref reloc)| {
+                    let to = &self.resolve_alias(*to);
                     // FIXME: I think its safe to unwrap since the links are only ever constructed by us and we
                     // ensure it has a declaration
                     let (ref from_decl, ref to_decl) = (
@@ -346,6 +1008,41 @@ impl Artifact {
         self.define(name, definition)?;
         Ok(())
     }
+    /// Declare a new symbolic reference, with the given `decl`, whose size is already known to be
+    /// `size` bytes, e.g., from its type's layout.
+    ///
+    /// The size is used as the ELF symbol's `st_size`, instead of the size of its definition.
+    /// Mach-o symbols have no size, so it is ignored there.
+    ///
+    /// Returns an error if `size` is larger than the definition, when it is defined.
+    pub fn declare_with_size<T: AsRef<str>, D: Into<Decl>>(
+        &mut self,
+        name: T,
+        decl: D,
+        size: usize,
+    ) -> Result<(), ArtifactError> {
+        let name = name.as_ref();
+        let defined = self.strings.get(name).and_then(|id| {
+            self.local_definitions
+                .iter()
+                .chain(self.nonlocal_definitions.iter())
+                .find(|def| def.name == id)
+                .map(|def| def.data.memory_size())
+        });
+        if let Some(defined) = defined {
+            if size > defined {
+                return Err(ArtifactError::InvalidSize {
+                    name: name.to_string(),
+                    size,
+                    defined,
+                });
+            }
+        }
+        self.declare(name, decl)?;
+        let id = self.strings.get_or_intern(name);
+        self.sizes.insert(id, size);
+        Ok(())
+    }
     /// Declare a new symbolic reference, with the given `decl`.
     /// **Note**: All declarations _must_ precede their definitions.
     pub fn declare<T: AsRef<str>, D: Into<Decl>>(
@@ -353,6 +1050,7 @@ impl Artifact {
         name: T,
         decl: D,
     ) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
         let decl = decl.into();
         let decl_name = self.strings.get_or_intern(name.as_ref());
         let previous_was_import;
@@ -369,16 +1067,8 @@ impl Artifact {
             Decl::Import(_) => {
                 // we have to check because otherwise duplicate imports cause an error
                 // FIXME: ditto fixme, below, use orderset
-                let mut present = false;
-                for &(ref name, _) in self.imports.iter() {
-                    if *name == decl_name {
-                        present = true;
-                    }
-                }
-                if !present {
-                    let kind = ImportKind::from_decl(&new_idecl.decl)
-                        .expect("can convert from explicitly matched decls to importkind");
-                    self.imports.push((decl_name, kind));
+                if !self.imports.contains(&decl_name) {
+                    self.imports.push(decl_name);
                 }
                 Ok(())
             }
@@ -386,7 +1076,7 @@ impl Artifact {
             _ if previous_was_import => {
                 let mut index = None;
                 // FIXME: do binary search or make imports an indexmap
-                for (i, &(ref name, _)) in self.imports.iter().enumerate() {
+                for (i, name) in self.imports.iter().enumerate() {
                     if *name == decl_name {
                         index = Some(i);
                     }
@@ -409,6 +1099,27 @@ impl Artifact {
         }
         Ok(())
     }
+    /// [Declare](struct.Artifact.html#method.declare) every name, [Decl](enum.Decl.html) pair,
+    /// even after some of them fail, e.g., to report every conflict of a generated module at once.
+    /// Returns the name and error of every declaration which failed, in order.
+    pub fn declare_many<T: AsRef<str>, I: IntoIterator<Item = (T, Decl)>>(
+        &mut self,
+        declarations: I,
+    ) -> Result<(), Vec<(String, ArtifactError)>> {
+        let errors = declarations
+            .into_iter()
+            .filter_map(|(name, decl)| {
+                self.declare(name.as_ref(), decl)
+                    .err()
+                    .map(|e| (name.as_ref().to_string(), e))
+            })
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
     /// Defines a _previously declared_ program object with the given data.
     /// **NB**: If you attempt to define an import, this will return an error.
     /// If you attempt to define something which has not been declared, this will return an error.
@@ -420,6 +1131,22 @@ impl Artifact {
         self.define_with_symbols(name, Data::Blob(data), BTreeMap::new())
     }
 
+    /// Defines a _previously declared_ program object with the bytes `f` writes into a cursor,
+    /// exactly as if `define` was called with the cursor's contents afterwards, e.g., to build the
+    /// data with `scroll` or `byteorder` writes.
+    ///
+    /// If `f` fails, nothing is defined, and its error is returned.
+    pub fn define_with_cursor<T, F, E>(&mut self, name: T, f: F) -> Result<(), ArtifactError>
+    where
+        T: AsRef<str>,
+        F: FnOnce(&mut Cursor<Vec<u8>>) -> Result<(), E>,
+        E: Into<ArtifactError>,
+    {
+        let mut cursor = Cursor::new(Vec::new());
+        f(&mut cursor).map_err(Into::into)?;
+        self.define(name, cursor.into_inner())
+    }
+
     /// Defines a _previously declared_ program object with all zeros.
     /// **NB**: If you attempt to define an import, this will return an error.
     /// If you attempt to define something which has not been declared, this will return an error.
@@ -463,6 +1190,7 @@ impl Artifact {
         data: D,
         symbols: BTreeMap<String, u64>,
     ) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
         let decl_name = self.strings.get_or_intern(name.as_ref());
         let data = data.into();
         match self.declarations.get_mut(&decl_name) {
@@ -477,26 +1205,27 @@ impl Artifact {
                     Decl::Import(_) => {
                         return Err(ArtifactError::ImportDefined(name.as_ref().to_string()));
                     }
-                };
-
-                match decl {
-                    DefinedDecl::Section(_) => {}
-                    _ => {
-                        if !symbols.is_empty() {
-                            return Err(ArtifactError::NonSectionCustomSymbols(decl, symbols));
-                        }
+                    // absolute symbols have an address, but no data to store
+                    Decl::AbsoluteSymbol(_) => {
+                        stype.define();
+                        return Ok(());
                     }
-                }
-                match decl {
-                    DefinedDecl::Data(_) => {}
-                    _ => {
-                        if let Data::ZeroInit(_) = data {
-                            return Err(ArtifactError::InvalidZeroInit(decl));
-                        }
+                    Decl::Common { .. } => {
+                        return Err(ArtifactError::CommonDefined(name.as_ref().to_string()));
                     }
-                }
+                };
+
+                check_definition(
+                    name.as_ref(),
+                    decl,
+                    &data,
+                    &symbols,
+                    self.sizes.get(&decl_name).cloned(),
+                )?;
 
-                if decl.is_global() {
+                // weak definitions are not local either: they are visible to, and may be overridden
+                // by, other components
+                if decl.is_global() || decl.is_weak() {
                     self.nonlocal_definitions.insert(InternalDefinition {
                         name: decl_name,
                         data,
@@ -517,6 +1246,111 @@ impl Artifact {
         }
         Ok(())
     }
+    /// Declare and define `name` to be an Objective-C selector reference to the method name
+    /// `selector`, which is itself defined as the symbol `{name}.methname`.
+    pub fn declare_selector_ref<T: AsRef<str>>(
+        &mut self,
+        name: T,
+        selector: &str,
+    ) -> Result<(), ArtifactError> {
+        let name = name.as_ref();
+        let method_name = format!("{}.methname", name);
+        let mut bytes = selector.as_bytes().to_vec();
+        bytes.push(0);
+        self.declare_with(&method_name, Decl::objc_method_name(), bytes)?;
+        let pointer_size = self
+            .target
+            .pointer_width()
+            .map_or(8, |w| w.bytes() as usize);
+        self.declare_with(name, Decl::objc_selector_ref(), vec![0; pointer_size])?;
+        self.link(Link {
+            from: name,
+            to: &method_name,
+            at: 0,
+        })
+    }
+    /// Declare and define `name` to be an explicitly reserved Global Offset Table slot holding the
+    /// address of `target`, which must already be declared. The symbol `name` refers to the slot
+    /// itself, which is placed in `__DATA,__got` for Mach-o, or a `.got` section for ELF.
+    ///
+    /// The slot is relocated with a plain absolute pointer relocation (e.g., `R_X86_64_64`), not
+    /// `R_X86_64_GLOB_DAT`: the latter is a dynamic relocation, which only the static linker emits.
+    /// When the object is linked into a shared library, the slot's relocation becomes a dynamic
+    /// relocation against `target`, as `objdump -R` shows.
+    pub fn declare_got_entry<T: AsRef<str>>(
+        &mut self,
+        name: T,
+        target: &str,
+    ) -> Result<(), ArtifactError> {
+        let name = name.as_ref();
+        let pointer_size = self
+            .target
+            .pointer_width()
+            .map_or(8, |w| w.bytes() as usize);
+        self.declare_with(name, Decl::got_entry(), vec![0; pointer_size])?;
+        self.link(Link {
+            from: name,
+            to: target,
+            at: 0,
+        })
+    }
+    /// Declare and define `name` to be a global indirect function, i.e., a GNU `IFUNC`: when it is
+    /// bound, the dynamic linker calls `resolver`, which must be a function defined in this
+    /// artifact, and uses the implementation it returns, e.g., to dispatch on CPU features.
+    /// Links to `name` are relocated against the indirect function, never the resolver directly.
+    ///
+    /// Indirect functions are only supported for ELF targets, where `name` is emitted as an
+    /// `STT_GNU_IFUNC` symbol; Mach-o and COFF have no equivalent, so declaring one for them is an
+    /// `UnsupportedBinaryFormat` error.
+    pub fn declare_ifunc<T: AsRef<str>>(
+        &mut self,
+        name: T,
+        resolver: &str,
+    ) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        if self.target.binary_format != BinaryFormat::Elf {
+            return Err(ArtifactError::UnsupportedBinaryFormat(
+                self.target.binary_format,
+            ));
+        }
+        let name = name.as_ref();
+        let resolver_id = self.strings.get_or_intern(resolver);
+        match self.declarations.get(&resolver_id) {
+            Some(InternalDecl {
+                decl: Decl::Defined(DefinedDecl::Function(_)),
+                ..
+            }) => {}
+            Some(resolver) => {
+                return Err(ArtifactError::IncompatibleDeclaration {
+                    old: resolver.decl,
+                    new: Decl::function().into(),
+                })
+            }
+            None => return Err(ArtifactError::Undeclared(resolver.to_string())),
+        }
+        self.declare(name, Decl::function().global())?;
+        let id = self.strings.get_or_intern(name);
+        let decl = self
+            .declarations
+            .get_mut(&id)
+            .expect("ifunc was just declared");
+        if decl.defined {
+            return Err(ArtifactError::DuplicateDefinition(name.to_string()));
+        }
+        // the resolver stands in for its definition
+        decl.define();
+        self.ifuncs.insert(id, resolver_id);
+        Ok(())
+    }
+    /// Get this artifact's indirect functions, and their resolvers
+    pub(crate) fn ifuncs<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, &'a str)> + 'a> {
+        Box::new(self.ifuncs.iter().map(move |(&name, &resolver)| {
+            (
+                self.strings.resolve(name).expect("ifunc has a name"),
+                self.strings.resolve(resolver).expect("resolver has a name"),
+            )
+        }))
+    }
     /// Declare `import` to be an import with `kind`.
     /// This is just sugar for `declare("name", Decl::FunctionImport)` or `declare("data", Decl::DataImport)`
     pub fn import<T: AsRef<str>>(
@@ -527,6 +1361,35 @@ impl Artifact {
         self.declare(import.as_ref(), Decl::Import(kind))?;
         Ok(())
     }
+    /// Declare every one of `names` to be an import with `kind`, e.g., the libc functions this
+    /// artifact uses.
+    ///
+    /// **NB**: If any of `names` occurs more than once, or was already declared, this returns a
+    /// `DuplicateDefinition` error, without declaring any of them.
+    pub fn declare_import_list(
+        &mut self,
+        names: &[&str],
+        kind: ImportKind,
+    ) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        let mut seen = HashSet::new();
+        for &name in names {
+            let declared = self
+                .strings
+                .get(name)
+                .and_then(|id| self.declarations.get(&id))
+                .is_some();
+            if declared || !seen.insert(name) {
+                return Err(ArtifactError::DuplicateDefinition(name.to_string()));
+            }
+        }
+        self.declarations.reserve(names.len());
+        self.imports.reserve(names.len());
+        for &name in names {
+            self.import(name, kind)?;
+        }
+        Ok(())
+    }
     /// Link a relocation at `link.at` from `link.from` to `link.to`
     /// **NB**: If either `link.from` or `link.to` is undeclared, then this will return an error.
     /// If `link.from` is an import you previously declared, this will also return an error.
@@ -536,6 +1399,7 @@ impl Artifact {
     /// A variant of `link` with a `Reloc` provided. Has all of the same invariants as
     /// `link`.
     pub fn link_with<'a>(&mut self, link: Link<'a>, reloc: Reloc) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
         let (link_from, link_to) = (
             self.strings.get_or_intern(link.from),
             self.strings.get_or_intern(link.to),
@@ -548,6 +1412,12 @@ impl Artifact {
                 if from_type.decl.is_import() {
                     return Err(ArtifactError::RelocateImport(link.from.to_string()));
                 }
+                if from_type.decl.is_absolute() {
+                    return Err(ArtifactError::RelocateAbsolute(link.from.to_string()));
+                }
+                if from_type.decl.is_common() {
+                    return Err(ArtifactError::RelocateCommon(link.from.to_string()));
+                }
                 let link = (link_from, link_to, link.at, reloc);
                 self.links.push(link);
             }
@@ -561,57 +1431,1367 @@ impl Artifact {
         Ok(())
     }
 
-    /// Get set of non-import declarations that have not been defined. This must be an empty set in
-    /// order to `emit` the artifact.
-    pub fn undefined_symbols(&self) -> Vec<String> {
-        let mut syms = Vec::new();
-        for (&name, _) in self
-            .declarations
-            .iter()
-            .filter(|&(_, &int)| !int.defined && !int.decl.is_import())
-        {
-            syms.push(String::from(
-                self.strings.resolve(name).expect("declaration has a name"),
-            ));
-        }
-        syms
+    /// A variant of `link`, which uses a relocation of `kind` if it is given, instead of the one
+    /// faerie would deduce from the declarations of `link.from` and `link.to`. Has all of the same
+    /// invariants as `link`.
+    pub fn link_with_reloc<'a>(
+        &mut self,
+        link: Link<'a>,
+        kind: Option<RelocationKind>,
+    ) -> Result<(), ArtifactError> {
+        self.link_with(link, kind.map_or(Reloc::Auto, Reloc::Kind))
     }
 
-    /// Emit a blob of bytes representing the object file in the format specified in the target the
-    /// `Artifact` was constructed with.
-    pub fn emit(&self) -> Result<Vec<u8>, ArtifactError> {
-        self.emit_as(self.target.binary_format)
+    /// A variant of `link`, which refers to the address of `link.to` plus `addend` rather than
+    /// just its address. Has all of the same invariants as `link`.
+    pub fn link_with_addend<'a>(
+        &mut self,
+        link: Link<'a>,
+        addend: i64,
+    ) -> Result<(), ArtifactError> {
+        self.link_with(link, Reloc::AutoWithAddend { addend })
     }
 
-    /// Emit a blob of bytes representing an object file in the given format.
-    pub fn emit_as(&self, format: BinaryFormat) -> Result<Vec<u8>, ArtifactError> {
-        let undef = self.undefined_symbols();
-        if undef.is_empty() {
-            let bytes = match format {
-                BinaryFormat::Elf => elf::to_bytes(self)?,
-                BinaryFormat::Macho => mach::to_bytes(self)?,
-                _ => {
-                    return Err(ArtifactError::UnsupportedBinaryFormat(
-                        self.target.binary_format.to_owned(),
-                    ))
-                }
-            };
-            Ok(bytes)
-        } else {
-            return Err(ArtifactError::UndefinedSymbols(undef));
-        }
+    /// Store the absolute address of `target_symbol`, plus `addend`, in the data definition `from`
+    /// at offset `at`, e.g., to fill in a function pointer in a vtable. Has all of the same
+    /// invariants as `link`.
+    pub fn link_data_to_symbol(
+        &mut self,
+        from: &str,
+        at: u64,
+        target_symbol: &str,
+        addend: i32,
+    ) -> Result<(), ArtifactError> {
+        self.link_with(
+            Link {
+                from,
+                to: target_symbol,
+                at,
+            },
+            Reloc::Absolute { addend },
+        )
     }
 
-    /// Emit and write to disk a blob of bytes representing the object file in the format specified
-    /// in the target the `Artifact` was constructed with.
-    pub fn write(&self, sink: File) -> Result<(), ArtifactError> {
-        self.write_as(sink, self.target.binary_format)
+    /// Store the difference of two symbols, `minuend - subtrahend`, as a `size` byte integer in
+    /// `from` at offset `at`, e.g., an entry of a jump table which is relative to the table. Has
+    /// all of the same invariants as `link`, and `size` must be 4 or 8.
+    ///
+    /// Currently only supported by the Mach-o backend, as an `X86_64_RELOC_SUBTRACTOR` (or
+    /// `ARM64_RELOC_SUBTRACTOR`) relocation followed by an unsigned one; other formats return an
+    /// `UnsupportedBinaryFormat` error. Removing either symbol's definition removes both halves
+    /// of the difference.
+    pub fn link_difference(
+        &mut self,
+        from: &str,
+        at: u64,
+        minuend: &str,
+        subtrahend: &str,
+        size: u8,
+    ) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        if self.target.binary_format != BinaryFormat::Macho {
+            return Err(ArtifactError::UnsupportedBinaryFormat(
+                self.target.binary_format,
+            ));
+        }
+        if size != 4 && size != 8 {
+            return Err(ArtifactError::UnsupportedRelocation(format!(
+                "the difference of two symbols must be 4 or 8 bytes, not {}",
+                size
+            )));
+        }
+        // check both ends first, so that a failure never leaves half of the pair behind
+        for name in &[minuend, subtrahend] {
+            if !matches!(self.strings.get(name), Some(id) if self.declarations.contains_key(&id)) {
+                return Err(ArtifactError::Undeclared(name.to_string()));
+            }
+        }
+        self.link_with(
+            Link {
+                from,
+                to: subtrahend,
+                at,
+            },
+            Reloc::Subtrahend { size },
+        )?;
+        self.link_with(
+            Link {
+                from,
+                to: minuend,
+                at,
+            },
+            Reloc::Minuend { size },
+        )
     }
 
-    /// Emit and write to disk a blob of bytes representing an object file in the given format.
-    pub fn write_as(&self, mut sink: File, format: BinaryFormat) -> Result<(), ArtifactError> {
-        let bytes = self.emit_as(format)?;
-        sink.write_all(&bytes)?;
+    /// Freeze this artifact: from now on, changing it in any way, e.g., declaring, defining,
+    /// linking, or removing anything, is a [Sealed](enum.ArtifactError.html#variant.Sealed) error. Since nothing can be added anymore,
+    /// the deferred passes which rely on the artifact being complete are run now, i.e.,
+    /// [intern_strings](#method.intern_strings). Sealing twice does nothing.
+    ///
+    /// **NB**: interning merges identical local string constants, so distinct `Decl::cstring()`
+    /// definitions with the same contents end up at the same address.
+    ///
+    /// Objects are always emitted from a sealed artifact: since emitting only borrows the
+    /// artifact, an artifact which was never sealed is copied, and the copy is sealed and emitted.
+    /// Sealing a large artifact before emitting it avoids the copy.
+    pub fn seal(&mut self) {
+        if self.sealed {
+            return;
+        }
+        let saved = self.intern_strings().expect("artifact is not sealed yet");
+        debug!(
+            "Sealed {}: interning strings saved {} bytes",
+            self.name, saved
+        );
+        self.sealed = true;
+    }
+    /// Whether this artifact was [sealed](#method.seal)
+    pub fn is_sealed(&self) -> bool {
+        self.sealed
+    }
+    /// This artifact if it is sealed, otherwise a sealed copy of it, which is what is emitted
+    fn sealed(&self) -> Cow<'_, Artifact> {
+        if self.sealed {
+            Cow::Borrowed(self)
+        } else {
+            let mut artifact = self.clone();
+            artifact.seal();
+            Cow::Owned(artifact)
+        }
+    }
+    /// Whether `name` is declared
+    pub(crate) fn is_declared(&self, name: &str) -> bool {
+        matches!(self.strings.get(name), Some(id) if self.declarations.contains_key(&id))
+    }
+    fn check_unsealed(&self) -> Result<(), ArtifactError> {
+        if self.sealed {
+            Err(ArtifactError::Sealed)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Remove the definition of `name`, along with its declaration, and every link from or to it;
+    /// returns the number of links removed. The symbol tables are built when emitting, so the
+    /// remaining symbols, and the relocations against them, are renumbered accordingly.
+    ///
+    /// An indirect function cannot be emitted without its resolver, so removing a resolver also
+    /// removes every indirect function it resolves, and the links to them.
+    pub fn remove_definition(&mut self, name: &str) -> Result<usize, ArtifactError> {
+        self.check_unsealed()?;
+        let id = match self.strings.get(name) {
+            Some(id) if self.declarations.contains_key(&id) => id,
+            _ => return Err(ArtifactError::Undeclared(name.to_string())),
+        };
+        if !self.declarations[&id].defined || self.declarations[&id].decl.is_import() {
+            return Err(ArtifactError::UndefinedSymbols(vec![name.to_string()]));
+        }
+        let resolved = self
+            .ifuncs
+            .iter()
+            .filter(|&(_, &resolver)| resolver == id)
+            .map(|(&ifunc, _)| ifunc)
+            .collect::<Vec<_>>();
+        let mut nremoved = 0;
+        for ifunc in resolved {
+            let ifunc = self
+                .strings
+                .resolve(ifunc)
+                .expect("ifunc has a name")
+                .to_string();
+            nremoved += self.remove_definition(&ifunc)?;
+        }
+        self.ifuncs.shift_remove(&id);
+        self.declarations.shift_remove(&id);
+        self.local_definitions.retain(|def| def.name != id);
+        self.nonlocal_definitions.retain(|def| def.name != id);
+        self.definition_order.remove(&id);
+        self.sizes.remove(&id);
+        self.aliases.remove(&id);
+        self.frame_info.retain(|&(function, _)| function != id);
+        if self.entry_point == Some(id) {
+            self.entry_point = None;
+        }
+        let nlinks = self.links.len();
+        let aliases = &self.aliases;
+        let removed = |&(from, to, _, _): &Relocation| {
+            from == id || aliases.get(&to).cloned().unwrap_or(to) == id
+        };
+        // the halves of a difference of two symbols are only meaningful together
+        let differences = self
+            .links
+            .iter()
+            .filter(|link| removed(link))
+            .filter(|(_, _, _, reloc)| {
+                matches!(reloc, Reloc::Subtrahend { .. } | Reloc::Minuend { .. })
+            })
+            .map(|&(from, _, at, _)| (from, at))
+            .collect::<HashSet<_>>();
+        self.links.retain(|link| {
+            let (from, _, at, reloc) = *link;
+            let difference = matches!(reloc, Reloc::Subtrahend { .. } | Reloc::Minuend { .. });
+            !removed(link) && (!difference || !differences.contains(&(from, at)))
+        });
+        self.aliases.retain(|_, canonical| *canonical != id);
+        Ok(nremoved + nlinks - self.links.len())
+    }
+
+    /// Rename the symbol `old` to `new`, e.g., to add a version suffix required by a mangling
+    /// scheme. Unlike redeclaring it, this keeps its declaration, definition, and position, and
+    /// every link from or to it now refers to `new`.
+    ///
+    /// Returns an error if `old` is undeclared, or `new` is already declared.
+    pub fn rename_symbol(&mut self, old: &str, new: &str) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        let id = match self.strings.get(old) {
+            Some(id) if self.declarations.contains_key(&id) => id,
+            _ => return Err(ArtifactError::Undeclared(old.to_string())),
+        };
+        if old == new {
+            return Ok(());
+        }
+        if let Some(new_id) = self.strings.get(new) {
+            if self.declarations.contains_key(&new_id) {
+                return Err(ArtifactError::NameTaken(new.to_string()));
+            }
+        }
+        self.rename(id, new);
+        Ok(())
+    }
+
+    /// Remove every definition which is not reachable from `entry_points` by following links, like
+    /// the linker's `--gc-sections`, but before the object file is emitted. Global definitions are
+    /// removed too if they are unreachable, whereas imports are never removed. An indirect function
+    /// and its resolver are kept or removed together.
+    ///
+    /// Returns an error if any of `entry_points` is undeclared.
+    pub fn gc_sections(
+        &mut self,
+        entry_points: &[&str],
+    ) -> Result<ArtifactGcReport, ArtifactError> {
+        self.check_unsealed()?;
+        let mut worklist = Vec::new();
+        for &name in entry_points {
+            match self.strings.get(name) {
+                Some(id) if self.declarations.contains_key(&id) => {
+                    worklist.push(self.resolve_alias(id))
+                }
+                _ => return Err(ArtifactError::Undeclared(name.to_string())),
+            }
+        }
+        let mut edges: HashMap<StringID, Vec<StringID>> = HashMap::new();
+        for &(from, to, _, _) in self.links.iter() {
+            edges.entry(from).or_default().push(self.resolve_alias(to));
+        }
+        // an indirect function is implemented by its resolver, and cannot be emitted without it
+        for (&ifunc, &resolver) in self.ifuncs.iter() {
+            edges.entry(ifunc).or_default().push(resolver);
+            edges.entry(resolver).or_default().push(ifunc);
+        }
+        let mut reachable = HashSet::new();
+        while let Some(id) = worklist.pop() {
+            if reachable.insert(id) {
+                worklist.extend(edges.get(&id).into_iter().flatten());
+            }
+        }
+
+        // the indirect functions go first, since removing a resolver removes them too
+        let unreachable = self
+            .ifuncs
+            .keys()
+            .filter(|ifunc| !reachable.contains(ifunc))
+            .map(|&ifunc| (ifunc, 0))
+            .chain(
+                self.internal_definitions()
+                    .into_iter()
+                    .filter(|def| !reachable.contains(&def.name))
+                    .map(|def| (def.name, def.data.file_size())),
+            )
+            .map(|(id, size)| {
+                let name = self.strings.resolve(id).expect("definition has a name");
+                (name.to_string(), size)
+            })
+            .collect::<Vec<_>>();
+        let mut report = ArtifactGcReport::default();
+        for (name, size) in unreachable {
+            self.remove_definition(&name)?;
+            report.removed_symbols.push(name);
+            report.bytes_saved += size;
+        }
+        Ok(report)
+    }
+
+    /// Apply `patch` to this artifact: first its removed, then its changed, and finally its added
+    /// definitions. The layout of the object file, e.g., the offsets of the symbols following a
+    /// changed definition, is recomputed when it is emitted. A changed definition must satisfy the
+    /// same constraints as when it was defined, e.g., its declared size, and the links from it
+    /// which are past the end of its new contents are removed.
+    ///
+    /// **NB**: the patch is not applied atomically; if this returns an error, the changes up to the
+    /// offending definition have been applied.
+    pub fn apply_patch(&mut self, patch: ArtifactPatch) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        for name in patch.removed_definitions {
+            self.remove_definition(&name)?;
+        }
+        for (name, data) in patch.changed_definitions {
+            let id = match self.strings.get(&name) {
+                Some(id) if self.declarations.contains_key(&id) => id,
+                _ => return Err(ArtifactError::Undeclared(name)),
+            };
+            let def = self
+                .local_definitions
+                .iter()
+                .chain(self.nonlocal_definitions.iter())
+                .find(|def| def.name == id)
+                .cloned();
+            let mut def = match def {
+                Some(def) => def,
+                None => return Err(ArtifactError::UndefinedSymbols(vec![name])),
+            };
+            let data = Data::Blob(data);
+            check_definition(
+                &name,
+                def.decl,
+                &data,
+                &def.symbols,
+                self.sizes.get(&id).cloned(),
+            )?;
+            // links into the part of the old definition which is gone have nothing to relocate
+            let size = data.file_size() as u64;
+            self.links
+                .retain(|&(from, _, at, _)| from != id || at < size);
+            let definitions = if def.decl.is_global() || def.decl.is_weak() {
+                &mut self.nonlocal_definitions
+            } else {
+                &mut self.local_definitions
+            };
+            definitions.remove(&def);
+            def.data = data;
+            definitions.insert(def);
+        }
+        for (name, data) in patch.added_definitions {
+            self.define(name, data)?;
+        }
+        Ok(())
+    }
+
+    /// Merge `other`, e.g., a translation unit generated on another thread, into this artifact,
+    /// and return the combined artifact, which can be emitted with any backend.
+    ///
+    /// Both artifacts must have the same target. Their declarations are combined like repeated
+    /// declarations in a single artifact, so an import in one is satisfied by a definition in the
+    /// other. A symbol defined in both is an error, unless both definitions are COMDAT or weak, in
+    /// which case this artifact's definition is kept. Local definitions whose names clash with a
+    /// symbol of the other artifact are renamed with a `.merged.N` suffix. The data in code entries
+    /// are relative to the code section, whose layout changes, so `other` must not have any.
+    pub fn merge(mut self, other: Artifact) -> Result<Artifact, ArtifactError> {
+        self.check_unsealed()?;
+        if self.target != other.target {
+            return Err(ArtifactError::IncompatibleMerge(format!(
+                "the targets {} and {} differ",
+                self.target, other.target
+            )));
+        }
+        if !other.data_in_code.is_empty() {
+            return Err(ArtifactError::IncompatibleMerge(format!(
+                "{} has data in code entries",
+                other.name
+            )));
+        }
+        fn is_local(decl: &Decl) -> bool {
+            match decl {
+                Decl::Defined(d) => !d.is_global() && !d.is_weak(),
+                _ => false,
+            }
+        }
+        fn is_coalesced(decl: &Decl) -> bool {
+            match decl {
+                Decl::Defined(DefinedDecl::Function(d)) if d.is_comdat() => true,
+                Decl::Defined(d) => d.is_weak(),
+                _ => false,
+            }
+        }
+        let is_declared = |artifact: &Artifact, name: &str| matches!(artifact.strings.get(name), Some(id) if artifact.declarations.contains_key(&id));
+        let unique_name = |this: &Artifact, name: &str| {
+            (1..)
+                .map(|n| format!("{}.merged.{}", name, n))
+                .find(|name| !is_declared(this, name) && !is_declared(&other, name))
+                .expect("there is an unused name")
+        };
+
+        // this artifact's local definitions must not capture references to the other's global
+        // symbols; the other's clashing local definitions are renamed instead
+        let clashing = self
+            .declarations
+            .iter()
+            .filter(|(_, int)| is_local(&int.decl))
+            .map(|(&id, _)| id)
+            .filter(|&id| {
+                let name = self.strings.resolve(id).expect("has a name");
+                let int = other
+                    .strings
+                    .get(name)
+                    .and_then(|id| other.declarations.get(&id));
+                matches!(int, Some(int) if !is_local(&int.decl))
+            })
+            .collect::<Vec<_>>();
+        for id in clashing {
+            let name = self.strings.resolve(id).expect("has a name").to_string();
+            let renamed = unique_name(&self, &name);
+            self.rename(id, &renamed);
+        }
+
+        // the other's symbols are declared by name, and so get new ids in this artifact
+        let mut ids = HashMap::new();
+        let mut coalesced = HashSet::new();
+        for (&other_id, int) in other.declarations.iter() {
+            let name = other.strings.resolve(other_id).expect("has a name");
+            let name = if is_local(&int.decl) && is_declared(&self, name) {
+                unique_name(&self, name)
+            } else {
+                name.to_string()
+            };
+            let previous = self
+                .strings
+                .get(&name)
+                .and_then(|id| self.declarations.get(&id))
+                .cloned();
+            self.declare(&name, int.decl)?;
+            let id = self.strings.get_or_intern(&name);
+            ids.insert(other_id, id);
+            match previous {
+                Some(previous) if previous.defined && int.defined => {
+                    if is_coalesced(&previous.decl) && is_coalesced(&int.decl) {
+                        coalesced.insert(other_id);
+                    } else {
+                        return Err(ArtifactError::DuplicateDefinition(name));
+                    }
+                }
+                _ if int.defined => self.declarations[&id].define(),
+                _ => {}
+            }
+        }
+        let id = |other_id: &StringID| ids[&other.resolve_alias(*other_id)];
+
+        for def in other
+            .local_definitions
+            .iter()
+            .chain(other.nonlocal_definitions.iter())
+            .filter(|def| !coalesced.contains(&def.name))
+        {
+            let def = InternalDefinition {
+                name: id(&def.name),
+                ..def.clone()
+            };
+            if def.decl.is_global() || def.decl.is_weak() {
+                self.nonlocal_definitions.insert(def);
+            } else {
+                self.local_definitions.insert(def);
+            }
+        }
+        for &(from, to, at, reloc) in other.links.iter() {
+            if !coalesced.contains(&from) {
+                self.links.push((id(&from), id(&to), at, reloc));
+            }
+        }
+        for (function, entries) in other.frame_info.iter() {
+            if !coalesced.contains(function) {
+                self.frame_info.push((id(function), entries.clone()));
+            }
+        }
+        for (name, resolver) in other.ifuncs.iter() {
+            self.ifuncs.insert(id(name), id(resolver));
+        }
+        for (name, &size) in other.sizes.iter() {
+            self.sizes.entry(id(name)).or_insert(size);
+        }
+        self.raw_sections.extend(other.raw_sections.iter().cloned());
+        for (name, &flags) in other.section_flags.iter() {
+            *self.section_flags.entry(name.clone()).or_insert(0) |= flags;
+        }
+        self.odr_entries.extend(other.odr_entries.iter().cloned());
+        if self.debuglink.is_none() {
+            self.debuglink = other.debuglink.clone();
+        }
+        if self.platform.is_none() {
+            self.platform = other.platform;
+        }
+        if self.source_path.is_none() {
+            self.source_path = other.source_path.clone();
+        }
+        if self.build_id.is_none() {
+            self.build_id = other.build_id.clone();
+        }
+        self.comment.extend_from_slice(&other.comment);
+        self.subsections_via_symbols &= other.subsections_via_symbols;
+        self.pic |= other.pic;
+        if self.entry_point.is_none() {
+            self.entry_point = other.entry_point.map(|entry| id(&entry));
+        }
+        Ok(self)
+    }
+
+    /// Rename the symbol `id` to `name`, which must be unused
+    fn rename(&mut self, id: StringID, name: &str) {
+        let new_id = self.strings.get_or_intern(name);
+        let rename = |name: StringID| if name == id { new_id } else { name };
+        for import in self.imports.iter_mut() {
+            *import = rename(*import);
+        }
+        self.declarations = self
+            .declarations
+            .drain(..)
+            .map(|(name, int)| (rename(name), int))
+            .collect();
+        for definitions in &mut [&mut self.local_definitions, &mut self.nonlocal_definitions] {
+            if let Some(def) = definitions.iter().find(|def| def.name == id).cloned() {
+                definitions.remove(&def);
+                definitions.insert(InternalDefinition {
+                    name: new_id,
+                    ..def
+                });
+            }
+        }
+        for link in self.links.iter_mut() {
+            link.0 = rename(link.0);
+            link.1 = rename(link.1);
+        }
+        self.aliases = self
+            .aliases
+            .drain()
+            .map(|(alias, canonical)| (rename(alias), rename(canonical)))
+            .collect();
+        self.ifuncs = self
+            .ifuncs
+            .drain(..)
+            .map(|(name, resolver)| (rename(name), rename(resolver)))
+            .collect();
+        self.sizes = self
+            .sizes
+            .drain()
+            .map(|(name, size)| (rename(name), size))
+            .collect();
+        self.definition_order = self
+            .definition_order
+            .drain()
+            .map(|(name, position)| (rename(name), position))
+            .collect();
+        for (function, _) in self.frame_info.iter_mut() {
+            *function = rename(*function);
+        }
+        self.entry_point = self.entry_point.map(rename);
+    }
+
+    /// Record a range of data embedded in the code section, see
+    /// [DataInCodeEntry](struct.DataInCodeEntry.html)
+    pub fn add_data_in_code(&mut self, entry: DataInCodeEntry) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        self.data_in_code.push(entry);
+        Ok(())
+    }
+    /// Get the data in code entries recorded so far
+    pub(crate) fn data_in_code(&self) -> &[DataInCodeEntry] {
+        &self.data_in_code
+    }
+
+    /// Add a section named `name` whose contents are `data`, emitted verbatim, as an escape hatch
+    /// for anything the other abstractions cannot express. No symbols or relocations are generated
+    /// for it. The `flags` are interpreted by the backend:
+    ///
+    /// * ELF: the high 32 bits are the `sh_type`, and the low 32 bits the `sh_flags`
+    /// * Mach-o: the section's `flags`, i.e., its type and attributes; the section is placed in the
+    ///   `__TEXT` segment if it has any of the instruction attributes, and `__DATA` otherwise
+    pub fn add_raw_section(
+        &mut self,
+        name: String,
+        data: Vec<u8>,
+        flags: u64,
+    ) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        self.raw_sections.push(RawSection { name, data, flags });
+        Ok(())
+    }
+    /// Get the raw sections added so far
+    pub(crate) fn raw_sections(&self) -> &[RawSection] {
+        &self.raw_sections
+    }
+
+    /// Add `flags` to the flags the backend gives the section `name` of the emitted object, e.g.,
+    /// `__text` or `.text.f`. Setting flags again adds to the previous ones. The `flags` are
+    /// interpreted by the backend:
+    ///
+    /// * ELF: the section's `sh_flags`, e.g., `SHF_WRITE` or `SHF_MERGE` from
+    ///   `goblin::elf::section_header`, which are 32 bits wide in 32 bit ELF
+    /// * Mach-o: the section's 32 bit `flags`, whose low 8 bits are its type and the rest its
+    ///   attributes, e.g., `S_ATTR_PURE_INSTRUCTIONS`, `S_ATTR_SOME_INSTRUCTIONS` or
+    ///   `S_ATTR_NO_DEAD_STRIP` from `goblin::mach::constants`
+    ///
+    /// Returns an error if the `flags` do not fit in the format's section flags. The section is
+    /// only known once the object is emitted, which returns a `NoSuchSection` error if it is not
+    /// one of its [sections](#method.sections).
+    pub fn set_section_flags(&mut self, name: &str, flags: u64) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        let bits = match self.target.binary_format {
+            BinaryFormat::Elf => self.target.pointer_width().map_or(64, |w| w.bits()),
+            BinaryFormat::Macho => 32,
+            _ => {
+                return Err(ArtifactError::UnsupportedBinaryFormat(
+                    self.target.binary_format.to_owned(),
+                ))
+            }
+        };
+        if bits < 64 && flags >> bits != 0 {
+            return Err(ArtifactError::Unsupported(format!(
+                "section flags {:#x} of {} do not fit in {} bits",
+                flags, name, bits
+            )));
+        }
+        *self.section_flags.entry(name.to_string()).or_insert(0) |= flags;
+        Ok(())
+    }
+    /// Get the sections whose flags were set, and the flags added to them, see `set_section_flags`
+    pub(crate) fn section_flags(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.section_flags
+            .iter()
+            .map(|(name, &flags)| (name.as_str(), flags))
+    }
+
+    /// Record the hash of the type `type_name` for one definition rule checks during LTO, which
+    /// compares the hashes of identically named types across object files.
+    ///
+    /// In ELF, the entries are emitted as LLVM's `SHT_LLVM_ODRTAB` section `.llvm_odrtab`, whose
+    /// entries are the strtab offset of the type name and its hash. Mach-o has no such section,
+    /// so they are ignored there.
+    pub fn add_odr_entry(&mut self, type_name: &str, hash: u64) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        self.odr_entries.push((type_name.to_string(), hash));
+        Ok(())
+    }
+    /// Get the one definition rule entries recorded so far
+    pub(crate) fn odr_entries(&self) -> &[(String, u64)] {
+        &self.odr_entries
+    }
+
+    /// Record that the debug info of this artifact was stripped into `debug_file`, whose contents
+    /// have the checksum `crc32`, so that debuggers can find it.
+    ///
+    /// In ELF, this is emitted as a `.gnu_debuglink` section. Mach-o has no such section, so it is
+    /// ignored there.
+    pub fn set_debuglink(&mut self, debug_file: &str, crc32: u32) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        self.debuglink = Some((debug_file.to_string(), crc32));
+        Ok(())
+    }
+    /// Get the debug info file and its CRC32, if set
+    pub(crate) fn debuglink(&self) -> Option<(&str, u32)> {
+        self.debuglink
+            .as_ref()
+            .map(|(debug_file, crc32)| (debug_file.as_str(), *crc32))
+    }
+
+    /// Embed `build_id`, an arbitrary blob identifying this build, e.g., a hash of its inputs, so
+    /// that crash reports can be matched to it.
+    ///
+    /// In ELF, this is emitted as a `.note.gnu.build-id` section. In Mach-o, it is emitted as an
+    /// `LC_NOTE` load command.
+    pub fn set_build_id(&mut self, build_id: &[u8]) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        self.build_id = Some(build_id.to_vec());
+        Ok(())
+    }
+    /// Get the build id, if set
+    pub(crate) fn build_id(&self) -> Option<&[u8]> {
+        self.build_id.as_deref()
+    }
+
+    /// Append `text`, e.g., the name and version of the compiler, to the comment identifying the
+    /// tools this artifact was built with; every call adds another null terminated string.
+    ///
+    /// In ELF, this is emitted as a mergeable `.comment` string section. In Mach-o, it is emitted
+    /// as a `__TEXT,__comment` section. COFF and wasm have no such section, so it is ignored there.
+    pub fn add_comment(&mut self, text: &str) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        self.comment.extend_from_slice(text.as_bytes());
+        self.comment.push(0);
+        Ok(())
+    }
+    /// Get the null terminated comment strings, which are empty if none were added
+    pub(crate) fn comment(&self) -> &[u8] {
+        &self.comment
+    }
+
+    /// Record the `platform` this artifact is built for, the minimum OS version it runs on, and
+    /// the SDK version it was built with, as `(major, minor, patch)`, which Apple's linker checks,
+    /// e.g., to warn about linking objects built for newer OS versions.
+    ///
+    /// In Mach-o, this is emitted as an `LC_BUILD_VERSION` load command; other formats ignore it.
+    pub fn set_platform(
+        &mut self,
+        platform: MachPlatform,
+        min_os: (u32, u32, u32),
+        sdk: (u32, u32, u32),
+    ) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        self.platform = Some((platform, min_os, sdk));
+        Ok(())
+    }
+    /// Get the Mach-o platform, and the minimum OS and SDK versions, if set
+    pub(crate) fn platform(&self) -> Option<BuildVersion> {
+        self.platform
+    }
+
+    /// Set whether the code of this artifact is position independent, e.g., because it will be
+    /// linked into a shared object. Defaults to `false`.
+    ///
+    /// In ELF, references from functions to data which another module may preempt, i.e., global
+    /// or weak data with the default visibility, then go through the GOT, with
+    /// `R_X86_64_GOTPCRELX` relocations on x86_64. Calls already go through the PLT either way.
+    /// The file is still a relocatable object; the linker creates the shared object.
+    pub fn set_pic(&mut self, pic: bool) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        self.pic = pic;
+        Ok(())
+    }
+    /// Whether the code of this artifact is position independent
+    pub(crate) fn is_pic(&self) -> bool {
+        self.pic
+    }
+
+    /// Make the defined function `symbol` the entry point of this artifact.
+    ///
+    /// faerie only emits relocatable objects, which have no program headers or addresses, so
+    /// this does not produce an executable: in ELF, the file stays an `ET_REL`, whose `e_entry`
+    /// is the offset of `symbol` in its section, like its `st_value`, and the entry still has to
+    /// be passed to the linker, e.g., with `-e`. Mach-o objects ignore it.
+    ///
+    /// Returns an error if `symbol` is undeclared, not defined, or not a function, or if the
+    /// artifact is sealed.
+    pub fn set_entry_point(&mut self, symbol: &str) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        let id = match self.strings.get(symbol) {
+            Some(id) if self.declarations.contains_key(&id) => id,
+            _ => return Err(ArtifactError::Undeclared(symbol.to_string())),
+        };
+        let int = &self.declarations[&id];
+        match int.decl {
+            Decl::Defined(DefinedDecl::Function(_)) if int.defined => {}
+            Decl::Defined(_) if int.defined => {
+                return Err(ArtifactError::InvalidEntryPoint(symbol.to_string()));
+            }
+            _ => return Err(ArtifactError::UndefinedSymbols(vec![symbol.to_string()])),
+        }
+        self.entry_point = Some(id);
+        Ok(())
+    }
+    /// Get the entry point, if set
+    pub(crate) fn entry_point(&self) -> Option<&str> {
+        self.entry_point
+            .map(|id| self.strings.resolve(id).expect("entry point has a name"))
+    }
+
+    /// Record `path` as the object file this artifact is written to, so that `dsymutil` can find
+    /// its DWARF sections when linking the debug info of an executable it was linked into.
+    /// `dsymutil` skips objects whose modification time differs from `modification_time`, in
+    /// seconds since the epoch, unless it is 0, which keeps the object reproducible.
+    ///
+    /// In Mach-o, this is emitted as an `N_OSO` stab symbol, whose value is `modification_time`;
+    /// other formats ignore it.
+    pub fn set_source_path(
+        &mut self,
+        path: &Path,
+        modification_time: u64,
+    ) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        self.source_path = Some((path.to_path_buf(), modification_time));
+        Ok(())
+    }
+    /// Get the object file path, and its modification time, if set
+    pub(crate) fn source_path(&self) -> Option<(&Path, u64)> {
+        self.source_path
+            .as_ref()
+            .map(|(path, modification_time)| (path.as_path(), *modification_time))
+    }
+
+    /// Add the unwinding information of the function `symbol`, for debuggers, profilers, and
+    /// exception handling: `fde` is one or more `.eh_frame` entries, i.e., FDEs and the CIEs they
+    /// refer to, which may also be the CIEs of previously added functions, since the CIE pointer of
+    /// an FDE is relative to it.
+    ///
+    /// The initial location of every FDE must be encoded as `DW_EH_PE_pcrel | DW_EH_PE_sdata4`; it
+    /// is relocated to the address of `symbol`, so its value is ignored.
+    ///
+    /// In ELF, the entries are emitted in `.eh_frame`, and in Mach-o, in `__TEXT,__eh_frame`. COFF
+    /// has its own unwinding information, so they are ignored there.
+    pub fn add_frame_info(&mut self, symbol: &str, fde: &[u8]) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        let id = self.strings.get_or_intern(symbol);
+        match self.declarations.get(&id) {
+            Some(InternalDecl {
+                decl: Decl::Defined(DefinedDecl::Function(_)),
+                ..
+            }) => {}
+            Some(function) => {
+                return Err(ArtifactError::IncompatibleDeclaration {
+                    old: function.decl,
+                    new: Decl::function().into(),
+                })
+            }
+            None => return Err(ArtifactError::Undeclared(symbol.to_string())),
+        }
+        self.frame_info.push((id, fde.to_vec()));
+        Ok(())
+    }
+    /// Get the contents of `.eh_frame`, if any unwinding information was added
+    pub(crate) fn eh_frame<'a>(&'a self) -> Result<Option<EhFrame<'a>>, ArtifactError> {
+        if self.frame_info.is_empty() {
+            return Ok(None);
+        }
+        let read_u32 = |bytes: &[u8]| {
+            let mut word = [0; 4];
+            word.copy_from_slice(&bytes[..4]);
+            match self.target.endianness() {
+                Ok(target_lexicon::Endianness::Big) => u32::from_be_bytes(word),
+                _ => u32::from_le_bytes(word),
+            }
+        };
+        let mut data = Vec::new();
+        let mut initial_locations = Vec::new();
+        for (function, entries) in self.frame_info.iter() {
+            let function = self
+                .strings
+                .resolve(*function)
+                .expect("function has a name");
+            let start = data.len();
+            data.extend_from_slice(entries);
+            // every entry is its length, excluding the length itself, followed by its 4 byte id,
+            // which is 0 for a CIE, and the CIE pointer for an FDE, which is followed by the initial
+            // location; an extended length is 0xffffffff followed by the 64 bit length
+            let mut offset = start;
+            while offset + 12 <= data.len() {
+                let (length, header_size) = match read_u32(&data[offset..]) {
+                    // a terminator
+                    0 => break,
+                    0xffff_ffff => {
+                        let low = u64::from(read_u32(&data[offset + 4..]));
+                        let high = u64::from(read_u32(&data[offset + 8..]));
+                        let length = match self.target.endianness() {
+                            Ok(target_lexicon::Endianness::Big) => low << 32 | high,
+                            _ => high << 32 | low,
+                        };
+                        (length, 12)
+                    }
+                    length => (u64::from(length), 4),
+                };
+                let initial_location = offset + header_size + 4;
+                if initial_location + 4 > data.len() {
+                    break;
+                }
+                if read_u32(&data[offset + header_size..]) != 0 {
+                    data[initial_location..initial_location + 4].copy_from_slice(&[0; 4]);
+                    initial_locations.push((initial_location as u64, function));
+                }
+                offset = usize::try_from(length)
+                    .ok()
+                    .and_then(|length| length.checked_add(offset + header_size))
+                    .ok_or_else(|| {
+                        ArtifactError::Unsupported(format!(
+                            "the .eh_frame entry of {} at {:#x} has an invalid length {:#x}",
+                            function,
+                            offset - start,
+                            length
+                        ))
+                    })?;
+            }
+        }
+        Ok(Some(EhFrame {
+            data,
+            initial_locations,
+        }))
+    }
+
+    /// Don't let the linker split sections into subsections at symbol boundaries, e.g., for
+    /// hand-written assembly which computes label deltas, or branches to numeric offsets.
+    ///
+    /// In Mach-o, this clears `MH_SUBSECTIONS_VIA_SYMBOLS` from the header flags. ELF never
+    /// splits sections, so it is ignored there.
+    pub fn disable_subsections_via_symbols(&mut self) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        self.subsections_via_symbols = false;
+        Ok(())
+    }
+    /// Whether the linker may split sections into subsections at symbol boundaries
+    pub(crate) fn subsections_via_symbols(&self) -> bool {
+        self.subsections_via_symbols
+    }
+
+    /// The definition `name` was merged into, if any, otherwise `name` itself
+    fn resolve_alias(&self, name: StringID) -> StringID {
+        self.aliases.get(&name).cloned().unwrap_or(name)
+    }
+
+    /// Deduplicate identical local string constants, i.e., `Decl::cstring()` definitions, and
+    /// return the number of bytes saved.
+    ///
+    /// Of each group of identical strings, the first is kept, and the others become aliases of it:
+    /// links to them are relocated against the kept string, and they no longer get a symbol of
+    /// their own. Global strings are left untouched, as are strings which link to anything.
+    ///
+    /// Sealing the artifact already interns its strings, so this returns a `Sealed` error after.
+    pub fn intern_strings(&mut self) -> Result<usize, ArtifactError> {
+        self.check_unsealed()?;
+        let link_sources = self
+            .links
+            .iter()
+            .map(|&(from, _, _, _)| from)
+            .collect::<BTreeSet<_>>();
+        let mut canonicals: BTreeMap<(DefinedDecl, &Data), StringID> = BTreeMap::new();
+        let mut aliases = Vec::new();
+        let mut saved = 0;
+        for def in self.local_definitions.iter() {
+            match def.decl {
+                DefinedDecl::Data(decl) if decl.get_datatype() == DataType::String => {}
+                _ => continue,
+            }
+            if link_sources.contains(&def.name) {
+                continue;
+            }
+            let key = (def.decl, &def.data);
+            if let Some(&canonical) = canonicals.get(&key) {
+                aliases.push((def.name, canonical));
+                saved += def.data.file_size();
+            } else {
+                canonicals.insert(key, def.name);
+            }
+        }
+        for (alias, canonical) in aliases {
+            self.local_definitions.retain(|def| def.name != alias);
+            self.aliases.insert(alias, canonical);
+        }
+        Ok(saved)
+    }
+
+    /// Reorder the local definitions, and separately the global ones, so that each definition
+    /// precedes the definitions of the same binding it links to, e.g., each function comes before
+    /// the functions it calls. Local definitions always precede global ones, so a global definition
+    /// still follows the local definitions it links to. Definitions which are otherwise unordered
+    /// keep their relative order.
+    ///
+    /// Only the order in which definitions are emitted changes; this has no effect on their symbols.
+    /// **NB**: Since cyclic references are perfectly legal, this is opt-in, and returns an error
+    /// if the definitions link to each other in a cycle.
+    pub fn sort_definitions_topologically(&mut self) -> Result<(), ArtifactError> {
+        self.check_unsealed()?;
+        let names = self
+            .internal_definitions()
+            .into_iter()
+            .map(|def| def.name)
+            .collect::<Vec<_>>();
+        let positions = names
+            .iter()
+            .enumerate()
+            .map(|(position, &name)| (name, position))
+            .collect::<HashMap<_, _>>();
+        let mut edges = vec![Vec::new(); names.len()];
+        let mut indegrees = vec![0; names.len()];
+        for &(from, to, _, _) in self.links.iter() {
+            let to = self.resolve_alias(to);
+            if let (Some(&from), Some(&to)) = (positions.get(&from), positions.get(&to)) {
+                edges[from].push(to);
+                indegrees[to] += 1;
+            }
+        }
+        // always pick the earliest ready definition, so the sort is stable
+        let mut ready = indegrees
+            .iter()
+            .enumerate()
+            .filter(|&(_, &indegree)| indegree == 0)
+            .map(|(position, _)| Reverse(position))
+            .collect::<BinaryHeap<_>>();
+        let mut order = HashMap::new();
+        while let Some(Reverse(position)) = ready.pop() {
+            order.insert(names[position], order.len());
+            for &to in edges[position].iter() {
+                indegrees[to] -= 1;
+                if indegrees[to] == 0 {
+                    ready.push(Reverse(to));
+                }
+            }
+        }
+        if order.len() != names.len() {
+            let cyclic = names
+                .iter()
+                .filter(|name| !order.contains_key(name))
+                .map(|&name| {
+                    String::from(self.strings.resolve(name).expect("definition has a name"))
+                })
+                .collect();
+            return Err(ArtifactError::CyclicDependency(cyclic));
+        }
+        self.definition_order = order;
+        Ok(())
+    }
+
+    /// Get set of non-import declarations that have not been defined. This must be an empty set in
+    /// order to `emit` the artifact.
+    pub fn undefined_symbols(&self) -> Vec<String> {
+        let mut syms = Vec::new();
+        for (&name, _) in self.declarations.iter().filter(|&(_, &int)| {
+            !int.defined
+                && !int.decl.is_import()
+                && !int.decl.is_absolute()
+                && !int.decl.is_common()
+        }) {
+            syms.push(String::from(
+                self.strings.resolve(name).expect("declaration has a name"),
+            ));
+        }
+        syms
+    }
+
+    /// The names of the imports which are not among `known_symbols`, e.g., the symbols exported by
+    /// the libraries this artifact will be linked against, in the order they were imported
+    pub fn check_all_imports_satisfied(&self, known_symbols: &[&str]) -> Vec<String> {
+        self.imports()
+            .filter(|(name, _)| !known_symbols.contains(name))
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+
+    /// Check this artifact's declarations against `schema`, returning every violation, in the order
+    /// of declaration
+    pub fn verify_against_schema(&self, schema: &ArtifactSchema) -> Vec<SchemaViolation> {
+        let mut violations = Vec::new();
+        for (&name, int) in self.declarations.iter() {
+            let kind = match int.decl {
+                Decl::Import(kind) if schema.no_imports => SchemaViolationKind::Import(kind),
+                Decl::Defined(DefinedDecl::Data(_)) if schema.no_data => SchemaViolationKind::Data,
+                _ => continue,
+            };
+            violations.push(SchemaViolation {
+                name: String::from(self.strings.resolve(name).expect("declaration has a name")),
+                kind,
+            });
+        }
+        violations
+    }
+
+    /// The total size, in bytes, of all function definitions, without any alignment padding
+    pub fn compute_text_size(&self) -> usize {
+        self.local_definitions
+            .iter()
+            .chain(self.nonlocal_definitions.iter())
+            .filter(|def| def.decl.is_function())
+            .map(|def| def.data.memory_size())
+            .sum()
+    }
+
+    /// The total size, in bytes, of all data and C string definitions, without any alignment
+    /// padding. This includes zero-initialized data, which uses memory but not disk.
+    pub fn compute_data_size(&self) -> usize {
+        self.local_definitions
+            .iter()
+            .chain(self.nonlocal_definitions.iter())
+            .filter(|def| def.decl.is_data())
+            .map(|def| def.data.memory_size())
+            .sum()
+    }
+
+    /// The number of relocations this artifact's links produce. This is an estimate, as some
+    /// architectures need more than one relocation for a single link.
+    pub fn compute_relocation_count(&self) -> usize {
+        self.links.len()
+    }
+
+    /// Write a summary of the artifact's size, similar to `size(1)`: the total code and data bytes,
+    /// the number of symbols, imports and links, and the estimated size of the relocation tables.
+    /// Wasm relocations are LEB128s, whose sizes are only known once the object is laid out, so
+    /// their size is printed as `-`.
+    pub fn print_stats(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        use goblin::container::Container;
+        use goblin::elf::reloc::{reloc32, reloc64};
+        use goblin::mach::relocation::SIZEOF_RELOCATION_INFO;
+        use goblin::pe::relocation::COFF_RELOCATION_SIZE;
+
+        let sizeof_relocation = match self.target.binary_format {
+            BinaryFormat::Macho => Some(SIZEOF_RELOCATION_INFO),
+            BinaryFormat::Elf => match crate::target::make_ctx(&self.target).container {
+                Container::Big => Some(reloc64::SIZEOF_RELA),
+                Container::Little => Some(reloc32::SIZEOF_RELA),
+            },
+            BinaryFormat::Coff => Some(COFF_RELOCATION_SIZE),
+            _ => None,
+        };
+        let relocations_size = match sizeof_relocation {
+            Some(size) => (self.compute_relocation_count() * size).to_string(),
+            None => "-".to_string(),
+        };
+        writeln!(
+            w,
+            "{:>10} {:>10} {:>10} {:>10} {:>10} {:>10} filename",
+            "text", "data", "symbols", "imports", "links", "relocs"
+        )?;
+        writeln!(
+            w,
+            "{:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {}",
+            self.compute_text_size(),
+            self.compute_data_size(),
+            self.declarations.len(),
+            self.imports.len(),
+            self.links.len(),
+            relocations_size,
+            self.name
+        )
+    }
+
+    /// The sections of the object file emitted for the `Artifact`'s target, with their file
+    /// offsets, sizes and alignments, in the order they appear in the file.
+    ///
+    /// Only ELF and Mach-o are supported.
+    pub fn sections(&self) -> Result<Vec<SectionInfo>, ArtifactError> {
+        match self.target.binary_format {
+            BinaryFormat::Elf => elf::sections(self),
+            BinaryFormat::Macho => mach::sections(self),
+            _ => Err(ArtifactError::UnsupportedBinaryFormat(
+                self.target.binary_format.to_owned(),
+            )),
+        }
+    }
+
+    /// List the symbols of the object file emitted for the `Artifact`'s target in the format of
+    /// `nm`: a `<value> <type> <name>` line for every symbol, sorted by name, where the type is
+    /// `T`, `D`, `B` or `S` for symbols in text, data, bss or any other section, `A` for absolute,
+    /// `C` for common and `U` for undefined symbols, and lowercase for local symbols.
+    ///
+    /// Only Mach-o is supported.
+    pub fn to_nm_output(&self) -> Result<String, ArtifactError> {
+        match self.target.binary_format {
+            BinaryFormat::Macho => mach::nm(self),
+            _ => Err(ArtifactError::UnsupportedBinaryFormat(
+                self.target.binary_format.to_owned(),
+            )),
+        }
+    }
+
+    /// Write a linker map of where each defined symbol is placed in the object file emitted for
+    /// the `Artifact`'s target, in a format similar to GNU `ld`'s `--print-map`: every section
+    /// is listed with its address and size, followed by its symbols, sorted by address.
+    pub fn generate_mapfile(&self, w: &mut dyn Write) -> Result<(), ArtifactError> {
+        let mut sections = match self.target.binary_format {
+            BinaryFormat::Elf => elf::map(self)?,
+            BinaryFormat::Macho => mach::map(self)?,
+            BinaryFormat::Coff => coff::map(self),
+            _ => {
+                return Err(ArtifactError::UnsupportedBinaryFormat(
+                    self.target.binary_format.to_owned(),
+                ))
+            }
+        };
+        writeln!(w, "Memory map")?;
+        writeln!(w)?;
+        for section in sections.iter_mut() {
+            // like ld, names which do not fit in the first column go on a line of their own
+            if section.name.len() >= 15 {
+                writeln!(w, "{}", section.name)?;
+                write!(w, "{:16}", "")?;
+            } else {
+                write!(w, "{:<16}", section.name)?;
+            }
+            writeln!(
+                w,
+                "0x{:016x} {:>10} {}",
+                section.addr,
+                format!("{:#x}", section.size),
+                self.name
+            )?;
+            section.symbols.sort_by_key(|&(_, addr, _)| addr);
+            for (symbol, addr, size) in section.symbols.iter() {
+                writeln!(
+                    w,
+                    "{:16}0x{:016x} {:>10} {}",
+                    "",
+                    addr,
+                    format!("{:#x}", size),
+                    symbol
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Render the symbol dependency graph of this artifact as a GraphViz `digraph`, for debugging.
+    /// Every declared symbol is a node, colored blue for code, green for data and red for imports,
+    /// and every link is an edge labeled with the offset it is relocated at.
+    pub fn to_dot_graph(&self) -> String {
+        use std::fmt::Write;
+        fn quote(name: &str) -> String {
+            format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+        let mut dot = String::new();
+        writeln!(dot, "digraph {} {{", quote(&self.name)).unwrap();
+        for (&name, decl) in self.declarations.iter() {
+            let color = match decl.decl {
+                Decl::Import(_) => "red",
+                Decl::Defined(DefinedDecl::Function(_)) => "blue",
+                Decl::Defined(_) => "green",
+                Decl::AbsoluteSymbol(_) => "black",
+                Decl::Common { .. } => "gray",
+            };
+            let name = self.strings.resolve(name).expect("declaration has a name");
+            writeln!(dot, "    {} [color={}];", quote(name), color).unwrap();
+        }
+        for link in self.links() {
+            writeln!(
+                dot,
+                "    {} -> {} [label=\"{:#x}\"];",
+                quote(link.from.name),
+                quote(link.to.name),
+                link.at
+            )
+            .unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Emit a blob of bytes representing the object file in the format specified in the target the
+    /// `Artifact` was constructed with.
+    pub fn emit(&self) -> Result<Vec<u8>, ArtifactError> {
+        let backend = select_backend(self)?;
+        self.emit_with_backend(backend)
+    }
+
+    /// Emit a blob of bytes representing an object file in the given format.
+    pub fn emit_as(&self, format: BinaryFormat) -> Result<Vec<u8>, ArtifactError> {
+        self.emit_with_backend(Backend::for_format(format)?)
+    }
+
+    /// Check the object file the backend for `format` would emit for this artifact, without
+    /// writing it, returning every problem found, e.g., links to symbols which are missing from its
+    /// symbol table. Currently only supported by the Mach-o backend.
+    pub fn verify_with_backend(
+        &self,
+        format: BinaryFormat,
+    ) -> Result<Vec<VerificationError>, ArtifactError> {
+        match Backend::for_format(format)? {
+            Backend::Mach => mach::verify(&self.sealed()),
+            Backend::Elf | Backend::Coff | Backend::Wasm => {
+                Err(ArtifactError::UnsupportedBinaryFormat(format))
+            }
+        }
+    }
+
+    fn emit_with_backend(&self, backend: Backend) -> Result<Vec<u8>, ArtifactError> {
+        let undef = self.undefined_symbols();
+        if undef.is_empty() {
+            backend.to_bytes(&self.sealed())
+        } else {
+            Err(ArtifactError::UndefinedSymbols(undef))
+        }
+    }
+
+    /// Emit a blob of bytes representing an object file in the given `format`, or in the
+    /// platform-native format for the target the `Artifact` was constructed with if `None`.
+    pub fn emit_with_format(&self, format: Option<ObjectFormat>) -> Result<Vec<u8>, ArtifactError> {
+        match format.or_else(|| ObjectFormat::native(&self.target)) {
+            Some(format) => self.emit_as(format.into()),
+            None => Err(ArtifactError::UnsupportedBinaryFormat(
+                self.target.binary_format,
+            )),
+        }
+    }
+
+    /// Emit and write to disk a blob of bytes representing the object file in the format specified
+    /// in the target the `Artifact` was constructed with.
+    pub fn write(&self, sink: File) -> Result<(), ArtifactError> {
+        self.write_as(sink, self.target.binary_format)
+    }
+
+    /// Emit and write to disk a blob of bytes representing an object file in the given format.
+    pub fn write_as(&self, sink: File, format: BinaryFormat) -> Result<(), ArtifactError> {
+        self.write_incremental(sink, format)
+    }
+
+    /// Write an object file in the given format directly to `sink`, without first emitting it
+    /// into a buffer, so that the object's bytes are never held in memory alongside the
+    /// artifact's definitions; ELF and Mach-o objects are streamed section by section. The object
+    /// is written at the start of `sink`, since the offsets in its headers are absolute.
+    pub fn write_incremental<W: Write + Seek>(
+        &self,
+        sink: W,
+        format: BinaryFormat,
+    ) -> Result<(), ArtifactError> {
+        let backend = Backend::for_format(format)?;
+        let undef = self.undefined_symbols();
+        if undef.is_empty() {
+            backend.write(&self.sealed(), sink)
+        } else {
+            Err(ArtifactError::UndefinedSymbols(undef))
+        }
+    }
+
+    /// Write the object file in the format specified in the target the `Artifact` was constructed
+    /// with to `path`. With the `mmap` feature on unix, Mach-o objects are written into a memory
+    /// mapping of the file, which is created with its final size up front; this is faster than
+    /// the buffered writes of `write` for large objects. Otherwise, this is the same as `write`.
+    pub fn write_mmap<P: AsRef<Path>>(&self, path: P) -> Result<(), ArtifactError> {
+        let backend = select_backend(self)?;
+        let undef = self.undefined_symbols();
+        if !undef.is_empty() {
+            return Err(ArtifactError::UndefinedSymbols(undef));
+        }
+        let artifact = self.sealed();
+        match backend {
+            Backend::Mach => mach::write_mmap(&artifact, path.as_ref()),
+            backend => backend.write(&artifact, File::create(path)?),
+        }
+    }
+
+    /// Emit the object file in the format specified in the target the `Artifact` was constructed
+    /// with, and durably write it to `path`, such that readers never observe a partially written
+    /// file, even if the process crashes.
+    ///
+    /// The object is written to a temporary file in the same directory, which is synced to disk and
+    /// then atomically renamed to `path`, replacing any existing file.
+    pub fn emit_sync<P: AsRef<Path>>(&self, path: P) -> Result<(), ArtifactError> {
+        // distinguishes the temporary files of concurrent writers within this process
+        static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = path.as_ref();
+        let bytes = self.emit()?;
+        let file_name = path.file_name().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} does not name a file", path.display()),
+            )
+        })?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let tmp_path = dir.join(format!(
+            ".{}.{}.{}.tmp",
+            file_name.to_string_lossy(),
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let write_tmp = || -> std::io::Result<()> {
+            let mut tmp = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&tmp_path)?;
+            tmp.write_all(&bytes)?;
+            tmp.sync_all()?;
+            fs::rename(&tmp_path, path)
+        };
+        if let Err(e) = write_tmp() {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e.into());
+        }
+        // the rename itself is only durable once the directory is synced
+        #[cfg(unix)]
+        File::open(dir)?.sync_all()?;
         Ok(())
     }
 }
@@ -2,20 +2,40 @@ use crate::artifact::ArtifactError;
 
 /// The kind of declaration this is
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Decl {
     /// Declaration of an import
     Import(ImportKind),
     /// Declaration of an item to be defined in this artifact
     Defined(DefinedDecl),
+    /// Declaration of a global symbol with a fixed absolute address, e.g., as exported by a linker
+    /// script; it has no data, and so needs no definition
+    AbsoluteSymbol(u64),
+    /// Declaration of a common symbol, i.e., a FORTRAN common block or a C tentative definition,
+    /// of `size` bytes aligned to `align`; the linker merges every common symbol of the same name
+    /// into a single zero initialized allocation, and so it needs no definition
+    Common {
+        /// The size of the allocation in bytes
+        size: u64,
+        /// The alignment of the allocation in bytes
+        align: u64,
+    },
 }
 
 /// The kind of import this is - either a function, or a copy relocation of data from a shared library
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ImportKind {
     /// A function
     Function,
     /// An imported piece of data
     Data,
+    /// A function which may be missing at link and load time, in which case references to it
+    /// resolve to zero, i.e., `extern_weak` linkage
+    WeakFunction,
+    /// A piece of data which may be missing at link and load time, in which case references to it
+    /// resolve to zero, i.e., `extern_weak` linkage
+    WeakData,
 }
 
 impl ImportKind {
@@ -26,9 +46,24 @@ impl ImportKind {
             _ => None,
         }
     }
+    /// Is this a (possibly weak) function import?
+    pub fn is_function(&self) -> bool {
+        match *self {
+            ImportKind::Function | ImportKind::WeakFunction => true,
+            ImportKind::Data | ImportKind::WeakData => false,
+        }
+    }
+    /// Is this a weak import, i.e., allowed to be missing at link and load time?
+    pub fn is_weak(&self) -> bool {
+        match *self {
+            ImportKind::WeakFunction | ImportKind::WeakData => true,
+            ImportKind::Function | ImportKind::Data => false,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Linker binding scope of a definition
 pub enum Scope {
     /// Available to all components
@@ -72,9 +107,14 @@ macro_rules! scope_methods {
     pub fn is_global(&self) -> bool {
         self.scope == Scope::Global
     }
+    /// Check if scope is `Scope::Weak`.
+    pub fn is_weak(&self) -> bool {
+        self.scope == Scope::Weak
+    }
 }}
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Linker visibility of a definition
 pub enum Visibility {
     /// Visibility determined by the symbol's `Scope`.
@@ -85,6 +125,25 @@ pub enum Visibility {
     Protected,
     /// Not visible to other components, plus the constraints provided by `Protected`.
     Hidden,
+    /// Like `Hidden`, and in addition the symbol is never called from, nor its address taken by,
+    /// another component, which lets the compiler and linker optimize more aggressively.
+    Internal,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Symbol metadata which is common to all object file formats
+pub struct DeclAttributes {
+    /// The linker visibility of the symbol
+    pub visibility: Visibility,
+}
+
+impl Default for DeclAttributes {
+    fn default() -> Self {
+        DeclAttributes {
+            visibility: Visibility::Default,
+        }
+    }
 }
 
 macro_rules! visibility_methods {
@@ -101,28 +160,64 @@ macro_rules! visibility_methods {
     pub fn hidden(self) -> Self {
         self.with_visibility(Visibility::Hidden)
     }
+    /// Set visibility to internal
+    pub fn internal(self) -> Self {
+        self.with_visibility(Visibility::Internal)
+    }
     /// Builder for visibility
     pub fn with_visibility(mut self, visibility: Visibility) -> Self {
-        self.visibility =visibility;
+        self.attributes.visibility = visibility;
         self
     }
     /// Get visibility
     pub fn get_visibility(&self) -> Visibility {
-        self.visibility
+        self.attributes.visibility
     }
     /// Set visibility
     pub fn set_visibility(&mut self, visibility: Visibility) {
-        self.visibility = visibility;
+        self.attributes.visibility = visibility;
+    }
+    /// Get the cross-format symbol attributes
+    pub fn get_attributes(&self) -> DeclAttributes {
+        self.attributes
     }
 }}
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Type of data declared
 pub enum DataType {
     /// Ordinary raw bytes
     Bytes,
     /// 0-terminated C-style string.
     String,
+    /// 0-terminated Objective-C method name, referenced by selector references
+    ObjcMethodName,
+    /// A pointer to an Objective-C method name, i.e., a selector reference
+    ObjcSelectorRef,
+    /// A pointer to another symbol, in an explicitly reserved Global Offset Table slot
+    GotEntry,
+    /// A thread-local variable; its data is the initial value of every thread's copy
+    ThreadLocal,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// How code accesses a thread-local variable, which selects the relocations used in ELF; Mach-o
+/// always goes through the variable's descriptor. More models, e.g., local-exec, may be added.
+#[non_exhaustive]
+pub enum TlsModel {
+    /// The variable may be defined in any module, even one loaded with `dlopen`, and is looked up
+    /// with `__tls_get_addr`
+    GeneralDynamic,
+    /// The variable is defined in a module which is loaded at startup, so its offset from the
+    /// thread pointer is loaded from the GOT
+    InitialExec,
+    /// The variable is defined in the same module as the code accessing it, so only the module's
+    /// thread-local block is looked up, which the variable is at a constant offset into. On
+    /// x86_64, links to the variable are relocated with its offset into the block, and the lookup
+    /// of the module's block is linked with `Reloc::Kind(RelocationKind::GotPcRelative)`
+    LocalDynamic,
 }
 
 macro_rules! datatype_methods {
@@ -166,6 +261,7 @@ macro_rules! align_methods {
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A declaration that is defined inside this artifact
 pub enum DefinedDecl {
     /// A function defined in this artifact
@@ -210,6 +306,24 @@ impl DefinedDecl {
         }
     }
 
+    /// Accessor to determine whether scope is weak
+    pub fn is_weak(&self) -> bool {
+        match self {
+            DefinedDecl::Function(a) => a.is_weak(),
+            DefinedDecl::Data(a) => a.is_weak(),
+            DefinedDecl::Section(_) => false,
+        }
+    }
+
+    /// Accessor for the linker visibility; sections always have the default visibility
+    pub fn get_visibility(&self) -> Visibility {
+        match self {
+            DefinedDecl::Function(a) => a.get_visibility(),
+            DefinedDecl::Data(a) => a.get_visibility(),
+            DefinedDecl::Section(_) => Visibility::Default,
+        }
+    }
+
     /// Accessor to determine whether contents are writable
     pub fn is_writable(&self) -> bool {
         match self {
@@ -238,6 +352,14 @@ impl Decl {
     pub fn data_import() -> DataImportDecl {
         DataImportDecl::default()
     }
+    /// An import of a function which is allowed to be missing, in which case it resolves to zero
+    pub fn weak_function_import() -> FunctionImportDecl {
+        FunctionImportDecl::default().weak()
+    }
+    /// A GOT-based import of data which is allowed to be missing, in which case it resolves to zero
+    pub fn weak_data_import() -> DataImportDecl {
+        DataImportDecl::default().weak()
+    }
     /// A function defined in this artifact
     pub fn function() -> FunctionDecl {
         FunctionDecl::default()
@@ -246,14 +368,57 @@ impl Decl {
     pub fn data() -> DataDecl {
         DataDecl::default()
     }
-    /// A null-terminated string object defined in this artifact
+    /// A function defined in this artifact, which may be overridden by a global definition of the
+    /// same name, e.g., a C++ inline function
+    pub fn weak_function() -> FunctionDecl {
+        FunctionDecl::default().weak()
+    }
+    /// A data object defined in this artifact, which may be overridden by a global definition of
+    /// the same name, e.g., a C++ template's static member
+    pub fn weak_data() -> DataDecl {
+        DataDecl::default().weak()
+    }
+    /// A null-terminated string object defined in this artifact. Identical local strings are
+    /// merged when the artifact is sealed, so they may share an address.
     pub fn cstring() -> DataDecl {
         DataDecl::default().with_datatype(DataType::String)
     }
+    /// A null-terminated Objective-C method name defined in this artifact
+    pub fn objc_method_name() -> DataDecl {
+        DataDecl::default().with_datatype(DataType::ObjcMethodName)
+    }
+    /// An Objective-C selector reference defined in this artifact; it should be defined as a
+    /// pointer, and linked to the `objc_method_name` it refers to
+    pub fn objc_selector_ref() -> DataDecl {
+        DataDecl::default()
+            .with_datatype(DataType::ObjcSelectorRef)
+            .writable()
+    }
+    /// An explicitly reserved Global Offset Table slot defined in this artifact; it should be
+    /// defined as a pointer, and linked to the symbol it refers to
+    pub fn got_entry() -> DataDecl {
+        DataDecl::default()
+            .with_datatype(DataType::GotEntry)
+            .writable()
+            .with_align(Some(8))
+    }
+    /// A thread-local variable defined in this artifact; every thread has its own copy, initialized
+    /// to its definition. If it is defined with `define_zero_init`, it is placed in `.tbss` in
+    /// ELF and `__thread_bss` in Mach-o, and otherwise in `.tdata` and `__thread_data`.
+    pub fn tls_data() -> DataDecl {
+        DataDecl::default()
+            .with_datatype(DataType::ThreadLocal)
+            .writable()
+    }
     /// A section defined in this artifact
     pub fn section(kind: SectionKind) -> SectionDecl {
         SectionDecl::new(kind)
     }
+    /// A common symbol of `size` bytes aligned to `align`, which is merged with every other common
+    /// symbol of the same name at link time
+    pub fn common(size: u64, align: u64) -> Self {
+        Decl::Common { size, align }
+    }
 
     /// If it is compatible, absorb the new declaration (`other`) into the old (`self`); otherwise returns an error.
     ///
@@ -265,20 +430,30 @@ impl Decl {
     ///    declaration, it is said to be "upgraded", and forever after is considered a declaration in need of a definition.
     /// 3. **If** the previous declaration was a `Function` or `Data` declaration,
     ///    **then** a subsequent corresponding `FunctionImport` or `DataImport` is a no-op.
-    /// 4. Anything else is a [IncompatibleDeclaration](enum.ArtifactError.html#variant.IncompatibleDeclaration) error!
+    /// 4. Weak and non-weak imports of the same kind are compatible; if any of them is non-weak,
+    ///    the import is non-weak.
+    /// 5. Common symbols behave like data declarations, and several common declarations of the
+    ///    same name are merged into the largest size and alignment, like the linker does.
+    /// 6. Anything else is a [IncompatibleDeclaration](enum.ArtifactError.html#variant.IncompatibleDeclaration) error!
     // ref https://github.com/m4b/faerie/issues/24
     // ref https://github.com/m4b/faerie/issues/18
     pub fn absorb(&mut self, other: Self) -> Result<(), ArtifactError> {
         // FIXME: i can't think of a way offhand to not clone here, without unusual contortions
         match self.clone() {
-            Decl::Import(ImportKind::Data) => {
+            Decl::Import(kind) if !kind.is_function() => {
                 match other {
                     // data imports can be upgraded to any kind of data declaration
-                    Decl::Defined(DefinedDecl::Data { .. }) => {
+                    Decl::Defined(DefinedDecl::Data { .. }) | Decl::Common { .. } => {
                         *self = other;
                         Ok(())
                     }
-                    Decl::Import(ImportKind::Data) => Ok(()),
+                    // a non-weak reference makes the import non-weak
+                    Decl::Import(other_kind) if !other_kind.is_function() => {
+                        if !other_kind.is_weak() {
+                            *self = other;
+                        }
+                        Ok(())
+                    }
                     _ => Err(ArtifactError::IncompatibleDeclaration {
                         old: *self,
                         new: other,
@@ -286,14 +461,19 @@ impl Decl {
                     .into()),
                 }
             }
-            Decl::Import(ImportKind::Function) => {
+            Decl::Import(kind) if kind.is_function() => {
                 match other {
                     // function imports can be upgraded to any kind of function declaration
                     Decl::Defined(DefinedDecl::Function { .. }) => {
                         *self = other;
                         Ok(())
                     }
-                    Decl::Import(ImportKind::Function) => Ok(()),
+                    Decl::Import(other_kind) if other_kind.is_function() => {
+                        if !other_kind.is_weak() {
+                            *self = other;
+                        }
+                        Ok(())
+                    }
                     _ => Err(ArtifactError::IncompatibleDeclaration {
                         old: *self,
                         new: other,
@@ -304,7 +484,7 @@ impl Decl {
             // a previous data declaration can only be re-declared a data import, or it must match exactly the
             // next declaration
             decl @ Decl::Defined(DefinedDecl::Data { .. }) => match other {
-                Decl::Import(ImportKind::Data) => Ok(()),
+                Decl::Import(kind) if !kind.is_function() => Ok(()),
                 other => {
                     if decl == other {
                         Ok(())
@@ -317,10 +497,28 @@ impl Decl {
                     }
                 }
             },
+            // common symbols merge like the linker merges them
+            Decl::Common { size, align } => match other {
+                Decl::Import(kind) if !kind.is_function() => Ok(()),
+                Decl::Common {
+                    size: other_size,
+                    align: other_align,
+                } => {
+                    *self = Decl::Common {
+                        size: size.max(other_size),
+                        align: align.max(other_align),
+                    };
+                    Ok(())
+                }
+                other => Err(ArtifactError::IncompatibleDeclaration {
+                    old: *self,
+                    new: other,
+                }),
+            },
             // a previous function decl can only be re-declared a function import, or it must match exactly
             // the next declaration
             decl @ Decl::Defined(DefinedDecl::Function { .. }) => match other {
-                Decl::Import(ImportKind::Function) => Ok(()),
+                Decl::Import(kind) if kind.is_function() => Ok(()),
                 other => {
                     if decl == other {
                         Ok(())
@@ -353,6 +551,14 @@ impl Decl {
             _ => false,
         }
     }
+    /// Is this a symbol with a fixed absolute address?
+    pub fn is_absolute(&self) -> bool {
+        matches!(*self, Decl::AbsoluteSymbol(_))
+    }
+    /// Is this a common symbol?
+    pub fn is_common(&self) -> bool {
+        matches!(*self, Decl::Common { .. })
+    }
     /// Is this a section?
     pub fn is_section(&self) -> bool {
         match *self {
@@ -363,51 +569,104 @@ impl Decl {
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Builder for function import declarations
-pub struct FunctionImportDecl {}
+pub struct FunctionImportDecl {
+    weak: bool,
+}
 
 impl Default for FunctionImportDecl {
     fn default() -> Self {
-        FunctionImportDecl {}
+        FunctionImportDecl { weak: false }
+    }
+}
+
+impl FunctionImportDecl {
+    /// Allow the function to be missing at link and load time, in which case it resolves to zero
+    pub fn weak(mut self) -> Self {
+        self.weak = true;
+        self
+    }
+    /// Is this a weak import?
+    pub fn is_weak(&self) -> bool {
+        self.weak
     }
 }
 
 impl Into<Decl> for FunctionImportDecl {
     fn into(self) -> Decl {
-        Decl::Import(ImportKind::Function)
+        if self.weak {
+            Decl::Import(ImportKind::WeakFunction)
+        } else {
+            Decl::Import(ImportKind::Function)
+        }
     }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Builder for data import declarations
-pub struct DataImportDecl {}
+pub struct DataImportDecl {
+    weak: bool,
+}
 
 impl Default for DataImportDecl {
     fn default() -> Self {
-        DataImportDecl {}
+        DataImportDecl { weak: false }
+    }
+}
+
+impl DataImportDecl {
+    /// Allow the data to be missing at link and load time, in which case it resolves to zero
+    pub fn weak(mut self) -> Self {
+        self.weak = true;
+        self
+    }
+    /// Is this a weak import?
+    pub fn is_weak(&self) -> bool {
+        self.weak
     }
 }
 
 impl Into<Decl> for DataImportDecl {
     fn into(self) -> Decl {
-        Decl::Import(ImportKind::Data)
+        if self.weak {
+            Decl::Import(ImportKind::WeakData)
+        } else {
+            Decl::Import(ImportKind::Data)
+        }
     }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The assembly syntax a function was written in; an advisory hint for disassemblers
+pub enum AsmSyntax {
+    /// Intel syntax, i.e., `.intel_syntax`
+    Intel,
+    /// AT&T syntax, i.e., `.att_syntax`
+    Att,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Builder for function declarations
 pub struct FunctionDecl {
     scope: Scope,
-    visibility: Visibility,
+    attributes: DeclAttributes,
     align: Option<u64>,
+    syntax: Option<AsmSyntax>,
+    comdat: bool,
 }
 
 impl Default for FunctionDecl {
     fn default() -> Self {
         FunctionDecl {
             scope: Scope::Local,
-            visibility: Visibility::Default,
+            attributes: DeclAttributes::default(),
             align: None,
+            syntax: None,
+            comdat: false,
         }
     }
 }
@@ -416,6 +675,49 @@ impl FunctionDecl {
     scope_methods!();
     visibility_methods!();
     align_methods!();
+
+    /// Builder for the assembly syntax hint
+    pub fn with_syntax(mut self, syntax: Option<AsmSyntax>) -> Self {
+        self.syntax = syntax;
+        self
+    }
+    /// Hint that this function was written in Intel syntax
+    pub fn intel_syntax(self) -> Self {
+        self.with_syntax(Some(AsmSyntax::Intel))
+    }
+    /// Hint that this function was written in AT&T syntax
+    pub fn att_syntax(self) -> Self {
+        self.with_syntax(Some(AsmSyntax::Att))
+    }
+    /// Setter for the assembly syntax hint
+    pub fn set_syntax(&mut self, syntax: Option<AsmSyntax>) {
+        self.syntax = syntax;
+    }
+    /// Accessor for the assembly syntax hint
+    pub fn get_syntax(&self) -> Option<AsmSyntax> {
+        self.syntax
+    }
+    /// Builder for COMDAT. If set, the linker keeps only one copy of this function, even if
+    /// several object files define it, e.g., a C++ template instantiation; in ELF, its section is
+    /// placed in a COMDAT group, whose signature is this function's name. Mach-o has no groups,
+    /// so there it is emitted as a weak definition, which the linker coalesces likewise. The
+    /// function must not be local, since other objects could not find the copy it stands for.
+    pub fn with_comdat(mut self, comdat: bool) -> Self {
+        self.comdat = comdat;
+        self
+    }
+    /// Keep only one copy of this function at link time
+    pub fn comdat(self) -> Self {
+        self.with_comdat(true)
+    }
+    /// Setter for COMDAT
+    pub fn set_comdat(&mut self, comdat: bool) {
+        self.comdat = comdat;
+    }
+    /// Accessor for COMDAT
+    pub fn is_comdat(&self) -> bool {
+        self.comdat
+    }
 }
 
 impl Into<Decl> for FunctionDecl {
@@ -425,23 +727,28 @@ impl Into<Decl> for FunctionDecl {
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Builder for data declarations
 pub struct DataDecl {
     scope: Scope,
-    visibility: Visibility,
+    attributes: DeclAttributes,
     writable: bool,
     datatype: DataType,
     align: Option<u64>,
+    merge_strings: bool,
+    tls_model: TlsModel,
 }
 
 impl Default for DataDecl {
     fn default() -> Self {
         DataDecl {
             scope: Scope::Local,
-            visibility: Visibility::Default,
+            attributes: DeclAttributes::default(),
             writable: false,
             datatype: DataType::Bytes,
             align: None,
+            merge_strings: false,
+            tls_model: TlsModel::GeneralDynamic,
         }
     }
 }
@@ -473,6 +780,42 @@ impl DataDecl {
     pub fn is_writable(&self) -> bool {
         self.writable
     }
+    /// Builder for string merging. If set on a read-only string, the linker may merge it with
+    /// identical strings, including those of other object files; in ELF, it is placed in a
+    /// `.rodata.str1.1` section. Mach-o always merges the strings in its `__cstring` section.
+    pub fn with_merge_strings(mut self, merge_strings: bool) -> Self {
+        self.merge_strings = merge_strings;
+        self
+    }
+    /// Allow the linker to merge this string with identical strings
+    pub fn merge_strings(self) -> Self {
+        self.with_merge_strings(true)
+    }
+    /// Setter for string merging
+    pub fn set_merge_strings(&mut self, merge_strings: bool) {
+        self.merge_strings = merge_strings;
+    }
+    /// Accessor for string merging
+    pub fn is_merge_strings(&self) -> bool {
+        self.merge_strings
+    }
+    /// Accessor to determine whether this is a thread-local variable
+    pub fn is_thread_local(&self) -> bool {
+        self.datatype == DataType::ThreadLocal
+    }
+    /// Builder for the model code uses to access this thread-local variable
+    pub fn with_tls_model(mut self, tls_model: TlsModel) -> Self {
+        self.tls_model = tls_model;
+        self
+    }
+    /// Setter for the thread-local access model
+    pub fn set_tls_model(&mut self, tls_model: TlsModel) {
+        self.tls_model = tls_model;
+    }
+    /// Accessor for the thread-local access model
+    pub fn get_tls_model(&self) -> TlsModel {
+        self.tls_model
+    }
 }
 
 impl Into<Decl> for DataDecl {
@@ -482,6 +825,7 @@ impl Into<Decl> for DataDecl {
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// The kind of this section
 pub enum SectionKind {
     /// Mutable data
@@ -495,11 +839,13 @@ pub enum SectionKind {
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Builder for a section declaration
 pub struct SectionDecl {
     kind: SectionKind,
     datatype: DataType,
     align: Option<u64>,
+    address_space: Option<u8>,
 }
 
 impl SectionDecl {
@@ -512,9 +858,37 @@ impl SectionDecl {
             kind,
             datatype: DataType::Bytes,
             align: None,
+            address_space: None,
         }
     }
 
+    /// Place this section in the address space `space`, for segmented architectures like MSP430.
+    /// ELF only has room for 16 address spaces, in the processor specific section flags, so
+    /// larger ones are an error. Those flags mean something else on most other architectures,
+    /// e.g., `SHF_X86_64_LARGE` or `SHF_ARM_PURECODE`, so ELF objects only support address
+    /// spaces for MSP430.
+    pub fn with_address_space(mut self, space: u8) -> Result<Self, ArtifactError> {
+        self.set_address_space(Some(space))?;
+        Ok(self)
+    }
+    /// Set the address space, see `with_address_space`
+    pub fn set_address_space(&mut self, space: Option<u8>) -> Result<(), ArtifactError> {
+        if let Some(space) = space {
+            if space >= 16 {
+                return Err(ArtifactError::InvalidAddressSpace(format!(
+                    "{} does not fit in the processor specific section flags",
+                    space
+                )));
+            }
+        }
+        self.address_space = space;
+        Ok(())
+    }
+    /// Get the address space, if there is one
+    pub fn get_address_space(&self) -> Option<u8> {
+        self.address_space
+    }
+
     /// Sections are never global, but we have an accessor
     /// for symmetry with other section declarations
     pub fn is_global(&self) -> bool {
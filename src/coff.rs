@@ -0,0 +1,773 @@
+//! The COFF backend for transforming an artifact to a valid, COFF object file, as used by Windows.
+//!
+//! Definitions are placed into the usual `.text`, `.data`, `.rdata`, `.bss` and `.tls$` sections,
+//! except for weak and COMDAT definitions, which get a COMDAT section of their own, so that the
+//! linker can discard all but one of them. Weak imports are emitted as regular imports.
+
+use crate::artifact::{
+    self, Artifact, ArtifactError, Data, Decl, DefinedDecl, ImportKind, LinkAndDecl, Reloc,
+    RelocationKind, SectionKind,
+};
+
+use goblin::pe::header::{CoffHeader, COFF_MACHINE_X86, COFF_MACHINE_X86_64, SIZEOF_COFF_HEADER};
+use goblin::pe::relocation::{self as reloc, Relocation, COFF_RELOCATION_SIZE};
+use goblin::pe::section_table::*;
+use goblin::pe::symbol::{
+    AuxSectionDefinition, Symbol, COFF_SYMBOL_SIZE, IMAGE_COMDAT_SELECT_ANY, IMAGE_SYM_ABSOLUTE,
+    IMAGE_SYM_CLASS_EXTERNAL, IMAGE_SYM_CLASS_STATIC, IMAGE_SYM_DTYPE_FUNCTION,
+    IMAGE_SYM_DTYPE_SHIFT, IMAGE_SYM_UNDEFINED,
+};
+use indexmap::IndexMap;
+use scroll::{Pwrite, LE};
+use std::convert::TryFrom;
+use target_lexicon::Architecture;
+
+/// The machine type of ARM64 objects, which goblin lacks
+const COFF_MACHINE_ARM64: u16 = 0xaa64;
+
+// goblin lacks the ARM64 relocations
+const IMAGE_REL_ARM64_ADDR32: u16 = 0x0001;
+const IMAGE_REL_ARM64_BRANCH26: u16 = 0x0003;
+const IMAGE_REL_ARM64_PAGEBASE_REL21: u16 = 0x0004;
+const IMAGE_REL_ARM64_PAGEOFFSET_12A: u16 = 0x0006;
+const IMAGE_REL_ARM64_SECREL: u16 = 0x0008;
+const IMAGE_REL_ARM64_ADDR64: u16 = 0x000e;
+
+/// The type of function symbols
+const FUNCTION_TYPE: u16 = IMAGE_SYM_DTYPE_FUNCTION << IMAGE_SYM_DTYPE_SHIFT;
+/// The size of the section table entry of a section
+const SIZEOF_SECTION_HEADER: usize = SIZEOF_SECTION_TABLE;
+
+struct MachineTag(u16);
+
+impl TryFrom<Architecture> for MachineTag {
+    type Error = ArtifactError;
+    fn try_from(architecture: Architecture) -> Result<MachineTag, ArtifactError> {
+        use target_lexicon::Architecture::*;
+        Ok(MachineTag(match architecture {
+            X86_64 => COFF_MACHINE_X86_64,
+            I386 | I586 | I686 => COFF_MACHINE_X86,
+            Aarch64(_) => COFF_MACHINE_ARM64,
+            _ => {
+                return Err(ArtifactError::Unsupported(format!(
+                    "{} COFF objects",
+                    architecture
+                )))
+            }
+        }))
+    }
+}
+
+/// A section of the object file
+struct Section {
+    name: String,
+    characteristics: u32,
+    /// The contents of this section, empty if it is uninitialized
+    data: Vec<u8>,
+    /// The size of this section in memory
+    size: u64,
+    /// The alignment of this section, i.e., the largest alignment of a definition in it
+    align: u64,
+    relocations: Vec<Relocation>,
+    /// How the linker selects one of the sections with the same COMDAT symbol, if this is a COMDAT
+    /// section
+    comdat_selection: Option<u8>,
+}
+
+impl Section {
+    fn new(name: &str, characteristics: u32) -> Self {
+        Section {
+            name: name.to_string(),
+            characteristics,
+            data: Vec::new(),
+            size: 0,
+            align: 1,
+            relocations: Vec::new(),
+            comdat_selection: None,
+        }
+    }
+    fn is_uninitialized(&self) -> bool {
+        self.characteristics & IMAGE_SCN_CNT_UNINITIALIZED_DATA != 0
+    }
+    /// Append `data` aligned to `align`, and return its offset in this section
+    fn append(&mut self, data: &Data, align: u64) -> u64 {
+        self.align = self.align.max(align);
+        let offset = self.size + (align - self.size % align) % align;
+        self.size = offset + data.memory_size() as u64;
+        if !self.is_uninitialized() {
+            self.data.resize(offset as usize, 0);
+            match data {
+                Data::Blob(bytes) => self.data.extend_from_slice(bytes),
+                Data::ZeroInit(size) => self.data.resize(self.data.len() + size, 0),
+            }
+        }
+        offset
+    }
+    /// The alignment flag of this section, up to the maximum COFF supports
+    fn align_characteristics(&self) -> u32 {
+        let exponent = u64::from(self.align.min(8192).trailing_zeros());
+        ((exponent as u32) + 1) << 20
+    }
+}
+
+/// Where a definition is, and how large it is
+struct DefinitionInfo {
+    section: usize,
+    offset: u64,
+    size: u64,
+}
+
+/// A symbol, before its name is placed
+struct SymbolInfo {
+    /// The one-based section number, or `IMAGE_SYM_UNDEFINED` or `IMAGE_SYM_ABSOLUTE`
+    section_number: i16,
+    value: u32,
+    typ: u16,
+    storage_class: u8,
+}
+
+/// The names of the sections definitions are appended to, unless they need their own
+const TEXT: &str = ".text";
+const DATA: &str = ".data";
+const RDATA: &str = ".rdata";
+const BSS: &str = ".bss";
+const TLS: &str = ".tls$";
+
+/// The characteristics of the section named `name`
+fn characteristics(name: &str) -> u32 {
+    match name {
+        TEXT => IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        DATA | TLS => IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE,
+        RDATA => IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+        BSS => IMAGE_SCN_CNT_UNINITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE,
+        _ => unreachable!("{} has no default characteristics", name),
+    }
+}
+
+struct Coff<'a> {
+    architecture: Architecture,
+    sections: Vec<Section>,
+    /// The sections which definitions are appended to, by name
+    shared_sections: IndexMap<&'static str, usize>,
+    /// The sections of custom section declarations
+    section_decls: IndexMap<&'a str, usize>,
+    definitions: IndexMap<&'a str, DefinitionInfo>,
+    /// The symbols, after the section symbols, by their unmangled names
+    symbols: IndexMap<String, SymbolInfo>,
+}
+
+impl<'a> Coff<'a> {
+    fn new(artifact: &'a Artifact) -> Self {
+        let mut coff = Coff {
+            architecture: artifact.target.architecture,
+            sections: Vec::new(),
+            shared_sections: IndexMap::new(),
+            section_decls: IndexMap::new(),
+            definitions: IndexMap::new(),
+            symbols: IndexMap::new(),
+        };
+        // like other compilers, always emit these, even if they are empty
+        for name in &[TEXT, DATA, BSS] {
+            coff.shared_section(name);
+        }
+        coff
+    }
+    /// The index of the shared section `name`, which is added if it doesn't exist yet
+    fn shared_section(&mut self, name: &'static str) -> usize {
+        let sections = &mut self.sections;
+        *self.shared_sections.entry(name).or_insert_with(|| {
+            sections.push(Section::new(name, characteristics(name)));
+            sections.len() - 1
+        })
+    }
+    /// Add a COMDAT section named `name`, which only contains one definition
+    fn comdat_section(&mut self, name: &'static str) -> usize {
+        let mut section = Section::new(name, characteristics(name) | IMAGE_SCN_LNK_COMDAT);
+        section.comdat_selection = Some(IMAGE_COMDAT_SELECT_ANY);
+        self.sections.push(section);
+        self.sections.len() - 1
+    }
+    fn add_definition(&mut self, def: artifact::Definition<'a>) {
+        let is_comdat = match def.decl {
+            DefinedDecl::Function(d) => d.is_comdat() || d.is_weak(),
+            DefinedDecl::Data(d) => d.is_weak(),
+            DefinedDecl::Section(_) => false,
+        };
+        let section = match def.decl {
+            DefinedDecl::Function(_) => TEXT,
+            DefinedDecl::Data(d) if d.is_thread_local() => TLS,
+            DefinedDecl::Data(_) if def.data.is_zero_init() => BSS,
+            DefinedDecl::Data(d) if d.is_writable() => DATA,
+            DefinedDecl::Data(_) => RDATA,
+            DefinedDecl::Section(s) => {
+                let characteristics = match s.kind() {
+                    SectionKind::Text => {
+                        IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ
+                    }
+                    SectionKind::Data => {
+                        IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE
+                    }
+                    SectionKind::Debug => {
+                        IMAGE_SCN_CNT_INITIALIZED_DATA
+                            | IMAGE_SCN_MEM_READ
+                            | IMAGE_SCN_MEM_DISCARDABLE
+                    }
+                };
+                let mut section = Section::new(def.name, characteristics);
+                section.append(def.data, s.get_align().unwrap_or(1));
+                self.sections.push(section);
+                let idx = self.sections.len() - 1;
+                self.section_decls.insert(def.name, idx);
+                self.add_definition_info(&def, idx, 0);
+                for (symbol, offset) in def.symbols {
+                    self.symbols.insert(
+                        symbol.clone(),
+                        SymbolInfo {
+                            section_number: (idx + 1) as i16,
+                            value: *offset as u32,
+                            typ: 0,
+                            storage_class: IMAGE_SYM_CLASS_STATIC,
+                        },
+                    );
+                }
+                return;
+            }
+        };
+        let idx = if is_comdat {
+            self.comdat_section(section)
+        } else {
+            self.shared_section(section)
+        };
+        let offset = self.sections[idx].append(def.data, def.decl.get_align().unwrap_or(1));
+        self.add_definition_info(&def, idx, offset);
+        let typ = match def.decl {
+            DefinedDecl::Function(_) => FUNCTION_TYPE,
+            _ => 0,
+        };
+        let storage_class = if def.decl.is_global() || def.decl.is_weak() {
+            IMAGE_SYM_CLASS_EXTERNAL
+        } else {
+            IMAGE_SYM_CLASS_STATIC
+        };
+        self.symbols.insert(
+            def.name.to_string(),
+            SymbolInfo {
+                section_number: (idx + 1) as i16,
+                value: offset as u32,
+                typ,
+                storage_class,
+            },
+        );
+    }
+    fn add_definition_info(&mut self, def: &artifact::Definition<'a>, section: usize, offset: u64) {
+        self.definitions.insert(
+            def.name,
+            DefinitionInfo {
+                section,
+                offset,
+                size: def.data.memory_size() as u64,
+            },
+        );
+    }
+    fn import(&mut self, name: &str, kind: &ImportKind) {
+        let typ = match kind {
+            ImportKind::Function | ImportKind::WeakFunction => FUNCTION_TYPE,
+            ImportKind::Data | ImportKind::WeakData => 0,
+        };
+        self.symbols.insert(
+            name.to_string(),
+            SymbolInfo {
+                section_number: IMAGE_SYM_UNDEFINED,
+                value: 0,
+                typ,
+                storage_class: IMAGE_SYM_CLASS_EXTERNAL,
+            },
+        );
+    }
+    fn absolute(&mut self, name: &str, address: u64) {
+        self.symbols.insert(
+            name.to_string(),
+            SymbolInfo {
+                section_number: IMAGE_SYM_ABSOLUTE,
+                value: address as u32,
+                typ: 0,
+                storage_class: IMAGE_SYM_CLASS_EXTERNAL,
+            },
+        );
+    }
+    /// A common symbol is an undefined external symbol whose value is its size
+    fn common(&mut self, name: &str, size: u64) {
+        self.symbols.insert(
+            name.to_string(),
+            SymbolInfo {
+                section_number: IMAGE_SYM_UNDEFINED,
+                value: size as u32,
+                typ: 0,
+                storage_class: IMAGE_SYM_CLASS_EXTERNAL,
+            },
+        );
+    }
+    /// The symbols which precede the section symbols
+    fn special_symbols(&self) -> Vec<(&'static str, SymbolInfo)> {
+        match self.architecture {
+            // tells the linker that the object is compatible with safe exception handlers
+            Architecture::I386 | Architecture::I586 | Architecture::I686 => vec![(
+                "@feat.00",
+                SymbolInfo {
+                    section_number: IMAGE_SYM_ABSOLUTE,
+                    value: 1,
+                    typ: 0,
+                    storage_class: IMAGE_SYM_CLASS_STATIC,
+                },
+            )],
+            _ => Vec::new(),
+        }
+    }
+    fn link(&mut self, l: &LinkAndDecl) -> Result<(), ArtifactError> {
+        debug!("Link: {:?}", l);
+        let sym_idx = match self.section_decls.get(l.to.name) {
+            // every section symbol has an auxiliary record
+            Some(&idx) => self.special_symbols().len() + 2 * idx,
+            None => {
+                let (idx, _, _) = self
+                    .symbols
+                    .get_full(l.to.name)
+                    .expect("link target present in symbols");
+                self.special_symbols().len() + 2 * self.sections.len() + idx
+            }
+        };
+        let (section, offset) = {
+            let from = self
+                .definitions
+                .get(l.from.name)
+                .expect("link source present in definitions");
+            (from.section, from.offset + l.at)
+        };
+        let relocs = match l.reloc {
+            Reloc::Auto => self.auto_relocations(l)?,
+            Reloc::AutoWithAddend { addend } => {
+                let addend = i32::try_from(addend).map_err(|_| {
+                    ArtifactError::UnsupportedRelocation(format!(
+                        "addend {} does not fit in a COFF relocation {:?}",
+                        addend, l
+                    ))
+                })?;
+                self.auto_relocations(l)?
+                    .into_iter()
+                    .map(|(delta, typ, auto_addend)| (delta, typ, auto_addend + addend))
+                    .collect()
+            }
+            Reloc::Raw { reloc, addend } => vec![(0, reloc as u16, addend)],
+            Reloc::Absolute { addend } => vec![(0, self.pointer_relocation(l)?, addend)],
+            Reloc::Kind(kind) => self.kind_relocations(l, kind)?,
+            Reloc::Debug { size, addend } => vec![(0, self.debug_relocation(l, size)?, addend)],
+            Reloc::Authenticated { .. } => {
+                return Err(ArtifactError::UnsupportedRelocation(format!(
+                    "authenticated pointers are not yet supported for COFF: {:?}",
+                    l
+                )));
+            }
+            Reloc::Subtrahend { .. } | Reloc::Minuend { .. } => {
+                return Err(ArtifactError::UnsupportedRelocation(format!(
+                    "differences of symbols are not yet supported for COFF: {:?}",
+                    l
+                )));
+            }
+        };
+        for (delta, typ, addend) in relocs {
+            let virtual_address = offset + delta;
+            // COFF relocations have no addend, so it is placed in the relocated bytes instead
+            if addend != 0 {
+                let size = self.relocation_size(typ);
+                let data = &mut self.sections[section].data;
+                let at = virtual_address as usize;
+                match size {
+                    Some(8) => data.pwrite_with(i64::from(addend), at, LE),
+                    Some(_) => data.pwrite_with(addend, at, LE),
+                    None => {
+                        return Err(ArtifactError::UnsupportedRelocation(format!(
+                            "unsupported addend {} for relocation {:?}",
+                            addend, l
+                        )));
+                    }
+                }
+                .expect("relocation is inside its definition");
+            }
+            self.sections[section].relocations.push(Relocation {
+                virtual_address: virtual_address as u32,
+                symbol_table_index: sym_idx as u32,
+                typ,
+            });
+        }
+        Ok(())
+    }
+    /// The relocations, as `(offset from link.at, type, addend)`, that faerie deduces for `l`
+    fn auto_relocations(&self, l: &LinkAndDecl) -> Result<Vec<(u64, u16, i32)>, ArtifactError> {
+        let to_thread_local = match l.to.decl {
+            Decl::Defined(DefinedDecl::Data(d)) => d.is_thread_local(),
+            _ => false,
+        };
+        let to_function = match l.to.decl {
+            Decl::Defined(d) => d.is_function(),
+            Decl::Import(kind) => kind.is_function(),
+            Decl::AbsoluteSymbol(_) | Decl::Common { .. } => false,
+        };
+        Ok(match (l.from.decl, self.architecture) {
+            // thread-local variables are addressed relative to the start of the module's `.tls`
+            // section, e.g., `movl x@SECREL32(%rax), %eax`
+            (Decl::Defined(DefinedDecl::Function(_)), Architecture::X86_64) if to_thread_local => {
+                vec![(0, reloc::IMAGE_REL_AMD64_SECREL, 0)]
+            }
+            (Decl::Defined(DefinedDecl::Function(_)), Architecture::X86_64) => {
+                vec![(0, reloc::IMAGE_REL_AMD64_REL32, 0)]
+            }
+            (Decl::Defined(DefinedDecl::Function(_)), Architecture::I386)
+            | (Decl::Defined(DefinedDecl::Function(_)), Architecture::I586)
+            | (Decl::Defined(DefinedDecl::Function(_)), Architecture::I686) => {
+                if to_thread_local {
+                    vec![(0, reloc::IMAGE_REL_I386_SECREL, 0)]
+                } else {
+                    vec![(0, reloc::IMAGE_REL_I386_REL32, 0)]
+                }
+            }
+            (Decl::Defined(DefinedDecl::Function(_)), Architecture::Aarch64(_)) if to_function => {
+                vec![(0, IMAGE_REL_ARM64_BRANCH26, 0)]
+            }
+            // `adrp x0, x; add x0, x0, :lo12:x`
+            (Decl::Defined(DefinedDecl::Function(_)), Architecture::Aarch64(_))
+                if !to_thread_local =>
+            {
+                vec![
+                    (0, IMAGE_REL_ARM64_PAGEBASE_REL21, 0),
+                    (4, IMAGE_REL_ARM64_PAGEOFFSET_12A, 0),
+                ]
+            }
+            (Decl::Defined(DefinedDecl::Data(_)), _) => vec![(0, self.pointer_relocation(l)?, 0)],
+            _ => {
+                return Err(ArtifactError::UnsupportedRelocation(format!(
+                    "{:?} for {} COFF",
+                    l, self.architecture
+                )))
+            }
+        })
+    }
+    /// The relocations, as `(offset from link.at, type, addend)`, of the given `kind`
+    fn kind_relocations(
+        &self,
+        l: &LinkAndDecl,
+        kind: RelocationKind,
+    ) -> Result<Vec<(u64, u16, i32)>, ArtifactError> {
+        Ok(match (kind, self.architecture) {
+            (RelocationKind::Absolute, _) => vec![(0, self.pointer_relocation(l)?, 0)],
+            (RelocationKind::PcRelative, Architecture::X86_64)
+            | (RelocationKind::Branch, Architecture::X86_64) => {
+                vec![(0, reloc::IMAGE_REL_AMD64_REL32, 0)]
+            }
+            (RelocationKind::PcRelative, Architecture::I386)
+            | (RelocationKind::PcRelative, Architecture::I586)
+            | (RelocationKind::PcRelative, Architecture::I686)
+            | (RelocationKind::Branch, Architecture::I386)
+            | (RelocationKind::Branch, Architecture::I586)
+            | (RelocationKind::Branch, Architecture::I686) => {
+                vec![(0, reloc::IMAGE_REL_I386_REL32, 0)]
+            }
+            (RelocationKind::Branch, Architecture::Aarch64(_)) => {
+                vec![(0, IMAGE_REL_ARM64_BRANCH26, 0)]
+            }
+            // COFF has no Global Offset Table
+            _ => {
+                return Err(ArtifactError::UnsupportedRelocation(format!(
+                    "{:?} relocations are not yet supported for {} COFF: {:?}",
+                    kind, self.architecture, l
+                )))
+            }
+        })
+    }
+    /// The relocation type for an absolute pointer
+    fn pointer_relocation(&self, l: &LinkAndDecl) -> Result<u16, ArtifactError> {
+        Ok(match self.architecture {
+            Architecture::X86_64 => reloc::IMAGE_REL_AMD64_ADDR64,
+            Architecture::I386 | Architecture::I586 | Architecture::I686 => {
+                reloc::IMAGE_REL_I386_DIR32
+            }
+            Architecture::Aarch64(_) => IMAGE_REL_ARM64_ADDR64,
+            _ => return Err(ArtifactError::UnsupportedRelocation(format!("{:?}", l))),
+        })
+    }
+    /// The relocation type for a `size` byte pointer in a debug section; references to other debug
+    /// sections are offsets into them, i.e., section relative
+    fn debug_relocation(&self, l: &LinkAndDecl, size: u8) -> Result<u16, ArtifactError> {
+        let is_section = l.to.decl.is_section();
+        Ok(match (self.architecture, size) {
+            (Architecture::X86_64, 4) if is_section => reloc::IMAGE_REL_AMD64_SECREL,
+            (Architecture::X86_64, 4) => reloc::IMAGE_REL_AMD64_ADDR32,
+            (Architecture::X86_64, 8) => reloc::IMAGE_REL_AMD64_ADDR64,
+            (Architecture::I386, 4) | (Architecture::I586, 4) | (Architecture::I686, 4)
+                if is_section =>
+            {
+                reloc::IMAGE_REL_I386_SECREL
+            }
+            (Architecture::I386, 4) | (Architecture::I586, 4) | (Architecture::I686, 4) => {
+                reloc::IMAGE_REL_I386_DIR32
+            }
+            (Architecture::Aarch64(_), 4) if is_section => IMAGE_REL_ARM64_SECREL,
+            (Architecture::Aarch64(_), 4) => IMAGE_REL_ARM64_ADDR32,
+            (Architecture::Aarch64(_), 8) => IMAGE_REL_ARM64_ADDR64,
+            _ => {
+                return Err(ArtifactError::UnsupportedRelocation(format!(
+                    "{} byte pointers in debug sections: {:?}",
+                    size, l
+                )))
+            }
+        })
+    }
+    /// The size of the bytes relocated by `typ`, if they hold its addend
+    fn relocation_size(&self, typ: u16) -> Option<usize> {
+        match self.architecture {
+            Architecture::X86_64 => match typ {
+                reloc::IMAGE_REL_AMD64_ADDR64 => Some(8),
+                reloc::IMAGE_REL_AMD64_ADDR32
+                | reloc::IMAGE_REL_AMD64_ADDR32NB
+                | reloc::IMAGE_REL_AMD64_REL32
+                | reloc::IMAGE_REL_AMD64_SECREL => Some(4),
+                _ => None,
+            },
+            Architecture::I386 | Architecture::I586 | Architecture::I686 => match typ {
+                reloc::IMAGE_REL_I386_DIR32
+                | reloc::IMAGE_REL_I386_DIR32NB
+                | reloc::IMAGE_REL_I386_REL32
+                | reloc::IMAGE_REL_I386_SECREL => Some(4),
+                _ => None,
+            },
+            // the other relocations are encoded in instructions
+            Architecture::Aarch64(_) => match typ {
+                IMAGE_REL_ARM64_ADDR64 => Some(8),
+                IMAGE_REL_ARM64_ADDR32 | IMAGE_REL_ARM64_SECREL => Some(4),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    fn write(self, machine: MachineTag) -> goblin::error::Result<Vec<u8>> {
+        let special_symbols = self.special_symbols();
+        let mut strtab = Vec::new();
+        // names longer than 8 bytes are offsets into the string table, which starts with its size
+        let mut long_name = |name: &str| {
+            let offset = 4 + strtab.len();
+            strtab.extend_from_slice(name.as_bytes());
+            strtab.push(0);
+            offset
+        };
+
+        // the relocation count of sections with more than 0xffff relocations is in their first
+        let relocation_counts = self
+            .sections
+            .iter()
+            .map(|section| match section.relocations.len() {
+                0..=0xfffe => section.relocations.len(),
+                n => n + 1,
+            })
+            .collect::<Vec<_>>();
+        let mut offset = SIZEOF_COFF_HEADER + SIZEOF_SECTION_HEADER * self.sections.len();
+        let mut data_offsets = Vec::with_capacity(self.sections.len());
+        let mut relocation_offsets = Vec::with_capacity(self.sections.len());
+        for (section, &nrelocs) in self.sections.iter().zip(relocation_counts.iter()) {
+            data_offsets.push(offset);
+            offset += section.data.len();
+            relocation_offsets.push(offset);
+            offset += nrelocs * COFF_RELOCATION_SIZE;
+        }
+        let symtab_offset = offset;
+        let nsymbols = special_symbols.len() + 2 * self.sections.len() + self.symbols.len();
+        let strtab_offset = symtab_offset + nsymbols * COFF_SYMBOL_SIZE;
+
+        let mut symbols = Vec::with_capacity(nsymbols);
+        for (name, info) in special_symbols.iter() {
+            symbols.push((symbol(info, name, &mut long_name), None));
+        }
+        for (idx, section) in self.sections.iter().enumerate() {
+            let info = SymbolInfo {
+                section_number: (idx + 1) as i16,
+                value: 0,
+                typ: 0,
+                storage_class: IMAGE_SYM_CLASS_STATIC,
+            };
+            let mut sym = symbol(&info, &section.name, &mut long_name);
+            sym.number_of_aux_symbols = 1;
+            let aux = AuxSectionDefinition {
+                length: section.size as u32,
+                number_of_relocations: relocation_counts[idx].min(0xffff) as u16,
+                number_of_line_numbers: 0,
+                checksum: 0,
+                number: 0,
+                selection: section.comdat_selection.unwrap_or(0),
+                unused: [0; 3],
+            };
+            symbols.push((sym, Some(aux)));
+        }
+        for (name, info) in self.symbols.iter() {
+            let name = mangle(self.architecture, name);
+            symbols.push((symbol(info, &name, &mut long_name), None));
+        }
+        let mut section_names = Vec::with_capacity(self.sections.len());
+        for section in self.sections.iter() {
+            let mut name = [0u8; 8];
+            if section.name.len() <= 8 {
+                name[..section.name.len()].copy_from_slice(section.name.as_bytes());
+            } else {
+                let offset = format!("/{}", long_name(&section.name));
+                name[..offset.len()].copy_from_slice(offset.as_bytes());
+            }
+            section_names.push(name);
+        }
+
+        let size = strtab_offset + 4 + strtab.len();
+        let mut bytes = vec![0u8; size];
+        let header = CoffHeader {
+            machine: machine.0,
+            number_of_sections: self.sections.len() as u16,
+            time_date_stamp: 0,
+            pointer_to_symbol_table: symtab_offset as u32,
+            number_of_symbol_table: nsymbols as u32,
+            size_of_optional_header: 0,
+            characteristics: 0,
+        };
+        let mut offset = 0;
+        bytes.gwrite_with(header, &mut offset, LE)?;
+        for (idx, section) in self.sections.iter().enumerate() {
+            let nrelocs = relocation_counts[idx];
+            let mut characteristics = section.characteristics | section.align_characteristics();
+            if nrelocs > 0xffff {
+                characteristics |= IMAGE_SCN_LNK_NRELOC_OVFL;
+            }
+            let pointer_to_raw_data = if section.data.is_empty() {
+                0
+            } else {
+                data_offsets[idx]
+            };
+            let pointer_to_relocations = if nrelocs == 0 {
+                0
+            } else {
+                relocation_offsets[idx]
+            };
+            write_bytes(&mut bytes, &mut offset, &section_names[idx]);
+            bytes.gwrite_with(0u32, &mut offset, LE)?; // virtual_size
+            bytes.gwrite_with(0u32, &mut offset, LE)?; // virtual_address
+            bytes.gwrite_with(section.size as u32, &mut offset, LE)?;
+            bytes.gwrite_with(pointer_to_raw_data as u32, &mut offset, LE)?;
+            bytes.gwrite_with(pointer_to_relocations as u32, &mut offset, LE)?;
+            bytes.gwrite_with(0u32, &mut offset, LE)?; // pointer_to_linenumbers
+            bytes.gwrite_with(nrelocs.min(0xffff) as u16, &mut offset, LE)?;
+            bytes.gwrite_with(0u16, &mut offset, LE)?; // number_of_linenumbers
+            bytes.gwrite_with(characteristics, &mut offset, LE)?;
+        }
+        for (idx, section) in self.sections.iter().enumerate() {
+            debug_assert_eq!(offset, data_offsets[idx]);
+            write_bytes(&mut bytes, &mut offset, &section.data);
+            if relocation_counts[idx] > section.relocations.len() {
+                let count = Relocation {
+                    virtual_address: relocation_counts[idx] as u32,
+                    symbol_table_index: 0,
+                    typ: 0,
+                };
+                bytes.gwrite_with(count, &mut offset, LE)?;
+            }
+            for relocation in section.relocations.iter() {
+                bytes.gwrite_with(*relocation, &mut offset, LE)?;
+            }
+        }
+        debug_assert_eq!(offset, symtab_offset);
+        for (sym, aux) in symbols {
+            bytes.gwrite_with(sym, &mut offset, LE)?;
+            if let Some(aux) = aux {
+                bytes.gwrite_with(aux, &mut offset, LE)?;
+            }
+        }
+        bytes.gwrite_with((4 + strtab.len()) as u32, &mut offset, LE)?;
+        write_bytes(&mut bytes, &mut offset, &strtab);
+        Ok(bytes)
+    }
+}
+
+/// The name of `name` in the symbol table; 32-bit x86 prefixes every C symbol with an _
+pub(crate) fn mangle(architecture: Architecture, name: &str) -> String {
+    match architecture {
+        Architecture::I386 | Architecture::I586 | Architecture::I686 => format!("_{}", name),
+        _ => name.to_string(),
+    }
+}
+
+/// Copy `data` to `offset` in `bytes`, and advance `offset` past it; unlike scroll, this allows
+/// empty `data` at the end of `bytes`
+fn write_bytes(bytes: &mut [u8], offset: &mut usize, data: &[u8]) {
+    bytes[*offset..*offset + data.len()].copy_from_slice(data);
+    *offset += data.len();
+}
+
+/// Create the symbol `name`, placing its name into the string table with `long_name` if needed
+fn symbol(info: &SymbolInfo, name: &str, long_name: &mut dyn FnMut(&str) -> usize) -> Symbol {
+    let mut sym = Symbol {
+        name: [0; 8],
+        value: info.value,
+        section_number: info.section_number,
+        typ: info.typ,
+        storage_class: info.storage_class,
+        number_of_aux_symbols: 0,
+    };
+    if name.len() <= 8 {
+        sym.name[..name.len()].copy_from_slice(name.as_bytes());
+    } else {
+        let offset = long_name(name) as u32;
+        sym.name[4..].copy_from_slice(&offset.to_le_bytes());
+    }
+    sym
+}
+
+pub fn map(artifact: &Artifact) -> Vec<artifact::MapSection> {
+    let mut coff = Coff::new(artifact);
+    for def in artifact.definitions() {
+        coff.add_definition(def);
+    }
+    let mut sections = coff
+        .sections
+        .iter()
+        .map(|section| artifact::MapSection {
+            name: section.name.clone(),
+            addr: 0,
+            size: section.size,
+            symbols: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    for (name, def) in coff.definitions.iter() {
+        sections[def.section]
+            .symbols
+            .push((name.to_string(), def.offset, def.size));
+    }
+    sections
+}
+
+pub fn to_bytes(artifact: &Artifact) -> Result<Vec<u8>, ArtifactError> {
+    // the relocations are only known for these architectures, too
+    let machine = MachineTag::try_from(artifact.target.architecture)?;
+    let mut coff = Coff::new(artifact);
+    for def in artifact.definitions() {
+        debug!("Def: {:?}", def);
+        coff.add_definition(def);
+    }
+    for (ref import, ref kind) in artifact.imports() {
+        debug!("Import: {:?} -> {:?}", import, kind);
+        coff.import(import, kind);
+    }
+    for (name, address) in artifact.absolute_symbols() {
+        debug!("Absolute: {} = {:#x}", name, address);
+        coff.absolute(name, address);
+    }
+    for (name, size, align) in artifact.common_symbols() {
+        debug!("Common: {} ({} bytes, aligned to {})", name, size, align);
+        coff.common(name, size);
+    }
+    for link in artifact.links() {
+        coff.link(&link)?;
+    }
+    Ok(coff.write(machine)?)
+}
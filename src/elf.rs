@@ -7,17 +7,18 @@
 
 use crate::{
     artifact::{
-        self, Artifact, Data, DataType, Decl, DefinedDecl, ImportKind, LinkAndDecl, Reloc, Scope,
-        Visibility,
+        self, Artifact, AsmSyntax, Data, DataType, Decl, DefinedDecl, EhFrame, ImportKind,
+        LinkAndDecl, RawSection, Reloc, RelocationKind, Scope, TlsModel, Visibility,
     },
     target::make_ctx,
-    Ctx,
+    ArtifactError, Ctx,
 };
 use goblin;
 
 use indexmap::IndexMap;
 use scroll::{IOwrite, Pwrite};
-use std::collections::{hash_map, HashMap};
+use std::borrow::Cow;
+use std::collections::{hash_map, HashMap, HashSet};
 use std::fmt;
 use std::io::SeekFrom::*;
 use std::io::{BufWriter, Cursor, Seek, Write};
@@ -59,8 +60,12 @@ impl From<Architecture> for MachineTag {
             Sparc64 | Sparcv9 => EM_SPARCV9,
             Msp430 => EM_MSP430,
             Unknown => EM_NONE,
+            // goblin calls EM_HEXAGON by its older name
+            #[cfg(feature = "hexagon")]
+            Hexagon => EM_QDSP6,
+            #[cfg(not(feature = "hexagon"))]
+            Hexagon => panic!("hexagon support requires the `hexagon` feature"),
             Spirv => panic!("spirv does not exist in ELF"),
-            Hexagon => panic!("goblin does not have EM_HEXAGON yet"),
             Nvptx64 => panic!("nvptx64 does not exist in ELF"),
             AmdGcn => panic!("amdgcn does not exist in ELF"),
             Asmjs => panic!("asm.js does not exist in ELF"),
@@ -75,6 +80,14 @@ enum SymbolType<'a> {
     Decl(&'a DefinedDecl),
     /// An import
     Import,
+    /// An import which is allowed to be missing
+    WeakImport,
+    /// A global symbol with a fixed absolute address
+    Absolute,
+    /// A global common symbol, allocated by the linker
+    Common,
+    /// A global indirect function, i.e., a GNU `IFUNC`, located at its resolver
+    IFunc,
     /// A section reference
     Section,
     /// A file reference
@@ -130,10 +143,10 @@ impl<'a> SymbolBuilder<'a> {
     }
     /// Finalize and create the symbol
     pub fn create(self) -> Symbol {
-        use goblin::elf::section_header::SHN_ABS;
+        use goblin::elf::section_header::{SHN_ABS, SHN_COMMON};
         use goblin::elf::sym::{
-            STB_GLOBAL, STB_LOCAL, STB_WEAK, STT_FILE, STT_FUNC, STT_NOTYPE, STT_OBJECT,
-            STT_SECTION, STV_DEFAULT, STV_HIDDEN, STV_PROTECTED,
+            STB_GLOBAL, STB_LOCAL, STB_WEAK, STT_FILE, STT_FUNC, STT_GNU_IFUNC, STT_NOTYPE,
+            STT_OBJECT, STT_SECTION, STT_TLS, STV_DEFAULT, STV_HIDDEN, STV_INTERNAL, STV_PROTECTED,
         };
         let mut st_shndx = self.shndx;
         let mut st_info = 0;
@@ -154,6 +167,7 @@ impl<'a> SymbolBuilder<'a> {
                 Visibility::Default => STV_DEFAULT,
                 Visibility::Hidden => STV_HIDDEN,
                 Visibility::Protected => STV_PROTECTED,
+                Visibility::Internal => STV_INTERNAL,
             }
         }
 
@@ -164,7 +178,11 @@ impl<'a> SymbolBuilder<'a> {
                 st_other |= vis_stother_flags(d.get_visibility());
             }
             SymbolType::Decl(DefinedDecl::Data(d)) => {
-                st_info |= STT_OBJECT;
+                st_info |= if d.is_thread_local() {
+                    STT_TLS
+                } else {
+                    STT_OBJECT
+                };
                 st_info |= scope_stb_flags(d.get_scope());
                 st_other |= vis_stother_flags(d.get_visibility());
             }
@@ -172,6 +190,24 @@ impl<'a> SymbolBuilder<'a> {
                 st_info = STT_NOTYPE;
                 st_info |= STB_GLOBAL << 4;
             }
+            SymbolType::WeakImport => {
+                st_info = STT_NOTYPE;
+                st_info |= STB_WEAK << 4;
+            }
+            SymbolType::IFunc => {
+                st_info = STT_GNU_IFUNC;
+                st_info |= STB_GLOBAL << 4;
+            }
+            SymbolType::Absolute => {
+                st_info = STT_NOTYPE;
+                st_info |= STB_GLOBAL << 4;
+                st_shndx = SHN_ABS as usize;
+            }
+            SymbolType::Common => {
+                st_info = STT_OBJECT;
+                st_info |= STB_GLOBAL << 4;
+                st_shndx = SHN_COMMON as usize;
+            }
             SymbolType::Decl(DefinedDecl::Section(_)) | SymbolType::Section => {
                 st_info |= STT_SECTION;
                 st_info |= STB_LOCAL << 4;
@@ -204,9 +240,24 @@ enum SectionType {
     SymTab,
     Relocation,
     SymTabShndx,
+    /// LLVM's table of type hashes for one definition rule checking during LTO
+    OdrTab,
+    /// A section group, i.e., its flags followed by the indexes of its member sections
+    Group,
+    /// A section with a verbatim type and flags
+    Raw {
+        typ: u32,
+        flags: u64,
+    },
     None,
 }
 
+/// Whether the processor specific section flags of `architecture` are free to hold address spaces;
+/// most ABIs give them another meaning, e.g., `SHF_X86_64_LARGE` or `SHF_ARM_PURECODE`
+fn has_address_spaces(architecture: Architecture) -> bool {
+    architecture == Architecture::Msp430
+}
+
 /// A builder for creating a 32/64 bit section
 struct SectionBuilder {
     typ: SectionType,
@@ -216,6 +267,9 @@ struct SectionBuilder {
     size: u64,
     name_offset: usize,
     align: Option<u64>,
+    address_space: Option<u8>,
+    thread_local: bool,
+    group_member: bool,
 }
 
 impl SectionBuilder {
@@ -229,6 +283,9 @@ impl SectionBuilder {
             name_offset: 0,
             size,
             align: None,
+            address_space: None,
+            thread_local: false,
+            group_member: false,
         }
     }
     /// Make this section executable
@@ -246,11 +303,27 @@ impl SectionBuilder {
         self.write = writable;
         self
     }
+    /// Make this section hold the initial values of thread-local variables
+    pub fn thread_local(mut self, thread_local: bool) -> Self {
+        self.thread_local = thread_local;
+        self
+    }
+    /// Make this section a member of a section group
+    pub fn group_member(mut self, group_member: bool) -> Self {
+        self.group_member = group_member;
+        self
+    }
     /// Specify section alignment
     pub fn align(mut self, align: Option<u64>) -> Self {
         self.align = align;
         self
     }
+    /// Place this section in the address space `space`, which is encoded in the processor
+    /// specific bits of its flags, so must be less than 16
+    pub fn address_space(mut self, space: u8) -> Self {
+        self.address_space = Some(space);
+        self
+    }
 
     /// Set the byte offset of this section's name in the corresponding strtab
     pub fn name_offset(mut self, name_offset: usize) -> Self {
@@ -278,6 +351,15 @@ impl SectionBuilder {
         if self.alloc {
             shdr.sh_flags |= SHF_ALLOC as u64
         }
+        if self.thread_local {
+            shdr.sh_flags |= SHF_TLS as u64
+        }
+        if self.group_member {
+            shdr.sh_flags |= SHF_GROUP as u64
+        }
+        if let Some(space) = self.address_space {
+            shdr.sh_flags |= u64::from(space) << SHF_MASKPROC.trailing_zeros();
+        }
 
         let align = if let Some(align) = self.align {
             align as u64
@@ -298,6 +380,8 @@ impl SectionBuilder {
                 shdr.sh_addralign = align;
                 shdr.sh_type = SHT_PROGBITS;
                 shdr.sh_flags |= (SHF_MERGE | SHF_STRINGS) as u64;
+                // the size of a character; linkers only merge strings if it is set
+                shdr.sh_entsize = 1;
             }
             SectionType::Data => {
                 shdr.sh_addralign = align;
@@ -324,12 +408,30 @@ impl SectionBuilder {
                 shdr.sh_addralign = 4;
                 shdr.sh_type = SHT_SYMTAB_SHNDX;
             }
+            SectionType::OdrTab => {
+                shdr.sh_entsize = SIZEOF_ODRTAB_ENTRY as u64;
+                shdr.sh_addralign = 0x8;
+                shdr.sh_link = u32::from(STRTAB_LINK);
+                shdr.sh_type = SHT_LLVM_ODRTAB;
+            }
+            SectionType::Group => {
+                shdr.sh_entsize = 4;
+                shdr.sh_addralign = 4;
+                shdr.sh_link = u32::from(SYMTAB_LINK);
+                shdr.sh_type = SHT_GROUP;
+            }
             SectionType::NoBits => {
+                shdr.sh_addralign = align;
                 shdr.sh_type = SHT_NOBITS;
                 // .bss is always SHF_WRITE and SHF_ALLOC
                 // TODO: warn users if self.alloc is not set
                 shdr.sh_flags |= u64::from(SHF_WRITE | SHF_ALLOC);
             }
+            SectionType::Raw { typ, flags } => {
+                shdr.sh_addralign = self.align.unwrap_or(1);
+                shdr.sh_type = typ;
+                shdr.sh_flags = flags;
+            }
             SectionType::None => shdr.sh_type = SHT_NULL,
         }
         shdr
@@ -396,11 +498,21 @@ impl RelocationBuilder {
 /// An intermediate ELF object file container
 struct Elf<'a> {
     name: &'a str,
-    code: IndexMap<StringIndex, &'a [u8]>,
+    code: IndexMap<StringIndex, Cow<'a, [u8]>>,
     relocations: IndexMap<StringIndex, (Section, Vec<Relocation>)>,
     symbols: IndexMap<StringIndex, Symbol>,
     special_symbols: Vec<Symbol>,
     imports: HashMap<StringIndex, ImportKind>,
+    ifuncs: HashSet<StringIndex>,
+    syntax_hints: Vec<(&'a str, AsmSyntax)>,
+    /// COMDAT group sections, the section index of their member, and the symbol which is their
+    /// signature
+    comdat_groups: Vec<(StringIndex, usize, &'a str)>,
+    /// The local labels of RISC-V `auipc`s by their function and offset in it, as indexes into the
+    /// special symbols
+    pcrel_labels: HashMap<(StringIndex, u64), usize>,
+    /// The offsets of the initial locations of the FDEs in `.eh_frame`, and their functions
+    eh_frame_locations: Vec<(u64, &'a str)>,
     sections: IndexMap<StringIndex, SectionInfo>,
     offsets: HashMap<StringIndex, Offset>,
     sizeof_strtab: Offset,
@@ -410,6 +522,8 @@ struct Elf<'a> {
     ctx: Ctx,
     architecture: Architecture,
     nlocals: usize,
+    pic: bool,
+    entry_point: Option<&'a str>,
 }
 
 impl<'a> fmt::Debug for Elf<'a> {
@@ -428,9 +542,309 @@ impl<'a> fmt::Debug for Elf<'a> {
     }
 }
 
+/// Relocations for 64-bit SPARC (SPARC V9), which goblin does not provide
+#[cfg(feature = "sparc")]
+mod sparc {
+    use crate::artifact::{Decl, DefinedDecl, ImportKind, LinkAndDecl};
+
+    pub const R_SPARC_32: u32 = 3;
+    pub const R_SPARC_WDISP30: u32 = 7;
+    pub const R_SPARC_LO10: u32 = 12;
+    pub const R_SPARC_UA32: u32 = 23;
+    pub const R_SPARC_64: u32 = 32;
+    pub const R_SPARC_HH22: u32 = 34;
+    pub const R_SPARC_HM10: u32 = 35;
+    pub const R_SPARC_LM22: u32 = 36;
+    pub const R_SPARC_UA64: u32 = 54;
+
+    pub fn auto_relocations(l: &LinkAndDecl) -> Vec<(u64, u32, i32)> {
+        match *l.from.decl {
+            Decl::Defined(DefinedDecl::Function { .. }) => match *l.to.decl {
+                // `call` encodes a 30 bit word displacement
+                Decl::Defined(DefinedDecl::Function { .. })
+                | Decl::Import(ImportKind::Function)
+                | Decl::Import(ImportKind::WeakFunction) => {
+                    vec![(0, R_SPARC_WDISP30, 0)]
+                }
+                // a 64-bit address is materialized by the usual four instruction sequence:
+                //   sethi %hh(sym), %g1
+                //   or    %g1, %hm(sym), %g1
+                //   sethi %lm(sym), %g2
+                //   or    %g2, %lo(sym), %g2
+                Decl::Defined(DefinedDecl::Data { .. })
+                | Decl::Import(ImportKind::Data)
+                | Decl::Import(ImportKind::WeakData)
+                | Decl::Common { .. } => vec![
+                    (0, R_SPARC_HH22, 0),
+                    (4, R_SPARC_HM10, 0),
+                    (8, R_SPARC_LM22, 0),
+                    (12, R_SPARC_LO10, 0),
+                ],
+                _ => panic!("unsupported relocation {:?}", l),
+            },
+            Decl::Defined(DefinedDecl::Data { .. }) => vec![(0, R_SPARC_64, 0)],
+            _ => panic!("unsupported relocation {:?}", l),
+        }
+    }
+
+    pub fn debug_relocation(l: &LinkAndDecl, size: u8) -> u32 {
+        // debug info is not necessarily aligned, so use the unaligned variants
+        match size {
+            4 => R_SPARC_UA32,
+            8 => R_SPARC_UA64,
+            _ => panic!("unsupported relocation {:?}", l),
+        }
+    }
+}
+
+/// Relocations for Qualcomm Hexagon (QDSP6), which goblin does not provide
+#[cfg(feature = "hexagon")]
+mod hexagon {
+    use crate::artifact::{Decl, DefinedDecl, ImportKind, LinkAndDecl};
+
+    pub const R_HEX_B22_PCREL: u32 = 1;
+    pub const R_HEX_LO16: u32 = 4;
+    pub const R_HEX_HI16: u32 = 5;
+    pub const R_HEX_32: u32 = 6;
+
+    pub fn auto_relocations(l: &LinkAndDecl) -> Vec<(u64, u32, i32)> {
+        match *l.from.decl {
+            Decl::Defined(DefinedDecl::Function { .. }) => match *l.to.decl {
+                // `call` encodes a 22 bit word displacement
+                Decl::Defined(DefinedDecl::Function { .. })
+                | Decl::Import(ImportKind::Function)
+                | Decl::Import(ImportKind::WeakFunction) => vec![(0, R_HEX_B22_PCREL, 0)],
+                // a 32-bit address is loaded a half at a time:
+                //   r0.l = #lo(sym)
+                //   r0.h = #hi(sym)
+                Decl::Defined(DefinedDecl::Data { .. })
+                | Decl::Import(ImportKind::Data)
+                | Decl::Import(ImportKind::WeakData)
+                | Decl::Common { .. } => {
+                    vec![(0, R_HEX_LO16, 0), (4, R_HEX_HI16, 0)]
+                }
+                _ => panic!("unsupported relocation {:?}", l),
+            },
+            Decl::Defined(DefinedDecl::Data { .. }) => vec![(0, R_HEX_32, 0)],
+            _ => panic!("unsupported relocation {:?}", l),
+        }
+    }
+
+    pub fn debug_relocation(l: &LinkAndDecl, size: u8) -> u32 {
+        match size {
+            4 => R_HEX_32,
+            _ => panic!("unsupported relocation {:?}", l),
+        }
+    }
+}
+
+/// Relocations for RISC-V, whose pc-relative addresses are computed by an `auipc` and an
+/// instruction adding the low 12 bits
+mod riscv {
+    use crate::artifact::{Decl, DefinedDecl, ImportKind, LinkAndDecl, TlsModel};
+    use goblin::elf::reloc::*;
+    use target_lexicon::Architecture;
+
+    /// The code uses compressed instructions
+    const EF_RISCV_RVC: u32 = 0x1;
+    /// The code passes floating point arguments in double precision registers
+    const EF_RISCV_FLOAT_ABI_DOUBLE: u32 = 0x4;
+
+    pub fn is_riscv(architecture: Architecture) -> bool {
+        use target_lexicon::Architecture::*;
+        matches!(
+            architecture,
+            Riscv32 | Riscv32i | Riscv32imac | Riscv32imc | Riscv64 | Riscv64gc | Riscv64imac
+        )
+    }
+
+    /// The `e_flags` of the header, which linkers check agree with the other objects' ABI
+    pub fn flags(architecture: Architecture) -> u32 {
+        use target_lexicon::Architecture::*;
+        match architecture {
+            // `riscv64` on its own is the `gc` Linux target
+            Riscv64 | Riscv64gc => EF_RISCV_RVC | EF_RISCV_FLOAT_ABI_DOUBLE,
+            Riscv64imac | Riscv32imac | Riscv32imc => EF_RISCV_RVC,
+            _ => 0,
+        }
+    }
+
+    pub fn auto_relocations(l: &LinkAndDecl, is_64: bool) -> Vec<(u64, u32, i32)> {
+        // the `lo12` relocation refers to the label at the `auipc`, see `Elf::add_pcrel_label`
+        let pcrel = |hi20| vec![(0, hi20, 0), (4, R_RISCV_PCREL_LO12_I, 0)];
+        match *l.from.decl {
+            Decl::Defined(DefinedDecl::Function { .. }) => match *l.to.decl {
+                // `call` is an `auipc` and a `jalr` pair
+                Decl::Defined(DefinedDecl::Function { .. })
+                | Decl::Import(ImportKind::Function)
+                | Decl::Import(ImportKind::WeakFunction) => vec![(0, R_RISCV_CALL_PLT, 0)],
+                // e.g., `la.tls.gd a0, x` or `la.tls.ie a0, x`
+                Decl::Defined(DefinedDecl::Data(d)) if d.is_thread_local() => {
+                    match d.get_tls_model() {
+                        // RISC-V has no local-dynamic relocations, and uses general-dynamic instead
+                        TlsModel::GeneralDynamic | TlsModel::LocalDynamic => {
+                            pcrel(R_RISCV_TLS_GD_HI20)
+                        }
+                        TlsModel::InitialExec => pcrel(R_RISCV_TLS_GOT_HI20),
+                    }
+                }
+                // `auipc a0, %pcrel_hi(x)` and `addi a0, a0, %pcrel_lo(label)`
+                Decl::Defined(DefinedDecl::Data { .. }) | Decl::AbsoluteSymbol(_) => {
+                    pcrel(R_RISCV_PCREL_HI20)
+                }
+                // common symbols may be allocated in another module, like data imports
+                Decl::Import(ImportKind::Data)
+                | Decl::Import(ImportKind::WeakData)
+                | Decl::Common { .. } => pcrel(R_RISCV_GOT_HI20),
+                _ => panic!("unsupported relocation {:?}", l),
+            },
+            Decl::Defined(DefinedDecl::Data { .. }) => {
+                if is_64 {
+                    vec![(0, R_RISCV_64, 0)]
+                } else {
+                    vec![(0, R_RISCV_32, 0)]
+                }
+            }
+            _ => panic!("unsupported relocation {:?}", l),
+        }
+    }
+
+    pub fn debug_relocation(l: &LinkAndDecl, size: u8) -> u32 {
+        match size {
+            4 => R_RISCV_32,
+            8 => R_RISCV_64,
+            _ => panic!("unsupported relocation {:?}", l),
+        }
+    }
+
+    /// Whether `l` is the high half of a pc-relative address, whose low half needs a label
+    pub fn needs_pcrel_label(l: &LinkAndDecl) -> bool {
+        auto_relocations(l, true)
+            .iter()
+            .any(|&(_, reloc, _)| reloc == R_RISCV_PCREL_LO12_I)
+    }
+}
+
+/// Relocations for IBM Z (s390x), which goblin does not provide
+mod s390x {
+    use crate::artifact::{Decl, DefinedDecl, ImportKind, LinkAndDecl};
+
+    pub const R_390_32: u32 = 4;
+    pub const R_390_PC32DBL: u32 = 19;
+    pub const R_390_PLT32DBL: u32 = 20;
+    pub const R_390_64: u32 = 22;
+    pub const R_390_GOT64: u32 = 24;
+
+    pub fn auto_relocations(l: &LinkAndDecl) -> Vec<(u64, u32, i32)> {
+        // the halfword displacement of e.g. `brasl` and `larl` is relative to the start of the
+        // instruction, which is 2 bytes before the relocated field
+        match *l.from.decl {
+            Decl::Defined(DefinedDecl::Function { .. }) => match *l.to.decl {
+                Decl::Defined(DefinedDecl::Function { .. })
+                | Decl::Defined(DefinedDecl::Data { .. })
+                | Decl::AbsoluteSymbol(_) => vec![(0, R_390_PC32DBL, 2)],
+                Decl::Import(ImportKind::Function) | Decl::Import(ImportKind::WeakFunction) => {
+                    vec![(0, R_390_PLT32DBL, 2)]
+                }
+                // the offset of the import's GOT entry from the GOT pointer in %r12, e.g., in a
+                // literal pool
+                Decl::Import(ImportKind::Data)
+                | Decl::Import(ImportKind::WeakData)
+                | Decl::Common { .. } => {
+                    vec![(0, R_390_GOT64, 0)]
+                }
+                _ => panic!("unsupported relocation {:?}", l),
+            },
+            Decl::Defined(DefinedDecl::Data { .. }) => vec![(0, R_390_64, 0)],
+            _ => panic!("unsupported relocation {:?}", l),
+        }
+    }
+
+    pub fn debug_relocation(l: &LinkAndDecl, size: u8) -> u32 {
+        match size {
+            4 => R_390_32,
+            8 => R_390_64,
+            _ => panic!("unsupported relocation {:?}", l),
+        }
+    }
+}
+
+/// Relocations for 64-bit PowerPC, of either endianness, which goblin does not provide
+mod ppc64 {
+    use crate::artifact::{Decl, DefinedDecl, ImportKind, LinkAndDecl};
+
+    pub const R_PPC64_ADDR32: u32 = 1;
+    pub const R_PPC64_REL24: u32 = 10;
+    pub const R_PPC64_GOT16_HA: u32 = 17;
+    pub const R_PPC64_ADDR64: u32 = 38;
+    pub const R_PPC64_TOC16_HA: u32 = 50;
+    pub const R_PPC64_GOT16_LO_DS: u32 = 59;
+    pub const R_PPC64_TOC16_LO_DS: u32 = 64;
+
+    pub fn auto_relocations(l: &LinkAndDecl, is_big_endian: bool) -> Vec<(u64, u32, i32)> {
+        // an address relative to the TOC pointer in r2 is loaded a half at a time, e.g.:
+        //   addis r3, r2, sym@toc@ha
+        //   ld    r3, sym@toc@l(r3)
+        // and the relocated immediates are the low halfwords of the instructions
+        let half = if is_big_endian { 2 } else { 0 };
+        let toc_relative = |ha, lo_ds| vec![(half, ha, 0), (4 + half, lo_ds, 0)];
+        match *l.from.decl {
+            Decl::Defined(DefinedDecl::Function { .. }) => match *l.to.decl {
+                // `bl` encodes a 24 bit word displacement
+                Decl::Defined(DefinedDecl::Function { .. })
+                | Decl::Import(ImportKind::Function)
+                | Decl::Import(ImportKind::WeakFunction) => vec![(0, R_PPC64_REL24, 0)],
+                Decl::Defined(DefinedDecl::Data { .. }) => {
+                    toc_relative(R_PPC64_TOC16_HA, R_PPC64_TOC16_LO_DS)
+                }
+                // common symbols may be allocated in another module, like data imports, so their
+                // address is loaded from the GOT
+                Decl::Import(ImportKind::Data)
+                | Decl::Import(ImportKind::WeakData)
+                | Decl::Common { .. } => toc_relative(R_PPC64_GOT16_HA, R_PPC64_GOT16_LO_DS),
+                _ => panic!("unsupported relocation {:?}", l),
+            },
+            Decl::Defined(DefinedDecl::Data { .. }) => vec![(0, R_PPC64_ADDR64, 0)],
+            _ => panic!("unsupported relocation {:?}", l),
+        }
+    }
+
+    pub fn debug_relocation(l: &LinkAndDecl, size: u8) -> u32 {
+        match size {
+            4 => R_PPC64_ADDR32,
+            8 => R_PPC64_ADDR64,
+            _ => panic!("unsupported relocation {:?}", l),
+        }
+    }
+}
+
 const STRTAB_LINK: u16 = 1;
 const SYMTAB_LINK: u16 = 2;
 
+/// LLVM's section type for its one definition rule table
+const SHT_LLVM_ODRTAB: u32 = 0x6fff_4c00;
+/// An ODR table entry is the offset of the type's name in the strtab, and the type's hash
+const SIZEOF_ODRTAB_ENTRY: usize = 16;
+
+/// A GOT-relative reference which the linker may relax into a direct one; goblin does not have
+/// this yet
+const R_X86_64_GOTPCRELX: u32 = 41;
+
+/// The flag of a section group whose members are discarded if another group has the same signature
+const GRP_COMDAT: u32 = 0x1;
+
+/// The owner of the `.note.gnu.build-id` note
+const GNU_NOTE_OWNER: &[u8] = b"GNU\0";
+/// The note type of a build id; its descriptor is the build id itself
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// The owner of the notes in `.note.syntax`
+const SYNTAX_NOTE_OWNER: &[u8] = b"faerie\0";
+/// The note type for a function written in Intel syntax; its descriptor is the function's name
+const NT_FAERIE_INTEL_SYNTAX: u32 = 1;
+/// The note type for a function written in AT&T syntax; its descriptor is the function's name
+const NT_FAERIE_ATT_SYNTAX: u32 = 2;
+
 impl<'a> Elf<'a> {
     pub fn new(artifact: &'a Artifact) -> Self {
         let ctx = make_ctx(&artifact.target);
@@ -468,6 +882,11 @@ impl<'a> Elf<'a> {
             code: IndexMap::new(),
             relocations: IndexMap::new(),
             imports: HashMap::new(),
+            ifuncs: HashSet::new(),
+            syntax_hints: Vec::new(),
+            comdat_groups: Vec::new(),
+            pcrel_labels: HashMap::new(),
+            eh_frame_locations: Vec::new(),
             symbols: IndexMap::new(),
             special_symbols,
             sections: IndexMap::new(),
@@ -479,6 +898,8 @@ impl<'a> Elf<'a> {
             ctx,
             architecture: artifact.target.architecture,
             nlocals: 0,
+            pic: artifact.is_pic(),
+            entry_point: artifact.entry_point(),
         }
     }
     fn new_string(&mut self, name: String) -> (StringIndex, usize) {
@@ -498,20 +919,42 @@ impl<'a> Elf<'a> {
             return SectionType::NoBits;
         }
         match typ {
-            DataType::Bytes => SectionType::Data,
-            DataType::String => SectionType::String,
+            DataType::Bytes
+            | DataType::ObjcSelectorRef
+            | DataType::GotEntry
+            | DataType::ThreadLocal => SectionType::Data,
+            DataType::String | DataType::ObjcMethodName => SectionType::String,
         }
     }
-    pub fn add_definition(&mut self, def: artifact::Definition<'a>) {
+    pub fn add_definition(&mut self, def: artifact::Definition<'a>) -> Result<(), ArtifactError> {
         let name = def.name;
         let decl = def.decl;
-        let def_size = def.data.file_size();
+        let def_size = def.data.memory_size();
 
         let section_name = match (def.data, decl) {
             (Data::Blob(_), DefinedDecl::Function(_)) => format!(".text.{}", name),
             (Data::ZeroInit(_), DefinedDecl::Function(_)) => {
                 unreachable!("cannot define function as zero-init")
             }
+            (Data::Blob(_), DefinedDecl::Data(decl)) if decl.is_thread_local() => {
+                format!(".tdata.{}", name)
+            }
+            (Data::ZeroInit(_), DefinedDecl::Data(decl)) if decl.is_thread_local() => {
+                format!(".tbss.{}", name)
+            }
+            (Data::Blob(_), DefinedDecl::Data(decl))
+                if decl.get_datatype() == DataType::GotEntry =>
+            {
+                format!(".got.{}", name)
+            }
+            // mergeable strings are placed in sections named after their character size and alignment
+            (Data::Blob(_), DefinedDecl::Data(decl))
+                if decl.is_merge_strings()
+                    && !decl.is_writable()
+                    && decl.get_datatype() == DataType::String =>
+            {
+                format!(".rodata.str1.1.{}", name)
+            }
             (Data::Blob(_), DefinedDecl::Data(decl)) => format!(
                 ".{}.{}",
                 if decl.is_writable() { "data" } else { "rodata" },
@@ -527,6 +970,7 @@ impl<'a> Elf<'a> {
                 .alloc()
                 .writable(false)
                 .exec(true)
+                .group_member(d.is_comdat())
                 .align(d.get_align()),
             DefinedDecl::Data(d) => SectionBuilder::new(def_size as u64)
                 .section_type(Self::section_type_for_data(
@@ -535,25 +979,58 @@ impl<'a> Elf<'a> {
                 ))
                 .alloc()
                 .writable(d.is_writable())
+                .thread_local(d.is_thread_local())
                 .exec(false)
                 .align(d.get_align()),
-            DefinedDecl::Section(d) => SectionBuilder::new(def_size as u64)
-                .section_type(
-                    // TODO: this behavior should be deprecated, but we need to warn users!
-                    if name == ".debug_str" || name == ".debug_line_str" {
-                        SectionType::String
-                    } else {
-                        Self::section_type_for_data(d.get_datatype(), def.data.is_zero_init())
-                    },
-                )
-                .align(d.get_align()),
+            DefinedDecl::Section(d) => {
+                let section = SectionBuilder::new(def_size as u64)
+                    .section_type(
+                        // TODO: this behavior should be deprecated, but we need to warn users!
+                        if name == ".debug_str" || name == ".debug_line_str" {
+                            SectionType::String
+                        } else {
+                            Self::section_type_for_data(d.get_datatype(), def.data.is_zero_init())
+                        },
+                    )
+                    .align(d.get_align());
+                match d.get_address_space() {
+                    Some(_) if !has_address_spaces(self.architecture) => {
+                        return Err(ArtifactError::InvalidAddressSpace(format!(
+                            "{:?} gives the processor specific flags of section {} another meaning",
+                            self.architecture, name
+                        )));
+                    }
+                    Some(space) => section.address_space(space),
+                    None => section,
+                }
+            }
         };
 
+        // the group must precede its member
+        if let DefinedDecl::Function(d) = decl {
+            if d.is_comdat() {
+                // the signature of the group must be visible to the other objects defining it
+                if !decl.is_global() && !decl.is_weak() {
+                    return Err(ArtifactError::Unsupported(format!(
+                        "COMDAT function {} is local",
+                        name
+                    )));
+                }
+                self.add_comdat_group(name);
+            }
+        }
+
         let shndx = match def.data {
-            Data::Blob(bytes) => self.add_progbits(section_name, section, bytes),
+            Data::Blob(bytes) => self.add_progbits(section_name, section, bytes.into()),
             Data::ZeroInit(_) => self.add_section(section_name, section).1,
         };
 
+        if let DefinedDecl::Function(d) = decl {
+            if let Some(syntax) = d.get_syntax() {
+                self.syntax_hints.push((name, syntax));
+            }
+        }
+
         match decl {
             DefinedDecl::Function(_) | DefinedDecl::Data(_) => {
                 let (idx, offset) = self.new_string(name.to_string());
@@ -563,7 +1040,7 @@ impl<'a> Elf<'a> {
                 );
                 // build symbol based on this _and_ the properties of the definition
                 let symbol = SymbolBuilder::from_decl(decl)
-                    .size(def_size)
+                    .size(def.size_hint.unwrap_or(def_size))
                     .name_offset(offset)
                     .section_index(shndx)
                     .create();
@@ -572,7 +1049,7 @@ impl<'a> Elf<'a> {
                 // sh_info requires nsections + nlocals to add as delimiter; see the associated FunFact
                 // nonglobals go into the symbol table first (per iteration through definitions in
                 // caller)
-                if !decl.is_global() {
+                if !decl.is_global() && !decl.is_weak() {
                     self.nlocals += 1;
                 }
             }
@@ -596,9 +1073,15 @@ impl<'a> Elf<'a> {
                 }
             }
         }
+        Ok(())
     }
     /// Create a progbits section (and its section symbol), and return the section index.
-    fn add_progbits(&mut self, name: String, section: SectionBuilder, data: &'a [u8]) -> usize {
+    fn add_progbits(
+        &mut self,
+        name: String,
+        section: SectionBuilder,
+        data: Cow<'a, [u8]>,
+    ) -> usize {
         let (idx, shndx) = self.add_section(name, section);
         // increment the size
         self.sizeof_bits += data.len();
@@ -634,15 +1117,254 @@ impl<'a> Elf<'a> {
         self.nsections += 1;
         (idx, shndx)
     }
+    /// The sections with contents emitted so far, i.e., without the string and symbol tables,
+    /// and relocation sections; ELF relocatable objects have no segments
+    pub fn sections(&self) -> impl Iterator<Item = artifact::SectionInfo> + '_ {
+        self.sections
+            .values()
+            .map(move |section| artifact::SectionInfo {
+                name: self
+                    .strings
+                    .resolve(section.name)
+                    .expect("section name in strings")
+                    .to_string(),
+                segment: String::new(),
+                file_offset: section.header.sh_offset as usize,
+                size: section.header.sh_size as usize,
+                align: section.header.sh_addralign as usize,
+            })
+    }
+    pub fn add_raw_section(&mut self, raw: &'a RawSection) {
+        let section = SectionBuilder::new(raw.data.len() as u64).section_type(SectionType::Raw {
+            typ: (raw.flags >> 32) as u32,
+            flags: raw.flags & 0xffff_ffff,
+        });
+        self.add_progbits(raw.name.clone(), section, raw.data.as_slice().into());
+    }
+    /// Add a COMDAT group whose members are the section added next, and its relocations, and whose
+    /// signature is the symbol `name`, which is defined in that section
+    fn add_comdat_group(&mut self, name: &'a str) {
+        // the member comes right after the group, and the section indexes start after null +
+        // strtab + symtab
+        let member_shndx = self.sections.len() + 3 + 1;
+        // room is left for the relocation section, whose index is only known when writing
+        let mut group = vec![0u8; 12];
+        group.pwrite_with(GRP_COMDAT, 0, self.ctx.le).unwrap();
+        group
+            .pwrite_with(member_shndx as u32, 4, self.ctx.le)
+            .unwrap();
+        let section = SectionBuilder::new(group.len() as u64).section_type(SectionType::Group);
+        let group_name = format!(".group.{}", name);
+        self.add_progbits(group_name.clone(), section, group.into());
+        let group_idx = self.strings.get(group_name).expect("group name in strings");
+        self.comdat_groups.push((group_idx, member_shndx, name));
+    }
+    /// Add the `.gnu_debuglink` section, which names the file the debug info was stripped into
+    pub fn add_debuglink(&mut self, debug_file: &str, crc32: u32) {
+        // the file name is null terminated and padded to 4 bytes, followed by the file's CRC32
+        let crc_offset = (debug_file.len() + 1 + 3) & !3;
+        let mut debuglink = vec![0u8; crc_offset + 4];
+        debuglink.pwrite(debug_file.as_bytes(), 0).unwrap();
+        debuglink
+            .pwrite_with(crc32, crc_offset, self.ctx.le)
+            .unwrap();
+        let section = SectionBuilder::new(debuglink.len() as u64)
+            .section_type(SectionType::Raw {
+                typ: section_header::SHT_PROGBITS,
+                flags: 0,
+            })
+            .align(Some(4));
+        self.add_progbits(".gnu_debuglink".to_string(), section, debuglink.into());
+    }
+    /// Add the `.note.gnu.build-id` section, with a single note whose descriptor is `build_id`
+    pub fn add_build_id(&mut self, build_id: &[u8]) {
+        fn pad4(size: usize) -> usize {
+            (size + 3) & !3
+        }
+        let name_size = pad4(GNU_NOTE_OWNER.len());
+        let mut note = vec![0u8; 12 + name_size + pad4(build_id.len())];
+        note.pwrite_with(GNU_NOTE_OWNER.len() as u32, 0, self.ctx.le)
+            .unwrap();
+        note.pwrite_with(build_id.len() as u32, 4, self.ctx.le)
+            .unwrap();
+        note.pwrite_with(NT_GNU_BUILD_ID, 8, self.ctx.le).unwrap();
+        note.pwrite(GNU_NOTE_OWNER, 12).unwrap();
+        note.pwrite(build_id, 12 + name_size).unwrap();
+        let section = SectionBuilder::new(note.len() as u64)
+            .section_type(SectionType::Raw {
+                typ: section_header::SHT_NOTE,
+                flags: u64::from(section_header::SHF_ALLOC),
+            })
+            .align(Some(4));
+        self.add_progbits(".note.gnu.build-id".to_string(), section, note.into());
+    }
+    /// Add the `.comment` section, with the null terminated strings of `comment`
+    pub fn add_comment(&mut self, comment: &'a [u8]) {
+        let section = SectionBuilder::new(comment.len() as u64)
+            .section_type(SectionType::String)
+            .align(Some(1));
+        self.add_progbits(".comment".to_string(), section, comment.into());
+    }
+    /// Add the `.eh_frame` section, with the unwinding information of every function; its
+    /// relocations are added by `link_eh_frame`
+    pub fn add_eh_frame(&mut self, eh_frame: EhFrame<'a>) {
+        let section = SectionBuilder::new(eh_frame.data.len() as u64)
+            .section_type(SectionType::Bits)
+            .alloc()
+            .align(Some(8));
+        self.add_progbits(".eh_frame".to_string(), section, eh_frame.data.into());
+        self.eh_frame_locations = eh_frame.initial_locations;
+    }
+    /// Relocate the initial location of every FDE in `.eh_frame` to its function
+    pub fn link_eh_frame(&mut self) -> Result<(), ArtifactError> {
+        if self.eh_frame_locations.is_empty() {
+            return Ok(());
+        }
+        let reloc = match self.architecture {
+            Architecture::X86_64 => reloc::R_X86_64_PC32,
+            Architecture::I386 | Architecture::I586 | Architecture::I686 => reloc::R_386_PC32,
+            Architecture::Aarch64(_) => reloc::R_AARCH64_PREL32,
+            architecture => {
+                return Err(ArtifactError::Unsupported(format!(
+                    "unwinding information for {}",
+                    architecture
+                )))
+            }
+        };
+        let idx = self.strings.get(".eh_frame").expect(".eh_frame in strings");
+        let (section_idx, _, _) = self.sections.get_full(&idx).expect(".eh_frame in sections");
+        let shndx = section_idx + 3;
+        for (offset, function) in std::mem::take(&mut self.eh_frame_locations) {
+            let function = self.strings.get(function).expect("function in strings");
+            let (sym_idx, _, _) = self
+                .symbols
+                .get_full(&function)
+                .expect("function present in symbols");
+            let reloc = RelocationBuilder::new(reloc)
+                .sym(sym_idx + self.special_symbols.len() + self.sections.len())
+                .offset(offset)
+                .addend(0)
+                .create();
+            self.add_reloc(".eh_frame", reloc, section_idx, shndx);
+        }
+        Ok(())
+    }
+    /// Add the `.note.syntax` section, with a note for every function with an assembly syntax hint
+    pub fn add_syntax_notes(&mut self) {
+        fn pad4(size: usize) -> usize {
+            (size + 3) & !3
+        }
+        let mut notes = Vec::new();
+        for &(name, syntax) in self.syntax_hints.iter() {
+            let typ = match syntax {
+                AsmSyntax::Intel => NT_FAERIE_INTEL_SYNTAX,
+                AsmSyntax::Att => NT_FAERIE_ATT_SYNTAX,
+            };
+            let desc_size = name.len() + 1;
+            let mut note = vec![0u8; 12 + pad4(SYNTAX_NOTE_OWNER.len()) + pad4(desc_size)];
+            note.pwrite_with(SYNTAX_NOTE_OWNER.len() as u32, 0, self.ctx.le)
+                .unwrap();
+            note.pwrite_with(desc_size as u32, 4, self.ctx.le).unwrap();
+            note.pwrite_with(typ, 8, self.ctx.le).unwrap();
+            note.pwrite(SYNTAX_NOTE_OWNER, 12).unwrap();
+            note.pwrite(name.as_bytes(), 12 + pad4(SYNTAX_NOTE_OWNER.len()))
+                .unwrap();
+            notes.extend(note);
+        }
+        let section = SectionBuilder::new(notes.len() as u64).section_type(SectionType::Raw {
+            typ: section_header::SHT_NOTE,
+            flags: 0,
+        });
+        self.add_progbits(".note.syntax".to_string(), section, notes.into());
+    }
+    pub fn add_odr_table(&mut self, entries: &[(String, u64)]) {
+        let mut table = vec![0u8; entries.len() * SIZEOF_ODRTAB_ENTRY];
+        for (i, (type_name, hash)) in entries.iter().enumerate() {
+            let (_, name_offset) = self.new_string(type_name.clone());
+            let offset = i * SIZEOF_ODRTAB_ENTRY;
+            table
+                .pwrite_with(name_offset as u64, offset, self.ctx.le)
+                .unwrap();
+            table.pwrite_with(*hash, offset + 8, self.ctx.le).unwrap();
+        }
+        let section = SectionBuilder::new(table.len() as u64).section_type(SectionType::OdrTab);
+        self.add_progbits(".llvm_odrtab".to_string(), section, table.into());
+    }
     pub fn import(&mut self, import: String, kind: &ImportKind) {
         let (idx, offset) = self.new_string(import);
-        let symbol = SymbolBuilder::new(SymbolType::Import)
+        let typ = if kind.is_weak() {
+            SymbolType::WeakImport
+        } else {
+            SymbolType::Import
+        };
+        let symbol = SymbolBuilder::new(typ).name_offset(offset).create();
+        self.imports.insert(idx, kind.clone());
+        self.symbols.insert(idx, symbol);
+    }
+    pub fn absolute(&mut self, name: String, address: u64) {
+        let (idx, offset) = self.new_string(name);
+        let symbol = SymbolBuilder::new(SymbolType::Absolute)
             .name_offset(offset)
+            .value(address)
             .create();
-        self.imports.insert(idx, kind.clone());
         self.symbols.insert(idx, symbol);
     }
-    pub fn link(&mut self, l: &LinkAndDecl) {
+    /// A common symbol's value is its alignment, and the linker allocates it
+    pub fn common(&mut self, name: String, size: u64, align: u64) {
+        let (idx, offset) = self.new_string(name);
+        let symbol = SymbolBuilder::new(SymbolType::Common)
+            .name_offset(offset)
+            .value(align)
+            .size(size as usize)
+            .create();
+        self.symbols.insert(idx, symbol);
+    }
+    pub fn ifunc(&mut self, name: String, resolver: &str) {
+        let resolver = self
+            .strings
+            .get(resolver)
+            .and_then(|idx| self.symbols.get(&idx))
+            .copied()
+            .expect("resolver present in symbols");
+        let (idx, offset) = self.new_string(name);
+        let symbol = SymbolBuilder::new(SymbolType::IFunc)
+            .name_offset(offset)
+            .section_index(resolver.st_shndx)
+            .value(resolver.st_value)
+            .size(resolver.st_size as usize)
+            .create();
+        self.ifuncs.insert(idx);
+        self.symbols.insert(idx, symbol);
+    }
+    /// Add a local label at the `auipc` computing the high half of a RISC-V pc-relative address,
+    /// since the relocation of its low half refers to the label rather than the target
+    pub fn add_pcrel_label(&mut self, l: &LinkAndDecl) {
+        if !matches!(l.reloc, Reloc::Auto | Reloc::AutoWithAddend { .. })
+            || !riscv::is_riscv(self.architecture)
+            || !riscv::needs_pcrel_label(l)
+        {
+            return;
+        }
+        let from_idx = self
+            .strings
+            .get(l.from.name)
+            .expect("from present in strings");
+        let from = self.symbols[&from_idx];
+        let key = (from_idx, l.at);
+        if self.pcrel_labels.contains_key(&key) {
+            return;
+        }
+        let name = format!(".Lpcrel_hi{}", self.pcrel_labels.len());
+        let (_, offset) = self.new_string(name);
+        let label = SymbolBuilder::new(SymbolType::None)
+            .name_offset(offset)
+            .section_index(from.st_shndx)
+            .value(from.st_value + l.at)
+            .create();
+        self.pcrel_labels.insert(key, self.special_symbols.len());
+        self.special_symbols.push(label);
+    }
+    pub fn link(&mut self, l: &LinkAndDecl) -> Result<(), ArtifactError> {
         debug!("Link: {:?}", l);
         let (to_idx, to_shndx) = {
             let to_idx = self.strings.get_or_intern(l.to.name);
@@ -688,17 +1410,131 @@ impl<'a> Elf<'a> {
                 )
             }
         };
-        let (reloc, addend) = match l.reloc {
-            Reloc::Auto => {
-                match *l.from.decl {
+        let relocs = match l.reloc {
+            Reloc::Auto | Reloc::AutoWithAddend { .. } => self.auto_relocations(l),
+            Reloc::Raw { reloc, addend } => vec![(0, reloc, addend)],
+            Reloc::Absolute { addend } => {
+                let size = if self.ctx.is_big() { 8 } else { 4 };
+                vec![(0, self.debug_relocation(l, size)?, addend)]
+            }
+            Reloc::Kind(kind) => self.kind_relocations(l, kind)?,
+            Reloc::Debug { size, addend } => vec![(0, self.debug_relocation(l, size)?, addend)],
+            Reloc::Authenticated { .. } => {
+                return Err(ArtifactError::UnsupportedRelocation(format!(
+                    "authenticated pointers are not yet supported for ELF: {:?}",
+                    l
+                )));
+            }
+            Reloc::Subtrahend { .. } | Reloc::Minuend { .. } => {
+                return Err(ArtifactError::UnsupportedRelocation(format!(
+                    "differences of symbols are not yet supported for ELF: {:?}",
+                    l
+                )));
+            }
+        };
+
+        let sym_idx = match *l.to.decl {
+            // an indirect function shares its resolver's section, so it must be referred to by name
+            Decl::Defined(_) if self.ifuncs.contains(&self.strings.get_or_intern(l.to.name)) => {
+                to_idx
+            }
+            // the linker can only tell which string in a mergeable section is referred to by its
+            // symbol, since addends, e.g., of pc-relative relocations, may point outside of it
+            // thread-local variables are addressed relative to their module's TLS block, which only
+            // the linker knows the layout of
+            Decl::Defined(DefinedDecl::Data(d))
+                if d.get_datatype() == DataType::String
+                    || d.get_datatype() == DataType::ObjcMethodName
+                    || d.is_thread_local() =>
+            {
+                to_idx
+            }
+            // the section of a COMDAT function may be discarded in favor of another object's copy
+            Decl::Defined(DefinedDecl::Function(d)) if d.is_comdat() => to_idx,
+            Decl::Defined(_) => {
+                // We don't emit symbols for null + strtab + symtab, and
+                // section symbols come after special symbols.
+                (to_shndx - 3) + self.special_symbols.len()
+            }
+            Decl::Import(_) | Decl::AbsoluteSymbol(_) | Decl::Common { .. } => to_idx,
+        };
+
+        let link_addend = match l.reloc {
+            Reloc::AutoWithAddend { addend } => addend,
+            _ => 0,
+        };
+        for (delta, reloc, addend) in relocs {
+            // the low half of a RISC-V pc-relative address refers to the label at its high half,
+            // which already includes the link's addend
+            let (sym_idx, link_addend) =
+                if reloc == reloc::R_RISCV_PCREL_LO12_I && riscv::is_riscv(self.architecture) {
+                    let label = self
+                        .pcrel_labels
+                        .get(&(self.strings.get_or_intern(l.from.name), l.at))
+                        .copied()
+                        .unwrap_or(sym_idx);
+                    (label, 0)
+                } else {
+                    (sym_idx, link_addend)
+                };
+            let reloc = RelocationBuilder::new(reloc)
+                .sym(sym_idx)
+                .offset(l.at + delta)
+                .addend(i64::from(addend) + link_addend)
+                .create();
+            self.add_reloc(l.from.name, reloc, from_idx, from_shndx)
+        }
+        Ok(())
+    }
+    /// The relocations, as `(offset from link.at, type, addend)`, that faerie deduces for `l`
+    fn auto_relocations(&self, l: &LinkAndDecl) -> Vec<(u64, u32, i32)> {
+        match self.architecture {
+            #[cfg(feature = "sparc")]
+            Architecture::Sparc64 | Architecture::Sparcv9 => sparc::auto_relocations(l),
+            #[cfg(feature = "hexagon")]
+            Architecture::Hexagon => hexagon::auto_relocations(l),
+            architecture if riscv::is_riscv(architecture) => {
+                riscv::auto_relocations(l, self.ctx.is_big())
+            }
+            Architecture::S390x => s390x::auto_relocations(l),
+            Architecture::Powerpc64 | Architecture::Powerpc64le => {
+                ppc64::auto_relocations(l, !self.ctx.le.is_little())
+            }
+            _ => {
+                let (reloc, addend) = match *l.from.decl {
                     Decl::Defined(DefinedDecl::Function { .. }) => {
                         match *l.to.decl {
                             // NB: this now forces _all_ function references, whether local or not, through the PLT
                             // although we're not in the worst company here: https://github.com/ocaml/ocaml/pull/1330
                             Decl::Defined(DefinedDecl::Function { .. })
-                            | Decl::Import(ImportKind::Function) => (reloc::R_X86_64_PLT32, -4),
-                            Decl::Defined(DefinedDecl::Data { .. }) => (reloc::R_X86_64_PC32, -4),
-                            Decl::Import(ImportKind::Data) => (reloc::R_X86_64_GOTPCREL, -4),
+                            | Decl::Import(ImportKind::Function)
+                            | Decl::Import(ImportKind::WeakFunction) => (reloc::R_X86_64_PLT32, -4),
+                            // e.g., `leaq x@tlsgd(%rip), %rdi`, `movq x@gottpoff(%rip), %rax`, or
+                            // `leaq x@dtpoff(%rax), %rcx`, which is relative to the module's block
+                            Decl::Defined(DefinedDecl::Data(d)) if d.is_thread_local() => {
+                                match d.get_tls_model() {
+                                    TlsModel::GeneralDynamic => (reloc::R_X86_64_TLSGD, -4),
+                                    TlsModel::InitialExec => (reloc::R_X86_64_GOTTPOFF, -4),
+                                    TlsModel::LocalDynamic => (reloc::R_X86_64_DTPOFF32, 0),
+                                }
+                            }
+                            // position independent code references data which another module may
+                            // preempt through the GOT
+                            Decl::Defined(ref d @ DefinedDecl::Data { .. })
+                                if self.pic
+                                    && self.architecture == Architecture::X86_64
+                                    && (d.is_global() || d.is_weak())
+                                    && d.get_visibility() == Visibility::Default =>
+                            {
+                                (R_X86_64_GOTPCRELX, -4)
+                            }
+                            Decl::Defined(DefinedDecl::Data { .. }) | Decl::AbsoluteSymbol(_) => {
+                                (reloc::R_X86_64_PC32, -4)
+                            }
+                            // common symbols may be allocated in another module, like data imports
+                            Decl::Import(ImportKind::Data)
+                            | Decl::Import(ImportKind::WeakData)
+                            | Decl::Common { .. } => (reloc::R_X86_64_GOTPCREL, -4),
                             _ => panic!("unsupported relocation {:?}", l),
                         }
                     }
@@ -711,32 +1547,73 @@ impl<'a> Elf<'a> {
                         }
                     }
                     _ => panic!("unsupported relocation {:?}", l),
-                }
+                };
+                vec![(0, reloc, addend)]
             }
-            Reloc::Raw { reloc, addend } => (reloc, addend),
-            Reloc::Debug { size, addend } => match size {
-                4 => (reloc::R_X86_64_32, addend),
-                8 => (reloc::R_X86_64_64, addend),
-                _ => panic!("unsupported relocation {:?}", l),
-            },
+        }
+    }
+    /// The relocations, as `(offset from link.at, type, addend)`, of the given `kind`
+    fn kind_relocations(
+        &self,
+        l: &LinkAndDecl,
+        kind: RelocationKind,
+    ) -> Result<Vec<(u64, u32, i32)>, ArtifactError> {
+        let pointer_size = if self.ctx.is_big() { 8 } else { 4 };
+        let local_dynamic = match *l.to.decl {
+            Decl::Defined(DefinedDecl::Data(d)) => {
+                d.is_thread_local() && d.get_tls_model() == TlsModel::LocalDynamic
+            }
+            _ => false,
         };
-        let addend = i64::from(addend);
-
-        let sym_idx = match *l.to.decl {
-            Decl::Defined(_) => {
-                // We don't emit symbols for null + strtab + symtab, and
-                // section symbols come after special symbols.
-                (to_shndx - 3) + self.special_symbols.len()
+        let (reloc, addend) = match (kind, self.architecture) {
+            (RelocationKind::Absolute, _) => (self.debug_relocation(l, pointer_size)?, 0),
+            // the GOT entry of the module of a local-dynamic variable, which is passed to
+            // `__tls_get_addr`, e.g., `leaq x@tlsld(%rip), %rdi`
+            (RelocationKind::GotPcRelative, Architecture::X86_64) if local_dynamic => {
+                (reloc::R_X86_64_TLSLD, -4)
+            }
+            (RelocationKind::PcRelative, Architecture::X86_64) => (reloc::R_X86_64_PC32, -4),
+            (RelocationKind::Branch, Architecture::X86_64) => (reloc::R_X86_64_PLT32, -4),
+            (RelocationKind::GotPcRelative, Architecture::X86_64) => (reloc::R_X86_64_GOTPCREL, -4),
+            _ => {
+                return Err(ArtifactError::UnsupportedRelocation(format!(
+                    "{:?} relocations are not yet supported for {} ELF: {:?}",
+                    kind, self.architecture, l
+                )))
             }
-            Decl::Import(_) => to_idx,
         };
-
-        let reloc = RelocationBuilder::new(reloc)
-            .sym(sym_idx)
-            .offset(l.at)
-            .addend(addend)
-            .create();
-        self.add_reloc(l.from.name, reloc, from_idx, from_shndx)
+        Ok(vec![(0, reloc, addend)])
+    }
+    /// The relocation type for a `size` byte pointer in a debug section, or any other absolute
+    /// pointer
+    fn debug_relocation(&self, l: &LinkAndDecl, size: u8) -> Result<u32, ArtifactError> {
+        Ok(match (self.architecture, size) {
+            #[cfg(feature = "sparc")]
+            (Architecture::Sparc64, _) | (Architecture::Sparcv9, _) => {
+                sparc::debug_relocation(l, size)
+            }
+            #[cfg(feature = "hexagon")]
+            (Architecture::Hexagon, _) => hexagon::debug_relocation(l, size),
+            (architecture, _) if riscv::is_riscv(architecture) => riscv::debug_relocation(l, size),
+            (Architecture::S390x, _) => s390x::debug_relocation(l, size),
+            (Architecture::Powerpc64, _) | (Architecture::Powerpc64le, _) => {
+                ppc64::debug_relocation(l, size)
+            }
+            (Architecture::X86_64, 4) => reloc::R_X86_64_32,
+            (Architecture::X86_64, 8) => reloc::R_X86_64_64,
+            (Architecture::Aarch64(_), 4) => reloc::R_AARCH64_ABS32,
+            (Architecture::Aarch64(_), 8) => reloc::R_AARCH64_ABS64,
+            (Architecture::Arm(_), 4) => reloc::R_ARM_ABS32,
+            (Architecture::I386, 4) | (Architecture::I586, 4) | (Architecture::I686, 4) => {
+                reloc::R_386_32
+            }
+            _ => {
+                return Err(ArtifactError::UnsupportedRelocation(format!(
+                    "{} byte pointers are not yet supported for {} ELF: {:?}",
+                    size, self.architecture, l
+                )))
+            }
+        })
     }
     fn add_reloc(&mut self, relocee: &str, reloc: Relocation, idx: usize, shndx: usize) {
         debug!(
@@ -785,7 +1662,7 @@ impl<'a> Elf<'a> {
         }
     }
     pub fn write<T: Write + Seek>(mut self, file: T) -> goblin::error::Result<()> {
-        use goblin::elf::section_header::{SHN_LORESERVE, SHN_XINDEX};
+        use goblin::elf::section_header::{SHN_ABS, SHN_COMMON, SHN_LORESERVE, SHN_XINDEX};
         let mut file = BufWriter::new(file);
 
         /////////////////////////////////////
@@ -810,6 +1687,29 @@ impl<'a> Elf<'a> {
             .iter()
             .fold(0, |acc, (_, &(ref _shdr, ref rels))| rels.len() + acc)
             * Relocation::size(true, self.ctx);
+        // the relocations of a group's member are also members, and follow the other sections;
+        // the group's size is reduced if there are none
+        for &(group, member, _) in self.comdat_groups.iter() {
+            match self.relocations.get_full_mut(&member) {
+                Some((position, _, (relocations, _))) => {
+                    relocations.sh_flags |= section_header::SHF_GROUP as u64;
+                    let shndx = 3 + self.sections.len() + need_symtab_shndx as usize + position;
+                    self.code
+                        .get_mut(&group)
+                        .expect("group present in code")
+                        .to_mut()
+                        .pwrite_with(shndx as u32, 8, self.ctx.le)
+                        .expect("group has room for its relocations");
+                }
+                None => {
+                    self.sections
+                        .get_mut(&group)
+                        .expect("group present in sections")
+                        .header
+                        .sh_size = 8
+                }
+            }
+        }
         let nonexec_stack_note_name_offset = self.new_string(".note.GNU-stack".into()).1;
         let strtab_offset = self.sizeof_bits as u64;
 
@@ -840,7 +1740,18 @@ impl<'a> Elf<'a> {
         let mut header = Header::new(self.ctx);
         let machine: MachineTag = self.architecture.into();
         header.e_machine = machine.0;
+        if riscv::is_riscv(self.architecture) {
+            header.e_flags = riscv::flags(self.architecture);
+        }
         header.e_type = header::ET_REL;
+        // sections have no addresses yet, so the entry is relative to its section, like symbols
+        if let Some(symbol) = self
+            .entry_point
+            .and_then(|entry| self.strings.get(entry))
+            .and_then(|idx| self.symbols.get(&idx))
+        {
+            header.e_entry = symbol.st_value;
+        }
         header.e_shoff = sh_offset;
         header.e_shnum = if self.nsections >= SHN_LORESERVE.into() {
             0
@@ -859,7 +1770,7 @@ impl<'a> Elf<'a> {
         /////////////////////////////////////
 
         for (_idx, bytes) in self.code.drain(..) {
-            file.write_all(bytes)?;
+            file.write_all(&bytes)?;
         }
         let after_code = file.seek(Current(0))?;
         debug!("after_code {:#x}", after_code);
@@ -897,6 +1808,24 @@ impl<'a> Elf<'a> {
         symtab.sh_info = (self.special_symbols.len() + self.sections.len() + self.nlocals) as u32;
         section_headers.push(symtab);
 
+        // the signature of a group is the index of its symbol, which is only known now
+        for (group, _, signature) in self.comdat_groups.iter() {
+            let signature = self
+                .strings
+                .get(*signature)
+                .expect("group signature in strings");
+            let (signature_idx, _, _) = self
+                .symbols
+                .get_full(&signature)
+                .expect("group signature present in symbols");
+            self.sections
+                .get_mut(group)
+                .expect("group present in sections")
+                .header
+                .sh_info =
+                (self.special_symbols.len() + self.sections.len() + signature_idx) as u32;
+        }
+
         /////////////////////////////////////
         // Strtab
         /////////////////////////////////////
@@ -957,7 +1886,11 @@ impl<'a> Elf<'a> {
                     .gwrite_with(sym.st_shndx as u32, &mut offset, self.ctx.le)
                     .expect("preallocated shndx vector has enough space for symbols");
             }
-            if sym.st_shndx >= SHN_LORESERVE as usize {
+            // absolute and common symbols have reserved section indexes, which must be kept as is
+            if sym.st_shndx >= SHN_LORESERVE as usize
+                && sym.st_shndx != SHN_ABS as usize
+                && sym.st_shndx != SHN_COMMON as usize
+            {
                 sym.st_shndx = SHN_XINDEX as usize;
             }
             file.iowrite_with(sym, self.ctx)?;
@@ -1044,22 +1977,121 @@ impl<'a> Elf<'a> {
     }
 }
 
-pub fn to_bytes(artifact: &Artifact) -> goblin::error::Result<Vec<u8>> {
+pub fn map(artifact: &Artifact) -> Result<Vec<artifact::MapSection>, ArtifactError> {
+    let mut elf = Elf::new(artifact);
+    for def in artifact.definitions() {
+        elf.add_definition(def)?;
+    }
+    let mut sections = elf
+        .sections
+        .values()
+        .map(|section| artifact::MapSection {
+            name: elf
+                .strings
+                .resolve(section.name)
+                .expect("section name in strings")
+                .to_string(),
+            addr: section.header.sh_addr,
+            size: section.header.sh_size,
+            symbols: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    for (&idx, symbol) in elf.symbols.iter() {
+        // imports are undefined, and absolute symbols are not in any section
+        if symbol.st_shndx < 3 || symbol.st_shndx == section_header::SHN_ABS as usize {
+            continue;
+        }
+        let name = elf.strings.resolve(idx).expect("symbol name in strings");
+        sections[symbol.st_shndx - 3].symbols.push((
+            name.to_string(),
+            symbol.st_value,
+            symbol.st_size,
+        ));
+    }
+    Ok(sections)
+}
+
+pub fn to_bytes(artifact: &Artifact) -> Result<Vec<u8>, ArtifactError> {
+    let mut buffer = Cursor::new(Vec::new());
+    write(artifact, &mut buffer)?;
+    Ok(buffer.into_inner())
+}
+
+/// The sections of the ELF object emitted for `artifact`, in the order they appear in the file
+pub fn sections(artifact: &Artifact) -> Result<Vec<artifact::SectionInfo>, ArtifactError> {
+    let elf = with_sections(artifact)?;
+    Ok(elf.sections().collect())
+}
+
+/// Create the ELF object for `artifact`, with all of its sections added
+fn with_sections<'a>(artifact: &'a Artifact) -> Result<Elf<'a>, ArtifactError> {
     // TODO: make new fully construct the elf object, e.g., the definitions, imports, and links don't take self
     // this means that a call to new has a fully constructed object ready to marshal into bytes, similar to the mach backend
-    let mut elf = Elf::new(&artifact);
+    let mut elf = Elf::new(artifact);
     for def in artifact.definitions() {
         debug!("Def: {:?}", def);
-        elf.add_definition(def);
+        elf.add_definition(def)?;
+    }
+    // NB: sections must all be added before linking, since symbol indexes depend on their number
+    if !elf.syntax_hints.is_empty() {
+        elf.add_syntax_notes();
+    }
+    for raw in artifact.raw_sections() {
+        debug!("Raw section: {}", raw.name);
+        elf.add_raw_section(raw);
+    }
+    if let Some((debug_file, crc32)) = artifact.debuglink() {
+        elf.add_debuglink(debug_file, crc32);
+    }
+    if let Some(build_id) = artifact.build_id() {
+        elf.add_build_id(build_id);
+    }
+    if !artifact.comment().is_empty() {
+        elf.add_comment(artifact.comment());
+    }
+    if let Some(eh_frame) = artifact.eh_frame()? {
+        elf.add_eh_frame(eh_frame);
+    }
+    if !artifact.odr_entries().is_empty() {
+        elf.add_odr_table(artifact.odr_entries());
+    }
+    for (name, flags) in artifact.section_flags() {
+        let section = elf
+            .strings
+            .get(name)
+            .and_then(|idx| elf.sections.get_mut(&idx))
+            .ok_or_else(|| ArtifactError::NoSuchSection(name.to_string()))?;
+        section.header.sh_flags |= flags;
+    }
+    Ok(elf)
+}
+
+/// Write `artifact` as an ELF object directly to `sink`, without buffering the object in memory
+pub fn write<T: Write + Seek>(artifact: &Artifact, sink: T) -> Result<(), ArtifactError> {
+    let mut elf = with_sections(artifact)?;
+    for (name, resolver) in artifact.ifuncs() {
+        debug!("IFunc: {} resolved by {}", name, resolver);
+        elf.ifunc(name.to_string(), resolver);
     }
     for (ref import, ref kind) in artifact.imports() {
         debug!("Import: {:?} -> {:?}", import, kind);
         elf.import(import.to_string(), kind);
     }
+    for (name, address) in artifact.absolute_symbols() {
+        debug!("Absolute: {} = {:#x}", name, address);
+        elf.absolute(name.to_string(), address);
+    }
+    for (name, size, align) in artifact.common_symbols() {
+        debug!("Common: {} ({} bytes, aligned to {})", name, size, align);
+        elf.common(name.to_string(), size, align);
+    }
+    // NB: labels are special symbols, so they must all be added before linking too
     for link in artifact.links() {
-        elf.link(&link);
+        elf.add_pcrel_label(&link);
     }
-    let mut buffer = Cursor::new(Vec::new());
-    elf.write(&mut buffer)?;
-    Ok(buffer.into_inner())
+    for link in artifact.links() {
+        elf.link(&link)?;
+    }
+    elf.link_eh_frame()?;
+    Ok(elf.write(sink)?)
 }
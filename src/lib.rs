@@ -13,15 +13,26 @@ use goblin::container;
 
 type Ctx = container::Ctx;
 
+mod archive;
+mod coff;
 mod elf;
 mod mach;
+#[cfg(all(unix, feature = "mmap"))]
+mod mmap;
 mod target;
+mod wasm;
+
+pub use crate::archive::Archive;
+pub use crate::mach::{FatBinary, FatSlice, MachPlatform};
 
 pub mod artifact;
 pub use crate::artifact::{
     decl::{
-        DataDecl, DataImportDecl, DataType, Decl, FunctionDecl, FunctionImportDecl, Scope,
-        SectionDecl, SectionKind, Visibility,
+        AsmSyntax, DataDecl, DataImportDecl, DataType, Decl, DeclAttributes, FunctionDecl,
+        FunctionImportDecl, Scope, SectionDecl, SectionKind, TlsModel, Visibility,
     },
-    Artifact, ArtifactBuilder, ArtifactError, Data, ImportKind, Link, Reloc,
+    AlignError, Artifact, ArtifactBuilder, ArtifactError, ArtifactGcReport, ArtifactPatch,
+    ArtifactSchema, Data, DataInCodeEntry, ImportKind, Link, ObjectFormat, PtrauthKey, Reloc,
+    RelocationKind, SchemaViolation, SchemaViolationKind, SectionInfo, ValidationError,
+    VerificationError,
 };
@@ -1,30 +1,61 @@
 //! The Mach 32/64 bit backend for transforming an artifact to a valid, mach-o object file.
 
 use crate::artifact::{
-    Data, DataType, Decl, DefinedDecl, Definition, ImportKind, Reloc, SectionKind,
+    AlignError, Data, DataInCodeEntry, DataType, Decl, DefinedDecl, Definition, EhFrame,
+    ImportKind, MapSection, PtrauthKey, RawSection, Reloc, RelocationKind, SectionInfo,
+    SectionKind, ValidationError, VerificationError, Visibility,
 };
 use crate::target::make_ctx;
-use crate::{Artifact, Ctx};
+use crate::{Artifact, ArtifactError, Ctx};
 
 use indexmap::IndexMap;
 use scroll::ctx::SizeWith;
 use scroll::{IOwrite, Pwrite};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::SeekFrom::*;
 use std::io::{BufWriter, Cursor, Seek, Write};
+use std::path::Path;
 use string_interner::StringInterner;
-use target_lexicon::Architecture;
+use target_lexicon::{Architecture, BinaryFormat};
 
 use goblin::mach::constants::{
-    S_ATTR_DEBUG, S_ATTR_PURE_INSTRUCTIONS, S_ATTR_SOME_INSTRUCTIONS, S_CSTRING_LITERALS,
-    S_REGULAR, S_ZEROFILL,
+    SECTION_TYPE, S_ATTR_DEBUG, S_ATTR_LIVE_SUPPORT, S_ATTR_NO_DEAD_STRIP, S_ATTR_NO_TOC,
+    S_ATTR_PURE_INSTRUCTIONS, S_ATTR_SOME_INSTRUCTIONS, S_ATTR_STRIP_STATIC_SYMS, S_COALESCED,
+    S_CSTRING_LITERALS, S_LITERAL_POINTERS, S_REGULAR, S_THREAD_LOCAL_REGULAR,
+    S_THREAD_LOCAL_VARIABLES, S_THREAD_LOCAL_ZEROFILL, S_ZEROFILL,
 };
 use goblin::mach::cputype;
 use goblin::mach::header::{Header, MH_OBJECT, MH_SUBSECTIONS_VIA_SYMBOLS};
-use goblin::mach::load_command::SymtabCommand;
+use goblin::mach::load_command::{
+    self, LinkeditDataCommand, SymtabCommand, LC_DATA_IN_CODE, SIZEOF_LINKEDIT_DATA_COMMAND,
+};
 use goblin::mach::relocation::{RelocType, RelocationInfo, SIZEOF_RELOCATION_INFO};
 use goblin::mach::segment::{Section, Segment};
-use goblin::mach::symbols::Nlist;
+use goblin::mach::symbols::{Nlist, N_OSO};
+
+/// An ARM64e signed pointer; goblin does not have this yet
+const ARM64_RELOC_AUTHENTICATED_POINTER: RelocType = 11;
+/// A reference to a thread-local variable's descriptor on i386; goblin does not have this yet
+const GENERIC_RELOC_TLV: RelocType = 5;
+/// A note load command, pointing to arbitrary data with an owner; goblin does not have this yet
+const LC_NOTE: u32 = 0x31;
+/// The load command recording the platform, and the minimum OS and SDK versions; goblin does not
+/// have this yet
+const LC_BUILD_VERSION: u32 = 0x32;
+/// The size of an `LC_BUILD_VERSION` load command without any tool entries: `cmd`, `cmdsize`,
+/// `platform`, `minos`, `sdk` and `ntools`
+const SIZEOF_BUILD_VERSION_COMMAND: usize = 24;
+/// The size of an `LC_NOTE` load command: `cmd`, `cmdsize`, `data_owner[16]`, `offset` and `size`
+const SIZEOF_NOTE_COMMAND: usize = 40;
+/// The owner of the note recording the address spaces of sections
+const ADDRESS_SPACE_NOTE_OWNER: &[u8; 16] = b"faerie.addrspace";
+/// The owner of the note recording the build id
+const BUILD_ID_NOTE_OWNER: &[u8; 16] = b"faerie.buildid\0\0";
+/// The function which initializes thread-local variables on first access, which every thread-local
+/// variable descriptor points to; like all other symbols, its `_` prefix is added when written
+const TLV_BOOTSTRAP: &str = "_tlv_bootstrap";
+/// The size of an address space note entry: `sectname[16]`, `segname[16]` and a `u64` address space
+const SIZEOF_ADDRESS_SPACE_NOTE_ENTRY: usize = 40;
 
 struct CpuType(cputype::CpuType);
 
@@ -46,14 +77,72 @@ impl From<Architecture> for CpuType {
     }
 }
 
-fn align_to_align_exp(align: u64) -> u64 {
-    assert!(align != 0);
-    assert!(align.is_power_of_two());
-    let mut align_exp = 0;
-    while 1 << align_exp != align {
-        align_exp += 1;
+/// The cpu subtype of an architecture, which selects the variant of its `CpuType`
+struct CpuSubtype(cputype::CpuSubType);
+
+impl From<Architecture> for CpuSubtype {
+    fn from(architecture: Architecture) -> CpuSubtype {
+        use goblin::mach::cputype::*;
+        use target_lexicon::Architecture::*;
+        CpuSubtype(match architecture {
+            X86_64 => CPU_SUBTYPE_X86_64_ALL,
+            I386 | I586 | I686 => CPU_SUBTYPE_I386_ALL,
+            Aarch64(_) => CPU_SUBTYPE_ARM64_ALL,
+            Arm(arm) => {
+                // imported only here, since its `Arm` clashes with `Architecture::Arm`
+                use target_lexicon::ArmArchitecture::*;
+                match arm {
+                    Armv4t => CPU_SUBTYPE_ARM_V4T,
+                    Armv5tej => CPU_SUBTYPE_ARM_V5TEJ,
+                    Armv6 | Armv6j | Armv6k | Armv6z | Armv6kz | Armv6t2 => CPU_SUBTYPE_ARM_V6,
+                    Armv6m | Thumbv6m => CPU_SUBTYPE_ARM_V6M,
+                    Armv7 | Armv7a | Armv7ve | Armv7r | Thumbv7a | Thumbv7neon => {
+                        CPU_SUBTYPE_ARM_V7
+                    }
+                    Armv7s => CPU_SUBTYPE_ARM_V7S,
+                    Armv7m | Thumbv7m => CPU_SUBTYPE_ARM_V7M,
+                    Thumbv7em => CPU_SUBTYPE_ARM_V7EM,
+                    Armv8 | Armv8a | Armv8_1a | Armv8_2a | Armv8_3a | Armv8_4a | Armv8_5a
+                    | Armv8r => CPU_SUBTYPE_ARM_V8,
+                    _ => CPU_SUBTYPE_ARM_ALL,
+                }
+            }
+            Sparc => CPU_SUBTYPE_SPARC_ALL,
+            Powerpc | Powerpc64 | Powerpc64le => CPU_SUBTYPE_POWERPC_ALL,
+            _ => 0,
+        })
+    }
+}
+
+/// An alignment, which is always a power of two, since Mach-o stores it as its exponent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Align(u32);
+
+impl Align {
+    /// The alignment of `bytes`, which must be a power of two
+    pub fn from_bytes(bytes: u64) -> Result<Align, AlignError> {
+        if bytes.is_power_of_two() {
+            Ok(Align(bytes.trailing_zeros()))
+        } else {
+            Err(AlignError(bytes))
+        }
+    }
+    /// The alignment of `1 << log2` bytes
+    pub fn from_log2(log2: u32) -> Align {
+        Align(log2)
+    }
+    /// The alignment of a declaration, which defaults to a single byte
+    pub fn of(align: Option<u64>) -> Result<Align, AlignError> {
+        Align::from_bytes(align.unwrap_or(1))
+    }
+    /// The exponent of the alignment, which is how Mach-o encodes it
+    pub fn log2(self) -> u32 {
+        self.0
+    }
+    /// The alignment in bytes
+    pub fn bytes(self) -> u64 {
+        1 << self.0
     }
-    align_exp
 }
 
 type SectionIndex = usize;
@@ -62,16 +151,22 @@ type StrtableOffset = u64;
 const CODE_SECTION_INDEX: SectionIndex = 0;
 const DATA_SECTION_INDEX: SectionIndex = 1;
 const CSTRING_SECTION_INDEX: SectionIndex = 2;
-const BSS_SECTION_INDEX: SectionIndex = 3;
-const NUM_DEFAULT_SECTIONS: SectionIndex = 4;
+const RODATA_SECTION_INDEX: SectionIndex = 3;
+const BSS_SECTION_INDEX: SectionIndex = 4;
+const NUM_DEFAULT_SECTIONS: SectionIndex = 5;
 
 /// A builder for creating a 32/64 bit Mach-o Nlist symbol
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct SymbolBuilder {
     name: StrtableOffset,
     section: Option<SectionIndex>,
     global: bool,
+    weak: bool,
+    private_extern: bool,
     import: bool,
+    weak_import: bool,
+    common_align: Option<u64>,
+    absolute: bool,
     offset: u64,
     segment_relative_offset: u64,
 }
@@ -83,7 +178,12 @@ impl SymbolBuilder {
             name,
             section: None,
             global: false,
+            weak: false,
+            private_extern: false,
             import: false,
+            weak_import: false,
+            common_align: None,
+            absolute: false,
             offset: 0,
             segment_relative_offset: 0,
         }
@@ -98,6 +198,16 @@ impl SymbolBuilder {
         self.global = global;
         self
     }
+    /// Is this symbol a weak definition, i.e., external, but overridden by a non-weak definition?
+    pub fn weak(mut self, weak: bool) -> Self {
+        self.weak = weak;
+        self
+    }
+    /// Is this symbol a private external, i.e., external to its file, but not to the linked image?
+    pub fn private_extern(mut self, private_extern: bool) -> Self {
+        self.private_extern = private_extern;
+        self
+    }
     pub fn offset(mut self, offset: u64) -> Self {
         self.offset = offset;
         self
@@ -116,31 +226,70 @@ impl SymbolBuilder {
         self.import = true;
         self
     }
+    /// Is this symbol an import which is allowed to be missing, i.e., a weak reference?
+    pub fn weak_import(mut self) -> Self {
+        self.import = true;
+        self.weak_import = true;
+        self
+    }
+    /// Is this a common symbol of `size` bytes, aligned to `align`?
+    pub fn common(mut self, size: u64, align: u64) -> Self {
+        self.import = true;
+        self.offset = size;
+        self.common_align = Some(align);
+        self
+    }
+    /// Is this an absolute symbol, whose value is the fixed `address` rather than an offset into
+    /// a section?
+    pub fn absolute(mut self, address: u64) -> Self {
+        self.absolute = true;
+        self.offset = address;
+        self
+    }
     /// Finalize and create the symbol
     pub fn create(self) -> Nlist {
-        use goblin::mach::symbols::{NO_SECT, N_EXT, N_SECT, N_UNDF};
+        use goblin::mach::symbols::{
+            NO_SECT, N_ABS, N_EXT, N_PEXT, N_SECT, N_UNDF, N_WEAK_DEF, N_WEAK_REF,
+        };
         let n_strx = self.name;
         let mut n_sect = 0;
         let mut n_type = N_UNDF;
         let mut n_value = self.offset;
-        let n_desc = 0;
-        if self.global {
+        let mut n_desc = 0;
+        if self.global || self.weak {
             n_type |= N_EXT;
         } else {
             n_type &= !N_EXT;
         }
+        if self.weak {
+            n_desc |= N_WEAK_DEF;
+        }
+        if self.private_extern && n_type & N_EXT != 0 {
+            n_type |= N_PEXT;
+        }
         if let Some(idx) = self.section {
             n_sect = idx + 1; // add 1 because n_sect expects ordinal
             n_type |= N_SECT;
         }
 
         if self.import {
+            // imports are undefined, and always external, whether or not they were declared global
             n_sect = NO_SECT as usize;
-            // FIXME: this is broken i believe; we need to make it both undefined + global for imports
-            n_type = N_EXT;
+            n_type = N_UNDF | N_EXT;
             n_value = 0;
-        } else {
-            n_type |= N_SECT;
+            n_desc = 0;
+            if self.weak_import {
+                n_desc |= N_WEAK_REF;
+            }
+            // a common symbol's value is its size, and its description holds the log2 of its
+            // alignment, see `SET_COMM_ALIGN` in `<mach-o/nlist.h>`
+            if let Some(align) = self.common_align {
+                n_value = self.offset;
+                n_desc |= (align.max(1).trailing_zeros() as u16 & 0x0f) << 8;
+            }
+        } else if self.absolute {
+            n_sect = NO_SECT as usize;
+            n_type = (n_type & !N_SECT) | N_ABS;
         }
 
         Nlist {
@@ -164,6 +313,8 @@ struct RelocationBuilder {
     absolute: bool,
     size: u8,
     r_type: RelocType,
+    ptrauth: Option<(PtrauthKey, bool, u16)>,
+    section: Option<usize>,
 }
 
 impl RelocationBuilder {
@@ -175,6 +326,8 @@ impl RelocationBuilder {
             absolute: false,
             size: 0,
             r_type,
+            ptrauth: None,
+            section: None,
         }
     }
     /// This is an absolute relocation
@@ -187,10 +340,37 @@ impl RelocationBuilder {
         self.size = size;
         self
     }
+    /// This relocation is relative to the section with the 1-based `section_ordinal`, instead of
+    /// a symbol; the target's address must be stored at the relocated location
+    pub fn local(mut self, section_ordinal: usize) -> Self {
+        self.section = Some(section_ordinal);
+        self
+    }
+    /// This is an ARM64e signed pointer, signed with `key`, optionally blending in its address, and
+    /// `discriminator`
+    pub fn authenticated(mut self, key: PtrauthKey, addr_div: bool, discriminator: u16) -> Self {
+        self.r_type = ARM64_RELOC_AUTHENTICATED_POINTER;
+        self.absolute = true;
+        self.size = 8;
+        self.ptrauth = Some((key, addr_div, discriminator));
+        self
+    }
+    /// The value to store at the relocated location of an authenticated pointer, since Mach-o
+    /// relocations have no addend of their own: the low 32 bits are the `addend`, followed by the
+    /// 16 bit discriminator, 1 bit of address diversity, 2 bits of key, and finally the top bit is set
+    pub fn authenticated_value(&self, addend: i32) -> Option<u64> {
+        self.ptrauth.map(|(key, addr_div, discriminator)| {
+            u64::from(addend as u32)
+                | u64::from(discriminator) << 32
+                | u64::from(addr_div) << 48
+                | (key as u64) << 49
+                | 1 << 63
+        })
+    }
     /// Finalize and create the relocation
     pub fn create(self) -> RelocationInfo {
         // it basically goes sort of backwards than what you'd expect because C bitfields are bonkers
-        let r_symbolnum: u32 = self.symbol as u32;
+        let r_symbolnum: u32 = self.section.unwrap_or(self.symbol) as u32;
         let r_pcrel: u32 = if self.absolute { 0 } else { 1 } << 24;
         let r_length: u32 = match self.size {
             0 => {
@@ -204,7 +384,7 @@ impl RelocationBuilder {
             8 => 3,
             size => panic!("unsupported relocation size {}", size),
         } << 25;
-        let r_extern: u32 = 1 << 27;
+        let r_extern: u32 = if self.section.is_some() { 0 } else { 1 } << 27;
         let r_type = (self.r_type as u32) << 28;
         // r_symbolnum, 24 bits, r_pcrel 1 bit, r_length 2 bits, r_extern 1 bit, r_type 4 bits
         let r_info = r_symbolnum | r_pcrel | r_length | r_extern | r_type;
@@ -219,13 +399,16 @@ impl RelocationBuilder {
 #[derive(Debug, Clone)]
 struct SectionBuilder {
     addr: u64,
-    align: u64,
+    align: Align,
     offset: u64,
     size: u64,
     flags: u32,
     sectname: String,
     segname: &'static str,
     relocations: Vec<RelocationInfo>,
+    address_space: Option<u8>,
+    /// The padding in front of this section, which aligns its start
+    leading_pad: u64,
 }
 
 impl SectionBuilder {
@@ -233,15 +416,24 @@ impl SectionBuilder {
     pub fn new(sectname: String, segname: &'static str, size: u64) -> Self {
         SectionBuilder {
             addr: 0,
-            align: 4,
+            align: Align::from_log2(4),
             offset: 0,
             flags: S_REGULAR,
             size,
             sectname,
             segname,
             relocations: Vec::new(),
+            address_space: None,
+            leading_pad: 0,
         }
     }
+    /// Create a new section builder for a section of literal pointers, e.g., Objective-C selector
+    /// references, with `sectname`, `segname` and `size`
+    pub fn literal_pointers(sectname: String, segname: &'static str, size: u64) -> Self {
+        SectionBuilder::new(sectname, segname, size)
+            .align(Align::from_log2(3))
+            .flags(S_LITERAL_POINTERS | S_ATTR_NO_DEAD_STRIP)
+    }
     /// Set the vm address of this section
     pub fn addr(mut self, addr: u64) -> Self {
         self.addr = addr;
@@ -253,7 +445,7 @@ impl SectionBuilder {
         self
     }
     /// Set the alignment of this section
-    pub fn align(mut self, align: u64) -> Self {
+    pub fn align(mut self, align: Align) -> Self {
         self.align = align;
         self
     }
@@ -262,6 +454,17 @@ impl SectionBuilder {
         self.flags = flags;
         self
     }
+    /// Place this section in the address space `space`; Mach-o has no notion of address spaces,
+    /// so it is recorded in a note
+    pub fn address_space(mut self, space: u8) -> Self {
+        self.address_space = Some(space);
+        self
+    }
+    /// Pad this section's start with `pad` bytes, which are written right before its contents
+    pub fn leading_pad(mut self, pad: u64) -> Self {
+        self.leading_pad = pad;
+        self
+    }
     /// Finalize and create the actual Mach-o section
     pub fn create(&self, section_offset: &mut u64, relocation_offset: &mut u64) -> Section {
         let mut sectname = [0u8; 16];
@@ -274,14 +477,21 @@ impl SectionBuilder {
             addr: self.addr,
             size: self.size,
             offset: self.offset as u32,
-            align: self.align as u32,
+            align: self.align.log2(),
             // FIXME, client needs to set after all offsets known
             reloff: 0,
             nreloc: 0,
             flags: self.flags,
         };
-        section.offset = *section_offset as u32;
-        *section_offset += section.size;
+        // zero filled sections only take up memory, not space in the file
+        let section_type = self.flags & SECTION_TYPE;
+        if section_type == S_ZEROFILL || section_type == S_THREAD_LOCAL_ZEROFILL {
+            section.offset = 0;
+        } else {
+            *section_offset += self.leading_pad;
+            section.offset = *section_offset as u32;
+            *section_offset += section.size;
+        }
         if !self.relocations.is_empty() {
             let nrelocs = self.relocations.len();
             section.nreloc = nrelocs as _;
@@ -299,6 +509,16 @@ type StrTableIndex = usize;
 type StrTable = StringInterner<StrTableIndex>;
 type Symbols = IndexMap<StrTableIndex, SymbolBuilder>;
 
+/// A debugging symbol, e.g., an `N_OSO` entry; it has a different `n_type` namespace than
+/// ordinary symbols, and is never looked up by name
+#[derive(Debug)]
+struct Stab {
+    name: String,
+    n_type: u8,
+    n_desc: u16,
+    n_value: u64,
+}
+
 /// A mach object symbol table
 #[derive(Debug)]
 struct SymbolTable {
@@ -306,6 +526,8 @@ struct SymbolTable {
     strtable: StrTable,
     indexes: IndexMap<StrTableIndex, SymbolIndex>,
     strtable_size: StrtableOffset,
+    /// Written before the symbols, with their names written after the symbols' names, verbatim
+    stabs: Vec<Stab>,
 }
 
 // A manual implementation for Default because StringInterner<usize> does not have a Default impl:
@@ -316,22 +538,24 @@ impl Default for SymbolTable {
             strtable: StrTable::new(),
             indexes: IndexMap::default(),
             strtable_size: StrtableOffset::default(),
+            stabs: Vec::new(),
         }
     }
 }
 
-/// The kind of symbol this is
-enum SymbolType {
-    /// Which `section` this is defined in, the `absolute_offset` in the binary, and its
-    /// `segment_relative_offset`
-    Defined {
-        section: SectionIndex,
-        absolute_offset: u64,
-        segment_relative_offset: u64,
-        global: bool,
-    },
-    /// An undefined symbol (an import)
-    Undefined,
+/// Where a defined symbol is placed: which `section` it is defined in, the `absolute_offset` in
+/// the binary, and its `segment_relative_offset`
+#[derive(Debug, Clone, Copy)]
+struct SymbolLocation {
+    section: SectionIndex,
+    absolute_offset: u64,
+    segment_relative_offset: u64,
+}
+
+/// The size of `name`'s entry in the strtable: it is written with an underscore prefix and a null
+/// terminator, even if it already starts with an underscore
+fn strtable_entry_size(name: &str) -> u64 {
+    name.len() as u64 + 2
 }
 
 impl SymbolTable {
@@ -345,15 +569,60 @@ impl SymbolTable {
             strtable,
             strtable_size,
             indexes: IndexMap::new(),
+            stabs: Vec::new(),
         }
     }
-    /// The number of symbols in this table
+    /// Reserve space for at least `additional` more symbols, to avoid rehashing while inserting
+    /// many symbols at once. The strtable's strings are allocated individually, so only its index
+    /// can be reserved up front.
+    pub fn reserve(&mut self, additional: usize) {
+        self.symbols.reserve(additional);
+        self.indexes.reserve(additional);
+        self.strtable.reserve(additional);
+    }
+    /// The number of symbols in this table, including stabs
     pub fn len(&self) -> usize {
-        self.symbols.len()
+        self.stabs.len() + self.symbols.len()
     }
     /// Returns size of the string table, in bytes
     pub fn sizeof_strtable(&self) -> u64 {
-        self.strtable_size
+        self.strtable_size + self.sizeof_stab_strings()
+    }
+    /// The size of the stabs' names, which are written without an underscore prefix
+    fn sizeof_stab_strings(&self) -> u64 {
+        self.stabs
+            .iter()
+            .map(|stab| stab.name.len() as u64 + 1)
+            .sum()
+    }
+    /// Insert a stab named `name`, bypassing `insert`, since stabs are not looked up by name and
+    /// use their own `n_type`s; the stab precedes all symbols, so this must be called before any
+    /// symbol indexes are used
+    pub fn insert_stab(&mut self, name: &str, n_type: u8, n_desc: u16, n_value: u64) {
+        self.stabs.push(Stab {
+            name: name.to_string(),
+            n_type,
+            n_desc,
+            n_value,
+        });
+    }
+    /// The stabs, finalized, with their names placed after the symbols' names in the strtable
+    fn create_stabs(&self) -> Vec<Nlist> {
+        let mut n_strx = self.strtable_size;
+        self.stabs
+            .iter()
+            .map(|stab| {
+                let nlist = Nlist {
+                    n_strx: n_strx as usize,
+                    n_type: stab.n_type,
+                    n_sect: 0,
+                    n_desc: stab.n_desc,
+                    n_value: stab.n_value,
+                };
+                n_strx += stab.name.len() as u64 + 1;
+                nlist
+            })
+            .collect()
     }
     /// Lookup this symbols offset in the segment
     pub fn offset(&self, symbol_name: &str) -> Option<u64> {
@@ -362,19 +631,138 @@ impl SymbolTable {
             .and_then(|idx| self.symbols.get(&idx))
             .and_then(|sym| Some(sym.get_segment_relative_offset()))
     }
+    /// Lookup the section this symbol is defined in, if it is defined
+    pub fn section(&self, symbol_name: &str) -> Option<SectionIndex> {
+        self.strtable
+            .get(symbol_name)
+            .and_then(|idx| self.symbols.get(&idx))
+            .and_then(|sym| sym.section)
+    }
     /// Lookup this symbols ordinal index in the symbol table, if it has one
     pub fn index(&self, symbol_name: &str) -> Option<SymbolIndex> {
         self.strtable
             .get(symbol_name)
-            .and_then(|idx| self.indexes.get(&idx).cloned())
+            .and_then(|idx| self.indexes.get(&idx))
+            .map(|index| self.stabs.len() + index)
+    }
+    /// Lookup the symbol in `section` which starts at, or otherwise contains, the section relative
+    /// `offset`; i.e., the symbol with the greatest offset not past `offset`
+    pub fn lookup_by_offset(&self, section: SectionIndex, offset: usize) -> Option<&str> {
+        self.symbols
+            .iter()
+            .filter(|(_, sym)| {
+                sym.section == Some(section) && sym.get_segment_relative_offset() <= offset as u64
+            })
+            .max_by_key(|(_, sym)| sym.get_segment_relative_offset())
+            .and_then(|(&idx, _)| self.strtable.resolve(idx))
+    }
+    /// Lookup this symbol's builder for mutation, e.g., to adjust its offset once layout is known
+    pub fn get_mut(&mut self, symbol_name: &str) -> Option<&mut SymbolBuilder> {
+        let idx = self.strtable.get(symbol_name)?;
+        self.symbols.get_mut(&idx)
+    }
+    /// Format this symbol table like `nm`: a `<value> <type> <name>` line for every symbol, sorted
+    /// by name. The type is `T` for `__TEXT,__text`, `D` for `__DATA,__data`, `B` for
+    /// `__DATA,__bss`, `S` for any other section, `A` for absolute, `C` for common and `U` for
+    /// undefined symbols, and lowercase for local symbols.
+    fn to_nm_output(&self, sections: &IndexMap<String, SectionBuilder>) -> String {
+        use goblin::mach::symbols::{N_ABS, N_EXT, N_SECT, N_TYPE, N_UNDF};
+        let mut lines = self
+            .symbols
+            .iter()
+            .map(|(&idx, symbol)| {
+                let name = self.strtable.resolve(idx).expect("symbol has a name");
+                let nlist = symbol.clone().create();
+                let typ = match nlist.n_type & N_TYPE {
+                    N_UNDF if nlist.n_value != 0 => 'C',
+                    N_UNDF => 'U',
+                    N_ABS => 'A',
+                    N_SECT => {
+                        let (_, section) = sections
+                            .get_index(nlist.n_sect - 1)
+                            .expect("symbol is in a section");
+                        match (section.segname, section.sectname.as_str()) {
+                            ("__TEXT", "__text") => 'T',
+                            ("__DATA", "__data") => 'D',
+                            ("__DATA", "__bss") => 'B',
+                            _ => 'S',
+                        }
+                    }
+                    _ => '?',
+                };
+                let typ = if nlist.n_type & N_EXT != 0 {
+                    typ
+                } else {
+                    typ.to_ascii_lowercase()
+                };
+                let line = if typ == 'U' {
+                    format!("{:16} U _{}", "", name)
+                } else {
+                    format!("{:016x} {} _{}", nlist.n_value, typ, name)
+                };
+                (name, line)
+            })
+            .collect::<Vec<_>>();
+        lines.sort();
+        let mut output = String::new();
+        for (_, line) in lines {
+            output.push_str(&line);
+            output.push('\n');
+        }
+        output
+    }
+    /// Insert a new symbol for the definition `decl` at `location` into this objects symbol table
+    pub fn insert_definition(
+        &mut self,
+        symbol_name: &str,
+        decl: &DefinedDecl,
+        location: SymbolLocation,
+    ) {
+        self.insert(symbol_name, |name| {
+            SymbolBuilder::new(name)
+                .global(decl.is_global())
+                .weak(is_weak_definition(decl))
+                .private_extern(is_private_extern(decl))
+                .offset(location.absolute_offset)
+                .relative_offset(location.segment_relative_offset)
+                .section(location.section)
+        });
+    }
+    /// Insert a new symbol imported as `kind` into this objects symbol table
+    pub fn insert_import(&mut self, symbol_name: &str, kind: ImportKind) {
+        self.insert(symbol_name, |name| {
+            let builder = SymbolBuilder::new(name).global(true);
+            if kind.is_weak() {
+                builder.weak_import()
+            } else {
+                builder.import()
+            }
+        });
+    }
+    /// Insert a new common symbol of `size` bytes, aligned to `align`, into this objects symbol
+    /// table
+    pub fn insert_common(&mut self, symbol_name: &str, size: u64, align: u64) {
+        self.insert(symbol_name, |name| {
+            SymbolBuilder::new(name).global(true).common(size, align)
+        });
     }
-    /// Insert a new symbol into this objects symbol table
-    pub fn insert(&mut self, symbol_name: &str, kind: SymbolType) {
+    /// Insert a new symbol at the fixed `address` into this objects symbol table
+    pub fn insert_absolute(&mut self, symbol_name: &str, address: u64) {
+        self.insert(symbol_name, |name| {
+            SymbolBuilder::new(name).global(true).absolute(address)
+        });
+    }
+    /// Insert the symbol built by `builder` from its strtable offset, unless it is already present
+    fn insert<F: FnOnce(StrtableOffset) -> SymbolBuilder>(
+        &mut self,
+        symbol_name: &str,
+        builder: F,
+    ) {
         // mach-o requires _ prefixes on every symbol, we will allow this to be configurable later
         //let name = format!("_{}", symbol_name);
         let name = symbol_name;
-        // 1 for null terminator and 1 for _ prefix (defered until write time);
-        let name_len = name.len() as u64 + 1 + 1;
+        // the _ prefix is deferred until write time, but accounted for here
+        let name_len = strtable_entry_size(name);
         let last_index = self.strtable.len();
         let name_index = self.strtable.get_or_intern(name);
         debug!("{}: {} <= {}", symbol_name, last_index, name_index);
@@ -385,21 +773,7 @@ impl SymbolTable {
                 self.strtable.resolve(name_index).unwrap()
             );
             // TODO: add code offset into symbol n_value
-            let builder = match kind {
-                SymbolType::Undefined => {
-                    SymbolBuilder::new(self.strtable_size).global(true).import()
-                }
-                SymbolType::Defined {
-                    section,
-                    absolute_offset,
-                    global,
-                    segment_relative_offset,
-                } => SymbolBuilder::new(self.strtable_size)
-                    .global(global)
-                    .offset(absolute_offset)
-                    .relative_offset(segment_relative_offset)
-                    .section(section),
-            };
+            let builder = builder(self.strtable_size);
             // insert the builder for this symbol, using its strtab index
             self.symbols.insert(name_index, builder);
             // now create the symbols index, and using strtab name as lookup
@@ -410,6 +784,23 @@ impl SymbolTable {
     }
 }
 
+/// An artifact's definitions, partitioned by the section they are placed in
+#[derive(Debug, Default)]
+struct SectionDefinitions<'a> {
+    code: Vec<Definition<'a>>,
+    data: Vec<Definition<'a>>,
+    bss: Vec<Definition<'a>>,
+    cstrings: Vec<Definition<'a>>,
+    rodata: Vec<Definition<'a>>,
+    /// Custom sections, each of which is a section of its own
+    sections: Vec<Definition<'a>>,
+    method_names: Vec<Definition<'a>>,
+    selector_refs: Vec<Definition<'a>>,
+    got_entries: Vec<Definition<'a>>,
+    thread_data: Vec<Definition<'a>>,
+    thread_bss: Vec<Definition<'a>>,
+}
+
 #[derive(Debug)]
 /// A Mach-o program segment
 struct SegmentBuilder {
@@ -419,6 +810,9 @@ struct SegmentBuilder {
     pub offset: u64,
     size: u64,
     align_pad_map: HashMap<String, u64>,
+    /// Values to patch in at segment relative offsets once the section data has been written, as
+    /// `(offset, value, size in bytes)`
+    fixups: Vec<(u64, u64, u8)>,
 }
 
 impl SegmentBuilder {
@@ -426,15 +820,44 @@ impl SegmentBuilder {
     pub fn size(&self) -> u64 {
         self.size
     }
+    /// The size of this segment in memory, i.e., including its zero filled sections, in bytes
+    pub fn vmsize(&self) -> u64 {
+        self.sections
+            .values()
+            .map(|section| section.addr + section.size)
+            .fold(self.size, std::cmp::max)
+    }
     /// The size of this segment's _load command_, including its associated sections, in bytes
     pub fn load_command_size(&self, ctx: &Ctx) -> u64 {
         Segment::size_with(&ctx) as u64
             + (self.sections.len() as u64 * Section::size_with(&ctx) as u64)
     }
+    /// The file offset of the symbol table, which immediately follows this segment's section data;
+    /// `symtab_cmd_size` and `linkedit_cmd_size` are the sizes of the symtab load command and of
+    /// any other load commands following this segment's, e.g., `LC_DATA_IN_CODE`
+    pub fn symtab_offset(&self, ctx: &Ctx, symtab_cmd_size: u64, linkedit_cmd_size: u64) -> u64 {
+        // NB: self.offset already includes the header
+        self.offset + self.load_command_size(ctx) + symtab_cmd_size + linkedit_cmd_size
+    }
     fn _section_data_file_offset(&self, ctx: &Ctx) -> u64 {
         // section data
         Header::size_with(&ctx.container) as u64 + self.load_command_size(ctx)
     }
+    /// Advances `offset`, `addr` and `symbol_offset` such that the next section starts aligned to
+    /// `align`, returning the number of padding bytes
+    fn align_section_start(
+        offset: &mut u64,
+        addr: &mut u64,
+        symbol_offset: &mut u64,
+        align: Align,
+    ) -> u64 {
+        let align = align.bytes();
+        let pad = ((*addr + align - 1) & !(align - 1)) - *addr;
+        *offset += pad;
+        *addr += pad;
+        *symbol_offset += pad;
+        pad
+    }
     // FIXME: this is in desperate need of refactoring, obviously
     fn build_section(
         symtab: &mut SymbolTable,
@@ -446,44 +869,51 @@ impl SegmentBuilder {
         symbol_offset: &mut u64,
         section: SectionIndex,
         definitions: &[Definition],
-        min_alignment_exponent: u64,
+        min_align: Align,
         flags: Option<u32>,
         align_pad_map: &mut HashMap<String, u64>,
-    ) {
+    ) -> Result<(), AlignError> {
         let mut local_size = 0;
         let mut section_relative_offset = 0;
-        let mut alignment_exponent = min_alignment_exponent;
+        // the section is as aligned as its most aligned definition
+        let mut align = min_align;
+        for def in definitions {
+            align = std::cmp::max(align, Align::of(def.decl.get_align())?);
+        }
+        let leading_pad = if definitions.is_empty() {
+            0
+        } else {
+            Self::align_section_start(offset, addr, symbol_offset, align)
+        };
         let mut def_iter = definitions.iter().peekable();
         while let Some(def) = def_iter.next() {
             if let DefinedDecl::Section { .. } = def.decl {
                 unreachable!();
             }
 
-            symtab.insert(
+            symtab.insert_definition(
                 def.name,
-                SymbolType::Defined {
+                def.decl,
+                SymbolLocation {
                     section,
-                    segment_relative_offset: section_relative_offset,
                     absolute_offset: *symbol_offset,
-                    global: def.decl.is_global(),
+                    segment_relative_offset: section_relative_offset,
                 },
             );
             *symbol_offset += def.data.file_size() as u64;
             section_relative_offset += def.data.file_size() as u64;
             local_size += def.data.file_size() as u64;
 
-            let next_def_alignment_exponent = std::cmp::max(
-                min_alignment_exponent,
-                def_iter
-                    .peek()
-                    .map(|def| align_to_align_exp(def.decl.get_align().unwrap_or(1)))
-                    .unwrap_or(0),
-            );
-            alignment_exponent = std::cmp::max(alignment_exponent, next_def_alignment_exponent);
-
-            let align_pad = (1 << next_def_alignment_exponent)
-                - (section_relative_offset % (1 << next_def_alignment_exponent));
-            let align_pad = if align_pad == (1 << next_def_alignment_exponent) {
+            let next_def_align = std::cmp::max(
+                min_align,
+                match def_iter.peek() {
+                    Some(def) => Align::of(def.decl.get_align())?,
+                    None => Align::from_log2(0),
+                },
+            )
+            .bytes();
+            let align_pad = next_def_align - (section_relative_offset % next_def_align);
+            let align_pad = if align_pad == next_def_align {
                 0
             } else {
                 align_pad
@@ -497,13 +927,191 @@ impl SegmentBuilder {
         let mut section = SectionBuilder::new(sectname.to_string(), segname, local_size)
             .offset(*offset)
             .addr(*addr)
-            .align(alignment_exponent);
+            .align(align)
+            .leading_pad(leading_pad);
         if let Some(flags) = flags {
             section = section.flags(flags);
         }
         *offset += local_size;
         *addr += local_size;
         sections.insert(sectname.to_string(), section);
+        Ok(())
+    }
+    /// Build a zero filled section, e.g., `__bss`, which takes up no space in the file. Its
+    /// symbols are placed relative to the start of the section, since it is only placed after the
+    /// sections with file contents, by `place_zerofill_section`.
+    fn build_zerofill_section(
+        symtab: &mut SymbolTable,
+        sectname: &'static str,
+        segname: &'static str,
+        sections: &mut IndexMap<String, SectionBuilder>,
+        section: SectionIndex,
+        definitions: &[Definition],
+        min_align: Align,
+    ) -> Result<(), AlignError> {
+        let mut local_size = 0;
+        let mut section_align = min_align;
+        for def in definitions {
+            let def_align = std::cmp::max(min_align, Align::of(def.decl.get_align())?);
+            section_align = std::cmp::max(section_align, def_align);
+            let align = def_align.bytes();
+            local_size = (local_size + align - 1) & !(align - 1);
+            symtab.insert_definition(
+                def.name,
+                def.decl,
+                SymbolLocation {
+                    section,
+                    absolute_offset: local_size,
+                    segment_relative_offset: local_size,
+                },
+            );
+            local_size += def.data.memory_size() as u64;
+        }
+        let section = SectionBuilder::new(sectname.to_string(), segname, local_size)
+            .align(section_align)
+            .flags(S_ZEROFILL);
+        sections.insert(sectname.to_string(), section);
+        Ok(())
+    }
+    /// Place the zero filled section `sectname` at `addr`, after the sections with file contents,
+    /// and move its symbols along with it; returns the address right after it
+    fn place_zerofill_section(
+        symtab: &mut SymbolTable,
+        sectname: &str,
+        sections: &mut IndexMap<String, SectionBuilder>,
+        addr: u64,
+        definitions: &[Definition],
+    ) -> u64 {
+        let section = sections
+            .get_mut(sectname)
+            .expect("zero filled section was built");
+        let align = section.align.bytes();
+        section.addr = (addr + align - 1) & !(align - 1);
+        for def in definitions {
+            let symbol = symtab
+                .get_mut(def.name)
+                .expect("zero filled symbol was inserted");
+            *symbol = symbol
+                .clone()
+                .offset(section.addr + symbol.get_segment_relative_offset());
+        }
+        section.addr + section.size
+    }
+    /// Build `__thread_vars`, with a descriptor for every thread-local variable in `definitions`.
+    /// A descriptor is three pointers: to `__tlv_bootstrap`, a key which is filled in at runtime,
+    /// and the variable's initial value; the relocations of the first and last are added by
+    /// `build_relocations`.
+    fn build_thread_variables_section(
+        symtab: &mut SymbolTable,
+        sections: &mut IndexMap<String, SectionBuilder>,
+        offset: &mut u64,
+        addr: &mut u64,
+        symbol_offset: &mut u64,
+        definitions: &[Definition],
+        ctx: &Ctx,
+    ) {
+        let align = Align::from_log2(ctx.size().trailing_zeros());
+        let leading_pad = Self::align_section_start(offset, addr, symbol_offset, align);
+        let section = sections.len();
+        let mut local_size = 0;
+        for def in definitions {
+            symtab.insert_definition(
+                def.name,
+                def.decl,
+                SymbolLocation {
+                    section,
+                    absolute_offset: *symbol_offset,
+                    segment_relative_offset: local_size,
+                },
+            );
+            *symbol_offset += 3 * ctx.size() as u64;
+            local_size += 3 * ctx.size() as u64;
+        }
+        let section = SectionBuilder::new("__thread_vars".to_string(), "__DATA", local_size)
+            .offset(*offset)
+            .addr(*addr)
+            .align(align)
+            .leading_pad(leading_pad)
+            .flags(S_THREAD_LOCAL_VARIABLES);
+        *offset += local_size;
+        *addr += local_size;
+        sections.insert("__thread_vars".to_string(), section);
+    }
+    /// Build `__eh_frame`, with a local label at the initial location of every FDE, which its
+    /// relocations, added by `build_relocations`, are relative to
+    fn build_eh_frame_section(
+        symtab: &mut SymbolTable,
+        sections: &mut IndexMap<String, SectionBuilder>,
+        offset: &mut u64,
+        addr: &mut u64,
+        symbol_offset: &mut u64,
+        eh_frame: &EhFrame,
+        ctx: &Ctx,
+    ) {
+        let align = Align::from_log2(ctx.size().trailing_zeros());
+        let leading_pad = Self::align_section_start(offset, addr, symbol_offset, align);
+        let section = sections.len();
+        for (idx, &(location, _)) in eh_frame.initial_locations.iter().enumerate() {
+            symtab.insert_definition(
+                &eh_frame_label(idx),
+                &DefinedDecl::Data(Decl::data()),
+                SymbolLocation {
+                    section,
+                    absolute_offset: *symbol_offset + location,
+                    segment_relative_offset: location,
+                },
+            );
+        }
+        let local_size = eh_frame.data.len() as u64;
+        *symbol_offset += local_size;
+        let section = SectionBuilder::new("__eh_frame".to_string(), "__TEXT", local_size)
+            .offset(*offset)
+            .addr(*addr)
+            .align(align)
+            .leading_pad(leading_pad)
+            .flags(S_COALESCED | S_ATTR_NO_TOC | S_ATTR_STRIP_STATIC_SYMS | S_ATTR_LIVE_SUPPORT);
+        *offset += local_size;
+        *addr += local_size;
+        sections.insert("__eh_frame".to_string(), section);
+    }
+    /// Build the `__objc_selrefs` section, whose entries are pointers to the selectors' names
+    fn build_selector_refs_section(
+        symtab: &mut SymbolTable,
+        sections: &mut IndexMap<String, SectionBuilder>,
+        offset: &mut u64,
+        addr: &mut u64,
+        symbol_offset: &mut u64,
+        definitions: &[Definition],
+        ctx: &Ctx,
+    ) {
+        let sectname = "__objc_selrefs";
+        let section = sections.len();
+        let align = Align::from_log2(ctx.size().trailing_zeros());
+        let leading_pad = Self::align_section_start(offset, addr, symbol_offset, align);
+        let mut local_size = 0;
+        for def in definitions {
+            symtab.insert_definition(
+                def.name,
+                def.decl,
+                SymbolLocation {
+                    section,
+                    absolute_offset: *symbol_offset,
+                    segment_relative_offset: local_size,
+                },
+            );
+            // every entry is exactly one pointer, so they never need padding
+            debug_assert_eq!(def.data.file_size(), ctx.size());
+            *symbol_offset += def.data.file_size() as u64;
+            local_size += def.data.file_size() as u64;
+        }
+        let section = SectionBuilder::literal_pointers(sectname.to_string(), "__DATA", local_size)
+            .offset(*offset)
+            .addr(*addr)
+            .align(align)
+            .leading_pad(leading_pad);
+        *offset += local_size;
+        *addr += local_size;
+        sections.insert(sectname.to_string(), section);
     }
     fn build_custom_section(
         symtab: &mut SymbolTable,
@@ -513,7 +1121,7 @@ impl SegmentBuilder {
         symbol_offset: &mut u64,
         section_idx: SectionIndex,
         def: &Definition,
-    ) {
+    ) -> Result<(), AlignError> {
         let s = match def.decl {
             DefinedDecl::Section(s) => s,
             _ => unreachable!("in build_custom_section: def.decl != Section"),
@@ -532,46 +1140,63 @@ impl SegmentBuilder {
         };
 
         let mut flags = 0;
+        let align = Align::of(s.get_align())?;
+        let leading_pad = Self::align_section_start(offset, addr, symbol_offset, align);
 
         if s.kind() == SectionKind::Debug {
             flags |= S_ATTR_DEBUG;
         }
 
         for (symbol, symbol_dst_offset) in def.symbols {
-            symtab.insert(
+            symtab.insert_definition(
                 symbol,
-                SymbolType::Defined {
+                &DefinedDecl::Data(Decl::data().global()),
+                SymbolLocation {
                     section: section_idx,
-                    segment_relative_offset: *symbol_dst_offset,
                     absolute_offset: *symbol_offset + *symbol_dst_offset,
-                    global: true,
+                    segment_relative_offset: *symbol_dst_offset,
                 },
             );
         }
 
         let local_size = def.data.file_size() as u64;
         *symbol_offset += local_size;
-        let section = SectionBuilder::new(sectname, segment_name, local_size)
+        let mut section = SectionBuilder::new(sectname, segment_name, local_size)
             .offset(*offset)
             .addr(*addr)
-            .align(align_to_align_exp(s.get_align().unwrap_or(1)))
+            .align(align)
+            .leading_pad(leading_pad)
             .flags(flags);
+        if let Some(space) = s.get_address_space() {
+            section = section.address_space(space);
+        }
         *offset += local_size;
         *addr += local_size;
         sections.insert(def.name.to_string(), section);
+        Ok(())
     }
     /// Create a new program segment from an `artifact`, symbol table, and context
     // FIXME: this is pub(crate) for now because we can't leak pub(crate) Definition
     pub(crate) fn new(
         artifact: &Artifact,
-        code: &[Definition],
-        blob_data: &[Definition],
-        zeroed_data: &[Definition],
-        cstrings: &[Definition],
-        custom_sections: &[Definition],
+        definitions: &SectionDefinitions,
+        eh_frame: Option<&EhFrame>,
         symtab: &mut SymbolTable,
         ctx: &Ctx,
-    ) -> Self {
+    ) -> Result<Self, AlignError> {
+        let SectionDefinitions {
+            code,
+            data: blob_data,
+            bss: zeroed_data,
+            cstrings,
+            rodata,
+            sections: custom_sections,
+            method_names,
+            selector_refs,
+            got_entries,
+            thread_data,
+            thread_bss,
+        } = definitions;
         let mut offset = Header::size_with(&ctx.container) as u64;
         let mut size = 0;
         let mut symbol_offset = 0;
@@ -587,11 +1212,11 @@ impl SegmentBuilder {
             &mut size,
             &mut symbol_offset,
             CODE_SECTION_INDEX,
-            &code,
-            4,
+            code,
+            Align::from_log2(4),
             Some(S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS),
             &mut align_pad_map,
-        );
+        )?;
         Self::build_section(
             symtab,
             "__data",
@@ -601,11 +1226,11 @@ impl SegmentBuilder {
             &mut size,
             &mut symbol_offset,
             DATA_SECTION_INDEX,
-            &blob_data,
-            3,
+            blob_data,
+            Align::from_log2(3),
             None,
             &mut align_pad_map,
-        );
+        )?;
         Self::build_section(
             symtab,
             "__cstring",
@@ -615,25 +1240,34 @@ impl SegmentBuilder {
             &mut size,
             &mut symbol_offset,
             CSTRING_SECTION_INDEX,
-            &cstrings,
-            0,
+            cstrings,
+            Align::from_log2(0),
             Some(S_CSTRING_LITERALS),
             &mut align_pad_map,
-        );
+        )?;
         Self::build_section(
             symtab,
-            "__bss",
-            "__DATA",
+            "__const",
+            "__TEXT",
             &mut sections,
             &mut offset,
             &mut size,
             &mut symbol_offset,
-            BSS_SECTION_INDEX,
-            &zeroed_data,
-            0,
-            Some(S_ZEROFILL),
+            RODATA_SECTION_INDEX,
+            rodata,
+            Align::from_log2(3),
+            None,
             &mut align_pad_map,
-        );
+        )?;
+        Self::build_zerofill_section(
+            symtab,
+            "__bss",
+            "__DATA",
+            &mut sections,
+            BSS_SECTION_INDEX,
+            zeroed_data,
+            Align::from_log2(3),
+        )?;
         for (idx, def) in custom_sections.iter().enumerate() {
             Self::build_custom_section(
                 symtab,
@@ -643,27 +1277,359 @@ impl SegmentBuilder {
                 &mut symbol_offset,
                 idx + NUM_DEFAULT_SECTIONS,
                 def,
+            )?;
+        }
+        // the Objective-C sections are only present if they are used
+        if !method_names.is_empty() {
+            let section_idx = sections.len();
+            Self::build_section(
+                symtab,
+                "__objc_methnames",
+                "__TEXT",
+                &mut sections,
+                &mut offset,
+                &mut size,
+                &mut symbol_offset,
+                section_idx,
+                method_names,
+                Align::from_log2(0),
+                Some(S_CSTRING_LITERALS),
+                &mut align_pad_map,
+            )?;
+        }
+        if !selector_refs.is_empty() {
+            Self::build_selector_refs_section(
+                symtab,
+                &mut sections,
+                &mut offset,
+                &mut size,
+                &mut symbol_offset,
+                selector_refs,
+                ctx,
             );
         }
-        for (ref import, _) in artifact.imports() {
-            symtab.insert(import, SymbolType::Undefined);
+        // explicitly reserved GOT slots; these are regular pointers, since we have no indirect symbol table
+        if !got_entries.is_empty() {
+            let section_idx = sections.len();
+            Self::build_section(
+                symtab,
+                "__got",
+                "__DATA",
+                &mut sections,
+                &mut offset,
+                &mut size,
+                &mut symbol_offset,
+                section_idx,
+                got_entries,
+                Align::from_log2(3),
+                None,
+                &mut align_pad_map,
+            )?;
         }
-        // FIXME re add assert
-        //assert_eq!(offset, Header::size_with(&ctx.container) + Self::load_command_size(ctx));
-        debug!(
-            "Segment Size: {} Symtable LoadCommand Offset: {}",
-            size, offset
-        );
-        SegmentBuilder {
-            size,
-            sections,
-            offset,
-            align_pad_map,
+        // thread-local variables are descriptors in __thread_vars, and their initial values go into
+        // __thread_data, or __thread_bss if they are zero filled, under their local init symbols
+        let thread_variables = thread_data
+            .iter()
+            .chain(thread_bss)
+            .cloned()
+            .collect::<Vec<_>>();
+        let init_symbols = thread_variables
+            .iter()
+            .map(|def| thread_local_init_symbol(def.name))
+            .collect::<Vec<_>>();
+        let init_decls = thread_variables
+            .iter()
+            .map(|def| match def.decl {
+                DefinedDecl::Data(d) => DefinedDecl::Data(d.local()),
+                _ => unreachable!("thread-local variables are data"),
+            })
+            .collect::<Vec<_>>();
+        let inits = thread_variables
+            .iter()
+            .zip(init_symbols.iter().zip(init_decls.iter()))
+            .map(|(def, (name, decl))| Definition {
+                name,
+                decl,
+                ..def.clone()
+            })
+            .collect::<Vec<_>>();
+        let (data_inits, bss_inits) = inits.split_at(thread_data.len());
+        if !data_inits.is_empty() {
+            let section_idx = sections.len();
+            Self::build_section(
+                symtab,
+                "__thread_data",
+                "__DATA",
+                &mut sections,
+                &mut offset,
+                &mut size,
+                &mut symbol_offset,
+                section_idx,
+                data_inits,
+                Align::from_log2(3),
+                Some(S_THREAD_LOCAL_REGULAR),
+                &mut align_pad_map,
+            )?;
+            // the padding is written after the variable's initial value, which is looked up by the
+            // variable's name
+            for (def, init) in thread_data.iter().zip(data_inits) {
+                if let Some(align_pad) = align_pad_map.remove(init.name) {
+                    align_pad_map.insert(def.name.to_string(), align_pad);
+                }
+            }
         }
-    }
-}
-
-/// A Mach-o object file container
+        if !bss_inits.is_empty() {
+            let section_idx = sections.len();
+            Self::build_zerofill_section(
+                symtab,
+                "__thread_bss",
+                "__DATA",
+                &mut sections,
+                section_idx,
+                bss_inits,
+                Align::from_log2(3),
+            )?;
+            sections["__thread_bss"].flags = S_THREAD_LOCAL_ZEROFILL;
+        }
+        if !thread_variables.is_empty() {
+            Self::build_thread_variables_section(
+                symtab,
+                &mut sections,
+                &mut offset,
+                &mut size,
+                &mut symbol_offset,
+                &thread_variables,
+                ctx,
+            );
+            symtab.insert_import(TLV_BOOTSTRAP, ImportKind::Data);
+        }
+        if let Some(eh_frame) = eh_frame {
+            Self::build_eh_frame_section(
+                symtab,
+                &mut sections,
+                &mut offset,
+                &mut size,
+                &mut symbol_offset,
+                eh_frame,
+                ctx,
+            );
+        }
+        // raw sections go last, since they have no symbols
+        for raw in artifact.raw_sections() {
+            let flags = raw.flags as u32;
+            let segname = if flags & (S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS) != 0 {
+                "__TEXT"
+            } else {
+                "__DATA"
+            };
+            let local_size = raw.data.len() as u64;
+            let section = SectionBuilder::new(raw.name.clone(), segname, local_size)
+                .offset(offset)
+                .addr(size)
+                .align(Align::from_log2(0))
+                .flags(flags);
+            offset += local_size;
+            size += local_size;
+            sections.insert(raw.name.clone(), section);
+        }
+        let comment = artifact.comment();
+        if !comment.is_empty() {
+            let local_size = comment.len() as u64;
+            let section = SectionBuilder::new("__comment".to_string(), "__TEXT", local_size)
+                .offset(offset)
+                .addr(size)
+                .align(Align::from_log2(0))
+                .flags(S_REGULAR);
+            offset += local_size;
+            size += local_size;
+            sections.insert("__comment".to_string(), section);
+        }
+        // zero filled data goes after all of the data which is in the file
+        let bss_end =
+            Self::place_zerofill_section(symtab, "__bss", &mut sections, size, zeroed_data);
+        if !bss_inits.is_empty() {
+            Self::place_zerofill_section(symtab, "__thread_bss", &mut sections, bss_end, bss_inits);
+        }
+        for (name, address) in artifact.absolute_symbols() {
+            symtab.insert_absolute(name, address);
+        }
+        for (ref import, kind) in artifact.imports() {
+            symtab.insert_import(import, *kind);
+        }
+        for (name, size, align) in artifact.common_symbols() {
+            symtab.insert_common(name, size, align);
+        }
+        // FIXME re add assert
+        //assert_eq!(offset, Header::size_with(&ctx.container) + Self::load_command_size(ctx));
+        debug!(
+            "Segment Size: {} Symtable LoadCommand Offset: {}",
+            size, offset
+        );
+        Ok(SegmentBuilder {
+            size,
+            sections,
+            offset,
+            align_pad_map,
+            fixups: Vec::new(),
+        })
+    }
+}
+
+/// The layout of a Mach-o object file, i.e., the file offsets of its parts, which is computed
+/// before anything is written
+#[derive(Debug)]
+struct Layout {
+    /// Size of all of the load commands, in bytes
+    sizeof_load_commands: u64,
+    first_section_offset: u64,
+    symtab_offset: u64,
+    strtab_offset: u64,
+    reloc_offset_start: u64,
+}
+
+/// Log, and return, the current position of `file`, which has just finished writing `phase`
+fn log_position<T: Seek>(file: &mut T, phase: &'static str) -> Result<u64, std::io::Error> {
+    let position = file.seek(Current(0))?;
+    debug!("SEEK: after {}: {}", phase, position);
+    #[cfg(test)]
+    tests::PHASES.with(|phases| phases.borrow_mut().push((phase, position)));
+    Ok(position)
+}
+
+/// Whether the definition `decl` must not be exported from the linked image; Mach-o has no notion
+/// of protected or internal visibility, so internal symbols are treated like hidden ones
+fn is_private_extern(decl: &DefinedDecl) -> bool {
+    match decl.get_visibility() {
+        Visibility::Hidden | Visibility::Internal => true,
+        Visibility::Default | Visibility::Protected => false,
+    }
+}
+
+/// Whether the definition `decl` is weak; Mach-o has no section groups, so COMDAT functions are
+/// weak definitions, which the linker coalesces
+fn is_weak_definition(decl: &DefinedDecl) -> bool {
+    match decl {
+        DefinedDecl::Function(d) if d.is_comdat() => true,
+        _ => decl.is_weak(),
+    }
+}
+
+/// The local label at the initial location of the `idx`th FDE in `__eh_frame`
+fn eh_frame_label(idx: usize) -> String {
+    format!("eh_frame.fde{}", idx)
+}
+
+/// The name of the symbol of the initial value of the thread-local variable `name`
+fn thread_local_init_symbol(name: &str) -> String {
+    format!("{}$tlv$init", name)
+}
+
+/// Write the padding in front of the section `sectname`, if it has any, filled with `byte`
+fn write_leading_pad<T: Write>(
+    file: &mut T,
+    sections: &IndexMap<String, SectionBuilder>,
+    sectname: &str,
+    byte: u8,
+) -> Result<(), std::io::Error> {
+    if let Some(section) = sections.get(sectname) {
+        for _ in 0..section.leading_pad {
+            file.write_all(&[byte])?;
+        }
+    }
+    Ok(())
+}
+
+/// Computes the (IEEE) CRC32 checksum of `bytes`
+#[cfg(debug_assertions)]
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// A writer which keeps an in-memory copy of everything written to `inner`, so that the written
+/// object can be read back and verified in debug builds
+#[cfg(debug_assertions)]
+struct Mirror<T> {
+    inner: T,
+    mirror: Cursor<Vec<u8>>,
+}
+
+#[cfg(debug_assertions)]
+impl<T> Mirror<T> {
+    fn new(inner: T) -> Self {
+        Mirror {
+            inner,
+            mirror: Cursor::new(Vec::new()),
+        }
+    }
+    /// The bytes written so far, indexed by their position in `inner`
+    fn contents(&self) -> &[u8] {
+        self.mirror.get_ref()
+    }
+}
+
+#[cfg(debug_assertions)]
+impl<T: Write> Write for Mirror<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.mirror.write_all(&buf[..written])?;
+        Ok(written)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(debug_assertions)]
+impl<T: Seek> Seek for Mirror<T> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        let position = self.inner.seek(pos)?;
+        self.mirror.set_position(position);
+        Ok(position)
+    }
+}
+
+/// The expected checksum of a section's contents, at `addr` relative to the first section
+#[cfg(debug_assertions)]
+#[derive(Debug)]
+struct SectionChecksum {
+    name: String,
+    addr: u64,
+    size: u64,
+    crc: u32,
+}
+
+/// Panics if any section in `written` doesn't match its expected checksum
+#[cfg(debug_assertions)]
+fn verify_section_checksums(
+    written: &[u8],
+    first_section_offset: u64,
+    checksums: &[SectionChecksum],
+) {
+    for checksum in checksums {
+        let start = (first_section_offset + checksum.addr) as usize;
+        let end = start + checksum.size as usize;
+        let actual = crc32(written.get(start..end).unwrap_or(&[]));
+        if actual != checksum.crc {
+            panic!(
+                "section {} was written incorrectly: expected CRC32 {:#010x}, actual CRC32 {:#010x}",
+                checksum.name, checksum.crc, actual
+            );
+        }
+    }
+}
+
+/// A Mach-o object file container
 #[derive(Debug)]
 struct Mach<'a> {
     ctx: Ctx,
@@ -672,59 +1638,138 @@ struct Mach<'a> {
     segment: SegmentBuilder,
     code: ArtifactCode<'a>,
     data: ArtifactData<'a>,
-    bss_size: usize,
     cstrings: Vec<Definition<'a>>,
+    rodata: Vec<Definition<'a>>,
     sections: Vec<Definition<'a>>,
+    method_names: Vec<Definition<'a>>,
+    selector_refs: Vec<Definition<'a>>,
+    got_entries: Vec<Definition<'a>>,
+    thread_data: Vec<Definition<'a>>,
+    eh_frame: Option<EhFrame<'a>>,
+    raw_sections: &'a [RawSection],
+    comment: &'a [u8],
+    data_in_code: &'a [DataInCodeEntry],
+    build_id: Option<&'a [u8]>,
+    build_version: Option<BuildVersion>,
+    subsections_via_symbols: bool,
+    // whether any pointer is signed, which makes this an arm64e object
+    authenticated: bool,
     _p: ::std::marker::PhantomData<&'a ()>,
 }
 
 impl<'a> Mach<'a> {
-    pub fn new(artifact: &'a Artifact) -> Self {
+    pub fn new(artifact: &'a Artifact) -> Result<Self, ArtifactError> {
         let ctx = make_ctx(&artifact.target);
         // FIXME: I believe we can avoid this partition by refactoring SegmentBuilder::new
-        let (mut code, mut data, mut bss, mut cstrings, mut sections, mut bss_size) = (
-            Vec::new(),
-            Vec::new(),
-            Vec::new(),
-            Vec::new(),
-            Vec::new(),
-            0,
-        );
+        let mut definitions = SectionDefinitions::default();
+        // read-only data with pointers in it must stay writable, since the dynamic linker may have
+        // to fix them up, like __DATA,__const
+        let link_sources = artifact
+            .links()
+            .map(|link| link.from.name)
+            .collect::<HashSet<_>>();
         for def in artifact.definitions() {
             match def.decl {
                 DefinedDecl::Function { .. } => {
-                    code.push(def);
+                    definitions.code.push(def);
+                }
+                DefinedDecl::Data(d) if d.is_thread_local() => {
+                    if let Data::ZeroInit(_) = def.data {
+                        definitions.thread_bss.push(def);
+                    } else {
+                        definitions.thread_data.push(def);
+                    }
                 }
                 DefinedDecl::Data(d) => {
-                    if let Data::ZeroInit(size) = def.data {
-                        bss.push(def);
-                        bss_size += size;
-                    } else if d.get_datatype() == DataType::String {
-                        cstrings.push(def);
+                    if let Data::ZeroInit(_) = def.data {
+                        definitions.bss.push(def);
                     } else {
-                        data.push(def);
+                        match d.get_datatype() {
+                            DataType::String => definitions.cstrings.push(def),
+                            DataType::ObjcMethodName => definitions.method_names.push(def),
+                            DataType::ObjcSelectorRef => definitions.selector_refs.push(def),
+                            DataType::GotEntry => definitions.got_entries.push(def),
+                            DataType::Bytes
+                                if !d.is_writable() && !link_sources.contains(def.name) =>
+                            {
+                                definitions.rodata.push(def)
+                            }
+                            DataType::Bytes => definitions.data.push(def),
+                            DataType::ThreadLocal => unreachable!("handled above"),
+                        }
                     }
                 }
                 DefinedDecl::Section(_) => {
-                    sections.push(def);
+                    definitions.sections.push(def);
                 }
             }
         }
 
+        let eh_frame = artifact.eh_frame()?;
+        // the labels of the FDEs are symbols, like those of the definitions
+        if let Some(eh_frame) = eh_frame.as_ref() {
+            for idx in 0..eh_frame.initial_locations.len() {
+                let label = eh_frame_label(idx);
+                if artifact.is_declared(&label) {
+                    return Err(ArtifactError::NameTaken(label));
+                }
+            }
+        }
         let mut symtab = SymbolTable::new();
+        if let Some((path, modification_time)) = artifact.source_path() {
+            symtab.insert_stab(&path.to_string_lossy(), N_OSO, 1, modification_time);
+        }
+        symtab.reserve(
+            definitions.code.len()
+                + definitions.data.len()
+                + definitions.bss.len()
+                + definitions.cstrings.len()
+                + definitions.rodata.len()
+                + definitions
+                    .sections
+                    .iter()
+                    .map(|def| def.symbols.len())
+                    .sum::<usize>()
+                + definitions.method_names.len()
+                + definitions.selector_refs.len()
+                + definitions.got_entries.len()
+                // the descriptors and the init symbols, and __tlv_bootstrap
+                + 2 * (definitions.thread_data.len() + definitions.thread_bss.len())
+                + 1
+                + eh_frame.as_ref().map_or(0, |eh_frame| eh_frame.initial_locations.len())
+                + artifact.imports().count(),
+        );
         let mut segment = SegmentBuilder::new(
             &artifact,
-            &code,
-            &data,
-            &bss,
-            &cstrings,
-            &sections,
+            &definitions,
+            eh_frame.as_ref(),
             &mut symtab,
             &ctx,
-        );
-        build_relocations(&mut segment, &artifact, &symtab);
+        )?;
+        build_relocations(&mut segment, &artifact, &symtab, eh_frame.as_ref())?;
+        for (name, flags) in artifact.section_flags() {
+            let section = segment
+                .sections
+                .values_mut()
+                .find(|section| section.sectname == name)
+                .ok_or_else(|| ArtifactError::NoSuchSection(name.to_string()))?;
+            // `set_section_flags` checked that the flags fit
+            section.flags |= flags as u32;
+        }
 
-        Mach {
+        let SectionDefinitions {
+            code,
+            data,
+            cstrings,
+            rodata,
+            sections,
+            method_names,
+            selector_refs,
+            got_entries,
+            thread_data,
+            ..
+        } = definitions;
+        Ok(Mach {
             ctx,
             architecture: artifact.target.architecture,
             symtab,
@@ -732,41 +1777,346 @@ impl<'a> Mach<'a> {
             _p: ::std::marker::PhantomData::default(),
             code,
             data,
-            bss_size,
             cstrings,
+            rodata,
             sections,
+            method_names,
+            selector_refs,
+            got_entries,
+            thread_data,
+            eh_frame,
+            raw_sections: artifact.raw_sections(),
+            comment: artifact.comment(),
+            data_in_code: artifact.data_in_code(),
+            build_id: artifact.build_id(),
+            build_version: artifact.platform(),
+            subsections_via_symbols: artifact.subsections_via_symbols(),
+            authenticated: artifact
+                .links()
+                .any(|link| matches!(link.reloc, Reloc::Authenticated { .. })),
+        })
+    }
+    /// The number of load commands in this object
+    fn ncmds(&self) -> usize {
+        let mut ncmds = 2;
+        if self.build_version.is_some() {
+            ncmds += 1;
+        }
+        if !self.data_in_code.is_empty() {
+            ncmds += 1;
+        }
+        ncmds + self.notes().len()
+    }
+    /// The size of the `LC_BUILD_VERSION` load command, if there is one
+    fn build_version_load_command_size(&self) -> u64 {
+        if self.build_version.is_some() {
+            SIZEOF_BUILD_VERSION_COMMAND as u64
+        } else {
+            0
+        }
+    }
+    /// The size of the `LC_DATA_IN_CODE` load command, if there is one
+    fn data_in_code_load_command_size(&self) -> u64 {
+        if self.data_in_code.is_empty() {
+            0
+        } else {
+            SIZEOF_LINKEDIT_DATA_COMMAND as u64
         }
     }
+    /// Whether any section is placed in an address space, which is recorded in an `LC_NOTE`
+    fn has_address_spaces(&self) -> bool {
+        self.segment
+            .sections
+            .values()
+            .any(|section| section.address_space.is_some())
+    }
+    /// The size of the `LC_NOTE` load commands, one for every note
+    fn note_load_commands_size(&self) -> u64 {
+        (self.notes().len() * SIZEOF_NOTE_COMMAND) as u64
+    }
+    /// The owner and contents of every note, each of which gets its own `LC_NOTE`
+    fn notes(&self) -> Vec<(&'static [u8; 16], Vec<u8>)> {
+        let mut notes = Vec::new();
+        if self.has_address_spaces() {
+            notes.push((ADDRESS_SPACE_NOTE_OWNER, self.address_space_note()));
+        }
+        if let Some(build_id) = self.build_id {
+            notes.push((BUILD_ID_NOTE_OWNER, build_id.to_vec()));
+        }
+        notes
+    }
+    /// The contents of the address space note: an entry with the section name, segment name and
+    /// address space of every section which is placed in an address space
+    fn address_space_note(&self) -> Vec<u8> {
+        let mut note = Vec::new();
+        for section in self.segment.sections.values() {
+            if let Some(space) = section.address_space {
+                let mut entry = [0u8; SIZEOF_ADDRESS_SPACE_NOTE_ENTRY];
+                entry.pwrite(&*section.sectname, 0).unwrap();
+                entry.pwrite(section.segname, 16).unwrap();
+                entry
+                    .pwrite_with(u64::from(space), 32, self.ctx.le)
+                    .unwrap();
+                note.extend_from_slice(&entry);
+            }
+        }
+        note
+    }
     fn header(&self, sizeofcmds: u64) -> Header {
         let mut header = Header::new(self.ctx);
         header.filetype = MH_OBJECT;
-        // safe to divide up the sections into sub-sections via symbols for dead code stripping
-        header.flags = MH_SUBSECTIONS_VIA_SYMBOLS;
+        // safe to divide up the sections into sub-sections via symbols for dead code stripping,
+        // unless disabled
+        if self.subsections_via_symbols {
+            header.flags = MH_SUBSECTIONS_VIA_SYMBOLS;
+        }
         header.cputype = CpuType::from(self.architecture).0;
-        header.cpusubtype = 3;
-        header.ncmds = 2;
+        // signed pointers only exist in the arm64e ABI, which target-lexicon has no architecture for
+        header.cpusubtype = if self.authenticated {
+            cputype::CPU_SUBTYPE_ARM64_E
+        } else {
+            CpuSubtype::from(self.architecture).0
+        };
+        header.ncmds = self.ncmds();
         header.sizeofcmds = sizeofcmds as u32;
         header
     }
+    /// Compute the layout of the object file, which `write_with_layout` must adhere to
+    fn compute_layout(&self) -> Layout {
+        let symtab_cmd_size = SymtabCommand::new().cmdsize as u64;
+        let linkedit_cmd_size = self.build_version_load_command_size()
+            + self.data_in_code_load_command_size()
+            + self.note_load_commands_size();
+        let sizeof_load_commands =
+            self.segment.load_command_size(&self.ctx) + symtab_cmd_size + linkedit_cmd_size;
+        let symtab_offset =
+            self.segment
+                .symtab_offset(&self.ctx, symtab_cmd_size, linkedit_cmd_size);
+        let strtab_offset =
+            symtab_offset + (self.symtab.len() as u64 * Nlist::size_with(&self.ctx) as u64);
+        let reloc_offset_start = strtab_offset + self.symtab.sizeof_strtable();
+        let first_section_offset = Header::size_with(&self.ctx) as u64 + sizeof_load_commands;
+        Layout {
+            sizeof_load_commands,
+            first_section_offset,
+            symtab_offset,
+            strtab_offset,
+            reloc_offset_start,
+        }
+    }
+    /// Check this object for problems which `write` would not notice, e.g., relocations which were
+    /// dropped since their symbols are missing from the symbol table; `artifact` must be the
+    /// artifact this object was built from
+    pub fn verify(&self, artifact: &Artifact) -> Vec<VerificationError> {
+        let mut errors = Vec::new();
+        let mut missing = HashSet::new();
+        for link in artifact.links() {
+            // sections are relocated by their names, which are not in the symbol table
+            let ends = [link.from, link.to];
+            let symbols = ends.iter().filter(|end| !end.decl.is_section());
+            for name in symbols.map(|end| end.name) {
+                if self.symtab.index(name).is_none() && missing.insert(name) {
+                    errors.push(VerificationError::MissingSymbol {
+                        name: name.to_string(),
+                    });
+                }
+            }
+        }
+        let mut sections_size = 0;
+        for section in self.segment.sections.values() {
+            for reloc in &section.relocations {
+                let size = 1 << reloc.r_length();
+                if reloc.r_address < 0 || reloc.r_address as u64 + size > section.size {
+                    errors.push(VerificationError::RelocationOutOfBounds {
+                        section: section.sectname.clone(),
+                        offset: reloc.r_address as u64,
+                        size,
+                        section_size: section.size,
+                    });
+                }
+            }
+            let section_type = section.flags & SECTION_TYPE;
+            if section_type != S_ZEROFILL && section_type != S_THREAD_LOCAL_ZEROFILL {
+                sections_size += section.leading_pad + section.size;
+            }
+        }
+        if sections_size != self.segment.size() {
+            errors.push(VerificationError::SegmentSizeMismatch {
+                sections: sections_size,
+                segment: self.segment.size(),
+            });
+        }
+        // the first, empty string is a lone null byte
+        let strtable_size = 1
+            + self
+                .symtab
+                .strtable
+                .iter()
+                .skip(1)
+                .map(|(_, string)| strtable_entry_size(string))
+                .sum::<u64>()
+            + self.symtab.sizeof_stab_strings();
+        if strtable_size != self.symtab.sizeof_strtable() {
+            errors.push(VerificationError::StrtableSizeMismatch {
+                expected: self.symtab.sizeof_strtable(),
+                actual: strtable_size,
+            });
+        }
+        errors
+    }
+    /// The sections of this object, with the file offsets `write` will place them at
+    pub fn sections(&self) -> impl Iterator<Item = SectionInfo> + '_ {
+        let mut section_offset = self.compute_layout().first_section_offset;
+        self.segment.sections.values().map(move |section| {
+            // zero filled sections only take up memory, not space in the file
+            let section_type = section.flags & SECTION_TYPE;
+            let file_offset =
+                if section_type == S_ZEROFILL || section_type == S_THREAD_LOCAL_ZEROFILL {
+                    0
+                } else {
+                    section_offset += section.leading_pad;
+                    let offset = section_offset;
+                    section_offset += section.size;
+                    offset
+                };
+            SectionInfo {
+                name: section.sectname.clone(),
+                segment: section.segname.to_string(),
+                file_offset: file_offset as usize,
+                size: section.size as usize,
+                align: section.align.bytes() as usize,
+            }
+        })
+    }
+    /// Computes the checksum of every section with file contents from the original definitions,
+    /// including the alignment padding `write` inserts after them
+    #[cfg(debug_assertions)]
+    fn section_checksums(&self) -> Vec<SectionChecksum> {
+        let mut contents: Vec<(&str, Vec<u8>)> = Vec::new();
+        let mut add = |name: &'a str, defs: &[Definition<'a>], pad: u8| {
+            let mut bytes = Vec::new();
+            for def in defs {
+                if let Data::Blob(blob) = def.data {
+                    bytes.extend_from_slice(blob);
+                }
+                if let Some(&align_pad) = self.segment.align_pad_map.get(def.name) {
+                    bytes.resize(bytes.len() + align_pad as usize, pad);
+                }
+            }
+            contents.push((name, bytes));
+        };
+        add("__text", &self.code, 0xcc);
+        add("__data", &self.data, 0xaa);
+        add("__cstring", &self.cstrings, 0xaa);
+        add("__const", &self.rodata, 0xaa);
+        for section in self.sections.iter() {
+            add(section.name, std::slice::from_ref(section), 0xaa);
+        }
+        add("__objc_methnames", &self.method_names, 0xaa);
+        add("__objc_selrefs", &self.selector_refs, 0xaa);
+        add("__got", &self.got_entries, 0xaa);
+        add("__thread_data", &self.thread_data, 0xaa);
+        if let Some(section) = self.segment.sections.get("__thread_vars") {
+            contents.push(("__thread_vars", vec![0; section.size as usize]));
+        }
+        if let Some(eh_frame) = self.eh_frame.as_ref() {
+            contents.push(("__eh_frame", eh_frame.data.clone()));
+        }
+        for raw in self.raw_sections {
+            contents.push((&raw.name, raw.data.clone()));
+        }
+        contents.push(("__comment", self.comment.to_vec()));
+        contents
+            .into_iter()
+            .filter_map(|(name, bytes)| {
+                let section = self.segment.sections.get(name)?;
+                Some(SectionChecksum {
+                    name: name.to_string(),
+                    addr: section.addr,
+                    size: bytes.len() as u64,
+                    crc: crc32(&bytes),
+                })
+            })
+            .collect()
+    }
     pub fn write<T: Write + Seek>(self, file: T) -> Result<(), std::io::Error> {
+        let layout = self.compute_layout();
         let mut file = BufWriter::new(file);
-        // FIXME: this is ugly af, need cmdsize to get symtable offset
-        // construct symtab command
+        self.write_with_layout(&mut file, layout)?;
+        file.flush()
+    }
+    /// Write the object file to `path` through a memory mapping of the file, which is created
+    /// with its final size up front, so that the many small writes of the symbols and relocations
+    /// are copies into memory instead of system calls
+    #[cfg(all(unix, feature = "mmap"))]
+    pub fn write_mmap(self, path: &Path) -> Result<(), std::io::Error> {
+        let layout = self.compute_layout();
+        let size = self.file_size(&layout);
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(size)?;
+        let mut map = crate::mmap::MmapMut::new(&file, size as usize)?;
+        self.write_with_layout(crate::mmap::MmapWriter::new(map.as_mut_slice()), layout)
+    }
+    /// Write the object file to `path`; without memory mappings, this is a buffered write
+    #[cfg(not(all(unix, feature = "mmap")))]
+    pub fn write_mmap(self, path: &Path) -> Result<(), std::io::Error> {
+        self.write(std::fs::File::create(path)?)
+    }
+    /// The size in bytes of the object file with `layout`; the relocations, data in code entries
+    /// and notes follow the string table, and the file ends with a single null byte
+    #[cfg(all(unix, feature = "mmap"))]
+    fn file_size(&self, layout: &Layout) -> u64 {
+        let nrelocs = self
+            .segment
+            .sections
+            .values()
+            .map(|section| section.relocations.len())
+            .sum::<usize>();
+        let sizeof_notes = self
+            .notes()
+            .iter()
+            .map(|(_, note)| note.len())
+            .sum::<usize>();
+        layout.reloc_offset_start
+            + (nrelocs * SIZEOF_RELOCATION_INFO) as u64
+            + (self.data_in_code.len() * load_command::DataInCodeEntry::size_with(&self.ctx.le))
+                as u64
+            + sizeof_notes as u64
+            + 1
+    }
+    /// Write the object file to `file`, with the previously computed `layout`
+    fn write_with_layout<T: Write + Seek>(
+        self,
+        file: T,
+        layout: Layout,
+    ) -> Result<(), std::io::Error> {
+        // in debug builds, every section is read back and checked after it has been written
+        #[cfg(debug_assertions)]
+        let checksums = self.section_checksums();
+        #[cfg(debug_assertions)]
+        let mut file = Mirror::new(file);
+        #[cfg(not(debug_assertions))]
+        let mut file = file;
         let mut symtab_load_command = SymtabCommand::new();
-        let segment_load_command_size = self.segment.load_command_size(&self.ctx);
-        let sizeof_load_commands = segment_load_command_size + symtab_load_command.cmdsize as u64;
-        let symtable_offset = self.segment.offset + sizeof_load_commands;
-        let strtable_offset =
-            symtable_offset + (self.symtab.len() as u64 * Nlist::size_with(&self.ctx) as u64);
-        let relocation_offset_start = strtable_offset + self.symtab.sizeof_strtable();
-        let first_section_offset = Header::size_with(&self.ctx) as u64 + sizeof_load_commands;
+        let Layout {
+            sizeof_load_commands,
+            first_section_offset,
+            symtab_offset,
+            strtab_offset,
+            reloc_offset_start,
+        } = layout;
         // start with setting the headers dependent value
         let header = self.header(sizeof_load_commands);
 
         debug!("Symtable: {:#?}", self.symtab);
         // marshall the sections into something we can actually write
         let mut raw_sections = Cursor::new(Vec::<u8>::new());
-        let mut relocation_offset = relocation_offset_start;
+        let mut relocation_offset = reloc_offset_start;
         let mut section_offset = first_section_offset;
         for section in self.segment.sections.values() {
             let header = section.create(&mut section_offset, &mut relocation_offset);
@@ -788,42 +2138,85 @@ impl<'a> Mach<'a> {
         segment_load_command.initprot = 7;
         segment_load_command.maxprot = 7;
         segment_load_command.filesize = self.segment.size();
-        // segment size, with __bss data sizes added
-        segment_load_command.vmsize = segment_load_command.filesize + self.bss_size as u64;
+        // segment size, with the zero filled sections added
+        segment_load_command.vmsize = self.segment.vmsize();
         segment_load_command.fileoff = first_section_offset;
         debug!("Segment: {:#?}", segment_load_command);
 
-        debug!("Symtable Offset: {:#?}", symtable_offset);
+        debug!("Symtable Offset: {:#?}", symtab_offset);
         assert_eq!(
-            symtable_offset,
+            symtab_offset,
             self.segment.offset
                 + segment_load_command.cmdsize as u64
                 + symtab_load_command.cmdsize as u64
+                + self.build_version_load_command_size()
+                + self.data_in_code_load_command_size()
+                + self.note_load_commands_size()
         );
         symtab_load_command.nsyms = self.symtab.len() as u32;
-        symtab_load_command.symoff = symtable_offset as u32;
-        symtab_load_command.stroff = strtable_offset as u32;
+        symtab_load_command.symoff = symtab_offset as u32;
+        symtab_load_command.stroff = strtab_offset as u32;
         symtab_load_command.strsize = self.symtab.sizeof_strtable() as u32;
 
         debug!("Symtab Load command: {:#?}", symtab_load_command);
 
+        // the data in code entries are placed after the relocations
+        let data_in_code_load_command = LinkeditDataCommand {
+            cmd: LC_DATA_IN_CODE,
+            cmdsize: SIZEOF_LINKEDIT_DATA_COMMAND as u32,
+            dataoff: relocation_offset as u32,
+            datasize: (self.data_in_code.len()
+                * load_command::DataInCodeEntry::size_with(&self.ctx.le))
+                as u32,
+        };
+        // and the notes after those
+        let notes = self.notes();
+        let notes_offset =
+            u64::from(data_in_code_load_command.dataoff + data_in_code_load_command.datasize);
+
         //////////////////////////////
         // write header
         //////////////////////////////
         file.iowrite_with(header, self.ctx)?;
-        debug!("SEEK: after header: {}", file.seek(Current(0))?);
+        log_position(&mut file, "header")?;
 
         //////////////////////////////
         // write load commands
         //////////////////////////////
         file.iowrite_with(segment_load_command, self.ctx)?;
         file.write_all(&raw_sections)?;
+        if let Some((platform, min_os, sdk)) = self.build_version {
+            file.iowrite_with(LC_BUILD_VERSION, self.ctx.le)?;
+            file.iowrite_with(SIZEOF_BUILD_VERSION_COMMAND as u32, self.ctx.le)?;
+            file.iowrite_with(platform as u32, self.ctx.le)?;
+            file.iowrite_with(encode_version(min_os), self.ctx.le)?;
+            file.iowrite_with(encode_version(sdk), self.ctx.le)?;
+            // no tool entries
+            file.iowrite_with(0u32, self.ctx.le)?;
+        }
         file.iowrite_with(symtab_load_command, self.ctx.le)?;
-        debug!("SEEK: after load commands: {}", file.seek(Current(0))?);
+        if !self.data_in_code.is_empty() {
+            debug!(
+                "Data in code Load command: {:#?}",
+                data_in_code_load_command
+            );
+            file.iowrite_with(data_in_code_load_command, self.ctx.le)?;
+        }
+        let mut note_offset = notes_offset;
+        for (owner, note) in notes.iter() {
+            file.iowrite_with(LC_NOTE, self.ctx.le)?;
+            file.iowrite_with(SIZEOF_NOTE_COMMAND as u32, self.ctx.le)?;
+            file.write_all(*owner)?;
+            file.iowrite_with(note_offset, self.ctx.le)?;
+            file.iowrite_with(note.len() as u64, self.ctx.le)?;
+            note_offset += note.len() as u64;
+        }
+        log_position(&mut file, "load commands")?;
 
         //////////////////////////////
         // write code
         //////////////////////////////
+        write_leading_pad(&mut file, &self.segment.sections, "__text", 0xcc)?;
         for code in self.code {
             if let Data::Blob(bytes) = code.data {
                 file.write_all(&bytes)?;
@@ -839,11 +2232,12 @@ impl<'a> Mach<'a> {
                 }
             }
         }
-        debug!("SEEK: after code: {}", file.seek(Current(0))?);
+        log_position(&mut file, "code")?;
 
         //////////////////////////////
         // write data
         //////////////////////////////
+        write_leading_pad(&mut file, &self.segment.sections, "__data", 0xaa)?;
         for data in self.data {
             if let Data::Blob(bytes) = data.data {
                 file.write_all(bytes)?;
@@ -858,11 +2252,12 @@ impl<'a> Mach<'a> {
                 }
             }
         }
-        debug!("SEEK: after data: {}", file.seek(Current(0))?);
+        log_position(&mut file, "data")?;
 
         //////////////////////////////
         // write cstrings
         //////////////////////////////
+        write_leading_pad(&mut file, &self.segment.sections, "__cstring", 0xaa)?;
         for cstring in self.cstrings {
             if let Data::Blob(bytes) = cstring.data {
                 file.write_all(bytes)?;
@@ -877,12 +2272,33 @@ impl<'a> Mach<'a> {
                 }
             }
         }
-        debug!("SEEK: after cstrings: {}", file.seek(Current(0))?);
+        log_position(&mut file, "cstrings")?;
+
+        //////////////////////////////
+        // write read-only data
+        //////////////////////////////
+        write_leading_pad(&mut file, &self.segment.sections, "__const", 0xaa)?;
+        for rodata in self.rodata {
+            if let Data::Blob(bytes) = rodata.data {
+                file.write_all(bytes)?;
+            } else {
+                unreachable!();
+            }
+
+            if let Some(&align_pad) = self.segment.align_pad_map.get(rodata.name) {
+                for _ in 0..align_pad {
+                    // See comment above for explanation of 0xaa
+                    file.write_all(&[0xaa])?;
+                }
+            }
+        }
+        log_position(&mut file, "read-only data")?;
 
         //////////////////////////////
         // write custom sections
         //////////////////////////////
         for section in self.sections {
+            write_leading_pad(&mut file, &self.segment.sections, section.name, 0xaa)?;
             if let Data::Blob(bytes) = section.data {
                 file.write_all(bytes)?;
             } else {
@@ -896,17 +2312,118 @@ impl<'a> Mach<'a> {
                 }
             }
         }
-        debug!("SEEK: after custom sections: {}", file.seek(Current(0))?);
+        log_position(&mut file, "custom sections")?;
+
+        //////////////////////////////
+        // write objective-c sections
+        //////////////////////////////
+        write_leading_pad(&mut file, &self.segment.sections, "__objc_methnames", 0xaa)?;
+        for method_name in self.method_names {
+            if let Data::Blob(bytes) = method_name.data {
+                file.write_all(bytes)?;
+            } else {
+                unreachable!();
+            }
+
+            if let Some(&align_pad) = self.segment.align_pad_map.get(method_name.name) {
+                for _ in 0..align_pad {
+                    // See comment above for explanation of 0xaa
+                    file.write_all(&[0xaa])?;
+                }
+            }
+        }
+        write_leading_pad(&mut file, &self.segment.sections, "__objc_selrefs", 0xaa)?;
+        for selector_ref in self.selector_refs {
+            if let Data::Blob(bytes) = selector_ref.data {
+                file.write_all(bytes)?;
+            } else {
+                unreachable!();
+            }
+        }
+        log_position(&mut file, "objective-c sections")?;
+
+        //////////////////////////////
+        // write got entries
+        //////////////////////////////
+        write_leading_pad(&mut file, &self.segment.sections, "__got", 0xaa)?;
+        for got_entry in self.got_entries {
+            if let Data::Blob(bytes) = got_entry.data {
+                file.write_all(bytes)?;
+            } else {
+                unreachable!();
+            }
+
+            if let Some(&align_pad) = self.segment.align_pad_map.get(got_entry.name) {
+                for _ in 0..align_pad {
+                    // See comment above for explanation of 0xaa
+                    file.write_all(&[0xaa])?;
+                }
+            }
+        }
+        log_position(&mut file, "got entries")?;
+
+        //////////////////////////////
+        // write thread-local variables
+        //////////////////////////////
+        write_leading_pad(&mut file, &self.segment.sections, "__thread_data", 0xaa)?;
+        for thread_data in self.thread_data {
+            if let Data::Blob(bytes) = thread_data.data {
+                file.write_all(bytes)?;
+            } else {
+                unreachable!();
+            }
+
+            if let Some(&align_pad) = self.segment.align_pad_map.get(thread_data.name) {
+                for _ in 0..align_pad {
+                    // See comment above for explanation of 0xaa
+                    file.write_all(&[0xaa])?;
+                }
+            }
+        }
+        if let Some(section) = self.segment.sections.get("__thread_vars") {
+            write_leading_pad(&mut file, &self.segment.sections, "__thread_vars", 0xaa)?;
+            // the descriptors are filled in entirely by relocations
+            file.write_all(&vec![0; section.size as usize])?;
+        }
+        log_position(&mut file, "thread-local variables")?;
+
+        //////////////////////////////
+        // write unwinding information
+        //////////////////////////////
+        if let Some(eh_frame) = self.eh_frame.as_ref() {
+            write_leading_pad(&mut file, &self.segment.sections, "__eh_frame", 0xaa)?;
+            file.write_all(&eh_frame.data)?;
+        }
+        log_position(&mut file, "unwinding information")?;
+
+        //////////////////////////////
+        // write raw sections, and the comment
+        //////////////////////////////
+        for raw in self.raw_sections {
+            file.write_all(&raw.data)?;
+        }
+        file.write_all(self.comment)?;
+        log_position(&mut file, "raw sections")?;
+
+        #[cfg(debug_assertions)]
+        {
+            file.flush()?;
+            verify_section_checksums(file.contents(), first_section_offset, &checksums);
+        }
 
         //////////////////////////////
         // write symtable
         //////////////////////////////
+        for stab in self.symtab.create_stabs() {
+            debug!("stab: {:?}", stab);
+            file.iowrite_with(stab, self.ctx)?;
+        }
         for (idx, symbol) in self.symtab.symbols.into_iter() {
             let symbol = symbol.create();
             debug!("{}: {:?}", idx, symbol);
             file.iowrite_with(symbol, self.ctx)?;
         }
-        debug!("SEEK: after symtable: {}", file.seek(Current(0))?);
+        log_position(&mut file, "symtable")?;
 
         //////////////////////////////
         // write strtable
@@ -920,7 +2437,11 @@ impl<'a> Mach<'a> {
             file.write_all(string.as_bytes())?;
             file.iowrite(0u8)?;
         }
-        debug!("SEEK: after strtable: {}", file.seek(Current(0))?);
+        for stab in self.symtab.stabs.iter() {
+            file.write_all(stab.name.as_bytes())?;
+            file.iowrite(0u8)?;
+        }
+        log_position(&mut file, "strtable")?;
 
         //////////////////////////////
         // write relocations
@@ -932,22 +2453,88 @@ impl<'a> Mach<'a> {
                 file.iowrite_with(reloc, self.ctx.le)?;
             }
         }
-        debug!("SEEK: after relocations: {}", file.seek(Current(0))?);
+        log_position(&mut file, "relocations")?;
+
+        //////////////////////////////
+        // write data in code entries
+        //////////////////////////////
+        for entry in self.data_in_code {
+            // the entry offsets are relative to the code section, but mach expects file offsets
+            let entry = load_command::DataInCodeEntry {
+                offset: first_section_offset as u32 + entry.offset,
+                length: entry.length,
+                kind: entry.kind,
+            };
+            file.iowrite_with(entry, self.ctx.le)?;
+        }
+        log_position(&mut file, "data in code")?;
+
+        //////////////////////////////
+        // write notes
+        //////////////////////////////
+        for (_, note) in notes.iter() {
+            file.write_all(note)?;
+        }
+        log_position(&mut file, "notes")?;
 
         file.iowrite(0u8)?;
 
+        //////////////////////////////
+        // patch in fixups
+        //////////////////////////////
+        for &(offset, value, size) in self.segment.fixups.iter() {
+            file.seek(Start(first_section_offset + offset))?;
+            if size == 4 {
+                file.iowrite_with(value as u32, self.ctx.le)?;
+            } else {
+                file.iowrite_with(value, self.ctx.le)?;
+            }
+        }
+
         Ok(())
     }
 }
 
+/// The value stored at an i386 pc-relative relocation at `address` for `addend`: unlike on x86_64,
+/// it is the displacement from the end of the relocated value to the target, as if the target
+/// were at address zero
+fn i386_pcrel_addend(address: u64, addend: i64) -> i64 {
+    addend - (address as i64 + 4)
+}
+
+/// The relocation of an absolute pointer on `architecture`
+fn unsigned_relocation(architecture: Architecture) -> RelocType {
+    use goblin::mach::relocation::{
+        ARM64_RELOC_UNSIGNED, ARM_RELOC_VANILLA, GENERIC_RELOC_VANILLA, X86_64_RELOC_UNSIGNED,
+    };
+    match architecture {
+        Architecture::X86_64 => X86_64_RELOC_UNSIGNED,
+        Architecture::Aarch64(_) => ARM64_RELOC_UNSIGNED,
+        Architecture::Arm(_) => ARM_RELOC_VANILLA,
+        _ => GENERIC_RELOC_VANILLA,
+    }
+}
+
 // FIXME: this should actually return a runtime error if we encounter a from.decl to.decl pair which we don't explicitly match on
-fn build_relocations(segment: &mut SegmentBuilder, artifact: &Artifact, symtab: &SymbolTable) {
+fn build_relocations(
+    segment: &mut SegmentBuilder,
+    artifact: &Artifact,
+    symtab: &SymbolTable,
+    eh_frame: Option<&EhFrame>,
+) -> Result<(), ArtifactError> {
     use goblin::mach::relocation::{
-        R_ABS, X86_64_RELOC_BRANCH, X86_64_RELOC_GOT_LOAD, X86_64_RELOC_SIGNED,
-        X86_64_RELOC_UNSIGNED,
+        ARM64_RELOC_BRANCH26, ARM64_RELOC_SUBTRACTOR, GENERIC_RELOC_VANILLA, R_ABS,
+        X86_64_RELOC_BRANCH, X86_64_RELOC_GOT_LOAD, X86_64_RELOC_SIGNED, X86_64_RELOC_SUBTRACTOR,
+        X86_64_RELOC_TLV,
     };
     let text_idx = segment.sections.get_full("__text").unwrap().0;
     let data_idx = segment.sections.get_full("__data").unwrap().0;
+    let pointer_size = artifact.target.pointer_width().map_or(8, |w| w.bytes());
+    let is_i386 = matches!(
+        artifact.target.architecture,
+        Architecture::I386 | Architecture::I586 | Architecture::I686
+    );
+    let unsigned = unsigned_relocation(artifact.target.architecture);
     debug!("Generating relocations");
     for link in artifact.links() {
         debug!(
@@ -955,7 +2542,7 @@ fn build_relocations(segment: &mut SegmentBuilder, artifact: &Artifact, symtab:
             link.from.name, link.to.name, link.at, link.reloc
         );
         let (absolute, reloc) = match link.reloc {
-            Reloc::Auto => {
+            Reloc::Auto | Reloc::AutoWithAddend { .. } => {
                 // NB: we currently deduce the meaning of our relocation from from decls -> to decl relocations
                 // e.g., global static data references, are constructed from Data -> Data links
                 match (link.from.decl, link.to.decl) {
@@ -979,23 +2566,40 @@ fn build_relocations(segment: &mut SegmentBuilder, artifact: &Artifact, symtab:
                     }
 
                     // from data object
-                    (Decl::Defined(DefinedDecl::Data { .. }), _) => (true, X86_64_RELOC_UNSIGNED),
+                    (Decl::Defined(DefinedDecl::Data { .. }), _) => (true, unsigned),
 
                     // from function
                     (Decl::Defined(DefinedDecl::Function { .. }), to) => match to {
                         Decl::Defined(DefinedDecl::Function { .. }) => (false, X86_64_RELOC_BRANCH),
-                        Decl::Import(ImportKind::Function) => (false, X86_64_RELOC_BRANCH),
+                        Decl::Import(ImportKind::Function)
+                        | Decl::Import(ImportKind::WeakFunction) => (false, X86_64_RELOC_BRANCH),
 
+                        // e.g., `movq _x@TLVP(%rip), %rdi`, which loads the address of the descriptor
+                        Decl::Defined(DefinedDecl::Data(d)) if d.is_thread_local() => {
+                            (false, X86_64_RELOC_TLV)
+                        }
                         Decl::Defined(DefinedDecl::Data { .. }) => (false, X86_64_RELOC_SIGNED),
-                        Decl::Import(ImportKind::Data) => (false, X86_64_RELOC_GOT_LOAD),
+                        // common symbols may be allocated in another module, like data imports
+                        Decl::Import(ImportKind::Data)
+                        | Decl::Import(ImportKind::WeakData)
+                        | Decl::Common { .. } => (false, X86_64_RELOC_GOT_LOAD),
 
                         // handled above
                         Decl::Defined(DefinedDecl::Section { .. }) => unreachable!(),
+
+                        // e.g., `movabsq $_reg, %rax`, which loads the address itself
+                        Decl::AbsoluteSymbol(_) => (true, unsigned),
                     },
 
                     (Decl::Import(_), _) => {
                         unreachable!("Tried to relocate import???");
                     }
+                    (Decl::AbsoluteSymbol(_), _) => {
+                        unreachable!("Tried to relocate absolute symbol???");
+                    }
+                    (Decl::Common { .. }, _) => {
+                        unreachable!("Tried to relocate common symbol???");
+                    }
                 }
             }
             Reloc::Raw { reloc, addend } => {
@@ -1006,13 +2610,37 @@ fn build_relocations(segment: &mut SegmentBuilder, artifact: &Artifact, symtab:
                     reloc => (false, reloc),
                 }
             }
+            Reloc::Authenticated { .. } => (true, ARM64_RELOC_AUTHENTICATED_POINTER),
+            Reloc::Absolute { .. } => (true, unsigned),
+            Reloc::Kind(kind) => match (kind, artifact.target.architecture) {
+                (RelocationKind::Absolute, _) => (true, unsigned),
+                (RelocationKind::Branch, Architecture::Aarch64(_)) => (false, ARM64_RELOC_BRANCH26),
+                // arm64 addresses are formed by a pair of instructions, each with its own relocation
+                (_, Architecture::Aarch64(_)) => {
+                    panic!(
+                        "{:?} relocations are not yet supported for arm64 Mach-o",
+                        kind
+                    )
+                }
+                (RelocationKind::PcRelative, _) => (false, X86_64_RELOC_SIGNED),
+                (RelocationKind::Branch, _) => (false, X86_64_RELOC_BRANCH),
+                (RelocationKind::GotPcRelative, _) => (false, X86_64_RELOC_GOT_LOAD),
+            },
+            Reloc::Subtrahend { .. } => match artifact.target.architecture {
+                _ if is_i386 => {
+                    panic!("differences of symbols are not yet supported for i386 Mach-o")
+                }
+                Architecture::Aarch64(_) => (true, ARM64_RELOC_SUBTRACTOR),
+                _ => (true, X86_64_RELOC_SUBTRACTOR),
+            },
+            Reloc::Minuend { .. } => (true, unsigned),
             Reloc::Debug { size, .. } => {
                 if link.to.decl.is_section() {
                     // TODO: not sure if these are needed for Mach
                 } else {
                     match symtab.index(link.to.name) {
                         Some(to_symbol_index) => {
-                            let builder = RelocationBuilder::new(to_symbol_index, link.at, X86_64_RELOC_UNSIGNED).absolute().size(size);
+                            let builder = RelocationBuilder::new(to_symbol_index, link.at, unsigned).absolute().size(size);
                             segment.sections[link.from.name].relocations.push(builder.create());
                         }
                         _ => error!("Import Relocation from {} to {} at {:#x} has a missing symbol. Dumping symtab {:?}", link.from.name, link.to.name, link.at, symtab)
@@ -1021,26 +2649,902 @@ fn build_relocations(segment: &mut SegmentBuilder, artifact: &Artifact, symtab:
                 continue;
             }
         };
+        // i386 has no rip-relative addressing, so apart from calls and explicitly pc-relative
+        // references, references are to absolute addresses; all of them are
+        // `GENERIC_RELOC_VANILLA`, except for thread-local variables
+        let (absolute, reloc) = match link.reloc {
+            Reloc::Kind(RelocationKind::PcRelative) if is_i386 => (false, GENERIC_RELOC_VANILLA),
+            Reloc::Kind(RelocationKind::GotPcRelative) if is_i386 => {
+                return Err(ArtifactError::UnsupportedRelocation(format!(
+                    "i386 has no GOT relative relocations, from {} to {} at {:#x}",
+                    link.from.name, link.to.name, link.at
+                )));
+            }
+            Reloc::Auto
+            | Reloc::AutoWithAddend { .. }
+            | Reloc::Absolute { .. }
+            | Reloc::Kind(_)
+                if is_i386 =>
+            {
+                match reloc {
+                    X86_64_RELOC_BRANCH => (false, GENERIC_RELOC_VANILLA),
+                    X86_64_RELOC_TLV => (true, GENERIC_RELOC_TLV),
+                    _ => (true, GENERIC_RELOC_VANILLA),
+                }
+            }
+            _ => (absolute, reloc),
+        };
+        // the linker may relax the load of the GOT entry, so there is nowhere to put an addend
+        if let Reloc::AutoWithAddend { addend } = link.reloc {
+            if addend != 0 && !is_i386 && reloc == X86_64_RELOC_GOT_LOAD {
+                return Err(ArtifactError::UnsupportedRelocation(format!(
+                    "GOT loads have no addend, from {} to {} at {:#x} with addend {}",
+                    link.from.name, link.to.name, link.at, addend
+                )));
+            }
+        }
         match (symtab.offset(link.from.name), symtab.index(link.to.name)) {
             (Some(base_offset), Some(to_symbol_index)) => {
                 debug!("{} offset: {}", link.to.name, base_offset + link.at);
+                // NB: relocations go in the section of the symbol they are from; if we somehow don't know it,
+                // we fall back to associating absolute relocations with data relocations
+                let section_idx = symtab.section(link.from.name).unwrap_or(if absolute { data_idx } else { text_idx });
+                debug!(
+                    "relocation is inside {:?}",
+                    symtab.lookup_by_offset(section_idx, (base_offset + link.at) as usize)
+                );
                 let builder = RelocationBuilder::new(to_symbol_index, base_offset + link.at, reloc);
-                // NB: we currently associate absolute relocations with data relocations; this may prove
-                // too fragile for future additions; needs analysis
-                if absolute {
-                    segment.sections.get_index_mut(data_idx).unwrap().1.relocations.push(builder.absolute().create());
+                let builder = if absolute { builder.absolute().size(pointer_size) } else { builder };
+                // references to a symbol in the same section are relative to the section, with the
+                // target's address stored at the relocated location; like on i386, the existing
+                // contents are not an implicit addend. Weak definitions may be coalesced with
+                // another module's, so they are always referenced by symbol, as are the targets of
+                // arm64 branches, which the linker requires to be external.
+                let local_addend = match (link.reloc, link.to.decl) {
+                    (_, Decl::Defined(decl)) if is_weak_definition(decl) => None,
+                    _ if !absolute && matches!(artifact.target.architecture, Architecture::Aarch64(_)) => None,
+                    (_, _) if symtab.section(link.to.name) != Some(section_idx) => None,
+                    (Reloc::Auto, _)
+                    | (Reloc::Kind(RelocationKind::Absolute), _)
+                    | (Reloc::Kind(RelocationKind::PcRelative), _)
+                    | (Reloc::Kind(RelocationKind::Branch), _) => Some(0),
+                    (Reloc::AutoWithAddend { addend }, _) => Some(addend),
+                    (Reloc::Absolute { addend }, _) => Some(i64::from(addend)),
+                    _ => None,
+                };
+                // NB: the subtractor must directly precede its unsigned relocation, which it does
+                // since the pair of links is always added together
+                let builder = match link.reloc {
+                    Reloc::Subtrahend { size } | Reloc::Minuend { size } => builder.size(size),
+                    _ => builder,
+                };
+                let builder = if let Some(addend) = local_addend {
+                    let addr = segment.sections.get_index(section_idx).unwrap().1.addr;
+                    let address = addr + base_offset + link.at;
+                    let target = (addr + symtab.offset(link.to.name).unwrap()) as i64 + addend;
+                    if absolute {
+                        segment.fixups.push((address, target as u64, pointer_size));
+                    } else {
+                        segment.fixups.push((address, (target - (address as i64 + 4)) as u64, 4));
+                    }
+                    builder.local(section_idx + 1)
+                } else if let Reloc::Authenticated { key, addr_div, discriminator, addend } = link.reloc {
+                    let builder = builder.authenticated(key, addr_div, discriminator);
+                    let addr = segment.sections.get_index(section_idx).unwrap().1.addr;
+                    segment.fixups.push((addr + base_offset + link.at, builder.authenticated_value(addend).unwrap(), 8));
+                    builder
+                } else if let Reloc::Absolute { addend } = link.reloc {
+                    // mach-o addends are implicit, i.e., stored in the relocated pointer itself
+                    let addr = segment.sections.get_index(section_idx).unwrap().1.addr;
+                    segment.fixups.push((addr + base_offset + link.at, i64::from(addend) as u64, pointer_size));
+                    builder
+                } else if let Reloc::AutoWithAddend { addend } = link.reloc {
+                    // pc-relative relocations are of 32 bit displacements, and absolute ones of pointers
+                    let size = if absolute { pointer_size } else { 4 };
+                    let addr = segment.sections.get_index(section_idx).unwrap().1.addr;
+                    let address = addr + base_offset + link.at;
+                    let addend = if is_i386 && !absolute { i386_pcrel_addend(address, addend) } else { addend };
+                    segment.fixups.push((address, addend as u64, size));
+                    builder
+                } else if is_i386 && !absolute && matches!(link.reloc, Reloc::Auto | Reloc::Kind(_)) {
+                    let addr = segment.sections.get_index(section_idx).unwrap().1.addr;
+                    let address = addr + base_offset + link.at;
+                    segment.fixups.push((address, i386_pcrel_addend(address, 0) as u64, 4));
+                    builder
                 } else {
-                    segment.sections.get_index_mut(text_idx).unwrap().1.relocations.push(builder.create());
-                }
+                    builder
+                };
+                segment.sections.get_index_mut(section_idx).unwrap().1.relocations.push(builder.create());
             },
             _ => error!("Import Relocation from {} to {} at {:#x} has a missing symbol. Dumping symtab {:?}", link.from.name, link.to.name, link.at, symtab)
         }
     }
+    // the initial location of every FDE is the address of its function relative to the location,
+    // i.e., the difference of the function and the location's label
+    if let Some(eh_frame) = eh_frame {
+        let subtractor = match artifact.target.architecture {
+            Architecture::Aarch64(_) => ARM64_RELOC_SUBTRACTOR,
+            _ => X86_64_RELOC_SUBTRACTOR,
+        };
+        let relocations = &mut segment.sections["__eh_frame"].relocations;
+        for (idx, &(location, function)) in eh_frame.initial_locations.iter().enumerate() {
+            let label = symtab
+                .index(&eh_frame_label(idx))
+                .expect("label was inserted");
+            let function = symtab.index(function).expect("function was inserted");
+            relocations.push(
+                RelocationBuilder::new(label, location, subtractor)
+                    .absolute()
+                    .size(4)
+                    .create(),
+            );
+            relocations.push(
+                RelocationBuilder::new(function, location, unsigned)
+                    .absolute()
+                    .size(4)
+                    .create(),
+            );
+        }
+    }
+    // point every thread-local variable descriptor to `__tlv_bootstrap` and its initial value
+    if let Some((vars_idx, _, vars)) = segment.sections.get_full_mut("__thread_vars") {
+        let bootstrap = symtab
+            .index(TLV_BOOTSTRAP)
+            .expect("__tlv_bootstrap was inserted");
+        let pointer_size = make_ctx(&artifact.target).size() as u64;
+        for def in artifact.definitions() {
+            if symtab.section(def.name) != Some(vars_idx) {
+                continue;
+            }
+            let offset = symtab.offset(def.name).expect("descriptor was inserted");
+            let init = symtab
+                .index(&thread_local_init_symbol(def.name))
+                .expect("init symbol was inserted");
+            vars.relocations.push(
+                RelocationBuilder::new(bootstrap, offset, unsigned)
+                    .absolute()
+                    .create(),
+            );
+            vars.relocations.push(
+                RelocationBuilder::new(init, offset + 2 * pointer_size, unsigned)
+                    .absolute()
+                    .create(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// List the symbols of `artifact`, as `nm` would
+pub fn nm(artifact: &Artifact) -> Result<String, ArtifactError> {
+    let mach = Mach::new(artifact)?;
+    Ok(mach.symtab.to_nm_output(&mach.segment.sections))
+}
+
+pub fn map(artifact: &Artifact) -> Result<Vec<MapSection>, ArtifactError> {
+    let mach = Mach::new(artifact)?;
+    let sizes = artifact
+        .definitions()
+        .map(|def| (def.name, def.data.memory_size() as u64))
+        .collect::<HashMap<_, _>>();
+    let mut sections = mach
+        .segment
+        .sections
+        .values()
+        .map(|section| MapSection {
+            name: format!("{},{}", section.segname, section.sectname),
+            addr: section.addr,
+            size: section.size,
+            symbols: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    for (&idx, symbol) in mach.symtab.symbols.iter() {
+        if let Some(section) = symbol.section {
+            let name = mach
+                .symtab
+                .strtable
+                .resolve(idx)
+                .expect("symbol has a name");
+            let section = &mut sections[section];
+            section.symbols.push((
+                name.to_string(),
+                section.addr + symbol.get_segment_relative_offset(),
+                // custom section symbols do not have a size
+                sizes.get(name).cloned().unwrap_or(0),
+            ));
+        }
+    }
+    Ok(sections)
+}
+
+/// The problems in the Mach-o object which would be emitted for `artifact`
+pub fn verify(artifact: &Artifact) -> Result<Vec<VerificationError>, ArtifactError> {
+    Ok(Mach::new(artifact)?.verify(artifact))
+}
+
+/// The sections of the Mach-o object emitted for `artifact`, in the order they appear in the file
+pub fn sections(artifact: &Artifact) -> Result<Vec<SectionInfo>, ArtifactError> {
+    Ok(Mach::new(artifact)?.sections().collect())
 }
 
-pub fn to_bytes(artifact: &Artifact) -> Result<Vec<u8>, std::io::Error> {
-    let mach = Mach::new(&artifact);
+pub fn to_bytes(artifact: &Artifact) -> Result<Vec<u8>, ArtifactError> {
     let mut buffer = Cursor::new(Vec::new());
-    mach.write(&mut buffer)?;
-    Ok(buffer.into_inner())
+    write(artifact, &mut buffer)?;
+    let bytes = buffer.into_inner();
+    #[cfg(any(test, feature = "validate"))]
+    validate(&bytes)?;
+    Ok(bytes)
+}
+
+/// Parse `bytes`, a Mach-o object, back with goblin, and check that the names of all of its
+/// symbols start inside of its string table, which goblin does not check
+#[cfg(any(test, feature = "validate"))]
+fn validate(bytes: &[u8]) -> Result<(), ValidationError> {
+    use goblin::container::{Container, Endian};
+    use goblin::mach::load_command::CommandVariant;
+    use goblin::mach::symbols::Nlist;
+    use goblin::mach::MachO;
+    use scroll::Pread;
+
+    let parse_error = |error: goblin::error::Error| ValidationError::Parse(error.to_string());
+    let mach = MachO::parse(bytes, 0).map_err(parse_error)?;
+    let symtab = mach.load_commands.iter().find_map(|lc| match lc.command {
+        CommandVariant::Symtab(symtab) => Some(symtab),
+        _ => None,
+    });
+    let symtab = match symtab {
+        Some(symtab) => symtab,
+        None => return Ok(()),
+    };
+    let container = if mach.is_64 {
+        Container::Big
+    } else {
+        Container::Little
+    };
+    let endian = if mach.little_endian {
+        Endian::Little
+    } else {
+        Endian::Big
+    };
+    let ctx = Ctx::new(container, endian);
+    let sizeof_nlist = Nlist::size_with(&ctx);
+    for index in 0..symtab.nsyms as usize {
+        let offset = symtab.symoff as usize + index * sizeof_nlist;
+        let nlist: Nlist = bytes.pread_with(offset, ctx).map_err(parse_error)?;
+        if nlist.n_strx >= symtab.strsize as usize {
+            return Err(ValidationError::StringOutOfBounds {
+                index,
+                n_strx: nlist.n_strx,
+                strsize: symtab.strsize as usize,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Write `artifact` as a Mach-o object directly to `sink`, without buffering the object in memory
+pub fn write<T: Write + Seek>(artifact: &Artifact, sink: T) -> Result<(), ArtifactError> {
+    Ok(Mach::new(&artifact)?.write(sink)?)
+}
+
+/// Write `artifact` as a Mach-o object to the file at `path` through a memory mapping, if the
+/// `mmap` feature is enabled
+pub fn write_mmap(artifact: &Artifact, path: &Path) -> Result<(), ArtifactError> {
+    Ok(Mach::new(artifact)?.write_mmap(path)?)
+}
+
+/// The platform a Mach-o object is built for, as recorded in its `LC_BUILD_VERSION` load command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MachPlatform {
+    /// `PLATFORM_MACOS`
+    MacOS = 1,
+    /// `PLATFORM_IOS`
+    IOS = 2,
+    /// `PLATFORM_TVOS`
+    TvOS = 3,
+    /// `PLATFORM_WATCHOS`
+    WatchOS = 4,
+    /// `PLATFORM_BRIDGEOS`
+    BridgeOS = 5,
+    /// `PLATFORM_MACCATALYST`
+    MacCatalyst = 6,
+    /// `PLATFORM_IOSSIMULATOR`
+    IOSSimulator = 7,
+    /// `PLATFORM_TVOSSIMULATOR`
+    TvOSSimulator = 8,
+    /// `PLATFORM_WATCHOSSIMULATOR`
+    WatchOSSimulator = 9,
+    /// `PLATFORM_DRIVERKIT`
+    DriverKit = 10,
+}
+
+/// A platform, and the minimum OS and SDK versions, as `(major, minor, patch)`
+pub(crate) type BuildVersion = (MachPlatform, (u32, u32, u32), (u32, u32, u32));
+
+/// A version as `LC_BUILD_VERSION` stores it: `xxxx.yy.zz` in nibbles
+fn encode_version((major, minor, patch): (u32, u32, u32)) -> u32 {
+    major << 16 | (minor & 0xff) << 8 | (patch & 0xff)
+}
+
+/// A single architecture's Mach-o object inside a universal (fat) binary
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FatSlice {
+    /// The cpu type of the object in this slice
+    pub cputype: u32,
+    /// The cpu subtype of the object in this slice
+    pub cpusubtype: u32,
+    /// The alignment of this slice in the fat binary, as a power of two
+    pub align: u32,
+    /// The complete Mach-o object of this slice
+    pub data: Vec<u8>,
+}
+
+/// A Mach-o universal (fat) binary, which contains an object per architecture
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FatBinary {
+    /// The slices in this fat binary, in the order of their headers
+    pub slices: Vec<FatSlice>,
+}
+
+impl FatBinary {
+    /// Parses a fat binary, starting with either `FAT_MAGIC` or the byte swapped `FAT_CIGAM`.
+    ///
+    /// The `data` of every slice is a regular Mach-o object, which can be parsed on its own.
+    pub fn from_bytes(bytes: &[u8]) -> Result<FatBinary, ArtifactError> {
+        Ok(Self::parse(bytes)?)
+    }
+    fn parse(bytes: &[u8]) -> goblin::error::Result<FatBinary> {
+        use goblin::error::Error;
+        use goblin::mach::fat::{
+            FatArch, FatHeader, FAT_MAGIC, SIZEOF_FAT_ARCH, SIZEOF_FAT_HEADER,
+        };
+        use scroll::{Pread, BE, LE};
+
+        // fat headers are big endian, but byte swapped ones are read as such by some tools
+        let endian = match bytes.pread_with::<u32>(0, BE)? {
+            FAT_MAGIC => BE,
+            magic if magic.swap_bytes() == FAT_MAGIC => LE,
+            magic => {
+                return Err(Error::BadMagic(u64::from(magic)));
+            }
+        };
+        let header: FatHeader = bytes.pread_with(0, endian)?;
+        // the count is untrusted, so it must not be able to allocate more than the headers fit in
+        let max_nfat_arch = (bytes.len() - SIZEOF_FAT_HEADER) / SIZEOF_FAT_ARCH;
+        if header.nfat_arch as usize > max_nfat_arch {
+            return Err(Error::Malformed(format!(
+                "{} fat archs do not fit in {:#x} bytes",
+                header.nfat_arch,
+                bytes.len()
+            )));
+        }
+        let mut slices = Vec::with_capacity(header.nfat_arch as usize);
+        for idx in 0..header.nfat_arch as usize {
+            let arch: FatArch =
+                bytes.pread_with(SIZEOF_FAT_HEADER + idx * SIZEOF_FAT_ARCH, endian)?;
+            let start = arch.offset as usize;
+            let end = start.saturating_add(arch.size as usize);
+            let data = bytes.get(start..end).ok_or_else(|| {
+                Error::Malformed(format!(
+                    "fat arch {} at {:#x}..{:#x} is out of bounds of {:#x} bytes",
+                    idx,
+                    start,
+                    end,
+                    bytes.len()
+                ))
+            })?;
+            slices.push(FatSlice {
+                cputype: arch.cputype,
+                cpusubtype: arch.cpusubtype,
+                align: arch.align,
+                data: data.to_vec(),
+            });
+        }
+        Ok(FatBinary { slices })
+    }
+    /// Emit `artifact` as a Mach-o object, and add it as the slice of its architecture; there may
+    /// only be one slice per architecture, i.e., per cpu type and subtype.
+    pub fn add(&mut self, artifact: &Artifact) -> Result<(), ArtifactError> {
+        use scroll::{Pread, LE};
+
+        let data = artifact.emit_as(BinaryFormat::Macho)?;
+        let cputype = data
+            .pread_with::<u32>(4, LE)
+            .map_err(goblin::error::Error::from)?;
+        let cpusubtype = data
+            .pread_with::<u32>(8, LE)
+            .map_err(goblin::error::Error::from)?;
+        if self
+            .slices
+            .iter()
+            .any(|slice| slice.cputype == cputype && slice.cpusubtype == cpusubtype)
+        {
+            return Err(ArtifactError::DuplicateArchitecture(
+                artifact.target.architecture.to_string(),
+            ));
+        }
+        // like lipo, arm64 slices are aligned to its 16KiB pages, and everything else to 4KiB
+        let align = if cputype == cputype::CPU_TYPE_ARM64 {
+            14
+        } else {
+            12
+        };
+        self.slices.push(FatSlice {
+            cputype,
+            cpusubtype,
+            align,
+            data,
+        });
+        Ok(())
+    }
+    /// Write this fat binary to `sink`: the big endian `fat_header` and a `fat_arch` per slice,
+    /// followed by the slices, each at an offset aligned to its alignment, with zeroes in between
+    pub fn write<W: Write + Seek>(&self, mut sink: W) -> Result<(), ArtifactError> {
+        use goblin::mach::fat::{
+            FatArch, FatHeader, FAT_MAGIC, SIZEOF_FAT_ARCH, SIZEOF_FAT_HEADER,
+        };
+        use scroll::BE;
+
+        let mut headers = vec![0u8; SIZEOF_FAT_HEADER + self.slices.len() * SIZEOF_FAT_ARCH];
+        let header = FatHeader {
+            magic: FAT_MAGIC,
+            nfat_arch: self.slices.len() as u32,
+        };
+        headers
+            .pwrite_with(header, 0, BE)
+            .map_err(goblin::error::Error::from)?;
+        let mut offset = headers.len() as u64;
+        let mut offsets = Vec::with_capacity(self.slices.len());
+        for (idx, slice) in self.slices.iter().enumerate() {
+            let align = 1u64 << slice.align;
+            offset = (offset + align - 1) & !(align - 1);
+            let arch = FatArch {
+                cputype: slice.cputype,
+                cpusubtype: slice.cpusubtype,
+                offset: offset as u32,
+                size: slice.data.len() as u32,
+                align: slice.align,
+            };
+            headers
+                .pwrite_with(arch, SIZEOF_FAT_HEADER + idx * SIZEOF_FAT_ARCH, BE)
+                .map_err(goblin::error::Error::from)?;
+            offsets.push(offset);
+            offset += slice.data.len() as u64;
+        }
+        sink.write_all(&headers)?;
+        let mut position = headers.len() as u64;
+        for (slice, offset) in self.slices.iter().zip(offsets) {
+            sink.write_all(&vec![0; (offset - position) as usize])?;
+            sink.write_all(&slice.data)?;
+            position = offset + slice.data.len() as u64;
+        }
+        sink.flush()?;
+        Ok(())
+    }
+    /// Write this fat binary into a buffer, see `write`
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ArtifactError> {
+        let mut buffer = Cursor::new(Vec::new());
+        self.write(&mut buffer)?;
+        Ok(buffer.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::{Decl, Link, SectionKind};
+    use std::cell::RefCell;
+    use std::str::FromStr;
+    use target_lexicon::triple;
+
+    thread_local! {
+        /// The position `Mach::write` reached after each phase of writing on this thread
+        pub(super) static PHASES: RefCell<Vec<(&'static str, u64)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// The positions logged by `Mach::write`, keyed by the phase which was just written
+    struct OffsetRecorder {
+        positions: Vec<(&'static str, u64)>,
+    }
+
+    impl OffsetRecorder {
+        /// Runs `write` against an in-memory cursor, returning the written bytes and the positions
+        fn write(write: impl FnOnce(&mut Cursor<Vec<u8>>)) -> (Vec<u8>, Self) {
+            PHASES.with(|phases| phases.borrow_mut().clear());
+            let mut cursor = Cursor::new(Vec::new());
+            write(&mut cursor);
+            let positions = PHASES.with(|phases| phases.borrow_mut().split_off(0));
+            (cursor.into_inner(), OffsetRecorder { positions })
+        }
+        /// The position after writing `phase`
+        fn after(&self, phase: &str) -> u64 {
+            self.positions
+                .iter()
+                .find(|&&(logged, _)| logged == phase)
+                .unwrap_or_else(|| panic!("no position was logged after {}", phase))
+                .1
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn align_is_stored_as_its_exponent() {
+        assert_eq!(Align::from_bytes(1).unwrap().log2(), 0);
+        assert_eq!(Align::from_bytes(16).unwrap().log2(), 4);
+        assert_eq!(Align::from_log2(3).bytes(), 8);
+        assert!(Align::from_bytes(0).is_err());
+        assert!(Align::from_bytes(12).is_err());
+
+        let section = SectionBuilder::new("__const".to_string(), "__TEXT", 8)
+            .align(Align::from_bytes(8).unwrap())
+            .create(&mut 0, &mut 0);
+        assert_eq!(section.align, 3);
+    }
+
+    #[test]
+    fn validate_rejects_malformed_objects() {
+        use goblin::mach::load_command::CommandVariant;
+        use goblin::mach::MachO;
+
+        let mut artifact = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+        artifact
+            .declare_with("f", Decl::function().global(), vec![0xc3])
+            .unwrap();
+        let mut bytes = to_bytes(&artifact).expect("valid objects are emitted");
+
+        let symoff = MachO::parse(&bytes, 0)
+            .unwrap()
+            .load_commands
+            .iter()
+            .find_map(|lc| match lc.command {
+                CommandVariant::Symtab(symtab) => Some(symtab.symoff as usize),
+                _ => None,
+            })
+            .expect("object has a symbol table");
+        bytes.pwrite_with(0xffff_u32, symoff, scroll::LE).unwrap();
+        match validate(&bytes) {
+            Err(ValidationError::StringOutOfBounds {
+                index: 0,
+                n_strx: 0xffff,
+                ..
+            }) => {}
+            result => panic!("validated an out of bounds symbol name: {:?}", result),
+        }
+        match validate(&bytes[..bytes.len() / 2]) {
+            Err(ValidationError::Parse(_)) => {}
+            result => panic!("validated a truncated object: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn symbol_builder_types() {
+        use goblin::mach::symbols::{N_ABS, N_EXT, N_SECT, N_UNDF};
+
+        for &(global, import, n_type, n_sect) in &[
+            (false, false, N_SECT, 3),
+            (true, false, N_SECT | N_EXT, 3),
+            (false, true, N_UNDF | N_EXT, 0),
+            (true, true, N_UNDF | N_EXT, 0),
+        ] {
+            let mut builder = SymbolBuilder::new(1).section(2).global(global).offset(8);
+            if import {
+                builder = builder.import();
+            }
+            let nlist = builder.create();
+            assert_eq!(
+                (nlist.n_type, nlist.n_sect),
+                (n_type, n_sect),
+                "global: {}, import: {}",
+                global,
+                import
+            );
+            assert_eq!(nlist.n_value, if import { 0 } else { 8 });
+            assert_eq!(nlist.n_desc, 0);
+        }
+
+        let nlist = SymbolBuilder::new(1)
+            .global(true)
+            .absolute(0x4000_1000)
+            .create();
+        assert_eq!(
+            (nlist.n_type, nlist.n_sect, nlist.n_value),
+            (N_ABS | N_EXT, 0, 0x4000_1000)
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "section __text was written incorrectly")]
+    fn misplaced_section_contents_panic() {
+        let checksums = [SectionChecksum {
+            name: "__text".to_string(),
+            addr: 0,
+            size: 4,
+            crc: crc32(&[0x55, 0x48, 0x89, 0xe5]),
+        }];
+        // the code ended up one byte too late
+        let written = [0x00, 0x00, 0x55, 0x48, 0x89, 0xe5];
+        verify_section_checksums(&written, 1, &checksums);
+    }
+
+    #[test]
+    fn write_matches_precomputed_offsets() {
+        let mut artifact = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+        artifact
+            .declare_with("f", Decl::function().global(), vec![0xc3; 7])
+            .unwrap();
+        artifact
+            .declare_with("d", Decl::data().writable(), vec![0; 9])
+            .unwrap();
+        artifact
+            .declare_with("s", Decl::cstring(), b"hello\0".to_vec())
+            .unwrap();
+        artifact
+            .declare_with("sect", Decl::section(SectionKind::Data), vec![0; 5])
+            .unwrap();
+        artifact
+            .import("i", crate::artifact::ImportKind::Function)
+            .unwrap();
+        artifact
+            .link(Link {
+                from: "f",
+                to: "i",
+                at: 1,
+            })
+            .unwrap();
+        artifact
+            .link(Link {
+                from: "d",
+                to: "s",
+                at: 0,
+            })
+            .unwrap();
+
+        let mach = Mach::new(&artifact).unwrap();
+        let layout = mach.compute_layout();
+        let ctx = mach.ctx;
+        let Layout {
+            sizeof_load_commands,
+            first_section_offset,
+            symtab_offset,
+            strtab_offset,
+            reloc_offset_start,
+        } = layout;
+        let (bytes, recorder) =
+            OffsetRecorder::write(|cursor| mach.write_with_layout(cursor, layout).unwrap());
+
+        let (header, load_commands, sections, symtable, strtable, relocations) = (
+            recorder.after("header"),
+            recorder.after("load commands"),
+            recorder.after("raw sections"),
+            recorder.after("symtable"),
+            recorder.after("strtable"),
+            recorder.after("relocations"),
+        );
+        assert_eq!(header, Header::size_with(&ctx) as u64);
+        assert_eq!(load_commands, first_section_offset);
+        assert_eq!(
+            load_commands - header,
+            sizeof_load_commands,
+            "load commands"
+        );
+        assert_eq!(sections, symtab_offset, "symtable");
+        assert_eq!(symtable, strtab_offset, "strtable");
+        assert_eq!(strtable, reloc_offset_start, "relocations");
+        assert_eq!(
+            relocations,
+            reloc_offset_start + 2 * SIZEOF_RELOCATION_INFO as u64
+        );
+        // all that's left is the trailing byte
+        assert_eq!(bytes.len() as u64, recorder.after("notes") + 1);
+    }
+
+    #[test]
+    fn symtab_offset_follows_section_data() {
+        let mut artifact = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+        artifact
+            .declare_with("f", Decl::function().global(), vec![0xc3; 5])
+            .unwrap();
+        artifact
+            .declare_with("d", Decl::data(), vec![0; 3])
+            .unwrap();
+        artifact
+            .add_data_in_code(DataInCodeEntry {
+                offset: 2,
+                length: 2,
+                kind: 1,
+            })
+            .expect("can add data in code");
+
+        let mach = Mach::new(&artifact).unwrap();
+        let ctx = mach.ctx;
+        let symtab_offset = mach.segment.symtab_offset(
+            &ctx,
+            SymtabCommand::new().cmdsize as u64,
+            SIZEOF_LINKEDIT_DATA_COMMAND as u64,
+        );
+        let segment_size = mach.segment.size();
+        let (_, recorder) = OffsetRecorder::write(|cursor| mach.write(cursor).unwrap());
+
+        // after the load commands comes the section data, and then the symbol table
+        let load_commands = recorder.after("load commands");
+        assert_eq!(load_commands + segment_size, symtab_offset);
+        assert_eq!(recorder.after("raw sections"), symtab_offset);
+    }
+
+    fn defined(section: SectionIndex, offset: u64) -> SymbolLocation {
+        SymbolLocation {
+            section,
+            absolute_offset: offset,
+            segment_relative_offset: offset,
+        }
+    }
+
+    #[test]
+    fn declared_alignments_are_powers_of_two() {
+        assert_eq!(Align::of(None).unwrap().bytes(), 1);
+        assert_eq!(Align::of(Some(16)).unwrap().log2(), 4);
+        assert_eq!(Align::of(Some(24)).unwrap_err(), AlignError(24));
+        match ArtifactError::from(AlignError(24)) {
+            ArtifactError::InvalidAlignment(AlignError(24)) => {}
+            error => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn authenticated_pointer_layout() {
+        let builder =
+            RelocationBuilder::new(3, 0x10, 0).authenticated(PtrauthKey::DB, true, 0xbeef);
+        assert_eq!(
+            builder.authenticated_value(-8),
+            Some(0xfffffff8 | 0xbeef << 32 | 1 << 48 | 3 << 49 | 1 << 63)
+        );
+        let builder = RelocationBuilder::new(3, 0x10, 0).authenticated(PtrauthKey::IA, false, 0);
+        assert_eq!(builder.authenticated_value(0), Some(1 << 63));
+
+        let reloc = builder.create();
+        assert_eq!(reloc.r_type(), ARM64_RELOC_AUTHENTICATED_POINTER);
+        assert_eq!(reloc.r_length(), 3);
+        assert!(!reloc.is_pic());
+        assert!(reloc.is_extern());
+        assert_eq!(reloc.r_symbolnum(), 3);
+        assert!(RelocationBuilder::new(3, 0x10, 0)
+            .authenticated_value(0)
+            .is_none());
+    }
+
+    #[test]
+    fn reserve_avoids_reallocation() {
+        const NSYMBOLS: usize = 10_000;
+        let mut symtab = SymbolTable::new();
+        symtab.reserve(NSYMBOLS);
+        let capacities = |symtab: &SymbolTable| {
+            (
+                symtab.symbols.capacity(),
+                symtab.indexes.capacity(),
+                symtab.strtable.capacity(),
+            )
+        };
+        let reserved = capacities(&symtab);
+        assert!(reserved.0 >= NSYMBOLS && reserved.1 >= NSYMBOLS && reserved.2 >= NSYMBOLS);
+        for i in 0..NSYMBOLS {
+            symtab.insert_definition(
+                &format!("f{}", i),
+                &DefinedDecl::Function(Decl::function().global()),
+                defined(CODE_SECTION_INDEX, i as u64),
+            );
+        }
+        assert_eq!(symtab.len(), NSYMBOLS);
+        assert_eq!(capacities(&symtab), reserved);
+    }
+
+    #[test]
+    fn nm_output() {
+        let mut artifact = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+        artifact
+            .declare_with("main", Decl::function().global(), vec![0xc3; 16])
+            .unwrap();
+        artifact
+            .declare_with("helper", Decl::function(), vec![0xc3; 4])
+            .unwrap();
+        artifact
+            .declare_with("table", Decl::data().global(), vec![0; 8])
+            .unwrap();
+        artifact
+            .declare_with("counter", Decl::data(), vec![0; 4])
+            .unwrap();
+        artifact
+            .import("malloc", crate::artifact::ImportKind::Function)
+            .unwrap();
+
+        let mach = Mach::new(&artifact).unwrap();
+        // read-only data is in `__TEXT,__const`, which is neither text nor data
+        let expected = [
+            "0000000000000020 s _counter",
+            "0000000000000000 t _helper",
+            "0000000000000010 T _main",
+            "                 U _malloc",
+            "0000000000000028 S _table",
+        ];
+        assert_eq!(
+            mach.symtab
+                .to_nm_output(&mach.segment.sections)
+                .lines()
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn get_mut_updates_offsets() {
+        let mut symtab = SymbolTable::new();
+        symtab.insert_definition(
+            "f",
+            &DefinedDecl::Function(Decl::function().global()),
+            defined(CODE_SECTION_INDEX, 0),
+        );
+        symtab.insert_definition(
+            "g",
+            &DefinedDecl::Function(Decl::function().global()),
+            defined(CODE_SECTION_INDEX, 4),
+        );
+        assert!(symtab.get_mut("h").is_none());
+
+        let g = symtab.get_mut("g").unwrap();
+        *g = g.clone().offset(0x40).relative_offset(0x10);
+
+        assert_eq!(symtab.offset("g"), Some(0x10));
+        assert_eq!(symtab.offset("f"), Some(0));
+        let nlists = symtab
+            .symbols
+            .values()
+            .cloned()
+            .map(SymbolBuilder::create)
+            .collect::<Vec<_>>();
+        assert_eq!(nlists[0].n_value, 0);
+        assert_eq!(nlists[1].n_value, 0x40);
+        assert_eq!(nlists[1].n_sect, CODE_SECTION_INDEX + 1);
+    }
+
+    #[test]
+    fn lookup_by_offset() {
+        let mut symtab = SymbolTable::new();
+        symtab.insert_definition(
+            "f",
+            &DefinedDecl::Function(Decl::function().global()),
+            defined(CODE_SECTION_INDEX, 0),
+        );
+        symtab.insert_definition(
+            "g",
+            &DefinedDecl::Function(Decl::function().global()),
+            defined(CODE_SECTION_INDEX, 16),
+        );
+        symtab.insert_definition(
+            "h",
+            &DefinedDecl::Function(Decl::function().global()),
+            defined(CODE_SECTION_INDEX, 32),
+        );
+        symtab.insert_definition(
+            "d",
+            &DefinedDecl::Function(Decl::function().global()),
+            defined(DATA_SECTION_INDEX, 0),
+        );
+        symtab.insert_import("i", ImportKind::Function);
+
+        assert_eq!(symtab.lookup_by_offset(CODE_SECTION_INDEX, 0), Some("f"));
+        assert_eq!(symtab.lookup_by_offset(CODE_SECTION_INDEX, 15), Some("f"));
+        assert_eq!(symtab.lookup_by_offset(CODE_SECTION_INDEX, 16), Some("g"));
+        assert_eq!(symtab.lookup_by_offset(CODE_SECTION_INDEX, 40), Some("h"));
+        assert_eq!(symtab.lookup_by_offset(DATA_SECTION_INDEX, 8), Some("d"));
+        assert_eq!(symtab.lookup_by_offset(CSTRING_SECTION_INDEX, 0), None);
+    }
 }
@@ -0,0 +1,99 @@
+//! A writable memory mapping of a file, which large objects are written into without going
+//! through many small `write` calls.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+
+/// A shared, writable mapping of the first `len` bytes of a file; it is unmapped when dropped
+pub struct MmapMut {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl MmapMut {
+    /// Map the first `len` bytes of `file`, which must be opened for reading and writing, and be
+    /// at least `len` bytes long; `len` must not be zero
+    pub fn new(file: &File, len: usize) -> io::Result<Self> {
+        debug_assert!(len > 0, "cannot map an empty region");
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(MmapMut {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+    /// The mapped bytes
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl Drop for MmapMut {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+/// A `Write + Seek` sink over a mapped region, which copies every write straight into the mapping
+pub struct MmapWriter<'a> {
+    map: &'a mut [u8],
+    position: usize,
+}
+
+impl<'a> MmapWriter<'a> {
+    /// Write into `map`, starting at its beginning
+    pub fn new(map: &'a mut [u8]) -> Self {
+        MmapWriter { map, position: 0 }
+    }
+}
+
+impl<'a> io::Write for MmapWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // the mapping has the final size of the file, so writing past its end is a layout bug,
+        // which `write_all` reports as a short write
+        let len = buf.len().min(self.map.len().saturating_sub(self.position));
+        unsafe {
+            ptr::copy_nonoverlapping(buf.as_ptr(), self.map.as_mut_ptr().add(self.position), len);
+        }
+        self.position += len;
+        Ok(len)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> io::Seek for MmapWriter<'a> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            io::SeekFrom::Start(offset) => Some(offset as i64),
+            io::SeekFrom::Current(offset) => (self.position as i64).checked_add(offset),
+            io::SeekFrom::End(offset) => (self.map.len() as i64).checked_add(offset),
+        };
+        match position {
+            Some(position) if position >= 0 && position as usize <= self.map.len() => {
+                self.position = position as usize;
+                Ok(position as u64)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek outside of the mapped region",
+            )),
+        }
+    }
+}
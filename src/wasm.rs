@@ -0,0 +1,670 @@
+//! The WebAssembly backend for transforming an artifact to a relocatable wasm object file, as
+//! described by the tool conventions' linking spec and consumed by `wasm-ld`.
+//!
+//! The definition of a function is its body in the code section, i.e., its local declarations
+//! followed by its expression. faerie has no notion of function signatures yet, so every function,
+//! defined or imported, has the type `[] -> []`. Every data definition is placed into a data
+//! segment of its own, in the memory imported as `env.__linear_memory`; imported data are
+//! undefined data symbols, since wasm cannot import them.
+
+use crate::artifact::{
+    Artifact, ArtifactError, Data, Decl, DefinedDecl, ImportKind, LinkAndDecl, Reloc,
+    RelocationKind, Visibility,
+};
+
+use indexmap::IndexMap;
+use target_lexicon::Architecture;
+
+/// The magic number every wasm module starts with, followed by its version
+const MAGIC: &[u8] = b"\0asm";
+const VERSION: u32 = 1;
+
+// the ids of the sections
+const SECTION_CUSTOM: u8 = 0;
+const SECTION_TYPE: u8 = 1;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_FUNCTION: u8 = 3;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_CODE: u8 = 10;
+const SECTION_DATA: u8 = 11;
+/// The index of the code section in the module, which its relocations refer to
+const CODE_SECTION_INDEX: u32 = 4;
+/// The index of the data section in the module, which its relocations refer to
+const DATA_SECTION_INDEX: u32 = 5;
+
+// the kinds of imports and exports
+const EXTERNAL_FUNCTION: u8 = 0;
+const EXTERNAL_MEMORY: u8 = 2;
+
+/// The form of function types
+const TYPE_FUNC: u8 = 0x60;
+// the instructions of the initializer expressions of data segments
+const I32_CONST: u8 = 0x41;
+const END: u8 = 0x0b;
+/// The size of a memory page
+const PAGE_SIZE: u64 = 0x1_0000;
+
+/// The version of the `linking` section
+const LINKING_VERSION: u32 = 2;
+// the subsections of the `linking` section
+const WASM_SEGMENT_INFO: u8 = 5;
+const WASM_SYMBOL_TABLE: u8 = 8;
+
+// the kinds of symbols
+const SYMTAB_FUNCTION: u8 = 0;
+const SYMTAB_DATA: u8 = 1;
+
+// the flags of symbols
+const WASM_SYM_BINDING_WEAK: u32 = 0x1;
+const WASM_SYM_BINDING_LOCAL: u32 = 0x2;
+const WASM_SYM_VISIBILITY_HIDDEN: u32 = 0x4;
+const WASM_SYM_UNDEFINED: u32 = 0x10;
+
+// the relocations
+const R_WASM_FUNCTION_INDEX_LEB: u8 = 0;
+const R_WASM_TABLE_INDEX_SLEB: u8 = 1;
+const R_WASM_TABLE_INDEX_I32: u8 = 2;
+const R_WASM_MEMORY_ADDR_LEB: u8 = 3;
+const R_WASM_MEMORY_ADDR_SLEB: u8 = 4;
+const R_WASM_MEMORY_ADDR_I32: u8 = 5;
+const R_WASM_FUNCTION_OFFSET_I32: u8 = 8;
+const R_WASM_SECTION_OFFSET_I32: u8 = 9;
+
+/// The size of the padded LEB128s relocations are applied to, so that the linker can patch them
+/// in place
+const SIZEOF_PADDED_LEB: usize = 5;
+
+/// Whether the relocation `typ` has an addend
+fn has_addend(typ: u8) -> bool {
+    matches!(
+        typ,
+        R_WASM_MEMORY_ADDR_LEB
+            | R_WASM_MEMORY_ADDR_SLEB
+            | R_WASM_MEMORY_ADDR_I32
+            | R_WASM_FUNCTION_OFFSET_I32
+            | R_WASM_SECTION_OFFSET_I32
+    )
+}
+
+/// Append `value` as an unsigned LEB128
+fn write_uleb(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Append `value` as a signed LEB128
+fn write_sleb(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Append `name` as a length prefixed string
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    write_uleb(bytes, name.len() as u64);
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+/// Append a section with the id `id` whose contents are `payload`
+fn write_section(bytes: &mut Vec<u8>, id: u8, payload: &[u8]) {
+    bytes.push(id);
+    write_uleb(bytes, payload.len() as u64);
+    bytes.extend_from_slice(payload);
+}
+
+/// Append a custom section named `name` whose contents are `payload`
+fn write_custom_section(bytes: &mut Vec<u8>, name: &str, payload: &[u8]) {
+    let mut contents = Vec::with_capacity(name.len() + 1 + payload.len());
+    write_name(&mut contents, name);
+    contents.extend_from_slice(payload);
+    write_section(bytes, SECTION_CUSTOM, &contents);
+}
+
+/// `value` as an unsigned LEB128 padded to `SIZEOF_PADDED_LEB` bytes
+fn padded_uleb(mut value: u32) -> [u8; SIZEOF_PADDED_LEB] {
+    let mut encoded = [0x80; SIZEOF_PADDED_LEB];
+    for byte in encoded.iter_mut() {
+        *byte |= (value & 0x7f) as u8;
+        value >>= 7;
+    }
+    encoded[SIZEOF_PADDED_LEB - 1] &= 0x7f;
+    encoded
+}
+
+/// `value` as a signed LEB128 padded to `SIZEOF_PADDED_LEB` bytes
+fn padded_sleb(mut value: i32) -> [u8; SIZEOF_PADDED_LEB] {
+    let mut encoded = [0x80; SIZEOF_PADDED_LEB];
+    for byte in encoded.iter_mut() {
+        *byte |= (value & 0x7f) as u8;
+        value >>= 7;
+    }
+    encoded[SIZEOF_PADDED_LEB - 1] &= 0x7f;
+    encoded
+}
+
+/// The number of bytes relocation `typ` is applied to, if it is known
+fn relocated_size(typ: u8) -> Option<usize> {
+    match typ {
+        R_WASM_FUNCTION_INDEX_LEB
+        | R_WASM_TABLE_INDEX_SLEB
+        | R_WASM_MEMORY_ADDR_LEB
+        | R_WASM_MEMORY_ADDR_SLEB => Some(SIZEOF_PADDED_LEB),
+        R_WASM_TABLE_INDEX_I32
+        | R_WASM_MEMORY_ADDR_I32
+        | R_WASM_FUNCTION_OFFSET_I32
+        | R_WASM_SECTION_OFFSET_I32 => Some(4),
+        _ => None,
+    }
+}
+
+/// Patch `value` into the bytes at `offset` that relocation `typ` is applied to; the bytes of
+/// unknown relocations are left as they are
+fn write_relocated(
+    bytes: &mut [u8],
+    offset: usize,
+    typ: u8,
+    value: i64,
+) -> Result<(), ArtifactError> {
+    let size = match relocated_size(typ) {
+        Some(size) => size,
+        None => return Ok(()),
+    };
+    let len = bytes.len();
+    let relocated = offset
+        .checked_add(size)
+        .and_then(|end| bytes.get_mut(offset..end))
+        .ok_or_else(|| {
+            ArtifactError::UnsupportedRelocation(format!(
+                "relocation {} at {:#x} is out of bounds of {} bytes",
+                typ, offset, len
+            ))
+        })?;
+    match typ {
+        R_WASM_FUNCTION_INDEX_LEB | R_WASM_MEMORY_ADDR_LEB => {
+            relocated.copy_from_slice(&padded_uleb(value as u32))
+        }
+        R_WASM_TABLE_INDEX_SLEB | R_WASM_MEMORY_ADDR_SLEB => {
+            relocated.copy_from_slice(&padded_sleb(value as i32))
+        }
+        _ => relocated.copy_from_slice(&(value as u32).to_le_bytes()),
+    }
+    Ok(())
+}
+
+/// Whether `decl` is a function, defined or imported
+fn is_function(decl: &Decl) -> bool {
+    match decl {
+        Decl::Defined(d) => d.is_function(),
+        Decl::Import(kind) => kind.is_function(),
+        Decl::AbsoluteSymbol(_) | Decl::Common { .. } => false,
+    }
+}
+
+/// The error for the link `l`, whose relocation the Wasm format cannot express
+fn unsupported(l: &LinkAndDecl) -> ArtifactError {
+    ArtifactError::UnsupportedRelocation(format!(
+        "{:?} from {} to {} at {:#x} in the Wasm format",
+        l.reloc, l.from.name, l.to.name, l.at
+    ))
+}
+
+/// A relocation, relative to the start of the function body or data segment it is in
+struct Relocation {
+    typ: u8,
+    offset: u64,
+    symbol: u32,
+    addend: i64,
+}
+
+/// A defined function, and the relocations in its body
+struct Function {
+    body: Vec<u8>,
+    relocations: Vec<Relocation>,
+}
+
+/// A data segment, which holds a single data definition
+struct Segment {
+    name: String,
+    /// The log2 of the alignment of this segment
+    align: u32,
+    /// The address of this segment in the linear memory
+    address: u64,
+    data: Vec<u8>,
+    relocations: Vec<Relocation>,
+}
+
+/// What a symbol refers to
+enum SymbolKind {
+    /// A function, by its index in the function index space, where imports come first
+    Function(u32),
+    /// A data definition, by its segment, or an undefined data symbol
+    Data(Option<u32>),
+}
+
+struct Symbol {
+    kind: SymbolKind,
+    flags: u32,
+}
+
+/// The symbol flags of the definition `decl`
+fn flags(decl: &DefinedDecl) -> u32 {
+    let mut flags = 0;
+    let is_comdat = match decl {
+        DefinedDecl::Function(d) => d.is_comdat(),
+        _ => false,
+    };
+    // like COFF, COMDAT functions are only discarded as weak definitions
+    if decl.is_weak() || is_comdat {
+        flags |= WASM_SYM_BINDING_WEAK;
+    } else if !decl.is_global() {
+        flags |= WASM_SYM_BINDING_LOCAL;
+    }
+    match decl.get_visibility() {
+        Visibility::Hidden | Visibility::Internal => flags |= WASM_SYM_VISIBILITY_HIDDEN,
+        Visibility::Default | Visibility::Protected => (),
+    }
+    flags
+}
+
+struct Wasm<'a> {
+    /// The imported functions, which precede the defined functions in the function index space
+    imports: Vec<&'a str>,
+    functions: IndexMap<&'a str, Function>,
+    segments: IndexMap<&'a str, Segment>,
+    /// The contents of custom section declarations, by name
+    custom_sections: Vec<(&'a str, &'a [u8])>,
+    symbols: IndexMap<&'a str, Symbol>,
+    /// The size of the data segments in the linear memory
+    memory_size: u64,
+}
+
+impl<'a> Wasm<'a> {
+    fn new(artifact: &'a Artifact) -> Result<Self, ArtifactError> {
+        match artifact.target.architecture {
+            Architecture::Wasm32 => (),
+            architecture => {
+                return Err(ArtifactError::Unsupported(format!(
+                    "{:?} objects in the Wasm format",
+                    architecture
+                )))
+            }
+        }
+        Ok(Wasm {
+            imports: Vec::new(),
+            functions: IndexMap::new(),
+            segments: IndexMap::new(),
+            custom_sections: Vec::new(),
+            symbols: IndexMap::new(),
+            memory_size: 0,
+        })
+    }
+    fn import(&mut self, name: &'a str, kind: &ImportKind) {
+        let mut flags = WASM_SYM_UNDEFINED;
+        if kind.is_weak() {
+            flags |= WASM_SYM_BINDING_WEAK;
+        }
+        let kind = if kind.is_function() {
+            self.imports.push(name);
+            SymbolKind::Function(self.imports.len() as u32 - 1)
+        } else {
+            SymbolKind::Data(None)
+        };
+        self.symbols.insert(name, Symbol { kind, flags });
+    }
+    /// Add the definition `def`; all imports must have been added before
+    fn add_definition(
+        &mut self,
+        def: crate::artifact::Definition<'a>,
+    ) -> Result<(), ArtifactError> {
+        let bytes = match def.data {
+            Data::Blob(bytes) => bytes.clone(),
+            Data::ZeroInit(size) => vec![0; *size],
+        };
+        let kind = match def.decl {
+            DefinedDecl::Function(_) => {
+                self.functions.insert(
+                    def.name,
+                    Function {
+                        body: bytes,
+                        relocations: Vec::new(),
+                    },
+                );
+                SymbolKind::Function((self.imports.len() + self.functions.len() - 1) as u32)
+            }
+            DefinedDecl::Data(d) if d.is_thread_local() => {
+                return Err(ArtifactError::Unsupported(format!(
+                    "thread-local variable {} in the Wasm format",
+                    def.name
+                )));
+            }
+            DefinedDecl::Data(d) => {
+                let prefix = if def.data.is_zero_init() {
+                    ".bss"
+                } else if d.is_writable() {
+                    ".data"
+                } else {
+                    ".rodata"
+                };
+                let align = d.get_align().unwrap_or(1);
+                let address = (self.memory_size + align - 1) & !(align - 1);
+                self.memory_size = address + bytes.len() as u64;
+                self.segments.insert(
+                    def.name,
+                    Segment {
+                        name: format!("{}.{}", prefix, def.name),
+                        align: align.trailing_zeros(),
+                        address,
+                        data: bytes,
+                        relocations: Vec::new(),
+                    },
+                );
+                SymbolKind::Data(Some(self.segments.len() as u32 - 1))
+            }
+            DefinedDecl::Section(_) => {
+                if let Data::Blob(bytes) = def.data {
+                    self.custom_sections.push((def.name, bytes));
+                }
+                return Ok(());
+            }
+        };
+        self.symbols.insert(
+            def.name,
+            Symbol {
+                kind,
+                flags: flags(def.decl),
+            },
+        );
+        Ok(())
+    }
+    fn link(&mut self, l: &LinkAndDecl) -> Result<(), ArtifactError> {
+        debug!("Link: {:?}", l);
+        if l.from.decl.is_section() || l.to.decl.is_section() {
+            return Err(unsupported(l));
+        }
+        let (typ, addend) = match l.reloc {
+            Reloc::Auto => (self.auto_relocation(l)?, 0),
+            Reloc::AutoWithAddend { addend } => (self.auto_relocation(l)?, addend),
+            Reloc::Absolute { addend } => (self.address_relocation(l)?, i64::from(addend)),
+            Reloc::Raw { reloc, addend } => (reloc as u8, i64::from(addend)),
+            Reloc::Kind(kind) => (self.kind_relocation(l, kind)?, 0),
+            Reloc::Debug { .. }
+            | Reloc::Authenticated { .. }
+            | Reloc::Subtrahend { .. }
+            | Reloc::Minuend { .. } => return Err(unsupported(l)),
+        };
+        // indices have no addends
+        if addend != 0 && !has_addend(typ) {
+            return Err(unsupported(l));
+        }
+        let (symbol, _, to) = self
+            .symbols
+            .get_full(l.to.name)
+            .expect("link target present in symbols");
+        // like the assembler, the relocated bytes hold the value as if the object was not linked
+        let value = match to.kind {
+            SymbolKind::Function(index) => i64::from(index),
+            SymbolKind::Data(Some(segment)) => {
+                self.segments[segment as usize].address as i64 + addend
+            }
+            SymbolKind::Data(None) => addend,
+        };
+        let relocation = Relocation {
+            typ,
+            offset: l.at,
+            symbol: symbol as u32,
+            addend,
+        };
+        let (bytes, relocations) = match self.functions.get_mut(l.from.name) {
+            Some(function) => (&mut function.body, &mut function.relocations),
+            None => {
+                let segment = self
+                    .segments
+                    .get_mut(l.from.name)
+                    .expect("link source present in definitions");
+                (&mut segment.data, &mut segment.relocations)
+            }
+        };
+        write_relocated(bytes, l.at as usize, typ, value)?;
+        relocations.push(relocation);
+        Ok(())
+    }
+    /// The relocation that faerie deduces for `l`
+    fn auto_relocation(&self, l: &LinkAndDecl) -> Result<u8, ArtifactError> {
+        match l.from.decl {
+            // e.g., `call f`
+            Decl::Defined(DefinedDecl::Function(_)) if is_function(l.to.decl) => {
+                Ok(R_WASM_FUNCTION_INDEX_LEB)
+            }
+            // e.g., `i32.const x`
+            _ => self.address_relocation(l),
+        }
+    }
+    /// The relocation of the address of the target: an `i32.const` operand in a function, and a
+    /// 32 bit pointer in data. The address of a function is its index in the indirect function
+    /// table, which the linker builds.
+    fn address_relocation(&self, l: &LinkAndDecl) -> Result<u8, ArtifactError> {
+        match (l.from.decl, is_function(l.to.decl)) {
+            (Decl::Defined(DefinedDecl::Function(_)), true) => Ok(R_WASM_TABLE_INDEX_SLEB),
+            (Decl::Defined(DefinedDecl::Function(_)), false) => Ok(R_WASM_MEMORY_ADDR_SLEB),
+            (Decl::Defined(DefinedDecl::Data(_)), true) => Ok(R_WASM_TABLE_INDEX_I32),
+            (Decl::Defined(DefinedDecl::Data(_)), false) => Ok(R_WASM_MEMORY_ADDR_I32),
+            _ => Err(unsupported(l)),
+        }
+    }
+    /// The relocation of the given `kind`
+    fn kind_relocation(&self, l: &LinkAndDecl, kind: RelocationKind) -> Result<u8, ArtifactError> {
+        match kind {
+            RelocationKind::Absolute => self.address_relocation(l),
+            RelocationKind::Branch => Ok(R_WASM_FUNCTION_INDEX_LEB),
+            // wasm has neither pc-relative addressing, nor a Global Offset Table
+            RelocationKind::PcRelative | RelocationKind::GotPcRelative => Err(unsupported(l)),
+        }
+    }
+    fn write(self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+
+        // the only type, `[] -> []`
+        let mut types = Vec::new();
+        write_uleb(&mut types, 1);
+        types.extend_from_slice(&[TYPE_FUNC, 0, 0]);
+        write_section(&mut bytes, SECTION_TYPE, &types);
+
+        let mut imports = Vec::new();
+        write_uleb(&mut imports, 1 + self.imports.len() as u64);
+        write_name(&mut imports, "env");
+        write_name(&mut imports, "__linear_memory");
+        imports.push(EXTERNAL_MEMORY);
+        // no maximum, and a minimum number of pages large enough for the data segments
+        imports.push(0);
+        write_uleb(&mut imports, self.memory_size.div_ceil(PAGE_SIZE));
+        for import in self.imports.iter() {
+            write_name(&mut imports, "env");
+            write_name(&mut imports, import);
+            imports.push(EXTERNAL_FUNCTION);
+            write_uleb(&mut imports, 0);
+        }
+        write_section(&mut bytes, SECTION_IMPORT, &imports);
+
+        let mut functions = Vec::new();
+        write_uleb(&mut functions, self.functions.len() as u64);
+        for _ in self.functions.iter() {
+            write_uleb(&mut functions, 0);
+        }
+        write_section(&mut bytes, SECTION_FUNCTION, &functions);
+
+        // global functions which are not hidden are exported by name
+        let exports = self
+            .symbols
+            .iter()
+            .filter_map(|(name, symbol)| match symbol.kind {
+                SymbolKind::Function(index)
+                    if index as usize >= self.imports.len()
+                        && symbol.flags & (WASM_SYM_BINDING_LOCAL | WASM_SYM_VISIBILITY_HIDDEN)
+                            == 0 =>
+                {
+                    Some((*name, index))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut export = Vec::new();
+        write_uleb(&mut export, exports.len() as u64);
+        for (name, index) in exports {
+            write_name(&mut export, name);
+            export.push(EXTERNAL_FUNCTION);
+            write_uleb(&mut export, u64::from(index));
+        }
+        write_section(&mut bytes, SECTION_EXPORT, &export);
+
+        // relocation offsets are relative to the start of the section's contents
+        let mut code = Vec::new();
+        let mut code_relocations = Vec::new();
+        write_uleb(&mut code, self.functions.len() as u64);
+        for function in self.functions.values() {
+            write_uleb(&mut code, function.body.len() as u64);
+            let start = code.len() as u64;
+            code.extend_from_slice(&function.body);
+            code_relocations.extend(function.relocations.iter().map(|reloc| (start, reloc)));
+        }
+        write_section(&mut bytes, SECTION_CODE, &code);
+
+        let mut data = Vec::new();
+        let mut data_relocations = Vec::new();
+        write_uleb(&mut data, self.segments.len() as u64);
+        for segment in self.segments.values() {
+            // an active segment in memory 0, at its address
+            write_uleb(&mut data, 0);
+            data.push(I32_CONST);
+            write_sleb(&mut data, segment.address as i64);
+            data.push(END);
+            write_uleb(&mut data, segment.data.len() as u64);
+            let start = data.len() as u64;
+            data.extend_from_slice(&segment.data);
+            data_relocations.extend(segment.relocations.iter().map(|reloc| (start, reloc)));
+        }
+        write_section(&mut bytes, SECTION_DATA, &data);
+
+        for (name, contents) in self.custom_sections.iter() {
+            write_custom_section(&mut bytes, name, contents);
+        }
+
+        let mut linking = Vec::new();
+        write_uleb(&mut linking, u64::from(LINKING_VERSION));
+        let mut symbol_table = Vec::new();
+        write_uleb(&mut symbol_table, self.symbols.len() as u64);
+        for (name, symbol) in self.symbols.iter() {
+            match symbol.kind {
+                SymbolKind::Function(index) => {
+                    symbol_table.push(SYMTAB_FUNCTION);
+                    write_uleb(&mut symbol_table, u64::from(symbol.flags));
+                    write_uleb(&mut symbol_table, u64::from(index));
+                    // the names of undefined functions are those of their imports
+                    if symbol.flags & WASM_SYM_UNDEFINED == 0 {
+                        write_name(&mut symbol_table, name);
+                    }
+                }
+                SymbolKind::Data(segment) => {
+                    symbol_table.push(SYMTAB_DATA);
+                    write_uleb(&mut symbol_table, u64::from(symbol.flags));
+                    write_name(&mut symbol_table, name);
+                    if let Some(segment) = segment {
+                        // the symbol is the whole segment
+                        let size = self.segments[segment as usize].data.len();
+                        write_uleb(&mut symbol_table, u64::from(segment));
+                        write_uleb(&mut symbol_table, 0);
+                        write_uleb(&mut symbol_table, size as u64);
+                    }
+                }
+            }
+        }
+        linking.push(WASM_SYMBOL_TABLE);
+        write_uleb(&mut linking, symbol_table.len() as u64);
+        linking.extend_from_slice(&symbol_table);
+        if !self.segments.is_empty() {
+            let mut segment_info = Vec::new();
+            write_uleb(&mut segment_info, self.segments.len() as u64);
+            for segment in self.segments.values() {
+                write_name(&mut segment_info, &segment.name);
+                write_uleb(&mut segment_info, u64::from(segment.align));
+                write_uleb(&mut segment_info, 0);
+            }
+            linking.push(WASM_SEGMENT_INFO);
+            write_uleb(&mut linking, segment_info.len() as u64);
+            linking.extend_from_slice(&segment_info);
+        }
+        write_custom_section(&mut bytes, "linking", &linking);
+
+        for &(name, section, ref relocations) in &[
+            ("reloc.CODE", CODE_SECTION_INDEX, code_relocations),
+            ("reloc.DATA", DATA_SECTION_INDEX, data_relocations),
+        ] {
+            if relocations.is_empty() {
+                continue;
+            }
+            let mut relocations = relocations
+                .iter()
+                .map(|&(start, reloc)| (start + reloc.offset, reloc))
+                .collect::<Vec<_>>();
+            // the linker requires relocations to be sorted by offset
+            relocations.sort_by_key(|&(offset, _)| offset);
+            let mut payload = Vec::new();
+            write_uleb(&mut payload, u64::from(section));
+            write_uleb(&mut payload, relocations.len() as u64);
+            for (offset, reloc) in relocations {
+                payload.push(reloc.typ);
+                write_uleb(&mut payload, offset);
+                write_uleb(&mut payload, u64::from(reloc.symbol));
+                if has_addend(reloc.typ) {
+                    write_sleb(&mut payload, reloc.addend);
+                }
+            }
+            write_custom_section(&mut bytes, name, &payload);
+        }
+        bytes
+    }
+}
+
+pub fn to_bytes(artifact: &Artifact) -> Result<Vec<u8>, ArtifactError> {
+    let mut wasm = Wasm::new(artifact)?;
+    // imported functions come first in the function index space
+    for (import, kind) in artifact.imports() {
+        debug!("Import: {:?} -> {:?}", import, kind);
+        wasm.import(import, kind);
+    }
+    for def in artifact.definitions() {
+        debug!("Def: {:?}", def);
+        wasm.add_definition(def)?;
+    }
+    if let Some((name, _)) = artifact.absolute_symbols().next() {
+        return Err(ArtifactError::Unsupported(format!(
+            "absolute symbol {} in the Wasm format",
+            name
+        )));
+    }
+    if let Some((name, _, _)) = artifact.common_symbols().next() {
+        return Err(ArtifactError::Unsupported(format!(
+            "common symbol {} in the Wasm format",
+            name
+        )));
+    }
+    for link in artifact.links() {
+        wasm.link(&link)?;
+    }
+    Ok(wasm.write())
+}
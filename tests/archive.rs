@@ -0,0 +1,91 @@
+extern crate faerie;
+extern crate goblin;
+#[macro_use]
+extern crate target_lexicon;
+
+use faerie::{Archive, Artifact, Decl};
+use std::str::FromStr;
+use target_lexicon::Triple;
+
+/// An archive of two members, one with a name too long for a member header
+fn two_member_archive(target: Triple) -> Archive {
+    let mut one = Artifact::new(target.clone(), "one.o".into());
+    one.declare_with("one", Decl::function().global(), vec![0xc3])
+        .expect("can declare one");
+    one.declare_with("helper", Decl::function(), vec![0xc3])
+        .expect("can declare helper");
+    let mut two = Artifact::new(target, "a_rather_long_member_name.o".into());
+    two.declare_with("two", Decl::function().global(), vec![0xc3])
+        .expect("can declare two");
+    two.declare_with("answer", Decl::data().global(), vec![42, 0, 0, 0])
+        .expect("can declare answer");
+
+    let mut archive = Archive::new();
+    archive.add(one);
+    archive.add(two);
+    archive
+}
+
+fn check_archive(bytes: &[u8], prefix: &str) {
+    let archive = goblin::archive::Archive::parse(bytes).expect("can parse archive");
+    let mut members = archive.members();
+    members.sort();
+    assert_eq!(members, vec!["a_rather_long_member_name.o", "one.o"]);
+    let mut symbols = archive
+        .summarize()
+        .into_iter()
+        .map(|(member, _, symbols)| (member.to_string(), symbols.len()))
+        .collect::<Vec<_>>();
+    symbols.sort();
+    assert_eq!(
+        symbols,
+        vec![
+            ("a_rather_long_member_name.o".to_string(), 2),
+            ("one.o".to_string(), 1),
+        ]
+    );
+    for (symbol, member) in &[
+        ("one", "one.o"),
+        ("two", "a_rather_long_member_name.o"),
+        ("answer", "a_rather_long_member_name.o"),
+    ] {
+        assert_eq!(
+            archive.member_of_symbol(&format!("{}{}", prefix, symbol)),
+            Some(*member)
+        );
+    }
+    assert_eq!(archive.member_of_symbol(&format!("{}helper", prefix)), None);
+
+    // every member is an object which can be parsed on its own
+    for member in archive.members() {
+        let object = archive.extract(member, bytes).expect("can extract member");
+        goblin::Object::parse(object).expect("can parse member");
+    }
+}
+
+#[test]
+fn gnu_archive() {
+    let bytes = two_member_archive(triple!("x86_64-unknown-linux-gnu"))
+        .emit()
+        .expect("can emit archive");
+    assert!(bytes.starts_with(b"!<arch>\n/ "));
+    check_archive(&bytes, "");
+}
+
+#[test]
+fn bsd_archive() {
+    let bytes = two_member_archive(triple!("x86_64-apple-darwin"))
+        .emit()
+        .expect("can emit archive");
+    assert!(bytes.starts_with(b"!<arch>\n#1/20"));
+    assert_eq!(&bytes[68..84], b"__.SYMDEF SORTED");
+    check_archive(&bytes, "_");
+}
+
+#[test]
+fn write_matches_emit() {
+    let archive = two_member_archive(triple!("x86_64-unknown-linux-gnu"));
+    let mut written = Vec::new();
+    archive.write(&mut written).expect("can write archive");
+    assert_eq!(written, archive.emit().expect("can emit archive"));
+}
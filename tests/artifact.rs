@@ -172,13 +172,72 @@ fn vary_output_formats() {
         _ => panic!("emitted as MachO but didn't parse as MachO"),
     }
 
-    /* TODO: Enable when COFF is supported.
+    // goblin only parses COFF objects as part of PE images
     let coff = obj.emit_as(BinaryFormat::Coff).unwrap();
-    match Object::parse(&coff).unwrap() {
-         Object::PE(_) => {}
-         _ => panic!("emitted as COFF but didn't parse as COFF"),
+    let header = goblin::pe::header::CoffHeader::parse(&coff, &mut 0).unwrap();
+    assert_eq!(header.machine, goblin::pe::header::COFF_MACHINE_X86_64);
+}
+
+#[test]
+fn emit_with_format() {
+    use goblin::Object;
+
+    let obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    match Object::parse(&obj.emit_with_format(None).unwrap()).unwrap() {
+        Object::Mach(_) => {}
+        _ => panic!("emitted natively for darwin but didn't parse as MachO"),
+    }
+    match Object::parse(&obj.emit_with_format(Some(ObjectFormat::Elf)).unwrap()).unwrap() {
+        Object::Elf(_) => {}
+        _ => panic!("emitted as ELF but didn't parse as ELF"),
+    }
+
+    let obj = Artifact::new(triple!("wasm32-unknown-unknown"), "t.o".into());
+    let wasm = obj.emit_with_format(Some(ObjectFormat::Wasm)).unwrap();
+    assert_eq!(&wasm[..4], b"\0asm");
+
+    let obj = Artifact::new(triple!("x86_64"), "t.o".into());
+    assert!(obj.emit_with_format(None).is_err());
+    assert!(obj.emit_with_format(Some(ObjectFormat::MachO)).is_ok());
+}
+
+#[test]
+fn emit_selects_backend_from_target() {
+    use goblin::Object;
+    use target_lexicon::BinaryFormat;
+
+    for &(target, is_mach) in &[
+        ("x86_64-apple-darwin", true),
+        ("aarch64-apple-ios", true),
+        ("x86_64-unknown-linux-gnu", false),
+        ("aarch64-unknown-freebsd", false),
+    ] {
+        let obj = Artifact::new(triple!(target), "t.o".into());
+        match Object::parse(&obj.emit().unwrap()).unwrap() {
+            Object::Mach(_) if is_mach => {}
+            Object::Elf(_) if !is_mach => {}
+            _ => panic!("{} was emitted in the wrong format", target),
+        }
+    }
+
+    let obj = Artifact::new(triple!("x86_64-pc-windows-msvc"), "t.o".into());
+    let coff = obj.emit().unwrap();
+    assert_eq!(
+        goblin::pe::header::CoffHeader::parse(&coff, &mut 0)
+            .unwrap()
+            .machine,
+        goblin::pe::header::COFF_MACHINE_X86_64
+    );
+
+    let obj = Artifact::new(triple!("wasm32-unknown-unknown"), "t.o".into());
+    assert_eq!(&obj.emit().unwrap()[..4], b"\0asm");
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.target.binary_format = BinaryFormat::Unknown;
+    match obj.emit() {
+        Err(ArtifactError::UnsupportedBinaryFormat(BinaryFormat::Unknown)) => {}
+        result => panic!("emitted an unknown format without a backend: {:?}", result),
     }
-    */
 }
 
 #[test]
@@ -225,3 +284,883 @@ fn invalid_bss() {
         .unwrap();
     assert!(artifact.define_zero_init("my_section", 100).is_err());
 }
+
+#[test]
+fn sort_definitions_topologically() {
+    use goblin::Object;
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    for name in &["a", "b", "c", "d"] {
+        obj.declare_with(name, Decl::function().global(), vec![0xc3])
+            .expect("can declare");
+    }
+    for &(from, to) in &[("c", "b"), ("b", "a"), ("d", "a")] {
+        obj.link(Link { from, to, at: 0 }).expect("can link");
+    }
+    obj.sort_definitions_topologically()
+        .expect("can sort acyclic definitions");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    match Object::parse(&bytes).unwrap() {
+        Object::Elf(elf) => {
+            let functions = elf
+                .syms
+                .iter()
+                .filter(|sym| sym.is_function())
+                .map(|sym| &elf.strtab[sym.st_name])
+                .collect::<Vec<_>>();
+            assert_eq!(functions, vec!["c", "b", "d", "a"]);
+        }
+        _ => panic!("emitted as ELF but didn't parse as ELF"),
+    }
+}
+
+#[test]
+fn sort_definitions_topologically_rejects_cycles() {
+    let mut obj = Artifact::new(triple!("x86_64"), "t.o".into());
+    for name in &["a", "b", "c"] {
+        obj.declare_with(name, Decl::function(), vec![0xc3])
+            .expect("can declare");
+    }
+    for &(from, to) in &[("a", "b"), ("b", "c"), ("c", "b")] {
+        obj.link(Link { from, to, at: 0 }).expect("can link");
+    }
+    match obj.sort_definitions_topologically() {
+        Err(ArtifactError::CyclicDependency(cyclic)) => {
+            assert_eq!(cyclic, vec![String::from("b"), String::from("c")])
+        }
+        _ => panic!("cycle between b and c was not detected"),
+    }
+}
+
+#[test]
+fn mapfile_lists_definitions() {
+    for target in &[
+        triple!("x86_64-unknown-unknown-unknown-elf"),
+        triple!("x86_64-apple-darwin"),
+    ] {
+        let mut obj = Artifact::new(target.clone(), "t.o".into());
+        obj.declare_with("f", Decl::function().global(), vec![0xc3; 5])
+            .expect("can declare f");
+        obj.declare_with("g", Decl::function(), vec![0xc3; 3])
+            .expect("can declare g");
+        obj.declare_with("d", Decl::data().writable(), vec![0; 12])
+            .expect("can declare d");
+        obj.import("i", ImportKind::Data).expect("can import i");
+
+        let mut map = Vec::new();
+        obj.generate_mapfile(&mut map).expect("can generate map");
+        let map = String::from_utf8(map).expect("map is utf8");
+
+        let symbols = map
+            .lines()
+            .filter(|line| line.starts_with(' '))
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            // skip the address and size of sections with long names
+            .filter(|columns| columns[2] != "t.o")
+            .map(|columns| (columns[2], columns[1]))
+            .collect::<Vec<_>>();
+        assert_eq!(symbols.len(), 3);
+        for &(name, size) in &[("f", "0x5"), ("g", "0x3"), ("d", "0xc")] {
+            assert!(symbols.contains(&(name, size)), "{} missing from map", name);
+        }
+    }
+}
+
+#[test]
+fn compute_text_and_data_size() {
+    use goblin::Object;
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3; 5])
+        .expect("can declare f");
+    obj.declare_with("g", Decl::function(), vec![0xc3; 3])
+        .expect("can declare g");
+    obj.declare_with("d", Decl::data().writable(), vec![0; 12])
+        .expect("can declare d");
+    obj.declare_with("s", Decl::cstring(), b"hello\0".to_vec())
+        .expect("can declare s");
+    obj.declare("sect", Decl::section(SectionKind::Data))
+        .expect("can declare sect");
+    obj.define("sect", vec![0; 100]).expect("can define sect");
+    assert_eq!(obj.compute_text_size(), 8);
+    assert_eq!(obj.compute_data_size(), 18);
+
+    let bytes = obj.emit().expect("can emit elf file");
+    match Object::parse(&bytes).unwrap() {
+        Object::Elf(elf) => {
+            let size_of = |prefix: &str| -> u64 {
+                elf.section_headers
+                    .iter()
+                    .filter(|shdr| elf.shdr_strtab[shdr.sh_name].starts_with(prefix))
+                    .map(|shdr| shdr.sh_size)
+                    .sum()
+            };
+            assert_eq!(size_of(".text.") as usize, obj.compute_text_size());
+            assert_eq!(
+                (size_of(".data.") + size_of(".rodata.")) as usize,
+                obj.compute_data_size()
+            );
+        }
+        _ => panic!("emitted as ELF but didn't parse as ELF"),
+    }
+
+    obj.declare("bss", Decl::data().writable())
+        .expect("can declare bss");
+    obj.define_zero_init("bss", 32).expect("can define bss");
+    assert_eq!(obj.compute_data_size(), 50);
+}
+
+#[test]
+fn print_stats() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.import("malloc", ImportKind::Function).unwrap();
+    obj.declare_with("main", Decl::function().global(), vec![0; 16])
+        .unwrap();
+    obj.declare_with("table", Decl::data(), vec![0; 8]).unwrap();
+    obj.link(Link {
+        from: "main",
+        to: "malloc",
+        at: 4,
+    })
+    .unwrap();
+    obj.link(Link {
+        from: "main",
+        to: "table",
+        at: 10,
+    })
+    .unwrap();
+    assert_eq!(obj.compute_relocation_count(), 2);
+
+    let mut stats = Vec::new();
+    obj.print_stats(&mut stats).expect("can print stats");
+    let stats = String::from_utf8(stats).expect("stats are utf8");
+    let lines = stats
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        vec![
+            vec!["text", "data", "symbols", "imports", "links", "relocs", "filename"],
+            vec!["16", "8", "3", "1", "2", "48", "t.o"],
+        ]
+    );
+
+    // the relocation entries of the other formats have other sizes
+    for &(target, relocs) in &[
+        ("x86_64-apple-darwin", "16"),
+        ("x86_64-pc-windows-msvc", "20"),
+        ("wasm32-unknown-unknown", "-"),
+    ] {
+        let mut obj = obj.clone();
+        obj.target = triple!(target);
+        let mut stats = Vec::new();
+        obj.print_stats(&mut stats).expect("can print stats");
+        let stats = String::from_utf8(stats).expect("stats are utf8");
+        let line = stats.lines().nth(1).expect("stats have a second line");
+        assert_eq!(line.split_whitespace().nth(5), Some(relocs), "{}", target);
+    }
+}
+
+#[test]
+fn dot_graph_contains_symbols_and_links() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.import("malloc", ImportKind::Function).unwrap();
+    obj.declare_with("main", Decl::function().global(), vec![0; 16])
+        .unwrap();
+    obj.declare_with("table", Decl::data(), vec![0; 8]).unwrap();
+    obj.link(Link {
+        from: "main",
+        to: "malloc",
+        at: 4,
+    })
+    .unwrap();
+    obj.link(Link {
+        from: "main",
+        to: "table",
+        at: 10,
+    })
+    .unwrap();
+
+    let dot = obj.to_dot_graph();
+    let mut lines = dot.lines();
+    assert_eq!(lines.next(), Some("digraph \"t.o\" {"));
+    let (mut nodes, mut edges) = (Vec::new(), Vec::new());
+    for line in lines {
+        let line = line.trim();
+        if line == "}" {
+            continue;
+        }
+        let (statement, attributes) = line
+            .trim_end_matches("];")
+            .split_at(line.find(" [").unwrap());
+        let attribute = attributes.trim_start_matches(" [");
+        match statement.find(" -> ") {
+            Some(arrow) => edges.push((
+                statement[..arrow].trim_matches('"').to_string(),
+                statement[arrow + 4..].trim_matches('"').to_string(),
+                attribute.to_string(),
+            )),
+            None => nodes.push((
+                statement.trim_matches('"').to_string(),
+                attribute.to_string(),
+            )),
+        }
+    }
+    assert_eq!(
+        nodes,
+        vec![
+            ("malloc".to_string(), "color=red".to_string()),
+            ("main".to_string(), "color=blue".to_string()),
+            ("table".to_string(), "color=green".to_string()),
+        ]
+    );
+    assert_eq!(
+        edges,
+        vec![
+            (
+                "main".to_string(),
+                "malloc".to_string(),
+                "label=\"0x4\"".to_string()
+            ),
+            (
+                "main".to_string(),
+                "table".to_string(),
+                "label=\"0xa\"".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn seal() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("a", Decl::cstring(), b"null\0".to_vec())
+        .unwrap();
+    obj.declare_with("b", Decl::cstring(), b"null\0".to_vec())
+        .unwrap();
+    obj.declare("f", Decl::function().global()).unwrap();
+    assert_eq!(obj.compute_data_size(), 10);
+    assert!(!obj.is_sealed());
+
+    obj.seal();
+    assert!(obj.is_sealed());
+    // the duplicate string was interned by sealing
+    assert_eq!(obj.compute_data_size(), 5);
+
+    match obj.define("f", vec![0xc3]) {
+        Err(ArtifactError::Sealed) => {}
+        result => panic!("defined f after sealing: {:?}", result),
+    }
+    match obj.declare("g", Decl::function()) {
+        Err(ArtifactError::Sealed) => {}
+        result => panic!("declared g after sealing: {:?}", result),
+    }
+    match obj.link(Link {
+        from: "f",
+        to: "a",
+        at: 0,
+    }) {
+        Err(ArtifactError::Sealed) => {}
+        result => panic!("linked after sealing: {:?}", result),
+    }
+
+    // every other way of changing the artifact is an error too
+    let entry = faerie::DataInCodeEntry {
+        offset: 0,
+        length: 1,
+        kind: 1,
+    };
+    let platform = faerie::MachPlatform::MacOS;
+    let path = std::path::Path::new("t.o");
+    let results = vec![
+        ("add_data_in_code", obj.add_data_in_code(entry)),
+        (
+            "add_raw_section",
+            obj.add_raw_section(".raw".to_string(), vec![0], 0),
+        ),
+        ("add_odr_entry", obj.add_odr_entry("Foo", 42)),
+        ("set_debuglink", obj.set_debuglink("t.debug", 0)),
+        ("set_build_id", obj.set_build_id(&[0; 4])),
+        ("add_comment", obj.add_comment("faerie")),
+        ("set_pic", obj.set_pic(true)),
+        ("set_entry_point", obj.set_entry_point("f")),
+        ("set_source_path", obj.set_source_path(path, 0)),
+        (
+            "set_platform",
+            obj.set_platform(platform, (11, 0, 0), (11, 0, 0)),
+        ),
+        ("add_frame_info", obj.add_frame_info("f", &[0; 4])),
+        (
+            "disable_subsections_via_symbols",
+            obj.disable_subsections_via_symbols(),
+        ),
+        ("intern_strings", obj.intern_strings().map(|_| ())),
+    ];
+    for (method, result) in results {
+        match result {
+            Err(ArtifactError::Sealed) => {}
+            result => panic!("{} succeeded after sealing: {:?}", method, result),
+        }
+    }
+}
+
+#[test]
+fn emit_seals_a_copy() {
+    for target in &["x86_64-unknown-linux-gnu", "x86_64-apple-darwin"] {
+        let mut obj = Artifact::new(triple!(target), "t.o".into());
+        obj.declare_with("a", Decl::cstring(), b"null\0".to_vec())
+            .unwrap();
+        obj.declare_with("b", Decl::cstring(), b"null\0".to_vec())
+            .unwrap();
+        let unsealed = obj.emit().expect("can emit unsealed artifact");
+        assert!(!obj.is_sealed());
+        assert_eq!(obj.compute_data_size(), 10);
+
+        obj.seal();
+        assert_eq!(unsealed, obj.emit().expect("can emit sealed artifact"));
+    }
+}
+
+#[test]
+fn verify_against_schema() {
+    let mut obj = Artifact::new(triple!("x86_64"), "t.o".into());
+    obj.declare("f", Decl::function().global())
+        .expect("can declare f");
+    obj.declare("g", Decl::function_import())
+        .expect("can declare g");
+    obj.declare("x", Decl::data()).expect("can declare x");
+    obj.import("y", ImportKind::Data).expect("can import y");
+
+    assert!(obj.verify_against_schema(&ArtifactSchema::any()).is_empty());
+    let violation = |name: &str, kind| SchemaViolation {
+        name: name.to_string(),
+        kind,
+    };
+    assert_eq!(
+        obj.verify_against_schema(&ArtifactSchema::no_imports()),
+        vec![
+            violation("g", SchemaViolationKind::Import(ImportKind::Function)),
+            violation("y", SchemaViolationKind::Import(ImportKind::Data)),
+        ]
+    );
+    assert_eq!(
+        obj.verify_against_schema(&ArtifactSchema::functions_only()),
+        vec![violation("x", SchemaViolationKind::Data)]
+    );
+    assert_eq!(
+        obj.verify_against_schema(
+            &ArtifactSchema::no_imports().and(ArtifactSchema::functions_only())
+        )
+        .len(),
+        3
+    );
+}
+
+#[test]
+fn check_all_imports_satisfied() {
+    let mut obj = Artifact::new(triple!("x86_64"), "t.o".into());
+    obj.import("malloc", ImportKind::Function)
+        .expect("can import malloc");
+    obj.import("environ", ImportKind::Data)
+        .expect("can import environ");
+    obj.import("frobnicate", ImportKind::Function)
+        .expect("can import frobnicate");
+    obj.declare_with("main", Decl::function().global(), vec![0xc3])
+        .expect("can declare main");
+
+    assert_eq!(
+        obj.check_all_imports_satisfied(&["malloc", "environ", "main"]),
+        vec!["frobnicate".to_string()]
+    );
+    assert!(obj
+        .check_all_imports_satisfied(&["frobnicate", "malloc", "environ"])
+        .is_empty());
+}
+
+#[test]
+fn emit_sync() {
+    let dir = std::env::temp_dir().join(format!("faerie-emit-sync-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("t.o");
+    std::fs::write(&path, b"stale").unwrap();
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.emit_sync(&path).expect("can write t.o");
+
+    assert_eq!(std::fs::read(&path).unwrap(), obj.emit().unwrap());
+    // the temporary file was renamed, not left behind
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    assert!(obj.emit_sync(dir.join("missing").join("t.o")).is_err());
+    assert!(obj.emit_sync("/").is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn apply_patch() {
+    for target in &["x86_64-unknown-linux-gnu", "x86_64-apple-darwin"] {
+        let declare = |obj: &mut Artifact, with_e: bool| {
+            obj.declare("f", Decl::function().global())
+                .expect("can declare f");
+            obj.declare("g", Decl::function()).expect("can declare g");
+            obj.declare("d", Decl::data().global())
+                .expect("can declare d");
+            if with_e {
+                obj.declare("e", Decl::data()).expect("can declare e");
+            }
+            obj.declare("h", Decl::function().global())
+                .expect("can declare h");
+        };
+        let link = |obj: &mut Artifact| {
+            obj.link(Link {
+                from: "f",
+                to: "g",
+                at: 1,
+            })
+            .expect("can link f to g");
+        };
+
+        let mut patched = Artifact::new(triple!(target), "t.o".into());
+        declare(&mut patched, true);
+        patched.define("f", vec![0xe8, 0, 0, 0, 0]).unwrap();
+        patched.define("g", vec![0xc3]).unwrap();
+        patched.define("d", vec![1, 2, 3, 4]).unwrap();
+        patched.define("e", vec![5; 8]).unwrap();
+        link(&mut patched);
+        patched
+            .apply_patch(ArtifactPatch {
+                added_definitions: vec![("h".to_string(), vec![0x90, 0xc3])],
+                removed_definitions: vec!["e".to_string()],
+                changed_definitions: vec![(
+                    "f".to_string(),
+                    vec![0x90, 0xe8, 0, 0, 0, 0, 0x90, 0xc3],
+                )],
+            })
+            .expect("can apply patch");
+
+        let mut rebuilt = Artifact::new(triple!(target), "t.o".into());
+        declare(&mut rebuilt, false);
+        rebuilt
+            .define("f", vec![0x90, 0xe8, 0, 0, 0, 0, 0x90, 0xc3])
+            .unwrap();
+        rebuilt.define("g", vec![0xc3]).unwrap();
+        rebuilt.define("d", vec![1, 2, 3, 4]).unwrap();
+        rebuilt.define("h", vec![0x90, 0xc3]).unwrap();
+        link(&mut rebuilt);
+
+        assert_eq!(patched.emit().unwrap(), rebuilt.emit().unwrap());
+
+        let change = |name: &str| ArtifactPatch {
+            changed_definitions: vec![(name.to_string(), vec![0xc3])],
+            ..ArtifactPatch::default()
+        };
+        match patched.apply_patch(change("e")) {
+            Err(ArtifactError::Undeclared(name)) => assert_eq!(name, "e"),
+            result => panic!("changed a removed definition: {:?}", result),
+        }
+
+        // the link from f is past the end of its new definition
+        patched.apply_patch(change("f")).expect("can shrink f");
+        let mut rebuilt = Artifact::new(triple!(target), "t.o".into());
+        declare(&mut rebuilt, false);
+        rebuilt.define("f", vec![0xc3]).unwrap();
+        rebuilt.define("g", vec![0xc3]).unwrap();
+        rebuilt.define("d", vec![1, 2, 3, 4]).unwrap();
+        rebuilt.define("h", vec![0x90, 0xc3]).unwrap();
+        assert_eq!(patched.emit().unwrap(), rebuilt.emit().unwrap());
+
+        let mut sized = Artifact::new(triple!(target), "t.o".into());
+        sized
+            .declare_with_size("d", Decl::data().global(), 8)
+            .expect("can declare d");
+        sized.define("d", vec![0; 8]).unwrap();
+        match sized.apply_patch(change("d")) {
+            Err(ArtifactError::InvalidSize {
+                name,
+                size,
+                defined,
+            }) => assert_eq!((name.as_str(), size, defined), ("d", 8, 1)),
+            result => panic!("shrank d below its declared size: {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn gc_sections() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0; 16])
+        .expect("can declare main");
+    obj.declare_with("helper", Decl::function(), vec![0xc3])
+        .expect("can declare helper");
+    obj.declare_with("greeting", Decl::cstring(), b"hi\0".to_vec())
+        .expect("can declare greeting");
+    obj.declare_with("dead", Decl::function(), vec![0; 8])
+        .expect("can declare dead");
+    obj.declare_with("unused", Decl::data().global(), vec![0; 32])
+        .expect("can declare unused");
+    obj.import("printf", ImportKind::Function)
+        .expect("can import printf");
+    obj.import("abort", ImportKind::Function)
+        .expect("can import abort");
+    for &(from, to) in &[
+        ("main", "helper"),
+        ("helper", "greeting"),
+        ("main", "printf"),
+        ("dead", "helper"),
+        ("dead", "abort"),
+    ] {
+        obj.link(Link { from, to, at: 0 }).expect("can link");
+    }
+
+    match obj.gc_sections(&["start"]) {
+        Err(ArtifactError::Undeclared(name)) => assert_eq!(name, "start"),
+        result => panic!("collected from an undeclared entry point: {:?}", result),
+    }
+
+    let mut report = obj.gc_sections(&["main"]).expect("can collect");
+    report.removed_symbols.sort();
+    assert_eq!(
+        report,
+        ArtifactGcReport {
+            removed_symbols: vec!["dead".to_string(), "unused".to_string()],
+            bytes_saved: 40,
+        }
+    );
+    // imports stay, even if they are no longer used
+    assert_eq!(obj.imports().count(), 2);
+    assert!(obj.undefined_symbols().is_empty());
+    assert!(obj.emit().is_ok());
+    assert_eq!(
+        obj.gc_sections(&["main"]).expect("can collect again"),
+        ArtifactGcReport::default()
+    );
+}
+
+#[test]
+fn common_symbols() {
+    let mut obj = Artifact::new(triple!("x86_64"), "t.o".into());
+    obj.declare("buf", Decl::common(16, 4))
+        .expect("can declare buf");
+    obj.declare("buf", Decl::data_import())
+        .expect("a data import is compatible with a common symbol");
+    // like the linker, the largest size and alignment win
+    obj.declare("buf", Decl::common(64, 1))
+        .expect("common declarations of different sizes are merged");
+    assert!(obj.declare("buf", Decl::data()).is_err());
+    assert!(obj.undefined_symbols().is_empty());
+    match obj.define("buf", vec![0; 64]) {
+        Err(ArtifactError::CommonDefined(name)) => assert_eq!(name, "buf"),
+        result => panic!("defined a common symbol: {:?}", result),
+    }
+}
+
+#[test]
+fn write_incremental() {
+    use std::io::Cursor;
+    use target_lexicon::BinaryFormat;
+
+    for (target, format) in &[
+        (triple!("x86_64-unknown-linux-gnu"), BinaryFormat::Elf),
+        (triple!("x86_64-apple-darwin"), BinaryFormat::Macho),
+        (triple!("x86_64-pc-windows-msvc"), BinaryFormat::Coff),
+    ] {
+        let mut obj = Artifact::new(target.clone(), "t.o".into());
+        obj.declare_with("f", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3])
+            .expect("can declare f");
+        obj.declare_with("x", Decl::data().global().writable(), vec![1, 2, 3, 4])
+            .expect("can declare x");
+        obj.import("g", ImportKind::Function).expect("can import g");
+        obj.link(Link {
+            from: "f",
+            to: "g",
+            at: 1,
+        })
+        .expect("can link from f to g");
+
+        let mut sink = Cursor::new(Vec::new());
+        obj.write_incremental(&mut sink, *format)
+            .expect("can write incrementally");
+        assert_eq!(sink.into_inner(), obj.emit().expect("can emit"));
+
+        obj.declare("y", Decl::data()).expect("can declare y");
+        match obj.write_incremental(Cursor::new(Vec::new()), *format) {
+            Err(ArtifactError::UndefinedSymbols(undef)) => assert_eq!(undef, vec!["y"]),
+            result => panic!("wrote an artifact with undefined symbols: {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn merge() {
+    let new = |target| {
+        let mut obj = Artifact::new(target, "t.o".into());
+        obj.declare_with("f", Decl::function().global(), vec![0xc3])
+            .expect("can declare f");
+        obj.declare_with("inline_fn", Decl::function().global().comdat(), vec![0xc3])
+            .expect("can declare inline_fn");
+        obj
+    };
+    match new(triple!("x86_64-unknown-linux-gnu")).merge(new(triple!("aarch64-unknown-linux-gnu")))
+    {
+        Err(ArtifactError::IncompatibleMerge(_)) => {}
+        result => panic!("merged artifacts of different targets: {:?}", result),
+    }
+    let target = triple!("x86_64-unknown-linux-gnu");
+    match new(target.clone()).merge(new(target.clone())) {
+        Err(ArtifactError::DuplicateDefinition(name)) => assert_eq!(name, "f"),
+        result => panic!("merged duplicate definitions: {:?}", result),
+    }
+
+    // COMDAT definitions are coalesced, and a local which clashes with a global is renamed
+    let mut obj = Artifact::new(target.clone(), "a.o".into());
+    obj.declare_with("inline_fn", Decl::function().global().comdat(), vec![0xc3])
+        .expect("can declare inline_fn");
+    obj.declare_with("g", Decl::function(), vec![0x90, 0xc3])
+        .expect("can declare g");
+    obj.declare_with("caller", Decl::function().global(), vec![0xe8, 0, 0, 0, 0])
+        .expect("can declare caller");
+    obj.link(Link {
+        from: "caller",
+        to: "g",
+        at: 1,
+    })
+    .expect("can link from caller to g");
+    let mut other = new(target);
+    other
+        .declare_with("g", Decl::function().global(), vec![0xc3])
+        .expect("can declare g");
+    let merged = obj.merge(other).expect("can merge");
+    assert!(merged.undefined_symbols().is_empty());
+    let links = merged
+        .to_dot_graph()
+        .lines()
+        .filter(|line| line.contains("->"))
+        .map(str::trim)
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    assert_eq!(links, vec!["\"caller\" -> \"g.merged.1\" [label=\"0x1\"];"]);
+    assert!(merged.emit().is_ok());
+}
+
+#[test]
+fn define_with_cursor() {
+    use std::io::{self, Write};
+
+    let artifact = |define: &dyn Fn(&mut Artifact)| {
+        let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+        obj.declare("data", Decl::data().global())
+            .expect("can declare data");
+        define(&mut obj);
+        obj.emit().expect("can emit artifact")
+    };
+    let expected = artifact(&|obj| {
+        obj.define("data", vec![1, 2, 3, 4, 0xde, 0xad])
+            .expect("can define data")
+    });
+    let written = artifact(&|obj| {
+        obj.define_with_cursor("data", |cursor| {
+            cursor.write_all(&[1, 2, 3, 4])?;
+            cursor.write_all(&[0xde, 0xad])
+        })
+        .expect("can define data with a cursor")
+    });
+    assert_eq!(written, expected);
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare("data", Decl::data().global())
+        .expect("can declare data");
+    match obj.define_with_cursor("data", |_| {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "oops"))
+    }) {
+        Err(ArtifactError::Io(e)) => assert_eq!(e.to_string(), "oops"),
+        result => panic!("the closure's error was not returned: {:?}", result),
+    }
+    // nothing was defined, so the data can still be defined
+    obj.define("data", vec![1]).expect("can define data");
+}
+
+#[test]
+fn imports_mut() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.import("f", ImportKind::Function).expect("can import f");
+    obj.declare_with("g", Decl::function().global(), vec![0xc3])
+        .expect("can declare g");
+    obj.import("x", ImportKind::Data).expect("can import x");
+    obj.import("h", ImportKind::Function).expect("can import h");
+    obj.declare_with("h", Decl::function(), vec![0xc3])
+        .expect("can define h");
+
+    let imports = obj
+        .imports_mut()
+        .expect("can modify imports")
+        .map(|(name, kind)| {
+            if name == "f" {
+                *kind = ImportKind::WeakFunction;
+            }
+            name.to_string()
+        })
+        .collect::<Vec<_>>();
+    // definitions are not imports
+    assert_eq!(imports, vec!["f", "x"]);
+    assert_eq!(
+        imports,
+        obj.imports().map(|(name, _)| name).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        obj.imports().collect::<Vec<_>>(),
+        vec![("f", &ImportKind::WeakFunction), ("x", &ImportKind::Data)]
+    );
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let f = elf
+        .syms
+        .iter()
+        .find(|sym| &elf.strtab[sym.st_name] == "f")
+        .expect("f is in the symbol table");
+    assert_eq!(f.st_bind(), goblin::elf::sym::STB_WEAK);
+
+    obj.seal();
+    assert!(obj.imports_mut().is_err());
+}
+
+#[test]
+fn declare_many_reports_every_error() {
+    let mut obj = Artifact::new(triple!("x86_64"), "t.o".into());
+    obj.declare_many(vec![
+        ("f", Decl::function().global().into()),
+        ("x", Decl::data().global().into()),
+    ])
+    .expect("can declare f and x");
+
+    let errors = obj
+        .declare_many(vec![
+            ("f", Decl::data().global().into()),
+            ("g", Decl::function().global().into()),
+            ("x", Decl::function().local().into()),
+        ])
+        .expect_err("f and x are redeclared incompatibly");
+    let names = errors
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["f", "x"]);
+    for (_, error) in errors {
+        match error {
+            ArtifactError::IncompatibleDeclaration { .. } => (),
+            error => panic!("unexpected error {:?}", error),
+        }
+    }
+    // the declarations after the first failure are still made
+    obj.define("g", vec![0xc3]).expect("g is declared");
+}
+
+#[test]
+fn rename_symbol() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    // call g; call puts; ret
+    obj.declare_with(
+        "f",
+        Decl::function().global(),
+        vec![0xe8, 0, 0, 0, 0, 0xe8, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare f");
+    obj.declare_with("g", Decl::function().global(), vec![0xc3])
+        .expect("can declare g");
+    obj.import("puts", ImportKind::Function)
+        .expect("can import puts");
+    obj.link(Link {
+        from: "f",
+        to: "g",
+        at: 1,
+    })
+    .expect("can link f to g");
+    obj.link(Link {
+        from: "f",
+        to: "puts",
+        at: 6,
+    })
+    .expect("can link f to puts");
+
+    obj.rename_symbol("f", "f.v2").expect("can rename f");
+    obj.rename_symbol("g", "g.v2").expect("can rename g");
+    obj.rename_symbol("puts", "puts$UNIX2003")
+        .expect("can rename puts");
+    match obj.rename_symbol("f.v2", "g.v2") {
+        Err(ArtifactError::NameTaken(name)) => assert_eq!(name, "g.v2"),
+        result => panic!("unexpected result {:?}", result),
+    }
+    match obj.rename_symbol("f", "f.v3") {
+        Err(ArtifactError::Undeclared(name)) => assert_eq!(name, "f"),
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    assert_eq!(
+        obj.imports().collect::<Vec<_>>(),
+        vec![("puts$UNIX2003", &ImportKind::Function)]
+    );
+    // the definitions are kept, and the old names are gone
+    obj.declare("f", Decl::data().global())
+        .expect("f can be declared anew");
+    obj.define("f", vec![0; 8]).expect("can define f");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let names = elf
+        .syms
+        .iter()
+        .map(|sym| &elf.strtab[sym.st_name])
+        .collect::<Vec<_>>();
+    for name in &["f", "f.v2", "g.v2", "puts$UNIX2003"] {
+        assert!(names.contains(name), "{} is in the symbol table", name);
+    }
+    assert!(!names.contains(&"g"));
+    assert!(!names.contains(&"puts"));
+    // the links were renamed along with their ends; `g.v2` is defined in the same section, so
+    // it is referenced by the section symbol
+    let targets = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| {
+            let sym = elf.syms.get(reloc.r_sym).unwrap();
+            (reloc.r_offset, &elf.strtab[sym.st_name])
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(targets, vec![(1, ""), (6, "puts$UNIX2003")]);
+}
+
+#[test]
+fn authenticated_pointers_are_mach_only() {
+    for target in &[
+        triple!("aarch64-unknown-linux-gnu"),
+        triple!("aarch64-pc-windows-msvc"),
+    ] {
+        let mut obj = Artifact::new(target.clone(), "t.o".into());
+        obj.declare_with("f", Decl::function().global(), vec![0xc0, 0x03, 0x5f, 0xd6])
+            .expect("can declare f");
+        obj.declare_with("vtable", Decl::data().global(), vec![0; 8])
+            .expect("can declare vtable");
+        obj.link_with(
+            Link {
+                from: "vtable",
+                to: "f",
+                at: 0,
+            },
+            Reloc::Authenticated {
+                key: PtrauthKey::IA,
+                addr_div: false,
+                discriminator: 0,
+                addend: 0,
+            },
+        )
+        .expect("can link vtable to f");
+        match obj.emit() {
+            Err(ArtifactError::UnsupportedRelocation(_)) => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+}
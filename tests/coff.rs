@@ -0,0 +1,396 @@
+extern crate faerie;
+extern crate goblin;
+#[macro_use]
+extern crate target_lexicon;
+
+use faerie::{Artifact, Decl, ImportKind, Link, Reloc, SectionKind};
+use goblin::pe::header::CoffHeader;
+use goblin::pe::relocation::*;
+use goblin::pe::section_table::*;
+use goblin::pe::symbol::*;
+use std::str::FromStr;
+use target_lexicon::BinaryFormat;
+
+/// A parsed COFF object, which goblin can only parse as part of a PE image
+struct Coff<'a> {
+    bytes: &'a [u8],
+    header: CoffHeader,
+    sections: Vec<SectionTable>,
+    symbols: Vec<(usize, String, Symbol)>,
+}
+
+impl<'a> Coff<'a> {
+    fn parse(bytes: &'a [u8]) -> Self {
+        let mut offset = 0;
+        let header = CoffHeader::parse(bytes, &mut offset).expect("can parse coff header");
+        let sections = header
+            .sections(bytes, &mut offset)
+            .expect("can parse section headers");
+        let strtab = header.strings(bytes).expect("can parse string table");
+        let symbols = header
+            .symbols(bytes)
+            .expect("can parse symbol table")
+            .iter()
+            .map(|(idx, _, sym)| {
+                let name = sym.name(&strtab).expect("symbol has a name").to_string();
+                (idx, name, sym)
+            })
+            .collect();
+        Coff {
+            bytes,
+            header,
+            sections,
+            symbols,
+        }
+    }
+    fn section(&self, number: i16) -> &SectionTable {
+        &self.sections[number as usize - 1]
+    }
+    fn symbol(&self, name: &str) -> &Symbol {
+        self.symbols
+            .iter()
+            .find(|(_, sym_name, _)| sym_name == name)
+            .map(|(_, _, sym)| sym)
+            .expect("symbol is present")
+    }
+    fn symbol_name(&self, idx: usize) -> &str {
+        self.symbols
+            .iter()
+            .find(|(sym_idx, _, _)| *sym_idx == idx)
+            .map(|(_, name, _)| name.as_str())
+            .expect("relocation has a symbol")
+    }
+    fn relocations(&self, section: &SectionTable) -> Vec<(u32, &str, u16)> {
+        section
+            .relocations(self.bytes)
+            .expect("can parse relocations")
+            .map(|reloc| {
+                (
+                    reloc.virtual_address,
+                    self.symbol_name(reloc.symbol_table_index as usize),
+                    reloc.typ,
+                )
+            })
+            .collect()
+    }
+    fn contents(&self, section: &SectionTable) -> &[u8] {
+        let start = section.pointer_to_raw_data as usize;
+        &self.bytes[start..start + section.size_of_raw_data as usize]
+    }
+}
+
+fn hello_world(target: &str) -> Artifact {
+    let target = target_lexicon::Triple::from_str(target).expect("can parse target");
+    let mut obj = Artifact::new(target, "t.obj".into());
+    // callq puts; movq counter(%rip), %rax; retq
+    obj.declare_with(
+        "main",
+        Decl::function().global(),
+        vec![0xe8, 0, 0, 0, 0, 0x48, 0x8b, 0x05, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare main");
+    obj.declare_with("helper", Decl::function(), vec![0xc3])
+        .expect("can declare helper");
+    obj.declare_with("message", Decl::cstring().global(), b"hello\0".to_vec())
+        .expect("can declare message");
+    obj.declare_with("counter", Decl::data().global().writable(), vec![0; 8])
+        .expect("can declare counter");
+    obj.declare_with("table", Decl::data().writable(), vec![0; 16])
+        .expect("can declare table");
+    obj.declare("zeros", Decl::data().global().writable())
+        .expect("can declare zeros");
+    obj.define_zero_init("zeros", 64).expect("can define zeros");
+    obj.import("puts", ImportKind::Function)
+        .expect("can import puts");
+    obj.link(Link {
+        from: "main",
+        to: "puts",
+        at: 1,
+    })
+    .expect("can link from main to puts");
+    obj.link(Link {
+        from: "main",
+        to: "counter",
+        at: 8,
+    })
+    .expect("can link from main to counter");
+    obj.link(Link {
+        from: "table",
+        to: "helper",
+        at: 0,
+    })
+    .expect("can link from table to helper");
+    obj.link_with(
+        Link {
+            from: "table",
+            to: "message",
+            at: 8,
+        },
+        Reloc::Absolute { addend: 2 },
+    )
+    .expect("can link from table to message");
+    obj
+}
+
+#[test]
+fn x86_64_object() {
+    let bytes = hello_world("x86_64-pc-windows-msvc")
+        .emit()
+        .expect("can emit coff file");
+    let coff = Coff::parse(&bytes);
+    assert_eq!(coff.header.machine, goblin::pe::header::COFF_MACHINE_X86_64);
+    let names = coff
+        .sections
+        .iter()
+        .map(|section| section.name().expect("section has a name"))
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec![".text", ".data", ".bss", ".rdata"]);
+
+    let main = coff.symbol("main");
+    assert_eq!(main.storage_class, IMAGE_SYM_CLASS_EXTERNAL);
+    assert!(main.is_function_definition());
+    let text = coff.section(main.section_number);
+    assert_ne!(text.characteristics & IMAGE_SCN_CNT_CODE, 0);
+    assert_eq!(
+        coff.relocations(text),
+        vec![
+            (main.value + 1, "puts", IMAGE_REL_AMD64_REL32),
+            (main.value + 8, "counter", IMAGE_REL_AMD64_REL32),
+        ]
+    );
+    assert_eq!(coff.symbol("helper").storage_class, IMAGE_SYM_CLASS_STATIC);
+    let puts = coff.symbol("puts");
+    assert_eq!(puts.storage_class, IMAGE_SYM_CLASS_EXTERNAL);
+    assert_eq!(puts.section_number, IMAGE_SYM_UNDEFINED);
+
+    let table = coff.symbol("table");
+    let data = coff.section(table.section_number);
+    assert_eq!(
+        data.characteristics & IMAGE_SCN_MEM_WRITE,
+        IMAGE_SCN_MEM_WRITE
+    );
+    assert_eq!(
+        coff.relocations(data),
+        vec![
+            (table.value, "helper", IMAGE_REL_AMD64_ADDR64),
+            (table.value + 8, "message", IMAGE_REL_AMD64_ADDR64),
+        ]
+    );
+    // the addend is in the relocated bytes
+    let at = (table.value + 8) as usize;
+    assert_eq!(&coff.contents(data)[at..at + 8], &[2, 0, 0, 0, 0, 0, 0, 0]);
+
+    let message = coff.symbol("message");
+    let rdata = coff.section(message.section_number);
+    assert_eq!(rdata.characteristics & IMAGE_SCN_MEM_WRITE, 0);
+    assert_eq!(coff.contents(rdata), b"hello\0");
+
+    let zeros = coff.symbol("zeros");
+    let bss = coff.section(zeros.section_number);
+    assert_ne!(bss.characteristics & IMAGE_SCN_CNT_UNINITIALIZED_DATA, 0);
+    assert_eq!(bss.size_of_raw_data, 64);
+    assert_eq!(bss.pointer_to_raw_data, 0);
+}
+
+#[test]
+fn i386_symbols_are_prefixed() {
+    let bytes = hello_world("i686-pc-windows-msvc")
+        .emit()
+        .expect("can emit coff file");
+    let coff = Coff::parse(&bytes);
+    assert_eq!(coff.header.machine, goblin::pe::header::COFF_MACHINE_X86);
+    assert_eq!(coff.symbol("@feat.00").section_number, IMAGE_SYM_ABSOLUTE);
+    let main = coff.symbol("_main");
+    assert_eq!(
+        coff.relocations(coff.section(main.section_number)),
+        vec![
+            (main.value + 1, "_puts", IMAGE_REL_I386_REL32),
+            (main.value + 8, "_counter", IMAGE_REL_I386_REL32),
+        ]
+    );
+    let table = coff.symbol("_table");
+    assert_eq!(
+        coff.relocations(coff.section(table.section_number)),
+        vec![
+            (table.value, "_helper", IMAGE_REL_I386_DIR32),
+            (table.value + 8, "_message", IMAGE_REL_I386_DIR32),
+        ]
+    );
+}
+
+#[test]
+fn comdat_and_weak_definitions() {
+    let mut obj = Artifact::new(triple!("x86_64-pc-windows-msvc"), "t.obj".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("inline_fn", Decl::function().global().comdat(), vec![0xc3])
+        .expect("can declare inline_fn");
+    obj.declare_with("selectany", Decl::weak_data(), vec![0; 8])
+        .expect("can declare selectany");
+
+    let bytes = obj.emit().expect("can emit coff file");
+    let coff = Coff::parse(&bytes);
+    let is_comdat = |name: &str| {
+        let section = coff.section(coff.symbol(name).section_number);
+        section.characteristics & IMAGE_SCN_LNK_COMDAT != 0
+    };
+    assert!(!is_comdat("f"));
+    assert!(is_comdat("inline_fn"));
+    assert!(is_comdat("selectany"));
+
+    // the section symbol of a COMDAT section records how the linker selects a section
+    let symtab = coff.header.symbols(&bytes).expect("can parse symbol table");
+    let section_number = coff.symbol("inline_fn").section_number;
+    let (idx, _, _) = coff
+        .symbols
+        .iter()
+        .find(|(_, _, sym)| sym.is_section_definition() && sym.section_number == section_number)
+        .expect("comdat section has a section symbol");
+    let aux = symtab
+        .aux_section_definition(*idx + 1)
+        .expect("section symbol has an auxiliary record");
+    assert_eq!(aux.selection, IMAGE_COMDAT_SELECT_ANY);
+}
+
+#[test]
+fn debug_sections() {
+    let mut obj = Artifact::new(triple!("x86_64-pc-windows-msvc"), "t.obj".into());
+    obj.declare_with(
+        ".debug_str",
+        Decl::section(SectionKind::Debug),
+        b"faerie\0".to_vec(),
+    )
+    .expect("can declare .debug_str");
+    obj.declare_with(".debug_info", Decl::section(SectionKind::Debug), vec![0; 4])
+        .expect("can declare .debug_info");
+    obj.link_with(
+        Link {
+            from: ".debug_info",
+            to: ".debug_str",
+            at: 0,
+        },
+        Reloc::Debug { size: 4, addend: 0 },
+    )
+    .expect("can link from .debug_info to .debug_str");
+
+    let bytes = obj.emit().expect("can emit coff file");
+    let coff = Coff::parse(&bytes);
+    // names longer than 8 bytes are in the string table
+    let debug_info = coff
+        .sections
+        .iter()
+        .find(|section| section.name().expect("section has a name") == ".debug_info")
+        .expect("section is present");
+    assert_ne!(debug_info.characteristics & IMAGE_SCN_MEM_DISCARDABLE, 0);
+    assert_eq!(
+        coff.relocations(debug_info),
+        vec![(0, ".debug_str", IMAGE_REL_AMD64_SECREL)]
+    );
+
+    obj.link_with(
+        Link {
+            from: ".debug_info",
+            to: ".debug_str",
+            at: 2,
+        },
+        Reloc::Debug { size: 2, addend: 0 },
+    )
+    .expect("can link from .debug_info to .debug_str");
+    match obj.emit() {
+        Err(faerie::ArtifactError::UnsupportedRelocation(_)) => {}
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn unsupported_architectures() {
+    for target in &[
+        "riscv64gc-unknown-linux-gnu",
+        "armv7-unknown-linux-gnueabihf",
+    ] {
+        let mut obj = Artifact::new(triple!(target), "t.obj".into());
+        obj.declare_with("f", Decl::function().global(), vec![0; 4])
+            .expect("can declare f");
+        obj.declare_with("p", Decl::data().global(), vec![0; 8])
+            .expect("can declare p");
+        obj.link(Link {
+            from: "p",
+            to: "f",
+            at: 0,
+        })
+        .expect("can link p to f");
+        match obj.emit_as(BinaryFormat::Coff) {
+            Err(faerie::ArtifactError::Unsupported(_)) => {}
+            result => panic!("emitted a {} COFF object: {:?}", target, result.map(|_| ())),
+        }
+    }
+}
+
+#[test]
+fn got_relocations_are_unsupported() {
+    let mut obj = Artifact::new(triple!("x86_64-pc-windows-msvc"), "t.obj".into());
+    obj.declare_with("f", Decl::function().global(), vec![0; 8])
+        .expect("can declare f");
+    obj.import("x", ImportKind::Data).expect("can import x");
+    obj.link_with_reloc(
+        Link {
+            from: "f",
+            to: "x",
+            at: 3,
+        },
+        Some(faerie::RelocationKind::GotPcRelative),
+    )
+    .expect("can link f to x through the GOT");
+    // COFF has no Global Offset Table
+    match obj.emit() {
+        Err(faerie::ArtifactError::UnsupportedRelocation(_)) => {}
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn link_with_addend() {
+    let mut obj = Artifact::new(triple!("x86_64-pc-windows-msvc"), "t.obj".into());
+    // movl pair+8(%rip), %eax; retq
+    obj.declare_with(
+        "f",
+        Decl::function().global(),
+        vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare f");
+    obj.declare_with("pair", Decl::data().global().writable(), vec![1; 16])
+        .expect("can declare pair");
+    obj.link_with_addend(
+        Link {
+            from: "f",
+            to: "pair",
+            at: 2,
+        },
+        8,
+    )
+    .expect("can link to pair+8");
+
+    let bytes = obj.emit().expect("can emit coff file");
+    let coff = Coff::parse(&bytes);
+    let text = coff.section(coff.symbol("f").section_number);
+    assert_eq!(
+        coff.relocations(text),
+        vec![(2, "pair", IMAGE_REL_AMD64_REL32)]
+    );
+    // COFF relocations have no addend, so it is stored in the relocated bytes
+    assert_eq!(&coff.contents(text)[..7], &[0x8b, 0x05, 8, 0, 0, 0, 0xc3]);
+
+    obj.link_with_addend(
+        Link {
+            from: "f",
+            to: "pair",
+            at: 2,
+        },
+        1 << 32,
+    )
+    .expect("can link to pair+2^32");
+    match obj.emit() {
+        Err(faerie::ArtifactError::UnsupportedRelocation(_)) => {}
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
@@ -189,6 +189,20 @@ fn decl_attributes() {
                 Ok(())
             },
         ),
+        DeclTestCase::new(
+            "internal_func",
+            Decl::function().global().internal(),
+            |sym, sect| {
+                ensure!(sym.is_function(), "symbol is func");
+                ensure!(sym.st_bind() == sym::STB_GLOBAL, "symbol is global");
+                ensure!(
+                    sym.st_visibility() == sym::STV_INTERNAL,
+                    "symbol is internal"
+                );
+                ensure!(sect.is_executable(), "executable");
+                Ok(())
+            },
+        ),
         DeclTestCase::new("ordinary_func", Decl::function(), |sym, sect| {
             ensure!(sym.is_function(), "symbol is function");
             ensure!(sym.st_bind() == sym::STB_LOCAL, "symbol is local");
@@ -361,3 +375,1632 @@ fn custom_section_symbols() {
         panic!("Elf file not parsed as elf file");
     }
 }
+
+#[cfg(feature = "sparc")]
+#[test]
+fn sparc64_call_relocation() {
+    let mut obj = Artifact::new(triple!("sparc64-unknown-linux-gnu"), "sparc.o".into());
+    obj.declare_with("caller", Decl::function().global(), vec![0; 8])
+        .expect("can declare caller");
+    obj.import("callee", faerie::ImportKind::Function)
+        .expect("can import callee");
+    obj.link(Link {
+        from: "caller",
+        to: "callee",
+        at: 0,
+    })
+    .expect("can link from caller to callee");
+    let bytes = obj.emit().expect("can emit sparc64 elf file");
+
+    match goblin::Object::parse(&bytes).expect("can parse elf file") {
+        goblin::Object::Elf(elf) => {
+            assert_eq!(elf.header.e_machine, header::EM_SPARCV9);
+            assert_eq!(elf.header.e_ident[header::EI_DATA], header::ELFDATA2MSB);
+            assert!(elf.is_64);
+            let relocs = elf
+                .shdr_relocs
+                .iter()
+                .flat_map(|(_, relocs)| relocs.iter())
+                .collect::<Vec<_>>();
+            assert_eq!(relocs.len(), 1);
+            // R_SPARC_WDISP30
+            assert_eq!(relocs[0].r_type, 7);
+            assert_eq!(
+                &elf.strtab[elf.syms.get(relocs[0].r_sym).unwrap().st_name],
+                "callee"
+            );
+        }
+        _ => panic!("Elf file not parsed as elf file"),
+    }
+}
+
+#[test]
+fn got_entries() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.import("malloc", faerie::ImportKind::Function)
+        .expect("can import malloc");
+    obj.declare_got_entry("malloc_got", "malloc")
+        .expect("can declare malloc_got");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let (idx, got) = elf
+        .section_headers
+        .iter()
+        .enumerate()
+        .find(|(_, shdr)| &elf.shdr_strtab[shdr.sh_name] == ".got.malloc_got")
+        .expect("there should be a .got section");
+    assert_eq!(got.sh_size, 8);
+    assert_eq!(got.sh_addralign, 8);
+    assert_ne!(got.sh_flags & u64::from(section_header::SHF_WRITE), 0);
+
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .find(|(target, _)| elf.section_headers[*target].sh_info as usize == idx)
+        .map(|(_, relocs)| relocs.iter().collect::<Vec<_>>())
+        .expect("there should be relocations for .got.malloc_got");
+    assert_eq!(relocs.len(), 1);
+    assert_eq!(relocs[0].r_type, reloc::R_X86_64_64);
+    let sym = elf
+        .syms
+        .get(relocs[0].r_sym)
+        .expect("relocation has a symbol");
+    assert_eq!(&elf.strtab[sym.st_name], "malloc");
+}
+
+#[test]
+fn absolute_symbols() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare("__stack_top", Decl::AbsoluteSymbol(0x2001_0000))
+        .expect("can declare __stack_top");
+    obj.declare_with("reset", Decl::function().global(), vec![0; 8])
+        .expect("can declare reset");
+    obj.link(Link {
+        from: "reset",
+        to: "__stack_top",
+        at: 2,
+    })
+    .expect("can link to __stack_top");
+    assert!(obj.undefined_symbols().is_empty());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let (idx, sym) = elf
+        .syms
+        .iter()
+        .enumerate()
+        .find(|(_, sym)| &elf.strtab[sym.st_name] == "__stack_top")
+        .expect("there should be a __stack_top symbol");
+    assert_eq!(sym.st_shndx, section_header::SHN_ABS as usize);
+    assert_eq!(sym.st_value, 0x2001_0000);
+    assert_eq!(sym.st_type(), sym::STT_NOTYPE);
+    assert_eq!(sym.st_bind(), sym::STB_GLOBAL);
+
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .collect::<Vec<_>>();
+    assert_eq!(relocs.len(), 1);
+    assert_eq!(relocs[0].r_sym, idx);
+}
+
+#[test]
+fn raw_sections() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    let flags = u64::from(section_header::SHT_NOTE) << 32 | u64::from(section_header::SHF_ALLOC);
+    obj.add_raw_section(".note.raw".to_string(), vec![1, 2, 3, 4], flags)
+        .expect("can add raw section");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let raw = elf
+        .section_headers
+        .iter()
+        .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".note.raw")
+        .expect("there should be a .note.raw section");
+    assert_eq!(raw.sh_type, section_header::SHT_NOTE);
+    assert_eq!(raw.sh_flags, u64::from(section_header::SHF_ALLOC));
+    assert_eq!(
+        &bytes[raw.sh_offset as usize..(raw.sh_offset + raw.sh_size) as usize],
+        &[1, 2, 3, 4]
+    );
+    assert!(elf.shdr_relocs.is_empty());
+}
+
+#[cfg(feature = "hexagon")]
+#[test]
+fn hexagon_machine_and_relocations() {
+    let mut obj = Artifact::new(triple!("hexagon-unknown-linux-musl"), "dsp.o".into());
+    obj.declare_with("caller", Decl::function().global(), vec![0; 12])
+        .expect("can declare caller");
+    obj.declare_with("table", Decl::data(), vec![0; 4])
+        .expect("can declare table");
+    obj.import("callee", faerie::ImportKind::Function)
+        .expect("can import callee");
+    obj.link(Link {
+        from: "caller",
+        to: "callee",
+        at: 0,
+    })
+    .expect("can link from caller to callee");
+    obj.link(Link {
+        from: "caller",
+        to: "table",
+        at: 4,
+    })
+    .expect("can link from caller to table");
+    let bytes = obj.emit().expect("can emit hexagon elf file");
+
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    assert_eq!(elf.header.e_machine, 164);
+    assert_eq!(elf.header.e_ident[header::EI_DATA], header::ELFDATA2LSB);
+    assert!(!elf.is_64);
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| (reloc.r_offset, reloc.r_type))
+        .collect::<Vec<_>>();
+    // R_HEX_B22_PCREL, then R_HEX_LO16 and R_HEX_HI16
+    assert_eq!(relocs, vec![(0, 1), (4, 4), (8, 5)]);
+}
+
+#[test]
+fn riscv64_machine_and_relocations() {
+    let mut obj = Artifact::new(triple!("riscv64gc-unknown-linux-gnu"), "rv.o".into());
+    // call callee; auipc a0, %pcrel_hi(counter); addi a0, a0, %pcrel_lo(1b); ret
+    obj.declare_with(
+        "caller",
+        Decl::function().global(),
+        vec![
+            0x97, 0x00, 0x00, 0x00, 0xe7, 0x80, 0x00, 0x00, 0x17, 0x05, 0x00, 0x00, 0x13, 0x05,
+            0x05, 0x00, 0x67, 0x80, 0x00, 0x00,
+        ],
+    )
+    .expect("can declare caller");
+    obj.declare_with("counter", Decl::data().writable(), vec![0; 8])
+        .expect("can declare counter");
+    obj.declare_with("table", Decl::data(), vec![0; 8])
+        .expect("can declare table");
+    obj.import("callee", faerie::ImportKind::Function)
+        .expect("can import callee");
+    obj.link(Link {
+        from: "caller",
+        to: "callee",
+        at: 0,
+    })
+    .expect("can link from caller to callee");
+    obj.link(Link {
+        from: "caller",
+        to: "counter",
+        at: 8,
+    })
+    .expect("can link from caller to counter");
+    obj.link(Link {
+        from: "table",
+        to: "caller",
+        at: 0,
+    })
+    .expect("can link from table to caller");
+    let bytes = obj.emit().expect("can emit riscv64 elf file");
+
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    assert_eq!(elf.header.e_machine, header::EM_RISCV);
+    assert_eq!(elf.header.e_ident[header::EI_DATA], header::ELFDATA2LSB);
+    assert!(elf.is_64);
+    // EF_RISCV_RVC | EF_RISCV_FLOAT_ABI_DOUBLE
+    assert_eq!(elf.header.e_flags, 0x5);
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| {
+            let sym = elf.syms.get(reloc.r_sym).expect("relocation has a symbol");
+            (reloc.r_offset, reloc.r_type, sym)
+        })
+        .collect::<Vec<_>>();
+    let types = relocs
+        .iter()
+        .map(|(offset, typ, _)| (*offset, *typ))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        vec![
+            (0, reloc::R_RISCV_CALL_PLT),
+            (8, reloc::R_RISCV_PCREL_HI20),
+            (12, reloc::R_RISCV_PCREL_LO12_I),
+            (0, reloc::R_RISCV_64),
+        ]
+    );
+    assert_eq!(&elf.strtab[relocs[0].2.st_name], "callee");
+    // the low half refers to a local label at the `auipc` computing the high half
+    let label = &relocs[2].2;
+    assert_eq!(&elf.strtab[label.st_name], ".Lpcrel_hi0");
+    assert_eq!(label.st_bind(), sym::STB_LOCAL);
+    assert_eq!(label.st_value, 8);
+    let caller = elf
+        .syms
+        .iter()
+        .find(|sym| &elf.strtab[sym.st_name] == "caller")
+        .expect("caller is in the symbol table");
+    assert_eq!(label.st_shndx, caller.st_shndx);
+}
+
+#[test]
+fn s390x_machine_and_relocations() {
+    let mut obj = Artifact::new(triple!("s390x-unknown-linux-gnu"), "z.o".into());
+    // brasl %r14, callee; larl %r1, counter; br %r14
+    obj.declare_with(
+        "caller",
+        Decl::function().global(),
+        vec![0xc0, 0xe5, 0, 0, 0, 0, 0xc0, 0x10, 0, 0, 0, 0, 0x07, 0xfe],
+    )
+    .expect("can declare caller");
+    obj.declare_with("counter", Decl::data().writable(), vec![0; 8])
+        .expect("can declare counter");
+    obj.import("callee", faerie::ImportKind::Function)
+        .expect("can import callee");
+    obj.link(Link {
+        from: "caller",
+        to: "callee",
+        at: 2,
+    })
+    .expect("can link from caller to callee");
+    obj.link(Link {
+        from: "caller",
+        to: "counter",
+        at: 8,
+    })
+    .expect("can link from caller to counter");
+    let bytes = obj.emit().expect("can emit s390x elf file");
+
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    assert_eq!(elf.header.e_machine, 22);
+    assert_eq!(elf.header.e_ident[header::EI_DATA], header::ELFDATA2MSB);
+    assert!(elf.is_64);
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| (reloc.r_offset, reloc.r_type, reloc.r_addend))
+        .collect::<Vec<_>>();
+    // R_390_PLT32DBL, then R_390_PC32DBL, relative to the start of each instruction
+    assert_eq!(relocs, vec![(2, 20, Some(2)), (8, 19, Some(2))]);
+}
+
+#[test]
+fn ppc64_machine_and_relocations() {
+    for &(target, data, half) in &[
+        ("powerpc64le-unknown-linux-gnu", header::ELFDATA2LSB, 0),
+        ("powerpc64-unknown-linux-gnu", header::ELFDATA2MSB, 2),
+    ] {
+        let mut obj = Artifact::new(triple!(target), "p.o".into());
+        // bl callee; nop; addis 3, 2, counter@toc@ha; ld 3, counter@toc@l(3);
+        // addis 3, 2, shared@got@ha; ld 3, shared@got@l(3)
+        obj.declare_with("caller", Decl::function().global(), vec![0; 24])
+            .expect("can declare caller");
+        obj.declare_with("counter", Decl::data().writable(), vec![0; 8])
+            .expect("can declare counter");
+        obj.import("callee", faerie::ImportKind::Function)
+            .expect("can import callee");
+        obj.import("shared", faerie::ImportKind::Data)
+            .expect("can import shared");
+        for &(to, at) in &[("callee", 0), ("counter", 8), ("shared", 16)] {
+            obj.link(Link {
+                from: "caller",
+                to,
+                at,
+            })
+            .expect("can link from caller");
+        }
+        obj.link(Link {
+            from: "counter",
+            to: "caller",
+            at: 0,
+        })
+        .expect("can link from counter to caller");
+        let bytes = obj.emit().expect("can emit ppc64 elf file");
+
+        let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+        assert_eq!(elf.header.e_machine, header::EM_PPC64);
+        assert_eq!(elf.header.e_ident[header::EI_DATA], data);
+        assert!(elf.is_64);
+        let relocs = elf
+            .shdr_relocs
+            .iter()
+            .flat_map(|(_, relocs)| relocs.iter())
+            .map(|reloc| (reloc.r_offset, reloc.r_type))
+            .collect::<Vec<_>>();
+        // R_PPC64_REL24, R_PPC64_TOC16_HA and R_PPC64_TOC16_LO_DS, R_PPC64_GOT16_HA and
+        // R_PPC64_GOT16_LO_DS at the immediate halfwords, and R_PPC64_ADDR64, as llvm-mc emits them
+        assert_eq!(
+            relocs,
+            vec![
+                (0, 10),
+                (8 + half, 50),
+                (12 + half, 64),
+                (16 + half, 17),
+                (20 + half, 59),
+                (0, 38)
+            ]
+        );
+    }
+}
+
+/// A CIE for x86_64 whose FDEs encode their initial location as `DW_EH_PE_pcrel | DW_EH_PE_sdata4`,
+/// followed by an FDE for a function of `size` bytes, with a garbage initial location
+fn frame_info(size: u8) -> Vec<u8> {
+    // length, id, version, "zR", code and data alignment factors, return address register,
+    // augmentation data, DW_CFA_def_cfa %rsp+8, DW_CFA_offset %rip at cfa-8, and padding
+    let cie = [
+        0x14, 0, 0, 0, 0, 0, 0, 0, 1, b'z', b'R', 0, 1, 0x78, 0x10, 1, 0x1b, 0x0c, 7, 8, 0x90, 1,
+        0, 0,
+    ];
+    // length, CIE pointer, initial location, range, augmentation data, and padding
+    let fde = [
+        0x14, 0, 0, 0, 0x1c, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, size, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    [&cie[..], &fde[..]].concat()
+}
+
+#[test]
+fn eh_frame() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("x", Decl::data(), vec![0; 4])
+        .expect("can declare x");
+    obj.add_frame_info("f", &frame_info(1))
+        .expect("can add frame info of f");
+    assert!(obj.add_frame_info("g", &frame_info(1)).is_err());
+    assert!(obj.add_frame_info("x", &frame_info(1)).is_err());
+    let bytes = obj.emit().expect("can emit elf file");
+
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let (shndx, eh_frame) = elf
+        .section_headers
+        .iter()
+        .enumerate()
+        .find(|(_, shdr)| &elf.shdr_strtab[shdr.sh_name] == ".eh_frame")
+        .expect("has .eh_frame");
+    assert_eq!(eh_frame.sh_type, section_header::SHT_PROGBITS);
+    assert_eq!(eh_frame.sh_flags, u64::from(section_header::SHF_ALLOC));
+    assert_eq!(eh_frame.sh_size, 48);
+    // the initial location is filled in by the relocation
+    let start = eh_frame.sh_offset as usize;
+    assert_eq!(&bytes[start + 32..start + 36], &[0, 0, 0, 0]);
+    let (_, relocs) = elf
+        .shdr_relocs
+        .iter()
+        .find(|(idx, _)| elf.section_headers[*idx].sh_info as usize == shndx)
+        .expect("has .rela.eh_frame");
+    let relocs = relocs
+        .iter()
+        .map(|reloc| {
+            let sym = elf.syms.get(reloc.r_sym).expect("relocation has a symbol");
+            (reloc.r_offset, reloc.r_type, &elf.strtab[sym.st_name])
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(relocs, vec![(32, reloc::R_X86_64_PC32, "f")]);
+
+    let mut obj = Artifact::new(triple!("riscv64gc-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0; 4])
+        .expect("can declare f");
+    obj.add_frame_info("f", &frame_info(4))
+        .expect("can add frame info of f");
+    match obj.emit() {
+        Err(faerie::ArtifactError::Unsupported(_)) => {}
+        result => panic!("emitted unwinding information for riscv: {:?}", result),
+    }
+}
+
+#[test]
+fn ifunc() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("resolve_strlen", Decl::function(), vec![0xc3; 4])
+        .expect("can declare resolve_strlen");
+    obj.declare_ifunc("strlen", "resolve_strlen")
+        .expect("can declare strlen");
+    obj.declare_with("main", Decl::function().global(), vec![0; 8])
+        .expect("can declare main");
+    obj.link(Link {
+        from: "main",
+        to: "strlen",
+        at: 1,
+    })
+    .expect("can link main to strlen");
+    assert!(obj.declare_ifunc("memcpy", "resolve_memcpy").is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let find = |name| {
+        elf.syms
+            .iter()
+            .enumerate()
+            .find(|(_, sym)| &elf.strtab[sym.st_name] == name)
+            .unwrap_or_else(|| panic!("there should be a {} symbol", name))
+    };
+    let (_, resolver) = find("resolve_strlen");
+    let (idx, strlen) = find("strlen");
+    assert_eq!(strlen.st_type(), sym::STT_GNU_IFUNC);
+    assert_eq!(strlen.st_bind(), sym::STB_GLOBAL);
+    assert_eq!(strlen.st_shndx, resolver.st_shndx);
+    assert_eq!(strlen.st_value, resolver.st_value);
+
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .collect::<Vec<_>>();
+    assert_eq!(relocs.len(), 1);
+    assert_eq!(relocs[0].r_type, reloc::R_X86_64_PLT32);
+    assert_eq!(relocs[0].r_sym, idx);
+}
+
+#[test]
+fn removing_a_resolver_removes_its_ifuncs() {
+    let declare = || {
+        let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+        obj.declare_with("resolver", Decl::function(), vec![0xc3; 4])
+            .expect("can declare resolver");
+        obj.declare_ifunc("f", "resolver").expect("can declare f");
+        obj.declare_with("main", Decl::function().global(), vec![0; 8])
+            .expect("can declare main");
+        obj.link(Link {
+            from: "main",
+            to: "f",
+            at: 1,
+        })
+        .expect("can link main to f");
+        obj
+    };
+    let symbols = |obj: &Artifact| {
+        let bytes = obj.emit().expect("can emit elf file");
+        let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+        elf.syms
+            .iter()
+            .map(|sym| elf.strtab[sym.st_name].to_string())
+            .filter(|name| !name.is_empty() && name != "t.o")
+            .collect::<Vec<_>>()
+    };
+
+    // the link to f goes along with it
+    let mut obj = declare();
+    assert_eq!(
+        obj.remove_definition("resolver")
+            .expect("can remove resolver"),
+        1
+    );
+    assert_eq!(symbols(&obj), vec!["main"]);
+
+    let mut obj = declare();
+    assert_eq!(obj.remove_definition("f").expect("can remove f"), 1);
+    assert_eq!(symbols(&obj), vec!["resolver", "main"]);
+    assert_eq!(
+        obj.remove_definition("resolver")
+            .expect("can remove resolver"),
+        0
+    );
+    assert_eq!(symbols(&obj), vec!["main"]);
+}
+
+#[test]
+fn gc_sections_keeps_ifuncs_with_their_resolvers() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0; 8])
+        .expect("can declare main");
+    obj.declare_with("resolver", Decl::function(), vec![0xc3; 4])
+        .expect("can declare resolver");
+    obj.declare_ifunc("f", "resolver").expect("can declare f");
+    obj.declare_with("resolve_g", Decl::function(), vec![0xc3; 2])
+        .expect("can declare resolve_g");
+    obj.declare_ifunc("g", "resolve_g").expect("can declare g");
+    obj.link(Link {
+        from: "main",
+        to: "g",
+        at: 1,
+    })
+    .expect("can link main to g");
+
+    let mut report = obj.gc_sections(&["main"]).expect("can collect");
+    report.removed_symbols.sort();
+    assert_eq!(
+        report,
+        faerie::ArtifactGcReport {
+            removed_symbols: vec!["f".to_string(), "resolver".to_string()],
+            bytes_saved: 4,
+        }
+    );
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let symbols = elf
+        .syms
+        .iter()
+        .map(|sym| &elf.strtab[sym.st_name])
+        .filter(|name| !name.is_empty() && *name != "t.o")
+        .collect::<Vec<_>>();
+    assert_eq!(symbols, vec!["resolve_g", "main", "g"]);
+}
+
+#[test]
+fn declare_with_size() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with_size("table", Decl::data().global(), 64)
+        .expect("can declare table");
+    match obj.define("table", vec![1; 16]) {
+        Err(faerie::ArtifactError::InvalidSize {
+            name,
+            size,
+            defined,
+        }) => assert_eq!((name.as_str(), size, defined), ("table", 64, 16)),
+        result => panic!("unexpected result {:?}", result),
+    }
+    obj.define("table", vec![1; 64]).expect("can define table");
+    obj.declare_with("buf", Decl::data().global(), vec![0; 8])
+        .expect("can declare buf");
+    match obj.declare_with_size("buf", Decl::data().global(), 16) {
+        Err(faerie::ArtifactError::InvalidSize { name, .. }) => assert_eq!(name, "buf"),
+        result => panic!("unexpected result {:?}", result),
+    }
+    obj.declare_with_size("buf", Decl::data().global(), 4)
+        .expect("can declare the size of buf");
+    obj.declare_with("f", Decl::function().global(), vec![0xc3; 3])
+        .expect("can declare f");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let size = |name| {
+        elf.syms
+            .iter()
+            .find(|sym| &elf.strtab[sym.st_name] == name)
+            .unwrap_or_else(|| panic!("there should be a {} symbol", name))
+            .st_size
+    };
+    assert_eq!(size("table"), 64);
+    assert_eq!(size("buf"), 4);
+    assert_eq!(size("f"), 3);
+}
+
+#[test]
+fn odr_table() {
+    use scroll::{Pread, LE};
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.add_odr_entry("Foo", 0x1234_5678_9abc_def0)
+        .expect("can add odr entry");
+    obj.add_odr_entry("ns::Bar", 42).expect("can add odr entry");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let odrtab = elf
+        .section_headers
+        .iter()
+        .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".llvm_odrtab")
+        .expect("there should be a .llvm_odrtab section");
+    assert_eq!(odrtab.sh_type, 0x6fff_4c00);
+    assert_eq!(odrtab.sh_entsize, 16);
+    assert_eq!(odrtab.sh_size, 32);
+    let strtab = &elf.section_headers[odrtab.sh_link as usize];
+    assert_eq!(strtab.sh_type, section_header::SHT_STRTAB);
+
+    let table = &bytes[odrtab.sh_offset as usize..(odrtab.sh_offset + odrtab.sh_size) as usize];
+    let entries = table
+        .chunks(16)
+        .map(|entry| {
+            let name_offset: u64 = entry.pread_with(0, LE).unwrap();
+            let hash: u64 = entry.pread_with(8, LE).unwrap();
+            (&elf.strtab[name_offset as usize], hash)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        entries,
+        vec![("Foo", 0x1234_5678_9abc_def0), ("ns::Bar", 42)]
+    );
+}
+
+#[test]
+fn syntax_notes() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("intel", Decl::function().intel_syntax(), vec![0xc3])
+        .expect("can declare intel");
+    obj.declare_with("att", Decl::function().global().att_syntax(), vec![0xc3])
+        .expect("can declare att");
+    obj.declare_with("plain", Decl::function(), vec![0xc3])
+        .expect("can declare plain");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let notes = elf
+        .iter_note_sections(&bytes, Some(".note.syntax"))
+        .expect("there should be a .note.syntax section")
+        .map(|note| {
+            let note = note.expect("can parse note");
+            assert_eq!(note.name, "faerie");
+            (note.n_type, note.desc.to_vec())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        notes,
+        vec![(1, b"intel\0".to_vec()), (2, b"att\0".to_vec())]
+    );
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("plain", Decl::function(), vec![0xc3])
+        .expect("can declare plain");
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    assert!(elf
+        .iter_note_sections(&bytes, Some(".note.syntax"))
+        .is_none());
+}
+
+#[test]
+fn debuglink() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.set_debuglink("t.debug", 0xdead_beef)
+        .expect("can set debuglink");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let debuglink = elf
+        .section_headers
+        .iter()
+        .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".gnu_debuglink")
+        .expect("there should be a .gnu_debuglink section");
+    assert_eq!(debuglink.sh_type, section_header::SHT_PROGBITS);
+    assert_eq!(debuglink.sh_addralign, 4);
+    assert_eq!(
+        &bytes[debuglink.sh_offset as usize..(debuglink.sh_offset + debuglink.sh_size) as usize],
+        b"t.debug\0\xef\xbe\xad\xde"
+    );
+}
+
+#[test]
+fn build_id() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.set_build_id(&[0xde, 0xad, 0xbe, 0xef, 0x42])
+        .expect("can set build id");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let notes = elf
+        .iter_note_sections(&bytes, Some(".note.gnu.build-id"))
+        .expect("there should be a .note.gnu.build-id section")
+        .map(|note| note.expect("can parse note"))
+        .collect::<Vec<_>>();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].n_type, goblin::elf::note::NT_GNU_BUILD_ID);
+    assert_eq!(notes[0].name, "GNU");
+    assert_eq!(notes[0].desc, &[0xde, 0xad, 0xbe, 0xef, 0x42]);
+}
+
+#[test]
+fn comment() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.add_comment("faerie 0.16").expect("can add comment");
+    obj.add_comment("rustc 1.40").expect("can add comment");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let comment = elf
+        .section_headers
+        .iter()
+        .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".comment")
+        .expect("there should be a .comment section");
+    assert_eq!(comment.sh_type, section_header::SHT_PROGBITS);
+    assert_eq!(
+        comment.sh_flags,
+        u64::from(section_header::SHF_MERGE | section_header::SHF_STRINGS)
+    );
+    assert_eq!(comment.sh_entsize, 1);
+    assert_eq!(
+        &bytes[comment.sh_offset as usize..(comment.sh_offset + comment.sh_size) as usize],
+        b"faerie 0.16\0rustc 1.40\0"
+    );
+}
+
+#[test]
+fn merge_strings() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("s", Decl::cstring().merge_strings(), b"hello\0".to_vec())
+        .expect("can declare s");
+    obj.declare_with("t", Decl::cstring(), b"world\0".to_vec())
+        .expect("can declare t");
+    obj.declare_with("f", Decl::function().global(), vec![0; 8])
+        .expect("can declare f");
+    obj.link(Link {
+        from: "f",
+        to: "s",
+        at: 3,
+    })
+    .expect("can link f to s");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let section = |name| {
+        elf.section_headers
+            .iter()
+            .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name)
+            .unwrap_or_else(|| panic!("there should be a {} section", name))
+    };
+    let merged = section(".rodata.str1.1.s");
+    let flags = section_header::SHF_ALLOC | section_header::SHF_MERGE | section_header::SHF_STRINGS;
+    assert_eq!(merged.sh_flags, u64::from(flags));
+    assert_eq!(merged.sh_entsize, 1);
+    assert_eq!(section(".rodata.t").sh_entsize, 1);
+
+    // the relocation refers to the string's symbol, so that the linker knows which one it is
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .collect::<Vec<_>>();
+    assert_eq!(relocs.len(), 1);
+    let sym = elf
+        .syms
+        .get(relocs[0].r_sym)
+        .expect("relocation has a symbol");
+    assert_eq!(&elf.strtab[sym.st_name], "s");
+}
+
+#[test]
+fn weak_imports() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3])
+        .expect("can declare f");
+    obj.declare("maybe_missing", Decl::weak_function_import())
+        .expect("can declare maybe_missing");
+    obj.import("malloc", faerie::ImportKind::Function)
+        .expect("can import malloc");
+    obj.link(Link {
+        from: "f",
+        to: "maybe_missing",
+        at: 1,
+    })
+    .expect("can link from f to maybe_missing");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let binding = |name: &str| {
+        elf.syms
+            .iter()
+            .find(|sym| &elf.strtab[sym.st_name] == name)
+            .map(|sym| (sym.st_bind(), sym.st_shndx))
+            .expect("symbol is present")
+    };
+    assert_eq!(binding("maybe_missing"), (sym::STB_WEAK, 0));
+    assert_eq!(binding("malloc"), (sym::STB_GLOBAL, 0));
+}
+
+#[test]
+fn address_space() {
+    let mut obj = Artifact::new(triple!("msp430-none-elf"), "t.o".into());
+    obj.declare_with(
+        ".fardata",
+        Decl::section(faerie::SectionKind::Data)
+            .with_address_space(3)
+            .expect("address space fits"),
+        vec![1, 2, 3, 4],
+    )
+    .expect("can declare .fardata");
+    obj.declare_with(
+        ".neardata",
+        Decl::section(faerie::SectionKind::Data),
+        vec![5, 6],
+    )
+    .expect("can declare .neardata");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let flags = |name: &str| {
+        elf.section_headers
+            .iter()
+            .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name)
+            .map(|shdr| shdr.sh_flags & u64::from(section_header::SHF_MASKPROC))
+            .expect("section is present")
+    };
+    assert_eq!(flags(".fardata"), 3 << 28);
+    assert_eq!(flags(".neardata"), 0);
+
+    match Decl::section(faerie::SectionKind::Data).with_address_space(16) {
+        Err(faerie::ArtifactError::InvalidAddressSpace(_)) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    // the processor specific flags of x86_64 sections already have a meaning
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with(
+        ".fardata",
+        Decl::section(faerie::SectionKind::Data)
+            .with_address_space(1)
+            .expect("address space fits"),
+        vec![1, 2, 3, 4],
+    )
+    .expect("can declare .fardata");
+    match obj.emit() {
+        Err(faerie::ArtifactError::InvalidAddressSpace(_)) => {}
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn link_data_to_symbol() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("g", Decl::function().global(), vec![0xc3])
+        .expect("can declare g");
+    obj.declare_with("vtable", Decl::data().global(), vec![0; 16])
+        .expect("can declare vtable");
+    obj.link_data_to_symbol("vtable", 0, "f", 0)
+        .expect("can link vtable to f");
+    obj.link_data_to_symbol("vtable", 8, "g", 4)
+        .expect("can link vtable to g");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let (idx, _) = elf
+        .section_headers
+        .iter()
+        .enumerate()
+        .find(|(_, shdr)| &elf.shdr_strtab[shdr.sh_name] == ".rodata.vtable")
+        .expect("there should be a .rodata.vtable section");
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .find(|(target, _)| elf.section_headers[*target].sh_info as usize == idx)
+        .map(|(_, relocs)| relocs.iter().collect::<Vec<_>>())
+        .expect("there should be relocations for .rodata.vtable");
+    let relocs = relocs
+        .iter()
+        .map(|reloc| {
+            let sym = elf.syms.get(reloc.r_sym).expect("relocation has a symbol");
+            let target = if sym.st_type() == sym::STT_SECTION {
+                &elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name]
+            } else {
+                &elf.strtab[sym.st_name]
+            };
+            (reloc.r_offset, reloc.r_type, target, reloc.r_addend)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        relocs,
+        vec![
+            (0, reloc::R_X86_64_64, ".text.f", Some(0)),
+            (8, reloc::R_X86_64_64, ".text.g", Some(4)),
+        ]
+    );
+
+    for &(target, expected) in &[
+        ("i686-unknown-linux-gnu", reloc::R_386_32),
+        ("aarch64-unknown-linux-gnu", reloc::R_AARCH64_ABS64),
+        ("armv7-unknown-linux-gnueabihf", reloc::R_ARM_ABS32),
+    ] {
+        let mut obj = Artifact::new(triple!(target), "t.o".into());
+        obj.declare_with("f", Decl::function().global(), vec![0; 4])
+            .expect("can declare f");
+        obj.declare_with("vtable", Decl::data().global(), vec![0; 8])
+            .expect("can declare vtable");
+        obj.link_data_to_symbol("vtable", 0, "f", 0)
+            .expect("can link vtable to f");
+        let bytes = obj.emit().expect("can emit elf file");
+        let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+        let types = elf
+            .shdr_relocs
+            .iter()
+            .flat_map(|(_, relocs)| relocs.iter())
+            .map(|reloc| reloc.r_type)
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec![expected], "{}", target);
+    }
+
+    let mut obj = Artifact::new(triple!("mips-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0; 4])
+        .expect("can declare f");
+    obj.declare_with("vtable", Decl::data().global(), vec![0; 4])
+        .expect("can declare vtable");
+    obj.link_data_to_symbol("vtable", 0, "f", 0)
+        .expect("can link vtable to f");
+    match obj.emit() {
+        Err(faerie::ArtifactError::UnsupportedRelocation(_)) => {}
+        result => panic!("emitted a mips pointer: {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn weak_definitions_are_not_local() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("inline_fn", Decl::weak_function(), vec![0xc3])
+        .expect("can declare inline_fn");
+    obj.declare_with("local", Decl::data(), vec![0; 8])
+        .expect("can declare local");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let symtab = elf
+        .section_headers
+        .iter()
+        .find(|shdr| shdr.sh_type == section_header::SHT_SYMTAB)
+        .expect("there should be a symbol table");
+    // sh_info is the index of the first non-local symbol, and all locals must precede it
+    for (idx, sym) in elf.syms.iter().enumerate() {
+        let name = &elf.strtab[sym.st_name];
+        assert_eq!(
+            sym.st_bind() == sym::STB_LOCAL,
+            idx < symtab.sh_info as usize,
+            "{} is on the wrong side of sh_info",
+            name
+        );
+        if name == "inline_fn" {
+            assert_eq!(sym.st_bind(), sym::STB_WEAK);
+        }
+    }
+}
+
+#[test]
+fn thread_local_variables() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    // data16 leaq x@tlsgd(%rip), %rdi; ...
+    obj.declare_with("get_x", Decl::function().global(), vec![0; 16])
+        .expect("can declare get_x");
+    // movq y@gottpoff(%rip), %rax; movl %fs:(%rax), %eax; ret
+    obj.declare_with(
+        "get_y",
+        Decl::function().global(),
+        vec![0x48, 0x8b, 0x05, 0, 0, 0, 0, 0x64, 0x8b, 0x00, 0xc3],
+    )
+    .expect("can declare get_y");
+    obj.declare_with("x", Decl::tls_data().global(), vec![42, 0, 0, 0])
+        .expect("can declare x");
+    obj.declare(
+        "y",
+        Decl::tls_data()
+            .global()
+            .with_tls_model(faerie::TlsModel::InitialExec),
+    )
+    .expect("can declare y");
+    obj.define_zero_init("y", 16).expect("can define y");
+    // leaq z@tlsld(%rip), %rdi; call __tls_get_addr@PLT; leaq z@dtpoff(%rax), %rax; ret
+    obj.declare_with(
+        "get_z",
+        Decl::function().global(),
+        vec![
+            0x48, 0x8d, 0x3d, 0, 0, 0, 0, 0xe8, 0, 0, 0, 0, 0x48, 0x8d, 0x80, 0, 0, 0, 0, 0xc3,
+        ],
+    )
+    .expect("can declare get_z");
+    obj.import("__tls_get_addr", faerie::ImportKind::Function)
+        .expect("can import __tls_get_addr");
+    obj.declare_with(
+        "z",
+        Decl::tls_data()
+            .local()
+            .with_tls_model(faerie::TlsModel::LocalDynamic),
+        vec![7, 0, 0, 0],
+    )
+    .expect("can declare z");
+    obj.link_with(
+        Link {
+            from: "get_z",
+            to: "z",
+            at: 3,
+        },
+        faerie::Reloc::Kind(faerie::RelocationKind::GotPcRelative),
+    )
+    .expect("can link from get_z to the module of z");
+    obj.link(Link {
+        from: "get_z",
+        to: "__tls_get_addr",
+        at: 8,
+    })
+    .expect("can link from get_z to __tls_get_addr");
+    obj.link(Link {
+        from: "get_z",
+        to: "z",
+        at: 15,
+    })
+    .expect("can link from get_z to z");
+    obj.link(Link {
+        from: "get_x",
+        to: "x",
+        at: 4,
+    })
+    .expect("can link from get_x to x");
+    obj.link(Link {
+        from: "get_y",
+        to: "y",
+        at: 3,
+    })
+    .expect("can link from get_y to y");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let section = |name: &str| {
+        elf.section_headers
+            .iter()
+            .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == name)
+            .expect("section is present")
+    };
+    let tdata = section(".tdata.x");
+    assert_eq!(tdata.sh_type, section_header::SHT_PROGBITS);
+    assert_ne!(tdata.sh_flags & u64::from(section_header::SHF_TLS), 0);
+    let tbss = section(".tbss.y");
+    assert_eq!(tbss.sh_type, section_header::SHT_NOBITS);
+    assert_ne!(tbss.sh_flags & u64::from(section_header::SHF_TLS), 0);
+    assert_eq!(tbss.sh_size, 16);
+    for name in &["x", "y"] {
+        let sym = elf
+            .syms
+            .iter()
+            .find(|sym| &elf.strtab[sym.st_name] == *name)
+            .expect("symbol is present");
+        assert_eq!(sym.st_type(), sym::STT_TLS);
+    }
+
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| {
+            let sym = elf.syms.get(reloc.r_sym).expect("relocation has a symbol");
+            (&elf.strtab[sym.st_name], reloc.r_type, reloc.r_addend)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        relocs,
+        vec![
+            ("z", reloc::R_X86_64_TLSLD, Some(-4)),
+            ("__tls_get_addr", reloc::R_X86_64_PLT32, Some(-4)),
+            ("z", reloc::R_X86_64_DTPOFF32, Some(0)),
+            ("x", reloc::R_X86_64_TLSGD, Some(-4)),
+            ("y", reloc::R_X86_64_GOTTPOFF, Some(-4)),
+        ]
+    );
+}
+
+#[test]
+fn comdat_groups() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("f", Decl::function().global().comdat(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("g", Decl::function().global(), vec![0xc3])
+        .expect("can declare g");
+    // call g; ret
+    obj.declare_with(
+        "h",
+        Decl::function().global().comdat(),
+        vec![0xe8, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare h");
+    obj.link(Link {
+        from: "h",
+        to: "g",
+        at: 1,
+    })
+    .expect("can link h to g");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let index = |name: &str| {
+        elf.section_headers
+            .iter()
+            .position(|shdr| &elf.shdr_strtab[shdr.sh_name] == name)
+            .expect("section is present")
+    };
+    let members = |name: &str| {
+        let group = &elf.section_headers[index(name)];
+        assert_eq!(group.sh_type, section_header::SHT_GROUP);
+        assert_eq!(group.sh_entsize, 4);
+        let signature = elf
+            .syms
+            .get(group.sh_info as usize)
+            .expect("group signature is a symbol");
+        assert_eq!(&elf.strtab[signature.st_name], &name[".group.".len()..]);
+        let offset = group.sh_offset as usize;
+        bytes[offset..offset + group.sh_size as usize]
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(members(".group.f"), vec![1, index(".text.f") as u32]);
+    // the relocations of a member are members too
+    assert_eq!(
+        members(".group.h"),
+        vec![1, index(".text.h") as u32, index(".rela.text.h") as u32]
+    );
+
+    let is_group_member = |name: &str| {
+        elf.section_headers[index(name)].sh_flags & u64::from(section_header::SHF_GROUP) != 0
+    };
+    assert!(is_group_member(".text.f"));
+    assert!(is_group_member(".text.h"));
+    assert!(is_group_member(".rela.text.h"));
+    assert!(!is_group_member(".text.g"));
+
+    // the signature of a group must be visible to other objects
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare_with("f", Decl::function().comdat(), vec![0xc3])
+        .expect("can declare f");
+    match obj.emit() {
+        Err(faerie::ArtifactError::Unsupported(_)) => {}
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn common_symbols() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-unknown-unknown-elf"), "t.o".into());
+    obj.declare("buf", Decl::common(16, 8))
+        .expect("can declare buf");
+    obj.declare("buf", Decl::common(64, 4))
+        .expect("can declare buf again");
+    // movq buf@GOTPCREL(%rip), %rax; ret
+    obj.declare_with(
+        "f",
+        Decl::function().global(),
+        vec![0x48, 0x8b, 0x05, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare f");
+    obj.link(Link {
+        from: "f",
+        to: "buf",
+        at: 3,
+    })
+    .expect("can link to buf");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let (idx, sym) = elf
+        .syms
+        .iter()
+        .enumerate()
+        .find(|(_, sym)| &elf.strtab[sym.st_name] == "buf")
+        .expect("there should be a buf symbol");
+    // the value of a common symbol is its alignment
+    assert_eq!(sym.st_shndx, section_header::SHN_COMMON as usize);
+    assert_eq!(sym.st_value, 8);
+    assert_eq!(sym.st_size, 64);
+    assert_eq!(sym.st_type(), sym::STT_OBJECT);
+    assert_eq!(sym.st_bind(), sym::STB_GLOBAL);
+
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .collect::<Vec<_>>();
+    assert_eq!(relocs.len(), 1);
+    assert_eq!(relocs[0].r_sym, idx);
+    assert_eq!(relocs[0].r_type, reloc::R_X86_64_GOTPCREL);
+}
+
+#[test]
+fn merge() {
+    let target = triple!("x86_64-unknown-linux-gnu");
+    // callq helper; movl counter(%rip), %eax; retq
+    let mut main = Artifact::new(target.clone(), "main.o".into());
+    main.declare_with(
+        "main",
+        Decl::function().global(),
+        vec![0xe8, 0, 0, 0, 0, 0x8b, 0x05, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare main");
+    main.declare_with("counter", Decl::data().writable(), vec![1, 0, 0, 0])
+        .expect("can declare counter");
+    main.declare("helper", Decl::function_import())
+        .expect("can import helper");
+    main.link(Link {
+        from: "main",
+        to: "helper",
+        at: 1,
+    })
+    .expect("can link from main to helper");
+    main.link(Link {
+        from: "main",
+        to: "counter",
+        at: 7,
+    })
+    .expect("can link from main to counter");
+
+    // movl counter(%rip), %eax; retq
+    let mut helper = Artifact::new(target, "helper.o".into());
+    helper
+        .declare_with(
+            "helper",
+            Decl::function().global(),
+            vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3],
+        )
+        .expect("can declare helper");
+    helper
+        .declare_with("counter", Decl::data().writable(), vec![2, 0, 0, 0])
+        .expect("can declare counter");
+    helper
+        .link(Link {
+            from: "helper",
+            to: "counter",
+            at: 2,
+        })
+        .expect("can link from helper to counter");
+
+    let merged = main.merge(helper).expect("can merge");
+    assert!(merged.undefined_symbols().is_empty());
+    let bytes = merged.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let symbol = |name: &str| {
+        elf.syms
+            .iter()
+            .find(|sym| &elf.strtab[sym.st_name] == name)
+            .expect("symbol is present")
+    };
+    // the import of helper is satisfied by the other artifact's definition
+    assert_eq!(symbol("helper").st_type(), sym::STT_FUNC);
+    assert_ne!(
+        symbol("helper").st_shndx,
+        section_header::SHN_UNDEF as usize
+    );
+    // each artifact keeps its own local counter
+    let counter = symbol("counter");
+    let other_counter = symbol("counter.merged.1");
+    let data_of = |sym: goblin::elf::Sym| {
+        let section = &elf.section_headers[sym.st_shndx];
+        let offset = (section.sh_offset + sym.st_value) as usize;
+        bytes[offset]
+    };
+    assert_eq!(data_of(counter), 1);
+    assert_eq!(data_of(other_counter), 2);
+
+    // every relocation refers to the section of the definition in the artifact it came from
+    let mut relocs = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| {
+            let sym = elf.syms.get(reloc.r_sym).expect("relocation has a symbol");
+            let name = if sym.st_type() == sym::STT_SECTION {
+                elf.shdr_strtab[elf.section_headers[sym.st_shndx].sh_name].to_string()
+            } else {
+                elf.strtab[sym.st_name].to_string()
+            };
+            (reloc.r_offset, name)
+        })
+        .collect::<Vec<_>>();
+    relocs.sort();
+    assert_eq!(
+        relocs,
+        vec![
+            (1, ".text.helper".to_string()),
+            (2, ".data.counter.merged.1".to_string()),
+            (7, ".data.counter".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn sections() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0x90, 0xc3])
+        .expect("can declare f");
+    obj.declare_with(
+        "value",
+        Decl::data().global().with_align(Some(8)),
+        vec![1; 8],
+    )
+    .expect("can declare value");
+
+    let sections = obj.sections().expect("can list sections");
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    for name in &[".text.f", ".rodata.value"] {
+        let info = sections
+            .iter()
+            .find(|info| info.name == *name)
+            .expect("section is listed");
+        let shdr = elf
+            .section_headers
+            .iter()
+            .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == *name)
+            .expect("section is emitted");
+        assert_eq!(info.segment, "");
+        assert_eq!(info.file_offset, shdr.sh_offset as usize);
+        assert_eq!(info.size, shdr.sh_size as usize);
+        assert_eq!(info.align, shdr.sh_addralign as usize);
+    }
+    let value = sections
+        .iter()
+        .find(|info| info.name == ".rodata.value")
+        .unwrap();
+    assert_eq!(value.align, 8);
+    assert_eq!(&bytes[value.file_offset..value.file_offset + 8], &[1; 8]);
+}
+
+#[test]
+fn link_with_addend() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    // movl pair+8(%rip), %eax; retq
+    obj.declare_with(
+        "f",
+        Decl::function().global(),
+        vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare f");
+    obj.declare_with("pair", Decl::data().global().writable(), vec![1; 16])
+        .expect("can declare pair");
+    obj.link_with_addend(
+        Link {
+            from: "f",
+            to: "pair",
+            at: 2,
+        },
+        8,
+    )
+    .expect("can link to pair+8");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .collect::<Vec<_>>();
+    assert_eq!(relocs.len(), 1);
+    // the link's addend is added to the usual -4 of a pc-relative relocation
+    assert_eq!(relocs[0].r_type, reloc::R_X86_64_PC32);
+    assert_eq!(relocs[0].r_addend, Some(4));
+    // and the relocated bytes are left alone
+    let text = elf
+        .section_headers
+        .iter()
+        .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".text.f")
+        .expect("f has a section");
+    let start = text.sh_offset as usize;
+    assert_eq!(&bytes[start + 2..start + 6], &[0; 4]);
+}
+
+#[test]
+fn section_flags() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.set_section_flags(".text.f", u64::from(section_header::SHF_WRITE))
+        .expect("can set the flags of .text.f");
+
+    // the section is only looked up when the object is emitted
+    let mut missing = obj.clone();
+    missing
+        .set_section_flags(".text.g", 0)
+        .expect("can set the flags of .text.g");
+    match missing.emit() {
+        Err(faerie::ArtifactError::NoSuchSection(name)) => assert_eq!(name, ".text.g"),
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+    // the flags of 32 bit ELF are 32 bits wide
+    let mut elf32 = Artifact::new(triple!("i686-unknown-linux-gnu"), "t.o".into());
+    assert!(elf32.set_section_flags(".text.f", 1 << 32).is_err());
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let text = elf
+        .section_headers
+        .iter()
+        .find(|shdr| &elf.shdr_strtab[shdr.sh_name] == ".text.f")
+        .expect("f has a section");
+    // the flags are added to the usual ones
+    assert_eq!(
+        text.sh_flags,
+        u64::from(
+            section_header::SHF_ALLOC | section_header::SHF_EXECINSTR | section_header::SHF_WRITE
+        )
+    );
+}
+
+#[test]
+fn link_with_reloc() {
+    use faerie::RelocationKind;
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    // movq x@GOTPCREL(%rip), %rax; movl y(%rip), %eax; retq
+    obj.declare_with(
+        "f",
+        Decl::function().global(),
+        vec![0x48, 0x8b, 0x05, 0, 0, 0, 0, 0x8b, 0x05, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare f");
+    obj.declare_with("x", Decl::data().global().writable(), vec![1; 4])
+        .expect("can declare x");
+    obj.declare_with("y", Decl::data().global().writable(), vec![2; 4])
+        .expect("can declare y");
+    obj.link_with_reloc(
+        Link {
+            from: "f",
+            to: "x",
+            at: 3,
+        },
+        Some(RelocationKind::GotPcRelative),
+    )
+    .expect("can link f to x through the GOT");
+    obj.link_with_reloc(
+        Link {
+            from: "f",
+            to: "y",
+            at: 9,
+        },
+        None,
+    )
+    .expect("can link f to y");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .flat_map(|(_, relocs)| relocs.iter())
+        .map(|reloc| (reloc.r_offset, reloc.r_type, reloc.r_addend))
+        .collect::<Vec<_>>();
+    // without a kind, the relocation is deduced as usual
+    assert_eq!(
+        relocs,
+        vec![
+            (3, reloc::R_X86_64_GOTPCREL, Some(-4)),
+            (9, reloc::R_X86_64_PC32, Some(-4)),
+        ]
+    );
+
+    let mut obj = Artifact::new(triple!("aarch64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0; 4])
+        .expect("can declare f");
+    obj.import("g", faerie::ImportKind::Function)
+        .expect("can import g");
+    obj.link_with_reloc(
+        Link {
+            from: "f",
+            to: "g",
+            at: 0,
+        },
+        Some(RelocationKind::Branch),
+    )
+    .expect("can link f to g");
+    match obj.emit() {
+        Err(faerie::ArtifactError::UnsupportedRelocation(_)) => {}
+        result => panic!("emitted an aarch64 branch: {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn pic() {
+    let build = |pic: bool| {
+        let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+        obj.set_pic(pic).expect("can set pic");
+        // movq x@GOTPCREL(%rip), %rax; movl y(%rip), %eax; movl z(%rip), %eax; call g; retq
+        obj.declare_with(
+            "f",
+            Decl::function().global(),
+            vec![
+                0x48, 0x8b, 0x05, 0, 0, 0, 0, 0x8b, 0x05, 0, 0, 0, 0, 0x8b, 0x05, 0, 0, 0, 0, 0xe8,
+                0, 0, 0, 0, 0xc3,
+            ],
+        )
+        .expect("can declare f");
+        obj.declare_with("x", Decl::data().global().writable(), vec![1; 4])
+            .expect("can declare x");
+        obj.declare_with(
+            "y",
+            Decl::data()
+                .global()
+                .with_visibility(faerie::Visibility::Hidden),
+            vec![2; 4],
+        )
+        .expect("can declare y");
+        obj.declare_with("z", Decl::data().local(), vec![3; 4])
+            .expect("can declare z");
+        obj.declare_with("g", Decl::function().global(), vec![0xc3])
+            .expect("can declare g");
+        for &(to, at) in &[("x", 3), ("y", 9), ("z", 15), ("g", 20)] {
+            obj.link(Link { from: "f", to, at })
+                .expect("can link from f");
+        }
+        let bytes = obj.emit().expect("can emit elf file");
+        let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+        assert_eq!(elf.header.e_type, goblin::elf::header::ET_REL);
+        elf.shdr_relocs
+            .iter()
+            .flat_map(|(_, relocs)| relocs.iter())
+            .map(|reloc| (reloc.r_offset, reloc.r_type))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        build(false),
+        vec![
+            (3, reloc::R_X86_64_PC32),
+            (9, reloc::R_X86_64_PC32),
+            (15, reloc::R_X86_64_PC32),
+            (20, reloc::R_X86_64_PLT32),
+        ]
+    );
+    // only the data which may be preempted is referenced through the GOT
+    assert_eq!(
+        build(true),
+        vec![
+            (3, 41), // R_X86_64_GOTPCRELX
+            (9, reloc::R_X86_64_PC32),
+            (15, reloc::R_X86_64_PC32),
+            (20, reloc::R_X86_64_PLT32),
+        ]
+    );
+}
+
+#[test]
+fn entry_point() {
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0x90; 5])
+        .expect("can declare f");
+    obj.declare_with("start", Decl::function().global(), vec![0xc3])
+        .expect("can declare start");
+    obj.declare("later", Decl::function().global())
+        .expect("can declare later");
+    obj.import("puts", faerie::ImportKind::Function)
+        .expect("can import puts");
+    obj.declare_with("x", Decl::data(), vec![0; 4])
+        .expect("can declare x");
+    obj.declare_with(
+        ".note",
+        Decl::section(faerie::SectionKind::Data),
+        vec![0; 4],
+    )
+    .expect("can declare .note");
+
+    match obj.set_entry_point("main") {
+        Err(faerie::ArtifactError::Undeclared(name)) => assert_eq!(name, "main"),
+        result => panic!("unexpected result {:?}", result),
+    }
+    for &name in &["later", "puts"] {
+        match obj.set_entry_point(name) {
+            Err(faerie::ArtifactError::UndefinedSymbols(names)) => assert_eq!(names, vec![name]),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+    for &name in &["x", ".note"] {
+        match obj.set_entry_point(name) {
+            Err(faerie::ArtifactError::InvalidEntryPoint(entry)) => assert_eq!(entry, name),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+    obj.set_entry_point("start")
+        .expect("can make start the entry point");
+    obj.define("later", vec![0xc3]).expect("can define later");
+
+    let bytes = obj.emit().expect("can emit elf file");
+    let elf = goblin::elf::Elf::parse(&bytes).expect("can parse elf file");
+    // the object is still relocatable, as faerie does not lay out segments
+    assert_eq!(elf.header.e_type, goblin::elf::header::ET_REL);
+    assert!(elf.program_headers.is_empty());
+    let start = elf
+        .syms
+        .iter()
+        .find(|sym| &elf.strtab[sym.st_name] == "start")
+        .expect("start is in the symbol table");
+    assert_eq!(elf.header.e_entry, start.st_value);
+
+    // Mach-o objects cannot have an entry point
+    obj.target = triple!("x86_64-apple-darwin");
+    let bytes = obj.emit().expect("can emit mach-o file");
+    match goblin::Object::parse(&bytes).expect("can parse mach-o file") {
+        goblin::Object::Mach(goblin::mach::Mach::Binary(mach)) => {
+            assert_eq!(mach.header.filetype, goblin::mach::header::MH_OBJECT)
+        }
+        _ => panic!("Mach-o file not parsed as Mach-o file"),
+    }
+}
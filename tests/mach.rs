@@ -0,0 +1,2068 @@
+extern crate faerie;
+extern crate goblin;
+extern crate scroll;
+#[macro_use]
+extern crate target_lexicon;
+
+use faerie::{Artifact, Decl, ImportKind, Link};
+use goblin::mach::relocation::RelocationInfo;
+use goblin::mach::{Mach, MachO};
+use std::str::FromStr;
+
+fn with_macho<F: FnOnce(&MachO)>(obj: &Artifact, f: F) {
+    let bytes = obj.emit().expect("can emit mach-o file");
+    match goblin::Object::parse(&bytes).expect("can parse mach-o file") {
+        goblin::Object::Mach(Mach::Binary(mach)) => f(&mach),
+        _ => panic!("Mach-o file not parsed as Mach-o file"),
+    }
+}
+
+fn relocations(mach: &MachO, sectname: &str) -> Vec<RelocationInfo> {
+    mach.relocations()
+        .expect("can parse relocations")
+        .into_iter()
+        .filter(|(_, _, section)| section.name().unwrap() == sectname)
+        .flat_map(|(_, relocs, _)| relocs.map(|reloc| reloc.expect("can parse relocation")))
+        .collect()
+}
+
+/// A CIE for x86_64 whose FDEs encode their initial location as `DW_EH_PE_pcrel | DW_EH_PE_sdata4`,
+/// followed by an FDE for a function of `size` bytes, with a garbage initial location
+fn frame_info(size: u8) -> Vec<u8> {
+    // length, id, version, "zR", code and data alignment factors, return address register,
+    // augmentation data, DW_CFA_def_cfa %rsp+8, DW_CFA_offset %rip at cfa-8, and padding
+    let cie = [
+        0x14, 0, 0, 0, 0, 0, 0, 0, 1, b'z', b'R', 0, 1, 0x78, 0x10, 1, 0x1b, 0x0c, 7, 8, 0x90, 1,
+        0, 0,
+    ];
+    // length, CIE pointer, initial location, range, augmentation data, and padding
+    let fde = [
+        0x14, 0, 0, 0, 0x1c, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, size, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    [&cie[..], &fde[..]].concat()
+}
+
+#[test]
+fn strtable_offsets_resolve_to_symbol_names() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("data", Decl::data().global(), vec![1, 2, 3, 4])
+        .expect("can declare data");
+    obj.import("g", ImportKind::Function).expect("can import g");
+    // redeclaring an existing name must not add a second strtable entry
+    obj.import("g", ImportKind::Function).expect("can import g");
+    obj.link(Link {
+        from: "f",
+        to: "g",
+        at: 0,
+    })
+    .expect("can link from f to g");
+
+    with_macho(&obj, |mach| {
+        let names = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol").0)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["_f", "_data", "_g"]);
+    });
+}
+
+#[test]
+fn strtable_offsets_with_underscore_prefixed_names() {
+    use goblin::mach::load_command::CommandVariant;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("_start", Decl::function().global(), vec![0xc3])
+        .expect("can declare _start");
+    obj.declare_with("__data", Decl::data().global(), vec![1, 2, 3, 4])
+        .expect("can declare __data");
+    obj.import("_g", ImportKind::Function)
+        .expect("can import _g");
+    obj.declare_with("h", Decl::function().global(), vec![0xc3])
+        .expect("can declare h");
+    obj.link(Link {
+        from: "_start",
+        to: "_g",
+        at: 0,
+    })
+    .expect("can link from _start to _g");
+
+    with_macho(&obj, |mach| {
+        let names = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol").0)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["__start", "_h", "___data", "__g"]);
+        let strsize = mach
+            .load_commands
+            .iter()
+            .find_map(|cmd| match cmd.command {
+                CommandVariant::Symtab(symtab) => Some(symtab.strsize),
+                _ => None,
+            })
+            .expect("there should be a symtab command");
+        // the leading null byte, and every name with its underscore and null terminator
+        let expected = 1 + names.iter().map(|name| name.len() + 1).sum::<usize>();
+        assert_eq!(strsize as usize, expected);
+    });
+}
+
+#[test]
+fn data_in_code_load_command() {
+    use goblin::mach::load_command::{CommandVariant, DataInCodeEntry};
+    use scroll::Pread;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0; 16])
+        .expect("can declare f");
+    obj.add_data_in_code(faerie::DataInCodeEntry {
+        offset: 8,
+        length: 8,
+        kind: 2,
+    })
+    .expect("can add data in code");
+
+    let bytes = obj.emit().expect("can emit mach-o file");
+    with_macho(&obj, |mach| {
+        let text = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .find(|(section, _)| section.name().unwrap() == "__text")
+            .expect("there is a __text section")
+            .0;
+        let dice = mach
+            .load_commands
+            .iter()
+            .find_map(|lc| match lc.command {
+                CommandVariant::DataInCode(dice) => Some(dice),
+                _ => None,
+            })
+            .expect("there is a LC_DATA_IN_CODE load command");
+        assert_eq!(dice.datasize, 8);
+        let entry: DataInCodeEntry = bytes
+            .pread_with(dice.dataoff as usize, scroll::LE)
+            .expect("can read data in code entry");
+        assert_eq!(entry.offset, text.offset + 8);
+        assert_eq!(entry.length, 8);
+        assert_eq!(entry.kind, 2);
+    });
+}
+
+#[test]
+fn objc_selector_refs() {
+    use goblin::mach::constants::{SECTION_TYPE, S_CSTRING_LITERALS, S_LITERAL_POINTERS};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_selector_ref("sel_init", "init")
+        .expect("can declare sel_init");
+    obj.declare_selector_ref("sel_alloc", "alloc")
+        .expect("can declare sel_alloc");
+
+    with_macho(&obj, |mach| {
+        let sections = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .map(|(section, data)| (section.name().unwrap().to_string(), (section, data)))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        let (methnames, data) = &sections["__objc_methnames"];
+        assert_eq!(methnames.flags & SECTION_TYPE, S_CSTRING_LITERALS);
+        assert_eq!(*data, b"init\0alloc\0");
+
+        let (selrefs, data) = &sections["__objc_selrefs"];
+        assert_eq!(selrefs.flags & SECTION_TYPE, S_LITERAL_POINTERS);
+        assert_eq!(selrefs.size, 16);
+        assert_eq!(data.len(), 16);
+        let relocs = relocations(mach, "__objc_selrefs");
+        assert_eq!(relocs.len(), 2);
+        assert!(relocs
+            .iter()
+            .all(|reloc| reloc.r_type() == 0 && !reloc.is_pic()));
+
+        let names = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol").0)
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"_sel_init"));
+        assert!(names.contains(&"_sel_alloc"));
+    });
+}
+
+#[test]
+fn got_entries() {
+    use faerie::{Decl, ImportKind};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.import("malloc", ImportKind::Function)
+        .expect("can import malloc");
+    obj.declare_with("counter", Decl::data().global(), vec![0; 8])
+        .expect("can declare counter");
+    obj.declare_got_entry("malloc_got", "malloc")
+        .expect("can declare malloc_got");
+    obj.declare_got_entry("counter_got", "counter")
+        .expect("can declare counter_got");
+
+    with_macho(&obj, |mach| {
+        let (got, data) = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .find(|(section, _)| section.name().unwrap() == "__got")
+            .expect("there should be a __got section");
+        assert_eq!(got.segname().unwrap(), "__DATA");
+        assert_eq!(got.align, 3);
+        assert_eq!(got.size, 16);
+        assert_eq!(data.len(), 16);
+
+        let relocs = relocations(mach, "__got");
+        assert_eq!(relocs.len(), 2);
+        assert!(relocs
+            .iter()
+            .all(|reloc| reloc.r_type() == 0 && reloc.r_length() == 3 && !reloc.is_pic()));
+    });
+}
+
+#[test]
+fn section_alignment_is_log2() {
+    use faerie::{Decl, SectionKind};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with(
+        "aligned",
+        Decl::section(SectionKind::Data).with_align(Some(16)),
+        vec![1; 16],
+    )
+    .expect("can declare aligned section");
+
+    with_macho(&obj, |mach| {
+        let (section, _) = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .find(|(section, _)| section.name().unwrap() == "aligned")
+            .expect("there should be an aligned section");
+        assert_eq!(section.align, 4);
+    });
+}
+
+#[test]
+fn absolute_symbols() {
+    use goblin::mach::relocation::X86_64_RELOC_UNSIGNED;
+    use goblin::mach::symbols::{NO_SECT, N_ABS, N_EXT};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare("__stack_top", Decl::AbsoluteSymbol(0x2001_0000))
+        .expect("can declare __stack_top");
+    obj.declare_with(
+        "reset",
+        Decl::function().global(),
+        vec![0x48, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare reset");
+    obj.link(Link {
+        from: "reset",
+        to: "__stack_top",
+        at: 2,
+    })
+    .expect("can link to __stack_top");
+
+    with_macho(&obj, |mach| {
+        let symbols = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .collect::<Vec<_>>();
+        let idx = symbols
+            .iter()
+            .position(|(name, _)| *name == "___stack_top")
+            .expect("there should be a ___stack_top symbol");
+        let (_, nlist) = &symbols[idx];
+        assert_eq!(nlist.n_type, N_ABS | N_EXT);
+        assert_eq!(nlist.n_sect, NO_SECT as usize);
+        assert_eq!(nlist.n_value, 0x2001_0000);
+
+        let relocs = relocations(mach, "__text");
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].r_type(), X86_64_RELOC_UNSIGNED);
+        assert_eq!(relocs[0].r_pcrel(), 0);
+        assert_eq!(relocs[0].r_extern(), 1);
+        assert_eq!(relocs[0].r_symbolnum(), idx);
+    });
+}
+
+#[test]
+fn ifuncs_are_unsupported() {
+    use faerie::ArtifactError;
+    use target_lexicon::BinaryFormat;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("resolve_strlen", Decl::function(), vec![0xc3])
+        .expect("can declare resolve_strlen");
+    match obj.declare_ifunc("strlen", "resolve_strlen") {
+        Err(ArtifactError::UnsupportedBinaryFormat(BinaryFormat::Macho)) => {}
+        result => panic!("declared an indirect function for mach-o: {:?}", result),
+    }
+}
+
+#[test]
+fn raw_sections() {
+    use goblin::mach::constants::{S_ATTR_NO_DEAD_STRIP, S_REGULAR};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    let flags = u64::from(S_REGULAR | S_ATTR_NO_DEAD_STRIP);
+    obj.add_raw_section("__raw".to_string(), vec![1, 2, 3, 4], flags)
+        .expect("can add raw section");
+
+    with_macho(&obj, |mach| {
+        let (raw, data) = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .find(|(section, _)| section.name().unwrap() == "__raw")
+            .expect("there should be a __raw section");
+        assert_eq!(raw.segname().unwrap(), "__DATA");
+        assert_eq!(u64::from(raw.flags), flags);
+        assert_eq!(data, &[1, 2, 3, 4]);
+        assert_eq!(raw.nreloc, 0);
+    });
+}
+
+#[test]
+fn comment() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.add_comment("faerie 0.16").expect("can add comment");
+    obj.add_comment("rustc 1.40").expect("can add comment");
+
+    with_macho(&obj, |mach| {
+        let (comment, data) = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .find(|(section, _)| section.name().unwrap() == "__comment")
+            .expect("there should be a __comment section");
+        assert_eq!(comment.segname().unwrap(), "__TEXT");
+        assert_eq!(data, b"faerie 0.16\0rustc 1.40\0");
+    });
+}
+
+#[test]
+fn intern_strings() {
+    use faerie::{Decl, Link};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0; 16])
+        .expect("can declare f");
+    for (name, string) in [("a", "null\0"), ("b", "other\0"), ("c", "null\0")].iter() {
+        obj.declare_with(*name, Decl::cstring(), string.as_bytes().to_vec())
+            .expect("can declare string");
+    }
+    for (to, at) in [("a", 3), ("c", 10)].iter() {
+        obj.link(Link {
+            from: "f",
+            to,
+            at: *at,
+        })
+        .expect("can link to string");
+    }
+    assert_eq!(obj.intern_strings().expect("can intern strings"), 5);
+    assert_eq!(obj.intern_strings().expect("can intern strings"), 0);
+
+    with_macho(&obj, |mach| {
+        let (_, data) = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .find(|(section, _)| section.name().unwrap() == "__cstring")
+            .expect("there should be a __cstring section");
+        assert_eq!(data, b"null\0other\0");
+
+        let relocs = relocations(mach, "__text");
+        assert_eq!(relocs.len(), 2);
+        assert_eq!(relocs[0].r_symbolnum(), relocs[1].r_symbolnum());
+        let (name, _) = mach
+            .symbols()
+            .nth(relocs[0].r_symbolnum())
+            .expect("relocation has a symbol")
+            .expect("can parse symbol");
+        assert_eq!(name, "_a");
+    });
+}
+
+#[test]
+fn authenticated_pointers() {
+    use faerie::{Decl, Link, PtrauthKey, Reloc};
+
+    let mut obj = Artifact::new(triple!("aarch64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0; 4])
+        .expect("can declare f");
+    obj.declare_with("vtable", Decl::data().global(), vec![0; 16])
+        .expect("can declare vtable");
+    obj.link_with(
+        Link {
+            from: "vtable",
+            to: "f",
+            at: 8,
+        },
+        Reloc::Authenticated {
+            key: PtrauthKey::IA,
+            addr_div: true,
+            discriminator: 0x1234,
+            addend: 4,
+        },
+    )
+    .expect("can link vtable to f");
+
+    with_macho(&obj, |mach| {
+        // signed pointers make this an arm64e object
+        assert_eq!(
+            mach.header.cpusubtype,
+            goblin::mach::cputype::CPU_SUBTYPE_ARM64_E
+        );
+        let (_, data) = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .find(|(section, _)| section.name().unwrap() == "__data")
+            .expect("there should be a __data section");
+        let mut expected = [0; 16];
+        expected[8..].copy_from_slice(&(4u64 | 0x1234 << 32 | 1 << 48 | 1 << 63).to_le_bytes());
+        assert_eq!(&data[..16], &expected);
+
+        let relocs = relocations(mach, "__data");
+        assert_eq!(relocs.len(), 1);
+        // ARM64_RELOC_AUTHENTICATED_POINTER
+        assert_eq!(relocs[0].r_type(), 11);
+        assert_eq!(relocs[0].r_address, 8);
+        assert_eq!(relocs[0].r_length(), 3);
+        assert!(!relocs[0].is_pic());
+    });
+}
+
+#[test]
+fn remove_definition() {
+    use faerie::{ArtifactError, Decl, Link};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    for name in ["a", "b", "c"].iter() {
+        obj.declare_with(*name, Decl::function().global(), vec![0; 4])
+            .expect("can declare function");
+    }
+    obj.declare_with("d", Decl::data().global(), vec![0; 8])
+        .expect("can declare d");
+    obj.link(Link {
+        from: "a",
+        to: "b",
+        at: 1,
+    })
+    .expect("can link a to b");
+    obj.link(Link {
+        from: "d",
+        to: "c",
+        at: 0,
+    })
+    .expect("can link d to c");
+
+    assert_eq!(obj.remove_definition("b").expect("can remove b"), 1);
+    match obj.remove_definition("b") {
+        Err(ArtifactError::Undeclared(name)) => assert_eq!(name, "b"),
+        result => panic!("removed b twice: {:?}", result),
+    }
+
+    with_macho(&obj, |mach| {
+        let symbols = mach
+            .symbols()
+            .map(|sym| {
+                let (name, nlist) = sym.expect("can parse symbol");
+                (name, nlist.n_value)
+            })
+            .collect::<Vec<_>>();
+        // functions are 16 byte aligned, and the data follows the code
+        assert_eq!(symbols, vec![("_a", 0), ("_c", 16), ("_d", 32)]);
+
+        assert!(relocations(mach, "__text").is_empty());
+        let relocs = relocations(mach, "__data");
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].r_symbolnum(), 1);
+    });
+}
+
+/// Assembles a fat binary from `slices` of `(cputype, object)`, with every slice 4096 byte aligned
+fn fat_binary(slices: &[(u32, Vec<u8>)], endian: scroll::Endian) -> Vec<u8> {
+    use goblin::mach::fat::{FatArch, FatHeader, FAT_MAGIC};
+    use scroll::Pwrite;
+
+    let mut bytes = vec![0u8; 4096];
+    bytes
+        .pwrite_with(
+            FatHeader {
+                magic: FAT_MAGIC,
+                nfat_arch: slices.len() as u32,
+            },
+            0,
+            endian,
+        )
+        .unwrap();
+    for (idx, (cputype, object)) in slices.iter().enumerate() {
+        let arch = FatArch {
+            cputype: *cputype,
+            cpusubtype: 0,
+            offset: bytes.len() as u32,
+            size: object.len() as u32,
+            align: 12,
+        };
+        bytes.pwrite_with(arch, 8 + idx * 20, endian).unwrap();
+        bytes.extend_from_slice(object);
+        bytes.resize((bytes.len() + 4095) & !4095, 0);
+    }
+    bytes
+}
+
+#[test]
+fn fat_binary_from_bytes() {
+    use faerie::{ArtifactError, FatBinary};
+    use goblin::mach::cputype::{CPU_TYPE_ARM64, CPU_TYPE_X86_64};
+
+    let objects = ["x86_64-apple-darwin", "aarch64-apple-darwin"]
+        .iter()
+        .map(|target| {
+            let mut obj = Artifact::new(triple!(target), "t.o".into());
+            obj.declare_with("f", Decl::function().global(), vec![0xc3])
+                .expect("can declare f");
+            obj.emit().expect("can emit mach-o file")
+        })
+        .collect::<Vec<_>>();
+    let slices = vec![
+        (CPU_TYPE_X86_64, objects[0].clone()),
+        (CPU_TYPE_ARM64, objects[1].clone()),
+    ];
+
+    for &endian in &[scroll::BE, scroll::LE] {
+        let fat =
+            FatBinary::from_bytes(&fat_binary(&slices, endian)).expect("can parse fat binary");
+        assert_eq!(fat.slices.len(), 2);
+        for (slice, (cputype, object)) in fat.slices.iter().zip(slices.iter()) {
+            assert_eq!(slice.cputype, *cputype);
+            assert_eq!(slice.align, 12);
+            assert_eq!(&slice.data, object);
+            let mach = MachO::parse(&slice.data, 0).expect("can parse slice");
+            assert_eq!(mach.header.cputype, *cputype);
+        }
+    }
+
+    assert!(FatBinary::from_bytes(&objects[0]).is_err());
+    let mut truncated = fat_binary(&slices, scroll::BE);
+    truncated.truncate(4096 + 16);
+    assert!(FatBinary::from_bytes(&truncated).is_err());
+
+    // a truncated header claiming far more archs than it has room for
+    let mut huge = fat_binary(&slices, scroll::BE);
+    huge.truncate(16);
+    huge[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+    match FatBinary::from_bytes(&huge) {
+        Err(ArtifactError::Goblin(goblin::error::Error::Malformed(_))) => {}
+        result => panic!("parsed a fat header with too many archs: {:?}", result),
+    }
+}
+
+#[test]
+fn declare_import_list() {
+    use faerie::ArtifactError;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("main", Decl::function().global(), vec![0xc3])
+        .expect("can declare main");
+    obj.declare_import_list(&["malloc", "free", "printf"], ImportKind::Function)
+        .expect("can import functions");
+    obj.declare_import_list(&["environ"], ImportKind::Data)
+        .expect("can import data");
+
+    for names in &[&["puts", "puts"][..], &["puts", "free"][..], &["main"][..]] {
+        match obj.declare_import_list(names, ImportKind::Function) {
+            Err(ArtifactError::DuplicateDefinition(_)) => {}
+            result => panic!("imported duplicates {:?}: {:?}", names, result),
+        }
+    }
+    // nothing was imported by the failed attempts
+    assert_eq!(obj.imports().count(), 4);
+
+    with_macho(&obj, |mach| {
+        let undefined = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .filter(|(_, nlist)| nlist.is_undefined())
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(undefined, vec!["_malloc", "_free", "_printf", "_environ"]);
+    });
+}
+
+#[test]
+fn weak_imports() {
+    use goblin::mach::symbols::N_WEAK_REF;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare("maybe_missing", Decl::weak_function_import())
+        .expect("can declare maybe_missing");
+    obj.import("maybe_data", ImportKind::WeakData)
+        .expect("can import maybe_data");
+    obj.import("malloc", ImportKind::Function)
+        .expect("can import malloc");
+    // a non-weak reference makes a weak import non-weak
+    obj.declare("upgraded", Decl::weak_function_import())
+        .expect("can declare upgraded");
+    obj.declare("upgraded", Decl::function_import())
+        .expect("can redeclare upgraded");
+
+    with_macho(&obj, |mach| {
+        let weak = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .filter(|(_, nlist)| nlist.is_undefined())
+            .map(|(name, nlist)| (name, nlist.n_desc & N_WEAK_REF != 0))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            weak,
+            vec![
+                ("_maybe_missing", true),
+                ("_maybe_data", true),
+                ("_malloc", false),
+                ("_upgraded", false),
+            ]
+        );
+    });
+}
+
+#[test]
+fn address_space_note() {
+    use faerie::SectionKind;
+    use goblin::mach::load_command::CommandVariant;
+    use scroll::Pread;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with(
+        "__far_data",
+        Decl::section(SectionKind::Data)
+            .with_address_space(2)
+            .expect("address space fits"),
+        vec![1, 2, 3, 4],
+    )
+    .expect("can declare __far_data");
+    obj.declare_with("near", Decl::section(SectionKind::Data), vec![5, 6])
+        .expect("can declare near");
+
+    let bytes = obj.emit().expect("can emit mach-o file");
+    let mach = MachO::parse(&bytes, 0).expect("can parse mach-o file");
+    let note = mach
+        .load_commands
+        .iter()
+        .find_map(|lc| match lc.command {
+            CommandVariant::Unimplemented(header) if header.cmd == 0x31 => Some(lc.offset),
+            _ => None,
+        })
+        .expect("there should be a note load command");
+    assert_eq!(&bytes[note + 8..note + 24], b"faerie.addrspace");
+    let offset: u64 = bytes.pread_with(note + 24, scroll::LE).unwrap();
+    let size: u64 = bytes.pread_with(note + 32, scroll::LE).unwrap();
+    assert_eq!(size, 40);
+    let entry = &bytes[offset as usize..(offset + size) as usize];
+    assert_eq!(&entry[..10], b"__far_data");
+    assert_eq!(&entry[16..22], b"__DATA");
+    assert_eq!(entry.pread_with::<u64>(32, scroll::LE).unwrap(), 2);
+}
+
+#[test]
+fn build_id_note() {
+    use faerie::SectionKind;
+    use goblin::mach::load_command::CommandVariant;
+    use scroll::Pread;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with(
+        "__far_data",
+        Decl::section(SectionKind::Data)
+            .with_address_space(1)
+            .expect("address space fits"),
+        vec![1, 2, 3, 4],
+    )
+    .expect("can declare __far_data");
+    obj.set_build_id(b"0123456789abcdef0123")
+        .expect("can set build id");
+
+    let bytes = obj.emit().expect("can emit mach-o file");
+    let mach = MachO::parse(&bytes, 0).expect("can parse mach-o file");
+    let notes = mach
+        .load_commands
+        .iter()
+        .filter_map(|lc| match lc.command {
+            CommandVariant::Unimplemented(header) if header.cmd == 0x31 => Some(lc.offset),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    // the address space note and the build id each get their own load command
+    assert_eq!(notes.len(), 2);
+    let note = notes[1];
+    assert_eq!(&bytes[note + 8..note + 24], b"faerie.buildid\0\0");
+    let offset: u64 = bytes.pread_with(note + 24, scroll::LE).unwrap();
+    let size: u64 = bytes.pread_with(note + 32, scroll::LE).unwrap();
+    assert_eq!(
+        &bytes[offset as usize..(offset + size) as usize],
+        b"0123456789abcdef0123"
+    );
+}
+
+#[test]
+fn link_data_to_symbol() {
+    use goblin::mach::relocation::X86_64_RELOC_UNSIGNED;
+    use scroll::Pread;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("g", Decl::function().global(), vec![0xc3])
+        .expect("can declare g");
+    obj.declare_with("vtable", Decl::data().global(), vec![0xff; 16])
+        .expect("can declare vtable");
+    obj.link_data_to_symbol("vtable", 0, "f", 0)
+        .expect("can link vtable to f");
+    obj.link_data_to_symbol("vtable", 8, "g", 4)
+        .expect("can link vtable to g");
+
+    with_macho(&obj, |mach| {
+        let relocs = relocations(mach, "__data");
+        assert_eq!(relocs.len(), 2);
+        assert!(relocs
+            .iter()
+            .all(|reloc| reloc.r_type() == X86_64_RELOC_UNSIGNED
+                && reloc.r_length() == 3
+                && reloc.is_extern()
+                && !reloc.is_pic()));
+
+        let (_, data) = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .find(|(section, _)| section.name().unwrap() == "__data")
+            .expect("there should be a __data section");
+        // the addends are stored in the vtable itself
+        assert_eq!(data.pread_with::<u64>(0, scroll::LE).unwrap(), 0);
+        assert_eq!(data.pread_with::<u64>(8, scroll::LE).unwrap(), 4);
+    });
+}
+
+#[test]
+fn disable_subsections_via_symbols() {
+    use goblin::mach::header::MH_SUBSECTIONS_VIA_SYMBOLS;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    with_macho(&obj, |mach| {
+        assert_ne!(mach.header.flags & MH_SUBSECTIONS_VIA_SYMBOLS, 0);
+    });
+
+    obj.disable_subsections_via_symbols()
+        .expect("can disable subsections via symbols");
+    with_macho(&obj, |mach| {
+        assert_eq!(mach.header.flags & MH_SUBSECTIONS_VIA_SYMBOLS, 0);
+    });
+}
+
+#[test]
+fn bss_section() {
+    use goblin::mach::constants::{SECTION_TYPE, S_ZEROFILL};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0; 8])
+        .expect("can declare f");
+    obj.declare_with("d", Decl::data().global(), vec![1; 4])
+        .expect("can declare d");
+    obj.declare("counter", Decl::data().global().writable())
+        .expect("can declare counter");
+    obj.define_zero_init("counter", 4)
+        .expect("can define counter");
+    obj.declare(
+        "buffer",
+        Decl::data().global().writable().with_align(Some(64)),
+    )
+    .expect("can declare buffer");
+    obj.define_zero_init("buffer", 4096)
+        .expect("can define buffer");
+    obj.link(Link {
+        from: "f",
+        to: "buffer",
+        at: 3,
+    })
+    .expect("can link from f to buffer");
+
+    let bytes = obj.emit().expect("can emit mach-o file");
+    // the zero filled data is not in the file
+    assert!(bytes.len() < 4096);
+    let mach = MachO::parse(&bytes, 0).expect("can parse mach-o file");
+    let segment = &mach.segments[0];
+    let sections = segment.sections().expect("can parse sections");
+    let (bss, bss_data) = sections
+        .iter()
+        .find(|(section, _)| section.name().unwrap() == "__bss")
+        .expect("there should be a __bss section");
+    assert_eq!(bss.flags & SECTION_TYPE, S_ZEROFILL);
+    assert_eq!(bss.offset, 0);
+    assert!(bss_data.is_empty());
+    assert_eq!(bss.addr % 64, 0);
+    // after all the sections with contents
+    for (section, _) in sections.iter().filter(|(section, _)| section.size > 0) {
+        if section.name().unwrap() != "__bss" {
+            assert!(section.addr + section.size <= bss.addr);
+        }
+    }
+    assert_eq!(segment.vmsize, bss.addr + bss.size);
+
+    let address = |name: &str| {
+        mach.symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .find(|(symbol, _)| *symbol == name)
+            .map(|(_, nlist)| nlist.n_value)
+            .expect("symbol is present")
+    };
+    let (counter, buffer) = (address("_counter"), address("_buffer"));
+    assert_ne!(counter, buffer);
+    assert_eq!(buffer % 64, 0);
+    for &(start, size) in &[(counter, 4), (buffer, 4096)] {
+        assert!(bss.addr <= start && start + size <= bss.addr + bss.size);
+    }
+
+    let relocs = relocations(&mach, "__text");
+    assert_eq!(relocs.len(), 1);
+    let (name, _) = mach
+        .symbols()
+        .nth(relocs[0].r_symbolnum())
+        .expect("relocation has a symbol")
+        .expect("can parse symbol");
+    assert_eq!(name, "_buffer");
+}
+
+#[test]
+fn weak_definitions() {
+    use goblin::mach::symbols::{N_EXT, N_WEAK_DEF};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3])
+        .expect("can declare f");
+    obj.declare_with("inline_fn", Decl::weak_function(), vec![0xc3])
+        .expect("can declare inline_fn");
+    obj.declare_with("template_static", Decl::weak_data(), vec![0; 8])
+        .expect("can declare template_static");
+    obj.declare_with("local", Decl::data(), vec![0; 8])
+        .expect("can declare local");
+    obj.link(Link {
+        from: "f",
+        to: "inline_fn",
+        at: 1,
+    })
+    .expect("can link from f to inline_fn");
+
+    with_macho(&obj, |mach| {
+        let symbols = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .map(|(name, nlist)| {
+                (
+                    name,
+                    nlist.n_type & N_EXT != 0,
+                    nlist.n_desc & N_WEAK_DEF != 0,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            vec![
+                ("_f", true, false),
+                ("_inline_fn", true, true),
+                ("_local", false, false),
+                ("_template_static", true, true),
+            ]
+        );
+
+        // calls to weak functions must go through the symbol, since they may be overridden
+        let relocs = relocations(mach, "__text");
+        assert_eq!(relocs.len(), 1);
+        assert!(relocs[0].is_extern());
+        let (name, _) = mach
+            .symbols()
+            .nth(relocs[0].r_symbolnum())
+            .expect("relocation has a symbol")
+            .expect("can parse symbol");
+        assert_eq!(name, "_inline_fn");
+    });
+}
+
+#[test]
+fn read_only_data() {
+    use goblin::mach::relocation::X86_64_RELOC_SIGNED;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0; 8])
+        .expect("can declare f");
+    obj.declare_with("table", Decl::data().global(), vec![1; 16])
+        .expect("can declare table");
+    obj.declare_with("counter", Decl::data().global().writable(), vec![0; 8])
+        .expect("can declare counter");
+    obj.declare_with("vtable", Decl::data().global(), vec![0; 8])
+        .expect("can declare vtable");
+    obj.link(Link {
+        from: "f",
+        to: "table",
+        at: 3,
+    })
+    .expect("can link from f to table");
+    obj.link_data_to_symbol("vtable", 0, "f", 0)
+        .expect("can link vtable to f");
+
+    with_macho(&obj, |mach| {
+        let sections = mach.segments[0].sections().expect("can parse sections");
+        let section_of = |name: &str| {
+            let (_, nlist) = mach
+                .symbols()
+                .map(|sym| sym.expect("can parse symbol"))
+                .find(|(symbol, _)| *symbol == name)
+                .expect("symbol is present");
+            let (section, _) = &sections[nlist.n_sect - 1];
+            (section.segname().unwrap(), section.name().unwrap())
+        };
+        assert_eq!(section_of("_table"), ("__TEXT", "__const"));
+        assert_eq!(section_of("_counter"), ("__DATA", "__data"));
+        // pointers must be writable by the dynamic linker
+        assert_eq!(section_of("_vtable"), ("__DATA", "__data"));
+
+        let relocs = relocations(mach, "__text");
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].r_type(), X86_64_RELOC_SIGNED);
+        let (name, _) = mach
+            .symbols()
+            .nth(relocs[0].r_symbolnum())
+            .expect("relocation has a symbol")
+            .expect("can parse symbol");
+        assert_eq!(name, "_table");
+    });
+}
+
+#[test]
+fn aligned_section_start() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("str", Decl::cstring().global(), b"abc\0".to_vec())
+        .expect("can declare str");
+    obj.declare_with(
+        "table",
+        Decl::data().global().with_align(Some(64)),
+        vec![1; 16],
+    )
+    .expect("can declare table");
+
+    with_macho(&obj, |mach| {
+        let sections = mach.segments[0].sections().expect("can parse sections");
+        let (text, _) = &sections[0];
+        let (rodata, contents) = sections
+            .iter()
+            .find(|(section, _)| section.name().unwrap() == "__const")
+            .expect("has a __const section");
+        // the section is as aligned as its most aligned symbol, and starts accordingly
+        assert_eq!(rodata.align, 6);
+        assert_eq!(rodata.addr % 64, 0);
+        assert_eq!(rodata.offset - text.offset, rodata.addr as u32);
+        assert_eq!(*contents, &[1; 16][..]);
+
+        let (_, table) = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .find(|(symbol, _)| *symbol == "_table")
+            .expect("table is present");
+        assert_eq!(table.n_value, rodata.addr);
+    });
+}
+
+#[test]
+fn hidden_symbols_are_private_extern() {
+    use goblin::mach::symbols::{N_EXT, N_PEXT};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("helper", Decl::function().global().hidden(), vec![0xc3])
+        .expect("can declare helper");
+    obj.declare_with("table", Decl::data().global().internal(), vec![0; 8])
+        .expect("can declare table");
+    obj.declare_with("local", Decl::data().hidden(), vec![0; 8])
+        .expect("can declare local");
+
+    with_macho(&obj, |mach| {
+        let symbols = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .map(|(name, nlist)| (name, nlist.n_type & N_EXT != 0, nlist.n_type & N_PEXT != 0))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            vec![
+                ("_f", true, false),
+                ("_helper", true, true),
+                ("_local", false, false),
+                ("_table", true, true),
+            ]
+        );
+    });
+}
+
+#[test]
+fn comdat_functions_are_weak() {
+    use goblin::mach::symbols::N_WEAK_DEF;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global().comdat(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("g", Decl::function().global(), vec![0xc3])
+        .expect("can declare g");
+
+    with_macho(&obj, |mach| {
+        let symbols = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .map(|(name, nlist)| (name, nlist.n_desc & N_WEAK_DEF != 0))
+            .collect::<Vec<_>>();
+        assert_eq!(symbols, vec![("_g", false), ("_f", true)]);
+    });
+}
+
+#[test]
+fn thread_local_variables() {
+    use goblin::mach::constants::{
+        SECTION_TYPE, S_THREAD_LOCAL_REGULAR, S_THREAD_LOCAL_VARIABLES, S_THREAD_LOCAL_ZEROFILL,
+    };
+    use goblin::mach::relocation::{X86_64_RELOC_TLV, X86_64_RELOC_UNSIGNED};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    // movq _x@TLVP(%rip), %rdi; callq *(%rdi); movl (%rax), %eax; ret
+    obj.declare_with(
+        "get_x",
+        Decl::function().global(),
+        vec![0x48, 0x8b, 0x3d, 0, 0, 0, 0, 0xff, 0x17, 0x8b, 0x00, 0xc3],
+    )
+    .expect("can declare get_x");
+    obj.declare_with("x", Decl::tls_data().global(), vec![42, 0, 0, 0])
+        .expect("can declare x");
+    obj.declare("y", Decl::tls_data()).expect("can declare y");
+    obj.define_zero_init("y", 16).expect("can define y");
+    obj.link(Link {
+        from: "get_x",
+        to: "x",
+        at: 3,
+    })
+    .expect("can link from get_x to x");
+
+    with_macho(&obj, |mach| {
+        let sections = mach.segments[0].sections().expect("can parse sections");
+        let section = |name: &str| {
+            sections
+                .iter()
+                .position(|(section, _)| section.name().unwrap() == name)
+                .expect("section is present")
+        };
+        let (thread_data, contents) = &sections[section("__thread_data")];
+        assert_eq!(thread_data.flags & SECTION_TYPE, S_THREAD_LOCAL_REGULAR);
+        assert_eq!(&contents[..4], &[42, 0, 0, 0]);
+        let (thread_bss, _) = &sections[section("__thread_bss")];
+        assert_eq!(thread_bss.flags & SECTION_TYPE, S_THREAD_LOCAL_ZEROFILL);
+        assert_eq!(thread_bss.size, 16);
+        let (thread_vars, _) = &sections[section("__thread_vars")];
+        assert_eq!(thread_vars.flags & SECTION_TYPE, S_THREAD_LOCAL_VARIABLES);
+        // a descriptor of three pointers for each variable
+        assert_eq!(thread_vars.size, 2 * 24);
+
+        let symbols = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .collect::<Vec<_>>();
+        let section_of = |name: &str| {
+            let (_, nlist) = symbols
+                .iter()
+                .find(|(symbol, _)| *symbol == name)
+                .expect("symbol is present");
+            nlist.n_sect - 1
+        };
+        assert_eq!(section_of("_x"), section("__thread_vars"));
+        assert_eq!(section_of("_y"), section("__thread_vars"));
+        assert_eq!(section_of("_x$tlv$init"), section("__thread_data"));
+        assert_eq!(section_of("_y$tlv$init"), section("__thread_bss"));
+
+        let relocs = relocations(mach, "__text");
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].r_type(), X86_64_RELOC_TLV);
+        assert_eq!(symbols[relocs[0].r_symbolnum()].0, "_x");
+
+        let mut targets = relocations(mach, "__thread_vars")
+            .into_iter()
+            .map(|reloc| {
+                assert_eq!(reloc.r_type(), X86_64_RELOC_UNSIGNED);
+                (reloc.r_address, symbols[reloc.r_symbolnum()].0)
+            })
+            .collect::<Vec<_>>();
+        targets.sort();
+        assert_eq!(
+            targets,
+            vec![
+                (0, "__tlv_bootstrap"),
+                (16, "_x$tlv$init"),
+                (24, "__tlv_bootstrap"),
+                (40, "_y$tlv$init"),
+            ]
+        );
+    });
+}
+
+#[test]
+fn eh_frame() {
+    use goblin::mach::constants::{SECTION_TYPE, S_COALESCED};
+    use goblin::mach::relocation::{X86_64_RELOC_SUBTRACTOR, X86_64_RELOC_UNSIGNED};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("g", Decl::function(), vec![0xc3])
+        .expect("can declare g");
+    obj.add_frame_info("f", &frame_info(1))
+        .expect("can add frame info of f");
+    obj.add_frame_info("g", &frame_info(1))
+        .expect("can add frame info of g");
+
+    with_macho(&obj, |mach| {
+        let sections = mach.segments[0].sections().expect("can parse sections");
+        let (eh_frame, contents) = sections
+            .iter()
+            .find(|(section, _)| section.name().unwrap() == "__eh_frame")
+            .expect("has __eh_frame");
+        assert_eq!(eh_frame.segname().unwrap(), "__TEXT");
+        assert_eq!(eh_frame.flags & SECTION_TYPE, S_COALESCED);
+        assert_eq!(eh_frame.size, 96);
+        // the initial locations are filled in by the relocations
+        assert_eq!(&contents[32..36], &[0, 0, 0, 0]);
+        assert_eq!(&contents[80..84], &[0, 0, 0, 0]);
+
+        let symbols = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .collect::<Vec<_>>();
+        let relocs = relocations(mach, "__eh_frame")
+            .into_iter()
+            .map(|reloc| {
+                assert!(!reloc.is_pic());
+                assert_eq!(reloc.r_length(), 2);
+                (
+                    reloc.r_address,
+                    reloc.r_type(),
+                    symbols[reloc.r_symbolnum()].0,
+                )
+            })
+            .collect::<Vec<_>>();
+        // the difference of the function and the label at the initial location
+        assert_eq!(
+            relocs,
+            vec![
+                (32, X86_64_RELOC_SUBTRACTOR, "_eh_frame.fde0"),
+                (32, X86_64_RELOC_UNSIGNED, "_f"),
+                (80, X86_64_RELOC_SUBTRACTOR, "_eh_frame.fde1"),
+                (80, X86_64_RELOC_UNSIGNED, "_g"),
+            ]
+        );
+    });
+
+    // the labels must not collide with other symbols
+    let mut taken = obj.clone();
+    taken
+        .declare_with("eh_frame.fde1", Decl::data(), vec![0; 4])
+        .expect("can declare eh_frame.fde1");
+    match taken.emit() {
+        Err(faerie::ArtifactError::NameTaken(name)) => assert_eq!(name, "eh_frame.fde1"),
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+
+    // an extended length which runs past the end of the address space
+    let mut overflow = [0xff; 24];
+    overflow[12..16].copy_from_slice(&[0; 4]);
+    obj.add_frame_info("f", &overflow)
+        .expect("can add frame info of f");
+    match obj.emit() {
+        Err(faerie::ArtifactError::Unsupported(_)) => {}
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn cstrings_are_literals() {
+    use goblin::mach::constants::{SECTION_TYPE, S_CSTRING_LITERALS};
+    use goblin::mach::relocation::X86_64_RELOC_SIGNED;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    // leaq _greeting(%rip), %rax; ret
+    obj.declare_with(
+        "f",
+        Decl::function().global(),
+        vec![0x48, 0x8d, 0x05, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare f");
+    obj.declare_with("greeting", Decl::cstring(), b"hello\0".to_vec())
+        .expect("can declare greeting");
+    obj.link(Link {
+        from: "f",
+        to: "greeting",
+        at: 3,
+    })
+    .expect("can link from f to greeting");
+
+    with_macho(&obj, |mach| {
+        let sections = mach.segments[0].sections().expect("can parse sections");
+        let (cstring, contents) = sections
+            .iter()
+            .find(|(section, _)| section.name().unwrap() == "__cstring")
+            .expect("has a __cstring section");
+        // the linker only deduplicates strings in a literal section in __TEXT
+        assert_eq!(cstring.segname().unwrap(), "__TEXT");
+        assert_eq!(cstring.flags & SECTION_TYPE, S_CSTRING_LITERALS);
+        assert_eq!(*contents, b"hello\0");
+        let (data, _) = sections
+            .iter()
+            .find(|(section, _)| section.name().unwrap() == "__data")
+            .expect("has a __data section");
+        assert_eq!(data.size, 0);
+
+        let relocs = relocations(mach, "__text");
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].r_type(), X86_64_RELOC_SIGNED);
+        assert!(relocs[0].is_pic());
+    });
+}
+
+#[test]
+fn common_symbols() {
+    use goblin::mach::relocation::X86_64_RELOC_GOT_LOAD;
+    use goblin::mach::symbols::{N_EXT, N_UNDF};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare("buf", Decl::common(64, 16))
+        .expect("can declare buf");
+    // movq _buf@GOTPCREL(%rip), %rax; ret
+    obj.declare_with(
+        "f",
+        Decl::function().global(),
+        vec![0x48, 0x8b, 0x05, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare f");
+    obj.link(Link {
+        from: "f",
+        to: "buf",
+        at: 3,
+    })
+    .expect("can link to buf");
+
+    with_macho(&obj, |mach| {
+        let (_, nlist) = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .find(|(name, _)| *name == "_buf")
+            .expect("there should be a _buf symbol");
+        // the value of a common symbol is its size, and its description holds log2 of its alignment
+        assert_eq!(nlist.n_type, N_UNDF | N_EXT);
+        assert_eq!(nlist.n_value, 64);
+        assert_eq!((nlist.n_desc >> 8) & 0x0f, 4);
+
+        let relocs = relocations(mach, "__text");
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].r_type(), X86_64_RELOC_GOT_LOAD);
+    });
+}
+
+#[test]
+fn write_mmap() {
+    let dir = std::env::temp_dir().join(format!("faerie-write-mmap-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("t.o");
+    // a stale, larger file is truncated
+    std::fs::write(&path, vec![0xff; 1 << 16]).unwrap();
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3])
+        .expect("can declare f");
+    obj.declare_with("message", Decl::cstring(), b"hello\0".to_vec())
+        .expect("can declare message");
+    obj.declare("zeros", Decl::data().global().writable())
+        .expect("can declare zeros");
+    obj.define_zero_init("zeros", 64).expect("can define zeros");
+    obj.import("g", ImportKind::Function).expect("can import g");
+    obj.link(Link {
+        from: "f",
+        to: "g",
+        at: 1,
+    })
+    .expect("can link from f to g");
+    obj.add_data_in_code(faerie::DataInCodeEntry {
+        offset: 0,
+        length: 5,
+        kind: 2,
+    })
+    .expect("can add data in code");
+    obj.set_build_id(&[1, 2, 3, 4]).expect("can set build id");
+
+    obj.write_mmap(&path).expect("can write t.o");
+    assert_eq!(std::fs::read(&path).unwrap(), obj.emit().unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sections() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0x90, 0xc3])
+        .expect("can declare f");
+    obj.declare_with("message", Decl::cstring(), b"hello\0".to_vec())
+        .expect("can declare message");
+    obj.declare_with(
+        "value",
+        Decl::data().global().with_align(Some(8)),
+        vec![1; 8],
+    )
+    .expect("can declare value");
+
+    let sections = obj.sections().expect("can list sections");
+    let bytes = obj.emit().expect("can emit mach-o file");
+    with_macho(&obj, |mach| {
+        let parsed = mach.segments[0].sections().expect("can parse sections");
+        assert_eq!(sections.len(), parsed.len());
+        for (info, (section, data)) in sections.iter().zip(parsed.iter()) {
+            assert_eq!(info.name, section.name().unwrap());
+            assert_eq!(info.segment, section.segname().unwrap());
+            assert_eq!(info.file_offset, section.offset as usize);
+            assert_eq!(info.size, section.size as usize);
+            assert_eq!(info.align, 1 << section.align);
+            assert_eq!(
+                &bytes[info.file_offset..info.file_offset + info.size],
+                *data
+            );
+        }
+    });
+}
+
+#[test]
+fn difference_of_symbols() {
+    use goblin::mach::relocation::{X86_64_RELOC_SUBTRACTOR, X86_64_RELOC_UNSIGNED};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0x90, 0xc3])
+        .expect("can declare f");
+    // a jump table, whose entries are relative to the table: .long _f - _table; .quad _f - _table
+    obj.declare_with("table", Decl::data().global().writable(), vec![0; 12])
+        .expect("can declare table");
+    obj.link_difference("table", 0, "f", "table", 4)
+        .expect("can link a 4 byte difference");
+    obj.link_difference("table", 4, "f", "table", 8)
+        .expect("can link an 8 byte difference");
+    assert!(obj.link_difference("table", 0, "f", "missing", 4).is_err());
+
+    with_macho(&obj, |mach| {
+        let symbols = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol").0)
+            .collect::<Vec<_>>();
+        let relocs = relocations(mach, "__data");
+        assert_eq!(relocs.len(), 4);
+        for (pair, &(address, length)) in relocs.chunks(2).zip(&[(0, 2), (4, 3)]) {
+            // the subtractor comes first, and both relocations are external, and at the same address
+            assert_eq!(pair[0].r_type(), X86_64_RELOC_SUBTRACTOR);
+            assert_eq!(symbols[pair[0].r_symbolnum()], "_table");
+            assert_eq!(pair[1].r_type(), X86_64_RELOC_UNSIGNED);
+            assert_eq!(symbols[pair[1].r_symbolnum()], "_f");
+            for reloc in pair {
+                assert_eq!(reloc.r_address, address);
+                assert_eq!(reloc.r_length(), length);
+                assert_eq!(reloc.r_extern(), 1);
+                assert!(!reloc.is_pic());
+            }
+        }
+    });
+
+    match obj.link_difference("table", 0, "f", "table", 2) {
+        Err(faerie::ArtifactError::UnsupportedRelocation(_)) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    // removing either symbol removes both halves of every difference it takes part in
+    assert_eq!(obj.remove_definition("f").expect("can remove f"), 4);
+    with_macho(&obj, |mach| assert!(relocations(mach, "__data").is_empty()));
+
+    let mut obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0x90, 0xc3])
+        .expect("can declare f");
+    obj.declare_with("table", Decl::data().global().writable(), vec![0; 4])
+        .expect("can declare table");
+    match obj.link_difference("table", 0, "f", "table", 4) {
+        Err(faerie::ArtifactError::UnsupportedBinaryFormat(_)) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn link_with_addend() {
+    use goblin::mach::relocation::{X86_64_RELOC_SIGNED, X86_64_RELOC_UNSIGNED};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    // movl _pair+8(%rip), %eax; retq
+    obj.declare_with(
+        "f",
+        Decl::function().global(),
+        vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare f");
+    obj.declare_with("pair", Decl::data().global().writable(), vec![1; 16])
+        .expect("can declare pair");
+    obj.declare_with("second", Decl::data().global().writable(), vec![0; 8])
+        .expect("can declare second");
+    obj.link_with_addend(
+        Link {
+            from: "f",
+            to: "pair",
+            at: 2,
+        },
+        8,
+    )
+    .expect("can link to pair+8");
+    obj.link_with_addend(
+        Link {
+            from: "second",
+            to: "pair",
+            at: 0,
+        },
+        -8,
+    )
+    .expect("can link to pair-8");
+
+    let bytes = obj.emit().expect("can emit mach-o file");
+    with_macho(&obj, |mach| {
+        let sections = mach.segments[0].sections().expect("can parse sections");
+        let contents = |name: &str| {
+            let (section, _) = sections
+                .iter()
+                .find(|(section, _)| section.name().unwrap() == name)
+                .expect("section is present");
+            &bytes[section.offset as usize..(section.offset as u64 + section.size) as usize]
+        };
+        // mach-o addends are implicit, i.e., stored in the relocated bytes, of the relocation's size
+        assert_eq!(relocations(mach, "__text")[0].r_type(), X86_64_RELOC_SIGNED);
+        assert_eq!(&contents("__text")[..7], &[0x8b, 0x05, 8, 0, 0, 0, 0xc3]);
+        assert_eq!(
+            relocations(mach, "__data")[0].r_type(),
+            X86_64_RELOC_UNSIGNED
+        );
+        // `pair` is in the same section, so the relocation is relative to it, and the relocated
+        // bytes hold the target's address
+        let data = sections
+            .iter()
+            .position(|(section, _)| section.name().unwrap() == "__data")
+            .unwrap();
+        assert!(!relocations(mach, "__data")[0].is_extern());
+        assert_eq!(relocations(mach, "__data")[0].r_symbolnum(), data + 1);
+        let pair = sections[data].0.addr as i64;
+        assert_eq!(&contents("__data")[16..24], &(pair - 8).to_le_bytes());
+    });
+
+    // loads of imported data go through the GOT, whose relocations have no addend
+    obj.declare("imported", Decl::data_import())
+        .expect("can declare imported");
+    obj.link_with_addend(
+        Link {
+            from: "f",
+            to: "imported",
+            at: 2,
+        },
+        8,
+    )
+    .expect("can link to imported+8");
+    match obj.emit() {
+        Err(faerie::ArtifactError::UnsupportedRelocation(_)) => {}
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn local_relocations() {
+    use goblin::mach::relocation::X86_64_RELOC_BRANCH;
+
+    let build = |triple: target_lexicon::Triple, code: Vec<u8>| {
+        let mut obj = Artifact::new(triple, "t.o".into());
+        // call _g; call _w; ret
+        obj.declare_with("f", Decl::function().global(), code)
+            .expect("can declare f");
+        obj.declare_with("g", Decl::function(), vec![0xc3])
+            .expect("can declare g");
+        obj.declare_with("w", Decl::function().global().weak(), vec![0xc3])
+            .expect("can declare w");
+        obj.link(Link {
+            from: "f",
+            to: "g",
+            at: 1,
+        })
+        .expect("can link f to g");
+        obj.link(Link {
+            from: "f",
+            to: "w",
+            at: 6,
+        })
+        .expect("can link f to w");
+        obj
+    };
+
+    let obj = build(
+        triple!("x86_64-apple-darwin"),
+        vec![0xe8, 0, 0, 0, 0, 0xe8, 0, 0, 0, 0, 0xc3],
+    );
+    let bytes = obj.emit().expect("can emit mach-o file");
+    with_macho(&obj, |mach| {
+        let address = |name: &str| {
+            mach.symbols()
+                .map(|sym| sym.expect("can parse symbol"))
+                .find(|(sym, _)| *sym == name)
+                .expect("symbol is present")
+                .1
+                .n_value as i32
+        };
+        let (f, g) = (address("_f"), address("_g"));
+        let relocs = relocations(mach, "__text");
+        assert_eq!(relocs.len(), 2);
+        // `g` is in `__text` too, so it is referenced relative to the section
+        let local = relocs
+            .iter()
+            .find(|reloc| reloc.r_address == f + 1)
+            .unwrap();
+        assert!(!local.is_extern());
+        assert_eq!(local.r_symbolnum(), 1);
+        assert_eq!(local.r_type(), X86_64_RELOC_BRANCH);
+        assert!(local.is_pic());
+        // and the call holds the displacement from its end to `g`
+        let (text, _) = &mach.segments[0].sections().expect("can parse sections")[0];
+        let call = text.offset as usize + f as usize + 1;
+        assert_eq!(&bytes[call..call + 4], &(g - (f + 5)).to_le_bytes());
+        // the weak `w` may be coalesced, so it is referenced by its symbol
+        let weak = relocs
+            .iter()
+            .find(|reloc| reloc.r_address == f + 6)
+            .unwrap();
+        assert!(weak.is_extern());
+        assert_eq!(&bytes[call + 5..call + 9], &[0, 0, 0, 0]);
+    });
+
+    // the linker requires arm64 branches to be external
+    let code = vec![0, 0, 0, 0x94, 0, 0, 0, 0x94, 0xc0, 0x03, 0x5f, 0xd6];
+    let mut obj = build(triple!("aarch64-apple-darwin"), code);
+    obj.link(Link {
+        from: "f",
+        to: "g",
+        at: 0,
+    })
+    .expect("can link f to g");
+    with_macho(&obj, |mach| {
+        let relocs = relocations(mach, "__text");
+        assert!(relocs.iter().all(|reloc| reloc.is_extern()));
+    });
+}
+
+#[test]
+fn section_flags() {
+    use goblin::mach::constants::{S_ATTR_NO_DEAD_STRIP, S_ATTR_PURE_INSTRUCTIONS};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc3])
+        .expect("can declare f");
+    obj.declare_with("x", Decl::data().global().writable(), vec![1; 4])
+        .expect("can declare x");
+    obj.set_section_flags("__data", u64::from(S_ATTR_NO_DEAD_STRIP))
+        .expect("can set the flags of __data");
+    // the flags of Mach-o sections are 32 bits wide
+    assert!(obj.set_section_flags("__text", 1 << 32).is_err());
+
+    // the section is only looked up when the object is emitted
+    let mut missing = obj.clone();
+    missing
+        .set_section_flags("__nonexistent", u64::from(S_ATTR_NO_DEAD_STRIP))
+        .expect("can set the flags of __nonexistent");
+    match missing.emit() {
+        Err(faerie::ArtifactError::NoSuchSection(name)) => assert_eq!(name, "__nonexistent"),
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+
+    with_macho(&obj, |mach| {
+        let sections = mach.segments[0].sections().expect("can parse sections");
+        let flags = |name: &str| {
+            sections
+                .iter()
+                .find(|(section, _)| section.name().unwrap() == name)
+                .map(|(section, _)| section.flags)
+                .expect("section is present")
+        };
+        // the flags are added to the usual ones
+        assert_eq!(flags("__data"), S_ATTR_NO_DEAD_STRIP);
+        assert_ne!(flags("__text") & S_ATTR_PURE_INSTRUCTIONS, 0);
+        assert_eq!(flags("__text") & S_ATTR_NO_DEAD_STRIP, 0);
+    });
+}
+
+#[test]
+fn cpu_subtypes() {
+    use goblin::mach::cputype::*;
+
+    for (target, cpusubtype) in &[
+        ("x86_64-apple-darwin", CPU_SUBTYPE_X86_64_ALL),
+        ("i686-apple-darwin", CPU_SUBTYPE_I386_ALL),
+        ("aarch64-apple-darwin", CPU_SUBTYPE_ARM64_ALL),
+        ("armv7-apple-ios", CPU_SUBTYPE_ARM_V7),
+        ("armv7s-apple-ios", CPU_SUBTYPE_ARM_V7S),
+        ("armv8-apple-ios", CPU_SUBTYPE_ARM_V8),
+    ] {
+        let obj = Artifact::new(triple!(target), "t.o".into());
+        with_macho(&obj, |mach| {
+            assert_eq!(mach.header.cpusubtype, *cpusubtype, "{}", target)
+        });
+    }
+}
+
+#[test]
+fn i386_objects() {
+    use goblin::mach::cputype::{CPU_SUBTYPE_I386_ALL, CPU_TYPE_X86};
+    use goblin::mach::header::{MH_MAGIC, MH_SUBSECTIONS_VIA_SYMBOLS};
+    use goblin::mach::relocation::GENERIC_RELOC_VANILLA;
+
+    let mut obj = Artifact::new(triple!("i686-apple-darwin"), "t.o".into());
+    obj.import("puts", ImportKind::Function)
+        .expect("can import puts");
+    // call puts; ret
+    obj.declare_with("f", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3])
+        .expect("can declare f");
+    obj.declare_with("ptr", Decl::data().global().writable(), vec![0; 4])
+        .expect("can declare ptr");
+    obj.link(Link {
+        from: "f",
+        to: "puts",
+        at: 1,
+    })
+    .expect("can link f to puts");
+    obj.link(Link {
+        from: "ptr",
+        to: "f",
+        at: 0,
+    })
+    .expect("can link ptr to f");
+
+    let bytes = obj.emit().expect("can emit mach-o file");
+    assert_eq!(&bytes[..4], &MH_MAGIC.to_le_bytes());
+    with_macho(&obj, |mach| {
+        assert!(!mach.is_64);
+        assert_eq!(mach.header.cputype, CPU_TYPE_X86);
+        assert_eq!(mach.header.cpusubtype, CPU_SUBTYPE_I386_ALL);
+        assert_eq!(mach.header.flags, MH_SUBSECTIONS_VIA_SYMBOLS);
+
+        let (_, text) = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .find(|(section, _)| section.name().unwrap() == "__text")
+            .expect("there should be a __text section");
+        // the displacement is relative to the end of the call, as if puts were at address zero
+        assert_eq!(&text[..6], &[0xe8, 0xfb, 0xff, 0xff, 0xff, 0xc3]);
+
+        let relocs = relocations(mach, "__text");
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].r_type(), GENERIC_RELOC_VANILLA);
+        assert_eq!(relocs[0].r_address, 1);
+        assert_eq!(relocs[0].r_length(), 2);
+        assert!(relocs[0].is_pic());
+
+        let relocs = relocations(mach, "__data");
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].r_type(), GENERIC_RELOC_VANILLA);
+        assert_eq!(relocs[0].r_length(), 2);
+        assert!(!relocs[0].is_pic());
+    });
+}
+
+#[test]
+fn i386_explicit_relocation_kinds() {
+    use faerie::{ArtifactError, Reloc, RelocationKind};
+    use goblin::mach::relocation::GENERIC_RELOC_VANILLA;
+
+    let mut obj = Artifact::new(triple!("i686-apple-darwin"), "t.o".into());
+    obj.import("g", ImportKind::Function).expect("can import g");
+    // call g; ret
+    obj.declare_with("f", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3])
+        .expect("can declare f");
+    obj.link_with(
+        Link {
+            from: "f",
+            to: "g",
+            at: 1,
+        },
+        Reloc::Kind(RelocationKind::PcRelative),
+    )
+    .expect("can link f to g");
+    with_macho(&obj, |mach| {
+        let (_, text) = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .find(|(section, _)| section.name().unwrap() == "__text")
+            .expect("there should be a __text section");
+        assert_eq!(&text[..6], &[0xe8, 0xfb, 0xff, 0xff, 0xff, 0xc3]);
+        let relocs = relocations(mach, "__text");
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].r_type(), GENERIC_RELOC_VANILLA);
+        assert!(relocs[0].is_pic());
+    });
+
+    let mut obj = Artifact::new(triple!("i686-apple-darwin"), "t.o".into());
+    obj.import("x", ImportKind::Data).expect("can import x");
+    obj.declare_with(
+        "f",
+        Decl::function().global(),
+        vec![0x8b, 0x05, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare f");
+    obj.link_with(
+        Link {
+            from: "f",
+            to: "x",
+            at: 2,
+        },
+        Reloc::Kind(RelocationKind::GotPcRelative),
+    )
+    .expect("can link f to x");
+    match obj.emit() {
+        Err(ArtifactError::UnsupportedRelocation(_)) => {}
+        _ => panic!("GOT relative relocations are not supported on i386"),
+    }
+}
+
+#[test]
+fn build_version() {
+    use faerie::MachPlatform;
+    use scroll::Pread;
+
+    let mut obj = Artifact::new(triple!("aarch64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xc0, 0x03, 0x5f, 0xd6])
+        .expect("can declare f");
+    obj.set_platform(MachPlatform::MacOS, (11, 0, 0), (12, 3, 1))
+        .expect("can set platform");
+
+    let bytes = obj.emit().expect("can emit mach-o file");
+    with_macho(&obj, |mach| {
+        assert_eq!(mach.header.ncmds, 3);
+        let cmd = mach
+            .load_commands
+            .iter()
+            .find(|cmd| cmd.command.cmd() == 0x32)
+            .expect("there should be an LC_BUILD_VERSION load command");
+        // it follows the segment load command
+        assert_eq!(mach.load_commands[1].offset, cmd.offset);
+        assert_eq!(cmd.command.cmdsize(), 24);
+        let field = |offset: usize| {
+            bytes
+                .pread_with::<u32>(cmd.offset + offset, scroll::LE)
+                .unwrap()
+        };
+        // platform, minos, sdk and ntools
+        assert_eq!(field(8), 1);
+        assert_eq!(field(12), 0x000b_0000);
+        assert_eq!(field(16), 0x000c_0301);
+        assert_eq!(field(20), 0);
+
+        // the symbols and sections are still where the other load commands say they are
+        let names = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol").0)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["_f"]);
+        let (_, text) = mach.segments[0]
+            .sections()
+            .expect("can parse sections")
+            .into_iter()
+            .find(|(section, _)| section.name().unwrap() == "__text")
+            .expect("there should be a __text section");
+        assert_eq!(&text[..4], &[0xc0, 0x03, 0x5f, 0xd6]);
+    });
+}
+
+#[test]
+fn source_path_stab() {
+    use goblin::mach::symbols::N_OSO;
+    use std::path::Path;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3])
+        .expect("can declare f");
+    obj.import("g", ImportKind::Function).expect("can import g");
+    obj.link(Link {
+        from: "f",
+        to: "g",
+        at: 1,
+    })
+    .expect("can link f to g");
+    obj.set_source_path(Path::new("/nonexistent/t.o"), 1_600_000_000)
+        .expect("can set source path");
+
+    with_macho(&obj, |mach| {
+        let symbols = mach
+            .symbols()
+            .map(|sym| sym.expect("can parse symbol"))
+            .collect::<Vec<_>>();
+        let names = symbols.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["/nonexistent/t.o", "_f", "_g"]);
+        // the modification time is the one given, whether or not the file exists
+        let oso = &symbols[0].1;
+        assert_eq!(
+            (oso.n_type, oso.n_sect, oso.n_desc, oso.n_value),
+            (N_OSO, 0, 1, 1_600_000_000)
+        );
+        assert!(oso.is_stab());
+        // the relocation still refers to `g`, past the stab
+        let relocs = relocations(mach, "__text");
+        assert_eq!(relocs[0].r_symbolnum(), 2);
+    });
+    assert_eq!(
+        obj.verify_with_backend(target_lexicon::BinaryFormat::Macho)
+            .expect("can verify mach-o"),
+        vec![]
+    );
+}
+
+#[test]
+fn link_with_reloc() {
+    use faerie::RelocationKind;
+    use goblin::mach::relocation::{X86_64_RELOC_GOT_LOAD, X86_64_RELOC_SIGNED};
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    // movq _x@GOTPCREL(%rip), %rax; movl _y(%rip), %eax; retq
+    obj.declare_with(
+        "f",
+        Decl::function().global(),
+        vec![0x48, 0x8b, 0x05, 0, 0, 0, 0, 0x8b, 0x05, 0, 0, 0, 0, 0xc3],
+    )
+    .expect("can declare f");
+    obj.declare_with("x", Decl::data().global().writable(), vec![1; 4])
+        .expect("can declare x");
+    obj.declare_with("y", Decl::data().global().writable(), vec![2; 4])
+        .expect("can declare y");
+    obj.link_with_reloc(
+        Link {
+            from: "f",
+            to: "x",
+            at: 3,
+        },
+        Some(RelocationKind::GotPcRelative),
+    )
+    .expect("can link f to x through the GOT");
+    obj.link_with_reloc(
+        Link {
+            from: "f",
+            to: "y",
+            at: 9,
+        },
+        None,
+    )
+    .expect("can link f to y");
+
+    with_macho(&obj, |mach| {
+        let mut relocs = relocations(mach, "__text")
+            .iter()
+            .map(|reloc| (reloc.r_address, reloc.r_type(), reloc.is_pic()))
+            .collect::<Vec<_>>();
+        relocs.sort();
+        // without a kind, the relocation is deduced as usual
+        assert_eq!(
+            relocs,
+            vec![
+                (3, X86_64_RELOC_GOT_LOAD, true),
+                (9, X86_64_RELOC_SIGNED, true)
+            ]
+        );
+    });
+}
+
+#[test]
+fn verify() {
+    use faerie::{Reloc, VerificationError};
+    use target_lexicon::BinaryFormat;
+
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0xe8, 0, 0, 0, 0, 0xc3])
+        .expect("can declare f");
+    obj.declare_with("msg", Decl::cstring().global(), b"hi\0".to_vec())
+        .expect("can declare msg");
+    obj.declare_with("ptr", Decl::data().global().writable(), vec![0; 8])
+        .expect("can declare ptr");
+    obj.declare("buf", Decl::data().global().writable())
+        .expect("can declare buf");
+    obj.define_zero_init("buf", 64).expect("can define buf");
+    obj.import("puts", ImportKind::Function)
+        .expect("can import puts");
+    obj.link(Link {
+        from: "f",
+        to: "puts",
+        at: 1,
+    })
+    .expect("can link f to puts");
+    obj.link(Link {
+        from: "ptr",
+        to: "msg",
+        at: 0,
+    })
+    .expect("can link ptr to msg");
+    assert_eq!(
+        obj.verify_with_backend(BinaryFormat::Macho)
+            .expect("can verify mach-o"),
+        vec![]
+    );
+
+    // a link to a symbol which was never defined, and one past the end of `f`
+    obj.declare("g", Decl::function().global())
+        .expect("can declare g");
+    obj.link(Link {
+        from: "f",
+        to: "g",
+        at: 1,
+    })
+    .expect("can link f to g");
+    obj.link_with(
+        Link {
+            from: "ptr",
+            to: "f",
+            at: 4,
+        },
+        Reloc::Absolute { addend: 0 },
+    )
+    .expect("can link ptr to f");
+    assert_eq!(
+        obj.verify_with_backend(BinaryFormat::Macho)
+            .expect("can verify mach-o"),
+        vec![
+            VerificationError::MissingSymbol { name: "g".into() },
+            VerificationError::RelocationOutOfBounds {
+                section: "__data".into(),
+                offset: 4,
+                size: 8,
+                section_size: 8,
+            },
+        ]
+    );
+
+    assert!(obj.verify_with_backend(BinaryFormat::Elf).is_err());
+}
+
+#[test]
+fn fat_binary_write() {
+    use faerie::{ArtifactError, FatBinary};
+    use goblin::mach::cputype::{CPU_TYPE_ARM64, CPU_TYPE_X86_64};
+
+    let artifact = |target: &str| {
+        let mut obj = Artifact::new(triple!(target), "t.o".into());
+        obj.declare_with("f", Decl::function().global(), vec![0xc3])
+            .expect("can declare f");
+        obj
+    };
+    let mut fat = FatBinary::default();
+    fat.add(&artifact("x86_64-apple-darwin"))
+        .expect("can add x86_64 slice");
+    fat.add(&artifact("aarch64-apple-darwin"))
+        .expect("can add arm64 slice");
+    match fat.add(&artifact("x86_64-apple-darwin")) {
+        Err(ArtifactError::DuplicateArchitecture(arch)) => assert_eq!(arch, "x86_64"),
+        result => panic!("a second x86_64 slice was not rejected: {:?}", result),
+    }
+    assert!(fat.add(&artifact("x86_64-unknown-linux-gnu")).is_err());
+
+    let bytes = fat.to_bytes().expect("can write fat binary");
+    let multi = match goblin::mach::Mach::parse(&bytes).expect("can parse fat binary") {
+        Mach::Fat(multi) => multi,
+        Mach::Binary(_) => panic!("fat binary parsed as a single Mach-o file"),
+    };
+    let arches = multi.arches().expect("can parse fat archs");
+    assert_eq!(arches.len(), 2);
+    let mut end = 8 + 2 * 20;
+    // the `_ALL` subtype of each cpu type, which lipo requires
+    for (arch, (cputype, cpusubtype, align)) in arches
+        .iter()
+        .zip(&[(CPU_TYPE_X86_64, 3, 12), (CPU_TYPE_ARM64, 0, 14)])
+    {
+        assert_eq!(arch.cputype, *cputype);
+        assert_eq!(arch.cpusubtype, *cpusubtype);
+        assert_eq!(arch.align, *align);
+        assert_eq!(arch.offset % (1 << align), 0);
+        // the padding in front of every slice is zeroed
+        assert!(bytes[end..arch.offset as usize].iter().all(|&b| b == 0));
+        end = (arch.offset + arch.size) as usize;
+        let mach = MachO::parse(&bytes, arch.offset as usize).expect("can parse slice");
+        assert_eq!(mach.header.cputype, *cputype);
+    }
+    assert_eq!(end, bytes.len());
+    assert_eq!(FatBinary::from_bytes(&bytes).expect("can read back"), fat);
+}
+
+#[test]
+fn nm_output() {
+    let mut obj = Artifact::new(triple!("x86_64-apple-darwin"), "t.o".into());
+    obj.declare_with("f", Decl::function().global(), vec![0x90, 0xc3])
+        .expect("can declare f");
+    obj.declare_with("g", Decl::function(), vec![0xc3])
+        .expect("can declare g");
+    obj.declare_with("x", Decl::data().global().writable(), vec![1; 8])
+        .expect("can declare x");
+    obj.declare_with("y", Decl::data().writable(), vec![2; 4])
+        .expect("can declare y");
+    obj.declare_with("r", Decl::data().global(), vec![3; 4])
+        .expect("can declare r");
+    obj.declare("z", Decl::data().global().writable())
+        .expect("can declare z");
+    obj.define_zero_init("z", 16).expect("can define z");
+    obj.declare("reg", Decl::AbsoluteSymbol(0x1000))
+        .expect("can declare reg");
+    obj.declare("buf", Decl::common(32, 8))
+        .expect("can declare buf");
+    obj.declare("imp", Decl::function_import())
+        .expect("can declare imp");
+    let nm = obj.to_nm_output().expect("can list symbols");
+    assert_eq!(
+        nm.lines().collect::<Vec<_>>(),
+        vec![
+            "0000000000000020 C _buf",
+            "0000000000000010 T _f",
+            "0000000000000000 t _g",
+            "                 U _imp",
+            "0000000000000030 S _r",
+            "0000000000001000 A _reg",
+            "0000000000000028 D _x",
+            "0000000000000020 d _y",
+            "0000000000000038 B _z",
+        ]
+    );
+    // the values are those of the emitted symbols
+    with_macho(&obj, |mach| {
+        for symbol in mach.symbols() {
+            let (name, nlist) = symbol.expect("can parse symbol");
+            let line = nm
+                .lines()
+                .find(|line| line.ends_with(&format!(" {}", name)))
+                .expect("symbol is listed");
+            if nlist.n_value != 0 {
+                assert!(line.starts_with(&format!("{:016x}", nlist.n_value)));
+            }
+        }
+    });
+
+    let obj = Artifact::new(triple!("x86_64-unknown-linux-gnu"), "t.o".into());
+    assert!(obj.to_nm_output().is_err());
+}
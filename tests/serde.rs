@@ -0,0 +1,486 @@
+//! Round trips of artifacts through serde. faerie has no serde data format as a dependency, so
+//! these use a minimal self-describing format of their own, which serializes into a `Value` tree.
+#![cfg(feature = "serde")]
+
+extern crate faerie;
+extern crate serde;
+#[macro_use]
+extern crate target_lexicon;
+
+use faerie::{Artifact, Decl, ImportKind, Link, Reloc};
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A serialized value
+#[derive(Debug)]
+enum Value {
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+    None,
+    Some(Box<Value>),
+    Unit,
+    Newtype(Box<Value>),
+    Seq(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    /// An enum variant, by name, and its contents
+    Variant(&'static str, Box<Value>),
+}
+
+#[derive(Debug)]
+struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+struct Serializer;
+
+/// The elements of a sequence, tuple, or tuple variant being serialized
+struct SerializeSeq {
+    variant: Option<&'static str>,
+    elements: Vec<Value>,
+}
+
+/// The entries of a map, struct, or struct variant being serialized
+struct SerializeMap {
+    variant: Option<&'static str>,
+    entries: Vec<(Value, Value)>,
+    key: Option<Value>,
+}
+
+/// `value` as the contents of `variant`, if it is one
+fn in_variant(variant: Option<&'static str>, value: Value) -> Value {
+    match variant {
+        Some(variant) => Value::Variant(variant, Box::new(value)),
+        None => value,
+    }
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = SerializeSeq;
+    type SerializeTuple = SerializeSeq;
+    type SerializeTupleStruct = SerializeSeq;
+    type SerializeTupleVariant = SerializeSeq;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeMap;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        Ok(Value::I64(v.into()))
+    }
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        Ok(Value::I64(v.into()))
+    }
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        Ok(Value::I64(v.into()))
+    }
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(Value::I64(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        Ok(Value::U64(v.into()))
+    }
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        Ok(Value::U64(v.into()))
+    }
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        Ok(Value::U64(v.into()))
+    }
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        Ok(Value::U64(v))
+    }
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(Value::F64(v.into()))
+    }
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::F64(v))
+    }
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::Char(v))
+    }
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::Str(v.to_string()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::None)
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Value, Error> {
+        Ok(Value::Some(Box::new(value.serialize(Serializer)?)))
+    }
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Unit)
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Ok(Value::Unit)
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(Value::Variant(variant, Box::new(Value::Unit)))
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        Ok(Value::Newtype(Box::new(value.serialize(Serializer)?)))
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        Ok(Value::Variant(
+            variant,
+            Box::new(value.serialize(Serializer)?),
+        ))
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SerializeSeq, Error> {
+        Ok(SerializeSeq {
+            variant: None,
+            elements: Vec::new(),
+        })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SerializeSeq, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeSeq, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeSeq, Error> {
+        Ok(SerializeSeq {
+            variant: Some(variant),
+            elements: Vec::new(),
+        })
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap, Error> {
+        Ok(SerializeMap {
+            variant: None,
+            entries: Vec::new(),
+            key: None,
+        })
+    }
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeMap, Error> {
+        self.serialize_map(Some(len))
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeMap, Error> {
+        Ok(SerializeMap {
+            variant: Some(variant),
+            entries: Vec::new(),
+            key: None,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SerializeSeq {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.elements.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+    fn end(self) -> Result<Value, Error> {
+        Ok(in_variant(self.variant, Value::Seq(self.elements)))
+    }
+}
+
+impl ser::SerializeTuple for SerializeSeq {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeSeq {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeSeq {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(Serializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().expect("key precedes its value");
+        self.entries.push((key, value.serialize(Serializer)?));
+        Ok(())
+    }
+    fn end(self) -> Result<Value, Error> {
+        Ok(in_variant(self.variant, Value::Map(self.entries)))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.entries
+            .push((Value::Str(key.to_string()), value.serialize(Serializer)?));
+        Ok(())
+    }
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+struct Deserializer(Value);
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::U64(v) => visitor.visit_u64(v),
+            Value::I64(v) => visitor.visit_i64(v),
+            Value::F64(v) => visitor.visit_f64(v),
+            Value::Char(v) => visitor.visit_char(v),
+            Value::Str(v) => visitor.visit_string(v),
+            Value::Bytes(v) => visitor.visit_byte_buf(v),
+            Value::None => visitor.visit_none(),
+            Value::Some(v) => visitor.visit_some(Deserializer(*v)),
+            Value::Unit => visitor.visit_unit(),
+            Value::Newtype(v) => visitor.visit_newtype_struct(Deserializer(*v)),
+            Value::Seq(v) => visitor.visit_seq(SeqAccess(v.into_iter())),
+            Value::Map(v) => visitor.visit_map(MapAccess {
+                entries: v.into_iter(),
+                value: None,
+            }),
+            Value::Variant(variant, value) => visitor.visit_enum(EnumAccess(variant, *value)),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
+    }
+}
+
+struct SeqAccess(std::vec::IntoIter<Value>);
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = Error;
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.0
+            .next()
+            .map(|value| seed.deserialize(Deserializer(value)))
+            .transpose()
+    }
+}
+
+struct MapAccess {
+    entries: std::vec::IntoIter<(Value, Value)>,
+    value: Option<Value>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = Error;
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Deserializer(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self.value.take().expect("key precedes its value");
+        seed.deserialize(Deserializer(value))
+    }
+}
+
+struct EnumAccess(&'static str, Value);
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = Error;
+    type Variant = Deserializer;
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Deserializer), Error> {
+        let variant = seed.deserialize(self.0.into_deserializer())?;
+        Ok((variant, Deserializer(self.1)))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer {
+    type Error = Error;
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+/// `obj` after serializing and deserializing it
+fn round_trip(obj: &Artifact) -> Artifact {
+    let value = obj.serialize(Serializer).expect("can serialize artifact");
+    serde::Deserialize::deserialize(Deserializer(value)).expect("can deserialize artifact")
+}
+
+#[test]
+fn round_trip_emits_identical_bytes() {
+    for target in &["x86_64-unknown-linux-gnu", "x86_64-apple-darwin"] {
+        let mut obj = Artifact::new(triple!(target), "t.o".into());
+        obj.import("puts", ImportKind::Function)
+            .expect("can import puts");
+        obj.declare_with("msg", Decl::cstring(), b"hello\0".to_vec())
+            .expect("can declare msg");
+        obj.declare_with("table", Decl::data().global().writable(), vec![0; 16])
+            .expect("can declare table");
+        obj.declare("buf", Decl::data().global())
+            .expect("can declare buf");
+        obj.define_zero_init("buf", 64).expect("can define buf");
+        obj.declare_with(
+            "main",
+            Decl::function().global(),
+            vec![0x48, 0x8d, 0x3d, 0, 0, 0, 0, 0xe8, 0, 0, 0, 0, 0xc3],
+        )
+        .expect("can declare main");
+        obj.link(Link {
+            from: "main",
+            to: "msg",
+            at: 3,
+        })
+        .expect("can link main to msg");
+        obj.link(Link {
+            from: "main",
+            to: "puts",
+            at: 8,
+        })
+        .expect("can link main to puts");
+        obj.link_with(
+            Link {
+                from: "table",
+                to: "main",
+                at: 8,
+            },
+            Reloc::Absolute { addend: 4 },
+        )
+        .expect("can link table to main");
+        obj.add_comment("faerie").expect("can add comment");
+        obj.set_build_id(&[1, 2, 3, 4]).expect("can set build id");
+
+        let copy = round_trip(&obj);
+        assert_eq!(copy.name, obj.name);
+        assert_eq!(copy.target, obj.target);
+        assert_eq!(
+            copy.emit().expect("can emit copy"),
+            obj.emit().expect("can emit artifact")
+        );
+    }
+}
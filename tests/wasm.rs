@@ -0,0 +1,317 @@
+extern crate faerie;
+#[macro_use]
+extern crate target_lexicon;
+
+use faerie::{Artifact, ArtifactError, Decl, Link, Reloc, RelocationKind};
+use std::str::FromStr;
+
+/// Read an unsigned LEB128 from `bytes` at `offset`
+fn uleb(bytes: &[u8], offset: &mut usize) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*offset];
+        *offset += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// Read a length prefixed string from `bytes` at `offset`
+fn name<'a>(bytes: &'a [u8], offset: &mut usize) -> &'a str {
+    let len = uleb(bytes, offset) as usize;
+    let name = std::str::from_utf8(&bytes[*offset..*offset + len]).expect("name is utf-8");
+    *offset += len;
+    name
+}
+
+/// The sections of a wasm module, as their id, the name of custom sections, and their payload
+fn sections(bytes: &[u8]) -> Vec<(u8, &str, &[u8])> {
+    assert_eq!(&bytes[..8], b"\0asm\x01\0\0\0");
+    let mut sections = Vec::new();
+    let mut offset = 8;
+    while offset < bytes.len() {
+        let id = bytes[offset];
+        offset += 1;
+        let len = uleb(bytes, &mut offset) as usize;
+        let mut payload = &bytes[offset..offset + len];
+        offset += len;
+        let mut name_len = 0;
+        let name = if id == 0 {
+            let name = name(payload, &mut name_len);
+            payload = &payload[name_len..];
+            name
+        } else {
+            ""
+        };
+        sections.push((id, name, payload));
+    }
+    sections
+}
+
+/// The relocations of the custom section `section`, as their type, offset and symbol index
+fn relocations(bytes: &[u8], section: &str) -> Vec<(u8, u64, u64)> {
+    let (_, _, payload) = sections(bytes)
+        .into_iter()
+        .find(|(id, name, _)| *id == 0 && *name == section)
+        .expect("relocation section is present");
+    let mut offset = 0;
+    uleb(payload, &mut offset);
+    (0..uleb(payload, &mut offset))
+        .map(|_| {
+            let typ = payload[offset];
+            offset += 1;
+            let at = uleb(payload, &mut offset);
+            let symbol = uleb(payload, &mut offset);
+            // all relocations but function and table indices have an addend
+            if typ > 2 {
+                uleb(payload, &mut offset);
+            }
+            (typ, at, symbol)
+        })
+        .collect()
+}
+
+#[test]
+fn object() {
+    const PADDED: [u8; 5] = [0x80, 0x80, 0x80, 0x80, 0];
+    let mut obj = Artifact::new(triple!("wasm32-unknown-unknown"), "t.o".into());
+    obj.declare("imp", Decl::function_import()).unwrap();
+    obj.declare("g", Decl::function()).unwrap();
+    obj.declare("f", Decl::function().global()).unwrap();
+    obj.declare("x", Decl::data().global().with_align(Some(4)))
+        .unwrap();
+    obj.declare("p", Decl::data().writable().with_align(Some(4)))
+        .unwrap();
+    // call g; call imp; i32.const x; drop; end
+    let mut f = vec![0, 0x10];
+    f.extend_from_slice(&PADDED);
+    f.push(0x10);
+    f.extend_from_slice(&PADDED);
+    f.push(0x41);
+    f.extend_from_slice(&PADDED);
+    f.extend_from_slice(&[0x1a, 0x0b]);
+    obj.define("f", f).unwrap();
+    obj.define("g", vec![0, 0x0b]).unwrap();
+    obj.define("x", vec![1, 2, 3, 4]).unwrap();
+    obj.define("p", vec![0; 4]).unwrap();
+    obj.link(Link {
+        from: "f",
+        to: "g",
+        at: 2,
+    })
+    .unwrap();
+    obj.link(Link {
+        from: "f",
+        to: "imp",
+        at: 8,
+    })
+    .unwrap();
+    obj.link(Link {
+        from: "f",
+        to: "x",
+        at: 14,
+    })
+    .unwrap();
+    obj.link_with(
+        Link {
+            from: "p",
+            to: "x",
+            at: 0,
+        },
+        Reloc::AutoWithAddend { addend: 2 },
+    )
+    .unwrap();
+    let bytes = obj.emit().expect("can emit wasm object");
+
+    let sections = sections(&bytes);
+    assert_eq!(
+        sections
+            .iter()
+            .map(|(id, name, _)| (*id, *name))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, ""),
+            (2, ""),
+            (3, ""),
+            (7, ""),
+            (10, ""),
+            (11, ""),
+            (0, "linking"),
+            (0, "reloc.CODE"),
+            (0, "reloc.DATA"),
+        ]
+    );
+
+    // the imported function comes first in the function index space
+    let (_, _, import) = sections[1];
+    let mut offset = 0;
+    assert_eq!(uleb(import, &mut offset), 2);
+    assert_eq!(name(import, &mut offset), "env");
+    assert_eq!(name(import, &mut offset), "__linear_memory");
+    // the kind of the memory, its limits' flags and its minimum size
+    offset += 3;
+    assert_eq!(name(import, &mut offset), "env");
+    assert_eq!(name(import, &mut offset), "imp");
+    let (_, _, export) = sections[3];
+    let mut offset = 0;
+    assert_eq!(uleb(export, &mut offset), 1);
+    assert_eq!(name(export, &mut offset), "f");
+
+    // the relocated bytes hold the indices and addresses as if the object was not linked
+    let (_, _, code) = sections[4];
+    let call_g = [0, 0x10, 0x81, 0x80, 0x80, 0x80];
+    let f = code.windows(6).position(|w| w == call_g);
+    let f = f.expect("call of g is present");
+    assert_eq!(&code[f + 8..f + 13], &[0x80, 0x80, 0x80, 0x80, 0]);
+    // x is placed after p
+    assert_eq!(&code[f + 14..f + 19], &[0x84, 0x80, 0x80, 0x80, 0]);
+    let (_, _, data) = sections[5];
+    assert!(data.windows(4).any(|w| w == [6, 0, 0, 0]));
+
+    // code relocations are relative to the start of the code section's payload
+    let code_relocations = relocations(&bytes, "reloc.CODE");
+    assert_eq!(code_relocations.len(), 3);
+    assert_eq!(code_relocations[0].0, 0);
+    assert_eq!(code_relocations[0].1, f as u64 + 2);
+    assert_eq!(code_relocations[1].0, 0);
+    assert_eq!(code_relocations[1].1, f as u64 + 8);
+    assert_eq!(code_relocations[2].0, 4);
+    assert_eq!(code_relocations[2].1, f as u64 + 14);
+    let data_relocations = relocations(&bytes, "reloc.DATA");
+    assert_eq!(data_relocations.len(), 1);
+    assert_eq!(data_relocations[0].0, 5);
+}
+
+#[test]
+fn function_pointers() {
+    let mut obj = Artifact::new(triple!("wasm32-unknown-unknown"), "t.o".into());
+    obj.declare_with("f", Decl::function(), vec![0, 0x0b])
+        .unwrap();
+    // i32.const f; drop; end
+    let mut g = vec![0, 0x41, 0x80, 0x80, 0x80, 0x80, 0];
+    g.extend_from_slice(&[0x1a, 0x0b]);
+    obj.declare_with("g", Decl::function(), g).unwrap();
+    obj.declare_with("fp", Decl::data(), vec![0; 4]).unwrap();
+    obj.link(Link {
+        from: "fp",
+        to: "f",
+        at: 0,
+    })
+    .unwrap();
+    // links from functions to functions are calls, unless they are explicitly absolute
+    obj.link_with(
+        Link {
+            from: "g",
+            to: "f",
+            at: 2,
+        },
+        Reloc::Kind(RelocationKind::Absolute),
+    )
+    .unwrap();
+    let bytes = obj.emit().expect("can emit wasm object");
+
+    // the addresses of functions are their indices in the table
+    let code_relocations = relocations(&bytes, "reloc.CODE");
+    assert_eq!(code_relocations.len(), 1);
+    assert_eq!(code_relocations[0].0, 1);
+    let data_relocations = relocations(&bytes, "reloc.DATA");
+    assert_eq!(data_relocations.len(), 1);
+    assert_eq!(data_relocations[0].0, 2);
+    assert_eq!(data_relocations[0].2, code_relocations[0].2);
+}
+
+/// Emit `obj` and check that it is rejected as unsupported
+fn assert_unsupported(obj: &Artifact) {
+    match obj.emit() {
+        Err(ArtifactError::Unsupported(_)) | Err(ArtifactError::UnsupportedRelocation(_)) => {}
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn unsupported() {
+    let object = || {
+        let mut obj = Artifact::new(triple!("wasm32-unknown-unknown"), "t.o".into());
+        obj.declare_with("f", Decl::function(), vec![0, 0x0b])
+            .unwrap();
+        obj.declare_with(
+            "g",
+            Decl::function(),
+            vec![0, 0x10, 0x80, 0x80, 0x80, 0x80, 0],
+        )
+        .unwrap();
+        obj.declare_with("x", Decl::data(), vec![0; 4]).unwrap();
+        obj
+    };
+
+    let mut obj = object();
+    obj.declare_with("tls", Decl::tls_data(), vec![0; 4])
+        .unwrap();
+    assert_unsupported(&obj);
+
+    let mut obj = object();
+    obj.declare("abs", Decl::AbsoluteSymbol(0x1000)).unwrap();
+    assert_unsupported(&obj);
+
+    let mut obj = object();
+    obj.declare("buf", Decl::common(16, 4)).unwrap();
+    assert_unsupported(&obj);
+
+    let mut obj = object();
+    obj.declare_with(
+        ".custom",
+        Decl::section(faerie::SectionKind::Data),
+        vec![0; 4],
+    )
+    .unwrap();
+    obj.link(Link {
+        from: ".custom",
+        to: "x",
+        at: 0,
+    })
+    .unwrap();
+    assert_unsupported(&obj);
+
+    let mut obj = object();
+    obj.link_with(
+        Link {
+            from: "x",
+            to: "f",
+            at: 0,
+        },
+        Reloc::Debug { size: 4, addend: 0 },
+    )
+    .unwrap();
+    assert_unsupported(&obj);
+
+    // function indices have no addends
+    let mut obj = object();
+    obj.link_with(
+        Link {
+            from: "g",
+            to: "f",
+            at: 2,
+        },
+        Reloc::AutoWithAddend { addend: 1 },
+    )
+    .unwrap();
+    assert_unsupported(&obj);
+
+    // the relocated bytes are past the end of the data
+    let mut obj = object();
+    obj.link(Link {
+        from: "x",
+        to: "x",
+        at: 2,
+    })
+    .unwrap();
+    assert_unsupported(&obj);
+
+    let mut obj = object();
+    obj.target = triple!("x86_64-unknown-unknown-wasm");
+    assert_unsupported(&obj);
+}